@@ -0,0 +1,434 @@
+// The COSMAC VIP's RCA CDP1802, for the 0x0NNN machine code subroutines some
+// VIP programs carry. Only the CPU, the machine it's in is the Bus.
+
+// What the CPU is wired to
+pub trait Bus {
+    fn read(&mut self, address: u16) -> u8;
+    fn write(&mut self, address: u16, value: u8);
+    // OUT 1-7 with the byte put on the bus
+    fn output(&mut self, port: u8, value: u8);
+    // INP 1-7, the byte read from the bus
+    fn input(&mut self, port: u8) -> u8;
+    // the EF1-EF4 input lines
+    fn flag(&mut self, line: u8) -> bool;
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Cdp1802 {
+    // the sixteen 16 bit scratchpad registers
+    pub r: [u16; 16],
+    // the accumulator and its carry
+    pub d: u8,
+    pub df: bool,
+    // which of r is the program counter and which the data pointer
+    pub p: u8,
+    pub x: u8,
+    // X and P saved by MARK or an interrupt
+    pub t: u8,
+    pub ie: bool,
+    // the Q output, the VIP's speaker
+    pub q: bool,
+}
+
+impl Cdp1802 {
+    pub fn new() -> Self {
+        Self {
+            ie: true,
+            ..Self::default()
+        }
+    }
+
+    // the byte at R(P), moving R(P) past it
+    fn fetch(&mut self, bus: &mut impl Bus) -> u8 {
+        let value = bus.read(self.r[self.p as usize]);
+        self.r[self.p as usize] = self.r[self.p as usize].wrapping_add(1);
+        value
+    }
+
+    fn rx(&self) -> u16 {
+        self.r[self.x as usize]
+    }
+
+    // D + value + carry, setting DF on overflow
+    fn add(&mut self, value: u8, carry: bool) {
+        let sum = self.d as u16 + value as u16 + carry as u16;
+        self.d = sum as u8;
+        self.df = sum > 0xFF;
+    }
+
+    // a - b - borrow, DF set when nothing was borrowed
+    fn subtract(&mut self, a: u8, b: u8, borrow: bool) {
+        let difference = a as i16 - b as i16 - borrow as i16;
+        self.d = difference as u8;
+        self.df = difference >= 0;
+    }
+
+    fn short_branch(&mut self, taken: bool, bus: &mut impl Bus) {
+        let p = self.p as usize;
+        if taken {
+            let target = bus.read(self.r[p]);
+            self.r[p] = (self.r[p] & 0xFF00) | target as u16;
+        } else {
+            self.r[p] = self.r[p].wrapping_add(1);
+        }
+    }
+
+    fn long_branch(&mut self, taken: bool, bus: &mut impl Bus) {
+        let p = self.p as usize;
+        if taken {
+            let high = bus.read(self.r[p]);
+            let low = bus.read(self.r[p].wrapping_add(1));
+            self.r[p] = u16::from_be_bytes([high, low]);
+        } else {
+            self.r[p] = self.r[p].wrapping_add(2);
+        }
+    }
+
+    fn long_skip(&mut self, taken: bool) {
+        if taken {
+            let p = self.p as usize;
+            self.r[p] = self.r[p].wrapping_add(2);
+        }
+    }
+
+    // Executes one instruction
+    pub fn step(&mut self, bus: &mut impl Bus) {
+        let opcode = self.fetch(bus);
+        let n = opcode & 0xF;
+        let rn = n as usize;
+        match opcode >> 4 {
+            // IDL, waiting for an interrupt, which on the VIP comes every
+            // frame and returns straight away
+            0x0 if n == 0 => {}
+            // LDN
+            0x0 => self.d = bus.read(self.r[rn]),
+            // INC, DEC
+            0x1 => self.r[rn] = self.r[rn].wrapping_add(1),
+            0x2 => self.r[rn] = self.r[rn].wrapping_sub(1),
+            0x3 => {
+                let condition = match n & 0x7 {
+                    0 => true,
+                    1 => self.q,
+                    2 => self.d == 0,
+                    3 => self.df,
+                    line => bus.flag(line - 3),
+                };
+                match n {
+                    // SKP
+                    0x8 => self.r[self.p as usize] = self.r[self.p as usize].wrapping_add(1),
+                    0x0..=0x7 => self.short_branch(condition, bus),
+                    _ => self.short_branch(!condition, bus),
+                }
+            }
+            // LDA, STR
+            0x4 => {
+                self.d = bus.read(self.r[rn]);
+                self.r[rn] = self.r[rn].wrapping_add(1);
+            }
+            0x5 => bus.write(self.r[rn], self.d),
+            0x6 => match n {
+                // IRX
+                0x0 => self.r[self.x as usize] = self.rx().wrapping_add(1),
+                // OUT
+                0x1..=0x7 => {
+                    let value = bus.read(self.rx());
+                    bus.output(n, value);
+                    self.r[self.x as usize] = self.rx().wrapping_add(1);
+                }
+                // the 1804's extended opcodes, not on the 1802
+                0x8 => {}
+                // INP
+                _ => {
+                    let value = bus.input(n - 8);
+                    bus.write(self.rx(), value);
+                    self.d = value;
+                }
+            },
+            0x7 => match n {
+                // RET, DIS
+                0x0 | 0x1 => {
+                    let value = bus.read(self.rx());
+                    self.r[self.x as usize] = self.rx().wrapping_add(1);
+                    self.x = value >> 4;
+                    self.p = value & 0xF;
+                    self.ie = n == 0x0;
+                }
+                // LDXA
+                0x2 => {
+                    self.d = bus.read(self.rx());
+                    self.r[self.x as usize] = self.rx().wrapping_add(1);
+                }
+                // STXD
+                0x3 => {
+                    bus.write(self.rx(), self.d);
+                    self.r[self.x as usize] = self.rx().wrapping_sub(1);
+                }
+                // ADC, SDB, SHRC, SMB
+                0x4 => {
+                    let value = bus.read(self.rx());
+                    self.add(value, self.df);
+                }
+                0x5 => {
+                    let value = bus.read(self.rx());
+                    self.subtract(value, self.d, !self.df);
+                }
+                0x6 => {
+                    let carry = self.df;
+                    self.df = self.d & 0x1 != 0;
+                    self.d = (self.d >> 1) | ((carry as u8) << 7);
+                }
+                0x7 => {
+                    let value = bus.read(self.rx());
+                    self.subtract(self.d, value, !self.df);
+                }
+                // SAV
+                0x8 => bus.write(self.rx(), self.t),
+                // MARK
+                0x9 => {
+                    self.t = (self.x << 4) | self.p;
+                    bus.write(self.r[2], self.t);
+                    self.x = self.p;
+                    self.r[2] = self.r[2].wrapping_sub(1);
+                }
+                // REQ, SEQ
+                0xA => self.q = false,
+                0xB => self.q = true,
+                // ADCI, SDBI, SHLC, SMBI
+                0xC => {
+                    let value = self.fetch(bus);
+                    self.add(value, self.df);
+                }
+                0xD => {
+                    let value = self.fetch(bus);
+                    self.subtract(value, self.d, !self.df);
+                }
+                0xE => {
+                    let carry = self.df;
+                    self.df = self.d & 0x80 != 0;
+                    self.d = (self.d << 1) | carry as u8;
+                }
+                _ => {
+                    let value = self.fetch(bus);
+                    self.subtract(self.d, value, !self.df);
+                }
+            },
+            // GLO, GHI, PLO, PHI
+            0x8 => self.d = self.r[rn] as u8,
+            0x9 => self.d = (self.r[rn] >> 8) as u8,
+            0xA => self.r[rn] = (self.r[rn] & 0xFF00) | self.d as u16,
+            0xB => self.r[rn] = (self.r[rn] & 0x00FF) | ((self.d as u16) << 8),
+            0xC => match n {
+                // LBR, LBQ, LBZ, LBDF, LBNQ, LBNZ, LBNF
+                0x0 => self.long_branch(true, bus),
+                0x1 => self.long_branch(self.q, bus),
+                0x2 => self.long_branch(self.d == 0, bus),
+                0x3 => self.long_branch(self.df, bus),
+                0x9 => self.long_branch(!self.q, bus),
+                0xA => self.long_branch(self.d != 0, bus),
+                0xB => self.long_branch(!self.df, bus),
+                // NOP
+                0x4 => {}
+                // LSNQ, LSNZ, LSNF, LSKP, LSIE, LSQ, LSZ, LSDF
+                0x5 => self.long_skip(!self.q),
+                0x6 => self.long_skip(self.d != 0),
+                0x7 => self.long_skip(!self.df),
+                0x8 => self.long_skip(true),
+                0xC => self.long_skip(self.ie),
+                0xD => self.long_skip(self.q),
+                0xE => self.long_skip(self.d == 0),
+                _ => self.long_skip(self.df),
+            },
+            // SEP, SEX
+            0xD => self.p = n,
+            0xE => self.x = n,
+            // SHR, SHL
+            0xF if n == 0x6 => {
+                self.df = self.d & 0x1 != 0;
+                self.d >>= 1;
+            }
+            0xF if n == 0xE => {
+                self.df = self.d & 0x80 != 0;
+                self.d <<= 1;
+            }
+            _ => {
+                // the immediate forms take their operand from R(P), the
+                // others from R(X)
+                let value = if n >= 0x8 {
+                    self.fetch(bus)
+                } else {
+                    bus.read(self.rx())
+                };
+                match n & 0x7 {
+                    // LDX, LDI
+                    0x0 => self.d = value,
+                    // OR, ORI, AND, ANI, XOR, XRI
+                    0x1 => self.d |= value,
+                    0x2 => self.d &= value,
+                    0x3 => self.d ^= value,
+                    // ADD, ADI, SD, SDI
+                    0x4 => self.add(value, false),
+                    0x5 => self.subtract(value, self.d, false),
+                    // SM, SMI
+                    _ => self.subtract(self.d, value, false),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 256 bytes of memory and the EF lines, no I/O
+    struct Memory {
+        bytes: [u8; 0x100],
+        flags: [bool; 4],
+    }
+
+    impl Bus for Memory {
+        fn read(&mut self, address: u16) -> u8 {
+            self.bytes[address as usize & 0xFF]
+        }
+
+        fn write(&mut self, address: u16, value: u8) {
+            self.bytes[address as usize & 0xFF] = value;
+        }
+
+        fn output(&mut self, _port: u8, _value: u8) {}
+
+        fn input(&mut self, _port: u8) -> u8 {
+            0
+        }
+
+        fn flag(&mut self, line: u8) -> bool {
+            self.flags[line as usize - 1]
+        }
+    }
+
+    // Runs steps instructions of program loaded at 0 with R0 as the program
+    // counter, after setup
+    fn run(
+        program: &[u8],
+        steps: usize,
+        setup: impl FnOnce(&mut Cdp1802, &mut Memory),
+    ) -> (Cdp1802, Memory) {
+        let mut memory = Memory {
+            bytes: [0; 0x100],
+            flags: [false; 4],
+        };
+        memory.bytes[..program.len()].copy_from_slice(program);
+        let mut cpu = Cdp1802::new();
+        setup(&mut cpu, &mut memory);
+        for _ in 0..steps {
+            cpu.step(&mut memory);
+        }
+        (cpu, memory)
+    }
+
+    #[test]
+    fn short_branches() {
+        // LDI 0, BZ 0x40
+        let (cpu, _) = run(&[0xF8, 0x00, 0x32, 0x40], 2, |_, _| {});
+        assert_eq!(cpu.r[0], 0x40);
+        // LDI 0, BNZ 0x40
+        let (cpu, _) = run(&[0xF8, 0x00, 0x3A, 0x40], 2, |_, _| {});
+        assert_eq!(cpu.r[0], 0x04);
+        // B2 0x40 follows EF2
+        let (cpu, _) = run(&[0x35, 0x40], 1, |_, memory| memory.flags[1] = true);
+        assert_eq!(cpu.r[0], 0x40);
+        // SKP
+        let (cpu, _) = run(&[0x38, 0x40], 1, |_, _| {});
+        assert_eq!(cpu.r[0], 0x02);
+    }
+
+    #[test]
+    fn long_branches() {
+        // LBR 0x1234
+        let (cpu, _) = run(&[0xC0, 0x12, 0x34], 1, |_, _| {});
+        assert_eq!(cpu.r[0], 0x1234);
+        // LDI 1, LBZ 0x1234 isn't taken and steps over the address
+        let (cpu, _) = run(&[0xF8, 0x01, 0xC2, 0x12, 0x34], 2, |_, _| {});
+        assert_eq!(cpu.r[0], 0x05);
+        // LBNF 0x1234 with DF clear
+        let (cpu, _) = run(&[0xCB, 0x12, 0x34], 1, |_, _| {});
+        assert_eq!(cpu.r[0], 0x1234);
+    }
+
+    #[test]
+    fn long_skips() {
+        // LDI 0, then LSZ skips, LSNZ doesn't
+        let (cpu, _) = run(&[0xF8, 0x00, 0xCE], 2, |_, _| {});
+        assert_eq!(cpu.r[0], 0x05);
+        let (cpu, _) = run(&[0xF8, 0x00, 0xC6], 2, |_, _| {});
+        assert_eq!(cpu.r[0], 0x03);
+        // LSKP always skips, NOP never does
+        let (cpu, _) = run(&[0xC8], 1, |_, _| {});
+        assert_eq!(cpu.r[0], 0x03);
+        let (cpu, _) = run(&[0xC4], 1, |_, _| {});
+        assert_eq!(cpu.r[0], 0x01);
+        // LSIE follows the interrupt enable
+        let (cpu, _) = run(&[0xCC], 1, |cpu, _| cpu.ie = false);
+        assert_eq!(cpu.r[0], 0x01);
+    }
+
+    #[test]
+    fn subtract_borrows_through_df() {
+        // LDI 5, SMI 7 borrows and clears DF
+        let (cpu, _) = run(&[0xF8, 0x05, 0xFF, 0x07], 2, |_, _| {});
+        assert_eq!((cpu.d, cpu.df), (0xFE, false));
+        // LDI 5, SMI 5 doesn't borrow
+        let (cpu, _) = run(&[0xF8, 0x05, 0xFF, 0x05], 2, |_, _| {});
+        assert_eq!((cpu.d, cpu.df), (0x00, true));
+        // LDI 5, SDI 7 subtracts D from the operand
+        let (cpu, _) = run(&[0xF8, 0x05, 0xFD, 0x07], 2, |_, _| {});
+        assert_eq!((cpu.d, cpu.df), (0x02, true));
+        // LDI 5, SMBI 2 with DF clear borrows one more
+        let (cpu, _) = run(&[0xF8, 0x05, 0x7F, 0x02], 2, |_, _| {});
+        assert_eq!((cpu.d, cpu.df), (0x02, true));
+        // and with DF set doesn't
+        let (cpu, _) = run(&[0xF8, 0x05, 0x7F, 0x02], 2, |cpu, _| cpu.df = true);
+        assert_eq!((cpu.d, cpu.df), (0x03, true));
+    }
+
+    #[test]
+    fn shifts_through_df() {
+        // LDI 0x81, SHRC with DF clear
+        let (cpu, _) = run(&[0xF8, 0x81, 0x76], 2, |_, _| {});
+        assert_eq!((cpu.d, cpu.df), (0x40, true));
+        // LDI 0x81, SHLC with DF set
+        let (cpu, _) = run(&[0xF8, 0x81, 0x7E], 2, |cpu, _| cpu.df = true);
+        assert_eq!((cpu.d, cpu.df), (0x03, true));
+        // LDI 0x81, SHR doesn't shift DF in
+        let (cpu, _) = run(&[0xF8, 0x81, 0xF6], 2, |cpu, _| cpu.df = true);
+        assert_eq!((cpu.d, cpu.df), (0x40, true));
+    }
+
+    #[test]
+    fn mark_saves_x_and_p() {
+        // SEX 2, MARK
+        let (cpu, memory) = run(&[0xE2, 0x79], 2, |cpu, _| cpu.r[2] = 0x80);
+        assert_eq!(cpu.t, 0x20);
+        assert_eq!(memory.bytes[0x80], 0x20);
+        assert_eq!((cpu.x, cpu.p), (0, 0));
+        assert_eq!(cpu.r[2], 0x7F);
+    }
+
+    #[test]
+    fn ret_and_dis_restore_x_and_p() {
+        // SEX 2, RET
+        let (cpu, _) = run(&[0xE2, 0x70], 2, |cpu, memory| {
+            cpu.r[2] = 0x80;
+            cpu.ie = false;
+            memory.bytes[0x80] = 0x35;
+        });
+        assert_eq!((cpu.x, cpu.p, cpu.ie), (3, 5, true));
+        assert_eq!(cpu.r[2], 0x81);
+        // SEX 2, DIS
+        let (cpu, _) = run(&[0xE2, 0x71], 2, |cpu, memory| {
+            cpu.r[2] = 0x80;
+            memory.bytes[0x80] = 0x35;
+        });
+        assert_eq!((cpu.x, cpu.p, cpu.ie), (3, 5, false));
+    }
+}
@@ -0,0 +1,1905 @@
+#[cfg(feature = "cdp1802")]
+use crate::cdp1802::{Bus, Cdp1802};
+use crate::clock::{Clock, PerCycle};
+use crate::font::{self, Font};
+use rand::prelude::*;
+use rand::rngs::StdRng;
+#[cfg(feature = "fs")]
+use std::fs;
+use std::{fmt, num::Wrapping};
+
+pub const DISPLAY_WIDTH: usize = 64;
+pub const DISPLAY_HEIGHT: usize = 32;
+
+// the original interpreter only had room for 16 return addresses
+pub const DEFAULT_STACK_LIMIT: usize = 16;
+
+// the COSMAC VIP's 4KB, XO-CHIP programs get the whole 64KB I can address
+pub const DEFAULT_MEMORY_SIZE: usize = 0x1000;
+pub const XO_CHIP_MEMORY_SIZE: usize = 0x10000;
+
+// where the 4x5 hex digits FX29 points at are loaded, the bottom of memory
+// as this emulator always has so save states keep matching
+pub const FONT_BASE: usize = 0x000;
+
+// instructions a machine code subroutine may run before it's taken to be stuck
+#[cfg(feature = "cdp1802")]
+const MACHINE_CODE_LIMIT: u32 = 1_000_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    // 0x2NNN with a full return stack
+    StackOverflow { pc: u16 },
+    // 0x00EE with nothing to return to
+    StackUnderflow { pc: u16 },
+    // the program counter ran off the end of memory
+    PcOutOfRange { pc: u16 },
+    // a write below 0x200 while write protection is on
+    ProtectedWrite { pc: u16, address: u16 },
+    // a read or write past the end of memory while addresses don't wrap
+    AddressOutOfRange { pc: u16, address: usize },
+    // a 0x0NNN machine code subroutine that never returned
+    MachineCodeStuck { pc: u16 },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::StackOverflow { pc } => write!(f, "Stack overflow at {:#05X}", pc),
+            Error::StackUnderflow { pc } => {
+                write!(f, "No subroutine to return from at {:#05X}", pc)
+            }
+            Error::PcOutOfRange { pc } => {
+                write!(f, "Program counter out of range: {:#05X}", pc)
+            }
+            Error::ProtectedWrite { pc, address } => write!(
+                f,
+                "Write to protected interpreter memory at {:#05X} from {:#05X}",
+                address, pc
+            ),
+            Error::AddressOutOfRange { pc, address } => write!(
+                f,
+                "Memory access out of range at {:#05X} from {:#05X}",
+                address, pc
+            ),
+            Error::MachineCodeStuck { pc } => {
+                write!(f, "Machine code called from {:#05X} never returned", pc)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+// Execution counters, reset by initialize()
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    pub cycles: u64,
+    // indexed by the high nibble of the opcode
+    pub opcode_families: [u64; 16],
+    // instructions that aren't CHIP-8, executed as no-ops
+    pub unknown_opcodes: u64,
+}
+
+// Something that happened in the machine, passed to every listener added with
+// subscribe so tracers, profilers and overlays don't each need a hook in the core
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    InstructionExecuted {
+        pc: u16,
+        opcode: u16,
+    },
+    ScreenCleared,
+    // collision is true when the sprite turned a pixel off
+    SpriteDrawn {
+        x: u8,
+        y: u8,
+        rows: u8,
+        collision: bool,
+    },
+    // a timer counting down to zero
+    TimerExpired(Timer),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timer {
+    Delay,
+    Sound,
+}
+
+pub type Listener = Box<dyn FnMut(&Event)>;
+
+// Called with the machine and NNN for every 0x0NNN that isn't 00E0 or 00EE,
+// the original's jump into machine code, for host services like debug
+// prints. The program counter moves past the instruction afterwards.
+pub type SyscallHandler<R = StdRng> = Box<dyn FnMut(&mut State<R>, u16)>;
+
+// The buzzer, turned on when the sound timer is set and off when it runs out,
+// for embedders without a frontend polling sound_timer() every frame
+pub trait SoundSink {
+    fn set_sound(&mut self, on: bool);
+}
+
+impl<F: FnMut(bool)> SoundSink for F {
+    fn set_sound(&mut self, on: bool) {
+        self(on)
+    }
+}
+
+// Behaviours that differ between interpreters, all off matches this emulator's
+// original behaviour
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quirks {
+    // 0x8XY6/0x8XYE shift VY into VX instead of shifting VX in place
+    pub shift_vy: bool,
+    // 0xFX55/0xFX65 leave I pointing just past the last register
+    pub load_store_increment: bool,
+    // 0xBNNN jumps to NNN + VX, X being the top nibble of NNN
+    pub jump_vx: bool,
+    // 0x8XY1/0x8XY2/0x8XY3 clear VF
+    pub vf_reset: bool,
+    // 0xDXYN cuts sprites off at the edges of the screen instead of wrapping
+    // them around to the other side
+    pub clip_sprites: bool,
+}
+
+impl Quirks {
+    pub const PRESETS: [&'static str; 3] = ["default", "chip8", "schip"];
+
+    pub fn preset(name: &str) -> Result<Self, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "default" => Ok(Self::default()),
+            // the COSMAC VIP interpreter
+            "chip8" => Ok(Self {
+                shift_vy: true,
+                load_store_increment: true,
+                jump_vx: false,
+                vf_reset: true,
+                clip_sprites: true,
+            }),
+            // SUPER-CHIP 1.1 on the HP48
+            "schip" => Ok(Self {
+                shift_vy: false,
+                load_store_increment: false,
+                jump_vx: true,
+                vf_reset: false,
+                clip_sprites: true,
+            }),
+            _ => Err(format!(
+                "Unknown quirk preset {}, expected one of {}",
+                name,
+                Self::PRESETS.join(", ")
+            )),
+        }
+    }
+
+    // the field names, in to_bits order
+    pub const NAMES: [&'static str; 5] = [
+        "shift_vy",
+        "load_store_increment",
+        "jump_vx",
+        "vf_reset",
+        "clip_sprites",
+    ];
+
+    // Changes one quirk from a name=value pair, by what the instructions do,
+    // e.g. shift=vx, loadstore=increment, jump=vx, vfreset=on or clip=off
+    pub fn set(&mut self, setting: &str) -> Result<(), String> {
+        let (name, value) = setting
+            .split_once('=')
+            .ok_or_else(|| format!("Expected quirk=value, not {}", setting))?;
+        let (quirk, on) = match (name.trim(), value.trim()) {
+            ("shift", "vy") => (&mut self.shift_vy, true),
+            ("shift", "vx") => (&mut self.shift_vy, false),
+            ("loadstore", "increment") => (&mut self.load_store_increment, true),
+            ("loadstore", "unchanged") => (&mut self.load_store_increment, false),
+            ("jump", "vx") => (&mut self.jump_vx, true),
+            ("jump", "v0") => (&mut self.jump_vx, false),
+            ("vfreset", value) => (&mut self.vf_reset, on_off(value)?),
+            ("clip", value) => (&mut self.clip_sprites, on_off(value)?),
+            ("shift", _) => return Err("shift takes vx or vy".to_string()),
+            ("loadstore", _) => return Err("loadstore takes increment or unchanged".to_string()),
+            ("jump", _) => return Err("jump takes vx or v0".to_string()),
+            (name, _) => {
+                return Err(format!(
+                    "Unknown quirk {}, expected shift, loadstore, jump, vfreset or clip",
+                    name
+                ))
+            }
+        };
+        *quirk = on;
+        Ok(())
+    }
+
+    // One bit per quirk in declaration order, for files that store them
+    pub fn to_bits(self) -> u8 {
+        self.shift_vy as u8
+            | (self.load_store_increment as u8) << 1
+            | (self.jump_vx as u8) << 2
+            | (self.vf_reset as u8) << 3
+            | (self.clip_sprites as u8) << 4
+    }
+
+    pub fn from_bits(bits: u8) -> Self {
+        Self {
+            shift_vy: bits & 0x1 != 0,
+            load_store_increment: bits & 0x2 != 0,
+            jump_vx: bits & 0x4 != 0,
+            vf_reset: bits & 0x8 != 0,
+            clip_sprites: bits & 0x10 != 0,
+        }
+    }
+}
+
+fn on_off(value: &str) -> Result<bool, String> {
+    match value {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("Expected on or off, not {}", value)),
+    }
+}
+
+// Coverage flags kept per address
+pub const COVERAGE_EXECUTED: u8 = 0x1;
+pub const COVERAGE_SPRITE: u8 = 0x2;
+
+// How often an address was touched, only counted while access counting is
+// on. Sprite rows and FX65 are reads, FX33 and FX55 writes, and fetching an
+// instruction executes both its bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Accesses {
+    pub reads: u32,
+    pub writes: u32,
+    pub executes: u32,
+}
+
+// R is the random number source for CXNN. Anything seedable will do, so
+// tests and embedded builds can bring their own generator.
+pub struct State<R = StdRng> {
+    // 0x000-0x1FF - Chip 8 interpreter (contains font set in emu)
+    // FONT_BASE - the built in 4x5 pixel font set (0-F), 80 bytes
+    // 0x200-0xFFF - Program ROM and work RAM, or up to 0xFFFF with more memory
+    memory: Vec<Wrapping<u8>>,
+    // addresses past the end of memory wrap around instead of faulting
+    wrap_addresses: bool,
+    // Drawing is done in XOR mode and if a pixel is turned off as a result of drawing,
+    // the VF register is set.
+    v: [Wrapping<u8>; 16],
+    // index and program counter
+    i: u16,
+    pc: u16,
+    opcode: u16,
+    // the screen
+    gfx: [u8; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+    // the display as take_display_changes last reported it
+    reported_gfx: [u8; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+
+    delay_timer: u8,
+    // The system’s buzzer sounds whenever the sound timer reaches zero.
+    sound_timer: u8,
+    // return stack
+    stack: Vec<u16>,
+    // how deep the return stack may grow before 0x2NNN faults
+    stack_limit: usize,
+    // fault on writes into the interpreter/font area instead of corrupting it
+    write_protection: bool,
+    // when the timers tick, kept across initialize()
+    clock: Box<dyn Clock>,
+    quirks: Quirks,
+    // bit n set while key n is held
+    keys: u16,
+    // the digits FX29 points at, kept across initialize()
+    font: Font,
+    instructions: [fn(&mut Self) -> (); 16],
+    arithmetic_instructions: [fn(&mut Self) -> (); 16],
+    // reseeded from seed on initialize() so runs can be reproduced
+    rng: R,
+    seed: u64,
+    // set by an instruction that can't continue, the machine stays halted until initialize()
+    error: Option<Error>,
+    stats: Stats,
+    // executions per address, only tracked while profiling
+    profile: Option<Vec<u64>>,
+    // COVERAGE_* flags per address, only tracked while coverage is on
+    coverage: Option<Vec<u8>>,
+    // per address, only tracked while access counting is on
+    accesses: Option<Vec<Accesses>>,
+    // called with every Event, kept across initialize()
+    listeners: Vec<Listener>,
+    // told when the buzzer starts and stops, kept across initialize()
+    sound_sink: Option<Box<dyn SoundSink>>,
+    // runs 0x0NNN, kept across initialize()
+    syscall_handler: Option<SyscallHandler<R>>,
+    // 0x0NNN runs 1802 machine code instead
+    #[cfg(feature = "cdp1802")]
+    machine_code: bool,
+    // whether the sink was last told the buzzer's on
+    sounding: bool,
+    // the last program loaded, put back by reset()
+    rom: Vec<u8>,
+    // bumped whenever the display may have changed
+    display_generation: u64,
+}
+
+impl State {
+    pub fn new() -> Self {
+        Self::with_rng()
+    }
+}
+
+impl<R: RngCore + SeedableRng> State<R> {
+    // new() with R generating the random numbers, e.g. State::<MyRng>::with_rng()
+    pub fn with_rng() -> Self {
+        #[cfg(feature = "thread-rng")]
+        let seed = rand::thread_rng().gen();
+        // the same numbers every run until set_seed
+        #[cfg(not(feature = "thread-rng"))]
+        let seed = 0;
+        Self {
+            memory: vec![Wrapping(0); DEFAULT_MEMORY_SIZE],
+            wrap_addresses: true,
+            // Drawing is done in XOR mode and if a pixel is turned off as a result of drawing,
+            // the VF register is set.
+            v: [Wrapping(0); 16],
+            i: 0,
+            pc: 0,
+            opcode: 0,
+            gfx: [0; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+            reported_gfx: [0; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+            delay_timer: 0,
+            sound_timer: 0,
+            stack: Vec::with_capacity(DEFAULT_STACK_LIMIT),
+            stack_limit: DEFAULT_STACK_LIMIT,
+            write_protection: false,
+            clock: Box::new(PerCycle),
+            quirks: Quirks::default(),
+            keys: 0,
+            font: font::DEFAULT,
+            instructions: [
+                State::zero_opcodes,
+                State::jump_to_address,
+                State::goto_address,
+                State::skip_next_if_eq,
+                State::skip_next_if_neq,
+                State::skip_next_if_xy_eq,
+                State::set_vx,
+                State::add_vx,
+                State::arithmetic_instruction,
+                State::skip_next_if_xy_neq,
+                State::set_i_to_address,
+                State::jump_to_address_plus_v0,
+                State::set_vx_random,
+                State::draw,
+                State::skip_if_key_pressed,
+                State::f_opcodes,
+            ],
+            arithmetic_instructions: [
+                State::set_vx_vy,
+                State::vx_or_eq_vy,
+                State::vx_and_eq_vy,
+                State::vx_xor_eq_vy,
+                State::vx_add_vy,
+                State::vx_sub_vy,
+                State::shift_vx_right,
+                State::vy_sub_vx,
+                State::invalid_instruction,
+                State::invalid_instruction,
+                State::invalid_instruction,
+                State::invalid_instruction,
+                State::invalid_instruction,
+                State::invalid_instruction,
+                State::vx_shift_left,
+                State::invalid_instruction,
+            ],
+            rng: R::seed_from_u64(seed),
+            seed,
+            error: None,
+            stats: Stats::default(),
+            profile: None,
+            coverage: None,
+            accesses: None,
+            listeners: Vec::new(),
+            sound_sink: None,
+            syscall_handler: None,
+            #[cfg(feature = "cdp1802")]
+            machine_code: false,
+            sounding: false,
+            rom: Vec::new(),
+            display_generation: 0,
+        }
+    }
+
+    pub fn initialize(&mut self) {
+        // reset everything
+        self.pc = 0x200;
+        self.i = 0;
+        self.gfx.fill(0);
+        self.display_generation += 1;
+        self.memory.fill(Wrapping(0));
+        self.keys = 0;
+        self.stack = Vec::with_capacity(self.stack_limit);
+        self.v.fill(Wrapping(0));
+
+        self.load_font();
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+        self.sound_changed();
+        self.rng = R::seed_from_u64(self.seed);
+        self.error = None;
+        self.stats = Stats::default();
+        if let Some(profile) = &mut self.profile {
+            profile.fill(0);
+        }
+        if let Some(coverage) = &mut self.coverage {
+            coverage.fill(0);
+        }
+        if let Some(accesses) = &mut self.accesses {
+            accesses.fill(Accesses::default());
+        }
+    }
+
+    // Random numbers restart from this seed, takes effect immediately
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = R::seed_from_u64(seed);
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // Some interpreters allow deeper nesting than the original 16 levels
+    pub fn set_stack_limit(&mut self, limit: usize) {
+        self.stack_limit = limit;
+    }
+
+    pub fn stack_limit(&self) -> usize {
+        self.stack_limit
+    }
+
+    // Off by default, some ROMs deliberately write below 0x200
+    pub fn set_write_protection(&mut self, enabled: bool) {
+        self.write_protection = enabled;
+    }
+
+    // Gives the machine size bytes of memory, cleared, from just past the
+    // interpreter area up to the 64KB I can address. Profiling, coverage and
+    // access counts start over.
+    pub fn set_memory_size(&mut self, size: usize) -> Result<(), String> {
+        if !(0x202..=XO_CHIP_MEMORY_SIZE).contains(&size) {
+            return Err(format!(
+                "Memory has to be between {} and {} bytes",
+                0x202, XO_CHIP_MEMORY_SIZE
+            ));
+        }
+        self.memory = vec![Wrapping(0); size];
+        self.load_font();
+        if self.profile.is_some() {
+            self.profile = Some(vec![0; size]);
+        }
+        if self.coverage.is_some() {
+            self.coverage = Some(vec![0; size]);
+        }
+        if self.accesses.is_some() {
+            self.accesses = Some(vec![Accesses::default(); size]);
+        }
+        Ok(())
+    }
+
+    pub fn memory_size(&self) -> usize {
+        self.memory.len()
+    }
+
+    // On by default like the original's masked addresses, off faults on
+    // sprites, BCD and register stores/loads running past the end of memory
+    pub fn set_address_wrapping(&mut self, enabled: bool) {
+        self.wrap_addresses = enabled;
+    }
+
+    pub fn address_wrapping(&self) -> bool {
+        self.wrap_addresses
+    }
+
+    // Replaces the digits FX29 points at, straight away and after every
+    // initialize()
+    pub fn set_font(&mut self, font: Font) {
+        self.font = font;
+        self.load_font();
+    }
+
+    pub fn font(&self) -> &Font {
+        &self.font
+    }
+
+    fn load_font(&mut self) {
+        for (i, value) in self.font.iter().enumerate() {
+            self.memory[FONT_BASE + i] = Wrapping(*value);
+        }
+    }
+
+    // When emulate_cycle ticks the timers, once per cycle by default
+    pub fn set_clock(&mut self, clock: impl Clock + 'static) {
+        self.clock = Box::new(clock);
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn error(&self) -> Option<&Error> {
+        self.error.as_ref()
+    }
+
+    #[cfg(feature = "fs")]
+    pub fn load_game(&mut self, path: String) -> Result<(), String> {
+        let rom = fs::read(&path).map_err(|op| op.to_string())?;
+        self.load_buffer(&rom);
+        info!("Loaded {} bytes from {}", rom.len(), path);
+        Ok(())
+    }
+
+    pub fn load_buffer(&mut self, buffer: &[u8]) {
+        let space = self.memory.len() - 0x200;
+        for (index, value) in buffer.iter().enumerate() {
+            if index >= space {
+                warn!("Buffer too long, truncated to {} bytes", index);
+                break;
+            }
+            self.memory[index + 0x200] = Wrapping(*value);
+        }
+        self.rom = buffer[..buffer.len().min(space)].to_vec();
+    }
+
+    // Restarts the machine with a different program, keeping its settings,
+    // seed and listeners. A program that doesn't fit changes nothing.
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), String> {
+        let space = self.memory.len() - 0x200;
+        if rom.len() > space {
+            return Err(format!(
+                "The program is {} bytes, only {} fit",
+                rom.len(),
+                space
+            ));
+        }
+        self.initialize();
+        self.load_buffer(rom);
+        Ok(())
+    }
+
+    // initialize() that keeps the loaded program, restarting it from the top
+    // without reading it again
+    pub fn reset(&mut self) {
+        self.initialize();
+        let rom = std::mem::take(&mut self.rom);
+        self.load_buffer(&rom);
+    }
+
+    // Snapshot of everything a program can observe, restored by load_state
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.memory.len() + self.gfx.len() + 64);
+        data.extend(self.memory.iter().map(|x| x.0));
+        data.extend(self.v.iter().map(|x| x.0));
+        data.extend_from_slice(&self.i.to_le_bytes());
+        data.extend_from_slice(&self.pc.to_le_bytes());
+        data.extend_from_slice(&self.opcode.to_le_bytes());
+        data.extend_from_slice(&self.gfx);
+        data.push(self.delay_timer);
+        data.push(self.sound_timer);
+        data.extend_from_slice(&(self.stack.len() as u16).to_le_bytes());
+        for address in &self.stack {
+            data.extend_from_slice(&address.to_le_bytes());
+        }
+        data
+    }
+
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), String> {
+        let mut reader = data.iter().copied();
+        let mut byte = || reader.next().ok_or("Truncated save state");
+
+        let mut memory = vec![Wrapping(0); self.memory.len()];
+        for value in memory.iter_mut() {
+            *value = Wrapping(byte()?);
+        }
+        let mut v = [Wrapping(0); 16];
+        for value in v.iter_mut() {
+            *value = Wrapping(byte()?);
+        }
+        let i = u16::from_le_bytes([byte()?, byte()?]);
+        let pc = u16::from_le_bytes([byte()?, byte()?]);
+        let opcode = u16::from_le_bytes([byte()?, byte()?]);
+        let mut gfx = [0; DISPLAY_WIDTH * DISPLAY_HEIGHT];
+        for value in gfx.iter_mut() {
+            *value = byte()?;
+        }
+        let delay_timer = byte()?;
+        let sound_timer = byte()?;
+        let depth = u16::from_le_bytes([byte()?, byte()?]) as usize;
+        if depth > self.stack_limit {
+            return Err("Save state stack is deeper than the stack limit".to_string());
+        }
+        let mut stack = Vec::with_capacity(self.stack_limit);
+        for _ in 0..depth {
+            stack.push(u16::from_le_bytes([byte()?, byte()?]));
+        }
+        // a state from a machine with more memory reads as this one's with
+        // bytes to spare
+        if reader.next().is_some() {
+            return Err(
+                "The save state is from a machine with a different memory size".to_string(),
+            );
+        }
+
+        self.memory = memory;
+        self.v = v;
+        self.i = i;
+        self.pc = pc;
+        self.opcode = opcode;
+        self.gfx = gfx;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.sound_changed();
+        self.stack = stack;
+        self.error = None;
+        self.display_generation += 1;
+        Ok(())
+    }
+
+    pub fn emulate_cycle(&mut self) -> Result<(), Error> {
+        if let Some(error) = &self.error {
+            return Err(error.clone());
+        }
+        // both opcode bytes have to be in memory
+        if self.pc as usize + 1 >= self.memory.len() {
+            let error = Error::PcOutOfRange { pc: self.pc };
+            self.error = Some(error.clone());
+            return Err(error);
+        }
+        self.opcode = ((self.memory[self.pc as usize].0 as u16) << 8u8)
+            | self.memory[(self.pc + 1) as usize].0 as u16;
+        let family = ((self.opcode & 0xF000) >> 12) as usize;
+        self.stats.cycles += 1;
+        self.stats.opcode_families[family] += 1;
+        if let Some(profile) = &mut self.profile {
+            profile[self.pc as usize] += 1;
+        }
+        if let Some(coverage) = &mut self.coverage {
+            coverage[self.pc as usize] |= COVERAGE_EXECUTED;
+            coverage[self.pc as usize + 1] |= COVERAGE_EXECUTED;
+        }
+        if let Some(accesses) = &mut self.accesses {
+            for access in &mut accesses[self.pc as usize..self.pc as usize + 2] {
+                access.executes = access.executes.saturating_add(1);
+            }
+        }
+        let pc = self.pc;
+        self.instructions[family](self);
+        if let Some(error) = &self.error {
+            return Err(error.clone());
+        }
+        self.pc = self.pc.wrapping_add(2);
+        self.emit(Event::InstructionExecuted {
+            pc,
+            opcode: self.opcode,
+        });
+
+        for _ in 0..self.clock.ticks_due() {
+            self.tick_timers();
+        }
+        Ok(())
+    }
+
+    // Timers tick as the clock says after each cycle, frontends call this
+    // directly to tick them without executing anything
+    pub fn tick_timers(&mut self) {
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+            if self.delay_timer == 0 {
+                self.emit(Event::TimerExpired(Timer::Delay));
+            }
+        }
+
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+            if self.sound_timer == 0 {
+                self.sound_changed();
+                self.emit(Event::TimerExpired(Timer::Sound));
+            }
+        }
+    }
+
+    // Calls sink whenever the buzzer starts or stops from now on
+    pub fn set_sound_sink(&mut self, sink: impl SoundSink + 'static) {
+        self.sounding = false;
+        self.sound_sink = Some(Box::new(sink));
+        self.sound_changed();
+    }
+
+    fn sound_changed(&mut self) {
+        let on = self.sound_timer > 0;
+        if on != self.sounding {
+            self.sounding = on;
+            if let Some(sink) = &mut self.sound_sink {
+                sink.set_sound(on);
+            }
+        }
+    }
+
+    // Calls listener with every Event from now on
+    pub fn subscribe(&mut self, listener: impl FnMut(&Event) + 'static) {
+        self.listeners.push(Box::new(listener));
+    }
+
+    // Replaces any handler given before, 0x0NNN is an unknown opcode without one
+    pub fn on_syscall(&mut self, handler: impl FnMut(&mut Self, u16) + 'static)
+    where
+        R: 'static,
+    {
+        self.syscall_handler = Some(Box::new(handler));
+    }
+
+    // 0x0NNN runs the 1802 subroutine at NNN like the VIP, in place of any
+    // syscall handler. The registers and display are kept where the VIP's
+    // interpreter keeps them at the top of memory while it runs.
+    #[cfg(feature = "cdp1802")]
+    pub fn set_machine_code(&mut self, enabled: bool) {
+        self.machine_code = enabled;
+    }
+
+    #[cfg(feature = "cdp1802")]
+    pub fn machine_code(&self) -> bool {
+        self.machine_code
+    }
+
+    fn emit(&mut self, event: Event) {
+        for listener in &mut self.listeners {
+            listener(&event);
+        }
+    }
+
+    // Return, clear screen, HCF
+    // 0x0NNN
+    fn zero_opcodes(&mut self) {
+        match self.opcode & 0x0FFF {
+            0x00EE => match self.stack.pop() {
+                Some(address) => self.pc = address,
+                None => self.error = Some(Error::StackUnderflow { pc: self.pc }),
+            },
+            0x00E0 => {
+                self.gfx.fill(0);
+                self.display_generation += 1;
+                self.emit(Event::ScreenCleared);
+            }
+            #[cfg(feature = "cdp1802")]
+            address if self.machine_code => self.run_machine_code(address),
+            address => match self.syscall_handler.take() {
+                Some(mut handler) => {
+                    handler(self, address);
+                    // unless the handler registered another
+                    self.syscall_handler.get_or_insert(handler);
+                }
+                None => self.invalid_instruction(),
+            },
+        }
+    }
+
+    // 0x1NNN
+    fn jump_to_address(&mut self) {
+        self.pc = (self.opcode & 0xFFF).wrapping_sub(2);
+    }
+
+    // 0x2NNN
+    fn goto_address(&mut self) {
+        if self.stack.len() >= self.stack_limit {
+            self.error = Some(Error::StackOverflow { pc: self.pc });
+            return;
+        }
+        self.stack.push(self.pc);
+        // the cycle moves on by 2 afterwards, like a jump
+        self.pc = (self.opcode & 0xFFF).wrapping_sub(2);
+    }
+
+    // 0x3XNN
+    fn skip_next_if_eq(&mut self) {
+        if self.v[((self.opcode & 0xF00) >> 8) as usize].0 == (self.opcode & 0xFF) as u8 {
+            self.pc = self.pc.wrapping_add(2);
+        }
+    }
+
+    // 0x4XNN
+    fn skip_next_if_neq(&mut self) {
+        if self.v[((self.opcode & 0xF00) >> 8) as usize].0 != (self.opcode & 0xFF) as u8 {
+            self.pc = self.pc.wrapping_add(2);
+        }
+    }
+
+    // 0x5XY0
+    fn skip_next_if_xy_eq(&mut self) {
+        if self.v[((self.opcode & 0xF00) >> 8) as usize]
+            == self.v[((self.opcode & 0xF0) >> 4) as usize]
+        {
+            self.pc = self.pc.wrapping_add(2);
+        }
+    }
+
+    // 0x6XNN
+    fn set_vx(&mut self) {
+        self.v[((self.opcode & 0x0F00) >> 8) as usize].0 = (self.opcode & 0x00FF) as u8;
+    }
+
+    // 0x7XNN
+    fn add_vx(&mut self) {
+        self.v[((self.opcode & 0x0F00) >> 8) as usize] += (self.opcode & 0x00FF) as u8;
+    }
+
+    // 0x8XY0
+    fn set_vx_vy(&mut self) {
+        self.v[((self.opcode & 0xF00) >> 8) as usize] =
+            self.v[((self.opcode & 0x00F0) >> 4) as usize];
+    }
+
+    // 0x8XY1
+    fn vx_or_eq_vy(&mut self) {
+        self.v[((self.opcode & 0x0F00) >> 8) as usize] |=
+            self.v[((self.opcode & 0x00F0) >> 4) as usize];
+        self.reset_vf();
+    }
+
+    // 0x8XY2
+    fn vx_and_eq_vy(&mut self) {
+        self.v[((self.opcode & 0xF00) >> 8) as usize] &=
+            self.v[((self.opcode & 0xF0) >> 4) as usize];
+        self.reset_vf();
+    }
+
+    // 0x8XY3
+    fn vx_xor_eq_vy(&mut self) {
+        self.v[((self.opcode & 0xF00) >> 8) as usize] ^=
+            self.v[((self.opcode & 0xF0) >> 4) as usize];
+        self.reset_vf();
+    }
+
+    fn reset_vf(&mut self) {
+        if self.quirks.vf_reset {
+            self.v[0xF] = Wrapping(0);
+        }
+    }
+
+    // the register 0x8XY6/0x8XYE shift, depending on the quirk
+    fn shift_source(&self) -> Wrapping<u8> {
+        if self.quirks.shift_vy {
+            self.v[((self.opcode & 0xF0) >> 4) as usize]
+        } else {
+            self.v[((self.opcode & 0xF00) >> 8) as usize]
+        }
+    }
+
+    // 0x8XY4
+    fn vx_add_vy(&mut self) {
+        let x = self.v[((self.opcode & 0xF00) >> 8) as usize];
+        let y = self.v[((self.opcode & 0xF0) >> 4) as usize];
+        if y > Wrapping(0xFF - x.0) {
+            self.v[0xF] = Wrapping(1);
+        } else {
+            self.v[0xF] = Wrapping(0);
+        }
+        self.v[((self.opcode & 0xF00) >> 8) as usize] += y;
+    }
+
+    // 0x8XY5
+    fn vx_sub_vy(&mut self) {
+        let x = self.v[((self.opcode & 0xF00) >> 8) as usize];
+        let y = self.v[((self.opcode & 0xF0) >> 4) as usize];
+        if y >= x {
+            self.v[0xF] = Wrapping(0);
+        } else {
+            self.v[0xF] = Wrapping(1);
+        }
+        self.v[((self.opcode & 0xF00) >> 8) as usize] -= y;
+    }
+
+    // 0x8XY6
+    fn shift_vx_right(&mut self) {
+        let source = self.shift_source();
+        self.v[0xF] = Wrapping(source.0 & 0x1);
+        self.v[((self.opcode & 0xF00) >> 8) as usize] = source >> 1;
+    }
+
+    // 0x8XY7
+    fn vy_sub_vx(&mut self) {
+        let x = self.v[((self.opcode & 0xF00) >> 8) as usize];
+        let y = self.v[((self.opcode & 0xF0) >> 4) as usize];
+        if x >= y {
+            self.v[0xF] = Wrapping(0);
+        } else {
+            self.v[0xF] = Wrapping(1);
+        }
+        self.v[((self.opcode & 0xF00) >> 8) as usize] = y - x;
+    }
+
+    // 0x8XYE
+    fn vx_shift_left(&mut self) {
+        let source = self.shift_source();
+        self.v[0xF] = Wrapping(source.0 >> 7);
+        self.v[((self.opcode & 0xF00) >> 8) as usize] = source << 1;
+    }
+
+    // 0x8NNN
+    fn arithmetic_instruction(&mut self) {
+        self.arithmetic_instructions[(self.opcode & 0xF) as usize](self);
+    }
+
+    // 0x9XY0
+    fn skip_next_if_xy_neq(&mut self) {
+        if self.v[((self.opcode & 0x0F00) >> 8) as usize]
+            != self.v[((self.opcode & 0x00F0) >> 4) as usize]
+        {
+            self.pc = self.pc.wrapping_add(2);
+        }
+    }
+
+    // 0xANNN
+    fn set_i_to_address(&mut self) {
+        self.i = self.opcode & 0x0FFF;
+    }
+
+    // 0xBNNN
+    fn jump_to_address_plus_v0(&mut self) {
+        let register = if self.quirks.jump_vx {
+            ((self.opcode & 0xF00) >> 8) as usize
+        } else {
+            0
+        };
+        self.pc = ((self.opcode & 0x0FFF) + self.v[register].0 as u16).wrapping_sub(2);
+    }
+
+    // 0xCXNN
+    fn set_vx_random(&mut self) {
+        self.v[(((self.opcode & 0x0F00) >> 8) as usize)] =
+            Wrapping(self.rng.gen::<u8>() & ((self.opcode & 0x00FF) as u8))
+    }
+
+    // 0xDXYN
+    fn draw(&mut self) {
+        // the sprite starts on screen whatever VX and VY are
+        let x = self.v[((self.opcode & 0x0F00) >> 8) as usize].0 as usize % DISPLAY_WIDTH;
+        let y = self.v[((self.opcode & 0x00F0) >> 4) as usize].0 as usize % DISPLAY_HEIGHT;
+        let height = (self.opcode & 0x000F) as u16;
+
+        self.v[0xF] = Wrapping(0);
+        for yline in 0..height {
+            let row = y + yline as usize;
+            if row >= DISPLAY_HEIGHT && self.quirks.clip_sprites {
+                break;
+            }
+            let Some(address) = self.address(self.i as usize + yline as usize) else {
+                return;
+            };
+            let pixel = self.memory[address].0;
+            if let Some(coverage) = &mut self.coverage {
+                coverage[address] |= COVERAGE_SPRITE;
+            }
+            self.count_read(address);
+            for xline in 0..8 {
+                let column = x + xline;
+                if column >= DISPLAY_WIDTH && self.quirks.clip_sprites {
+                    break;
+                }
+                if (pixel & (0x80 >> xline)) != 0 {
+                    let index = column % DISPLAY_WIDTH + row % DISPLAY_HEIGHT * DISPLAY_WIDTH;
+                    if self.gfx[index] == 1 {
+                        self.v[0xF] = Wrapping(1);
+                    }
+                    self.gfx[index] ^= 1;
+                }
+            }
+        }
+        self.display_generation += 1;
+        self.emit(Event::SpriteDrawn {
+            x: x as u8,
+            y: y as u8,
+            rows: height as u8,
+            collision: self.v[0xF].0 == 1,
+        });
+    }
+
+    // 0xEXxx
+    fn skip_if_key_pressed(&mut self) {
+        // 0xEX9E
+        if self.opcode & 0xFF == 0x9E {
+            if self.is_key_pressed(self.v[((self.opcode & 0xF00) >> 8) as usize].0 as usize) {
+                self.pc = self.pc.wrapping_add(2);
+            }
+        }
+        // 0xEXA1
+        else if self.opcode & 0xFF == 0xA1 {
+            if !self.is_key_pressed(self.v[((self.opcode & 0xF00) >> 8) as usize].0 as usize) {
+                self.pc = self.pc.wrapping_add(2);
+            }
+        } else {
+            self.invalid_instruction()
+        }
+    }
+
+    // 0xFXxx
+    fn f_opcodes(&mut self) {
+        let register = ((self.opcode & 0xF00) >> 8) as usize;
+        match self.opcode & 0xFF {
+            0x07 => {
+                self.v[register] = Wrapping(self.delay_timer);
+            }
+            0x0A => {
+                // the lowest key held wins
+                if self.keys != 0 {
+                    self.v[register] = Wrapping(self.keys.trailing_zeros() as u8);
+                } else {
+                    self.pc = self.pc.wrapping_sub(2);
+                }
+            }
+            0x15 => {
+                self.delay_timer = self.v[register].0;
+            }
+            0x18 => {
+                self.sound_timer = self.v[register].0;
+                self.sound_changed();
+            }
+            0x1E => {
+                self.i += self.v[register].0 as u16;
+            }
+            0x29 => {
+                // only the low nibble picks a digit
+                self.i = (FONT_BASE + (self.v[register].0 & 0xF) as usize * 5) as u16;
+            }
+            0x33 => {
+                let Some(addresses) = self.write_addresses(3) else {
+                    return;
+                };
+                let value = self.v[register].0;
+                self.memory[addresses[0]] = Wrapping(value / 100);
+                self.memory[addresses[1]] = Wrapping((value % 100) / 10);
+                self.memory[addresses[2]] = Wrapping(value % 10);
+                for address in addresses {
+                    self.count_write(address);
+                }
+                trace!(
+                    "BCD of V{:X} ({}) stored at {:#05X}: {}, {}, {}",
+                    register,
+                    value,
+                    self.i,
+                    value / 100,
+                    (value % 100) / 10,
+                    value % 10
+                );
+            }
+            0x55 => {
+                let Some(addresses) = self.write_addresses(register + 1) else {
+                    return;
+                };
+                for (i, address) in addresses.into_iter().enumerate() {
+                    self.memory[address] = self.v[i];
+                    self.count_write(address);
+                }
+                trace!("V0-V{:X} stored at {:#05X}", register, self.i);
+                if self.quirks.load_store_increment {
+                    self.i = self.i.wrapping_add(register as u16 + 1);
+                }
+            }
+            0x65 => {
+                for i in 0..(register + 1) {
+                    let Some(address) = self.address(self.i as usize + i) else {
+                        return;
+                    };
+                    self.v[i] = self.memory[address];
+                    self.count_read(address);
+                }
+                if self.quirks.load_store_increment {
+                    self.i = self.i.wrapping_add(register as u16 + 1);
+                }
+            }
+            _ => self.invalid_instruction(),
+        }
+    }
+
+    // The count addresses from I on that a store writes, None (and halted) if
+    // any of them is out of range or, wrapped around or not, in the
+    // interpreter area
+    fn write_addresses(&mut self, count: usize) -> Option<Vec<usize>> {
+        let addresses = (0..count)
+            .map(|offset| self.address(self.i as usize + offset))
+            .collect::<Option<Vec<_>>>()?;
+        if let Some(address) = addresses
+            .iter()
+            .find(|address| self.write_protection && **address < 0x200)
+        {
+            self.error = Some(Error::ProtectedWrite {
+                pc: self.pc,
+                address: *address as u16,
+            });
+            return None;
+        }
+        Some(addresses)
+    }
+
+    // Calls the 1802 subroutine at address, until it returns to the
+    // interpreter with SEP R4
+    #[cfg(feature = "cdp1802")]
+    fn run_machine_code(&mut self, address: u16) {
+        // the VIP's layout below the top of its 4KB: the stack, V0-VF, then
+        // the display a bit a pixel
+        let top = self.memory.len();
+        let variables = top - 0x110;
+        let display = top - 0x100;
+        for (index, value) in self.v.iter().enumerate() {
+            self.memory[variables + index] = *value;
+        }
+        for (index, pixels) in self.gfx.chunks(8).enumerate() {
+            let byte = pixels.iter().fold(0, |byte, pixel| (byte << 1) | pixel);
+            self.memory[display + index] = Wrapping(byte);
+        }
+
+        // set up as the interpreter leaves them for 0x0NNN
+        let mut cpu = Cdp1802::new();
+        cpu.p = 3;
+        cpu.r[3] = address;
+        cpu.x = 2;
+        cpu.r[2] = (top - 0x131) as u16;
+        cpu.r[5] = self.pc.wrapping_add(2);
+        cpu.r[6] = (variables + ((address as usize >> 8) & 0xF)) as u16;
+        cpu.r[7] = (variables + ((address as usize >> 4) & 0xF)) as u16;
+        cpu.r[0xA] = self.i;
+        let mut bus = Vip {
+            memory: &mut self.memory,
+            keys: self.keys,
+            key_latch: 0,
+            write_protection: self.write_protection,
+            protected_write: None,
+        };
+        let mut steps = 0;
+        while cpu.p != 4 {
+            if steps == MACHINE_CODE_LIMIT {
+                self.error = Some(Error::MachineCodeStuck { pc: self.pc });
+                return;
+            }
+            cpu.step(&mut bus);
+            if let Some(address) = bus.protected_write {
+                self.error = Some(Error::ProtectedWrite {
+                    pc: self.pc,
+                    address,
+                });
+                return;
+            }
+            steps += 1;
+        }
+
+        for (index, value) in self.v.iter_mut().enumerate() {
+            *value = self.memory[variables + index];
+        }
+        let mut gfx = [0; DISPLAY_WIDTH * DISPLAY_HEIGHT];
+        for (index, pixels) in gfx.chunks_mut(8).enumerate() {
+            let byte = self.memory[display + index].0;
+            for (bit, pixel) in pixels.iter_mut().enumerate() {
+                *pixel = (byte >> (7 - bit)) & 1;
+            }
+        }
+        if gfx != self.gfx {
+            self.gfx = gfx;
+            self.display_generation += 1;
+        }
+        self.i = cpu.r[0xA];
+        // the cycle moves on by 2 afterwards
+        self.pc = cpu.r[5].wrapping_sub(2);
+    }
+
+    // Where address lands in memory, None (and halted) if it's past the end
+    // and addresses don't wrap
+    fn address(&mut self, address: usize) -> Option<usize> {
+        if address < self.memory.len() {
+            Some(address)
+        } else if self.wrap_addresses {
+            Some(address % self.memory.len())
+        } else {
+            self.error = Some(Error::AddressOutOfRange {
+                pc: self.pc,
+                address,
+            });
+            None
+        }
+    }
+
+    fn count_read(&mut self, address: usize) {
+        if let Some(accesses) = &mut self.accesses {
+            accesses[address].reads = accesses[address].reads.saturating_add(1);
+        }
+    }
+
+    fn count_write(&mut self, address: usize) {
+        if let Some(accesses) = &mut self.accesses {
+            accesses[address].writes = accesses[address].writes.saturating_add(1);
+        }
+    }
+
+    fn invalid_instruction(&mut self) {
+        self.stats.unknown_opcodes += 1;
+        debug!("Invalid opcode {:#06X} at {:#05X}", self.opcode, self.pc);
+    }
+
+    pub fn set_key(&mut self, key: usize, value: u8) {
+        if key <= 0xF {
+            if value != 0 {
+                self.keys |= 1 << key;
+            } else {
+                self.keys &= !(1 << key);
+            }
+        }
+    }
+
+    // The whole keypad at once, bit n for key n, so a frontend can update it
+    // in one go each frame
+    pub fn set_keys(&mut self, keys: u16) {
+        self.keys = keys;
+    }
+
+    pub fn keys(&self) -> u16 {
+        self.keys
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.profile = enabled.then(|| vec![0; self.memory.len()]);
+    }
+
+    // how many times each address was executed, None unless profiling
+    pub fn profile(&self) -> Option<&[u64]> {
+        self.profile.as_deref()
+    }
+
+    pub fn set_coverage(&mut self, enabled: bool) {
+        self.coverage = enabled.then(|| vec![0; self.memory.len()]);
+    }
+
+    // COVERAGE_* flags per address, None unless coverage is on
+    pub fn coverage(&self) -> Option<&[u8]> {
+        self.coverage.as_deref()
+    }
+
+    // Counting starts from nothing each time it's turned on
+    pub fn set_access_counting(&mut self, enabled: bool) {
+        self.accesses = enabled.then(|| vec![Accesses::default(); self.memory.len()]);
+    }
+
+    // reads, writes and executions per address, None unless counting
+    pub fn accesses(&self) -> Option<&[Accesses]> {
+        self.accesses.as_deref()
+    }
+
+    pub fn read_memory(&self, address: u16) -> u8 {
+        self.memory[address as usize % self.memory.len()].0
+    }
+
+    pub fn write_memory(&mut self, address: u16, value: u8) {
+        let length = self.memory.len();
+        self.memory[address as usize % length] = Wrapping(value);
+    }
+
+    // Writes bytes from address on, wrapping at the end of memory. Cheats
+    // patch memory this way, write protection doesn't apply.
+    pub fn apply_patch(&mut self, address: u16, bytes: &[u8]) {
+        let length = self.memory.len();
+        for (offset, byte) in bytes.iter().enumerate() {
+            self.memory[(address as usize + offset) % length] = Wrapping(*byte);
+        }
+    }
+
+    pub fn set_register(&mut self, register: usize, value: u8) {
+        self.v[register & 0xF] = Wrapping(value);
+    }
+
+    pub fn set_i(&mut self, i: u16) {
+        self.i = i;
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value;
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer = value;
+        self.sound_changed();
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    // the most recently fetched opcode
+    pub fn opcode(&self) -> u16 {
+        self.opcode
+    }
+
+    pub fn registers(&self) -> [u8; 16] {
+        self.v.map(|x| x.0)
+    }
+
+    // return addresses, innermost last
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn is_key_pressed(&self, key: usize) -> bool {
+        key <= 0xF && self.keys & (1 << key) != 0
+    }
+
+    pub fn get_graphics_buffer(&self) -> Vec<u8> {
+        self.gfx.to_vec()
+    }
+
+    // The x and y of every pixel that's on, row by row
+    pub fn lit_pixels(&self) -> impl Iterator<Item = (u8, u8)> + '_ {
+        self.gfx
+            .iter()
+            .enumerate()
+            .filter(|(_, pixel)| **pixel != 0)
+            .map(|(index, _)| ((index % DISPLAY_WIDTH) as u8, (index / DISPLAY_WIDTH) as u8))
+    }
+
+    // Changes whenever the display might have, so each frontend can keep the
+    // generation it last showed and redraw when it's different
+    pub fn display_generation(&self) -> u64 {
+        self.display_generation
+    }
+
+    // The display top to bottom, a byte per pixel
+    pub fn rows(&self) -> impl Iterator<Item = &[u8]> {
+        self.gfx.chunks(DISPLAY_WIDTH)
+    }
+
+    // The x, y and new value of every pixel that changed since the last call,
+    // for frontends that send the screen somewhere slow. The first call
+    // reports what's on since the screen was blank.
+    pub fn take_display_changes(&mut self) -> Vec<(u8, u8, bool)> {
+        let mut changes = Vec::new();
+        for (index, (pixel, reported)) in self.gfx.iter().zip(&mut self.reported_gfx).enumerate() {
+            if *pixel != *reported {
+                let (x, y) = (index % DISPLAY_WIDTH, index / DISPLAY_WIDTH);
+                changes.push((x as u8, y as u8, *pixel != 0));
+                *reported = *pixel;
+            }
+        }
+        changes
+    }
+}
+
+// The VIP around the 1802: memory, and the keypad latched by OUT 2 and read
+// through EF3
+#[cfg(feature = "cdp1802")]
+struct Vip<'a> {
+    memory: &'a mut [Wrapping<u8>],
+    keys: u16,
+    key_latch: u8,
+    write_protection: bool,
+    // the first write below 0x200 while write protection is on, which isn't
+    // made and halts the interpreter
+    protected_write: Option<u16>,
+}
+
+#[cfg(feature = "cdp1802")]
+impl Bus for Vip<'_> {
+    fn read(&mut self, address: u16) -> u8 {
+        self.memory[address as usize % self.memory.len()].0
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        let address = address as usize % self.memory.len();
+        if self.write_protection && address < 0x200 {
+            self.protected_write.get_or_insert(address as u16);
+            return;
+        }
+        self.memory[address] = Wrapping(value);
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        if port == 2 {
+            self.key_latch = value & 0xF;
+        }
+    }
+
+    fn input(&mut self, _port: u8) -> u8 {
+        0
+    }
+
+    fn flag(&mut self, line: u8) -> bool {
+        line == 3 && self.keys & (1 << self.key_latch) != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Manual;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn machine(program: &[u8]) -> State {
+        let mut chip_8 = State::new();
+        chip_8.initialize();
+        chip_8.load_buffer(program);
+        chip_8
+    }
+
+    #[test]
+    fn skip_at_the_top_of_64k_wraps() {
+        let mut chip_8 = machine(&[]);
+        chip_8.set_memory_size(XO_CHIP_MEMORY_SIZE).unwrap();
+        // 0x3000, V0 is 0 so it skips
+        chip_8.write_memory(0xFFFE, 0x30);
+        chip_8.write_memory(0xFFFF, 0x00);
+        chip_8.set_pc(0xFFFE);
+        chip_8.emulate_cycle().unwrap();
+        assert_eq!(chip_8.pc(), 0x0002);
+    }
+
+    #[test]
+    fn wait_for_key_at_address_0_wraps() {
+        let mut chip_8 = machine(&[]);
+        chip_8.write_memory(0x000, 0xF0);
+        chip_8.write_memory(0x001, 0x0A);
+        chip_8.set_pc(0x000);
+        chip_8.emulate_cycle().unwrap();
+        assert_eq!(chip_8.pc(), 0x000);
+    }
+
+    #[test]
+    fn protected_stores_check_wrapped_addresses() {
+        // I = 0xFFE, then FX55 and FX33 reaching 0x000
+        for store in [[0xF2, 0x55], [0xF0, 0x33]] {
+            let mut chip_8 = machine(&[0xAF, 0xFE, store[0], store[1]]);
+            chip_8.set_write_protection(true);
+            chip_8.set_register(0, 0xFF);
+            chip_8.emulate_cycle().unwrap();
+            let _ = chip_8.emulate_cycle();
+            assert!(matches!(
+                chip_8.error(),
+                Some(Error::ProtectedWrite { address: 0, .. })
+            ));
+            assert_eq!(chip_8.read_memory(0xFFE), 0);
+        }
+    }
+
+    #[test]
+    fn call_past_the_stack_limit_overflows() {
+        // 0x2200, calling itself forever
+        let mut chip_8 = machine(&[0x22, 0x00]);
+        chip_8.set_stack_limit(4);
+        for _ in 0..4 {
+            chip_8.emulate_cycle().unwrap();
+        }
+        assert_eq!(
+            chip_8.emulate_cycle(),
+            Err(Error::StackOverflow { pc: 0x200 })
+        );
+        assert_eq!(chip_8.stack().len(), 4);
+        // halted machines stay halted
+        assert!(chip_8.emulate_cycle().is_err());
+    }
+
+    #[test]
+    fn return_with_an_empty_stack_underflows() {
+        let mut chip_8 = machine(&[0x00, 0xEE]);
+        assert_eq!(
+            chip_8.emulate_cycle(),
+            Err(Error::StackUnderflow { pc: 0x200 })
+        );
+    }
+
+    #[test]
+    fn call_and_return() {
+        // call 0x204, which returns to 0x202
+        let mut chip_8 = machine(&[0x22, 0x04, 0x00, 0x00, 0x00, 0xEE]);
+        chip_8.emulate_cycle().unwrap();
+        assert_eq!((chip_8.pc(), chip_8.stack()), (0x204, &[0x200][..]));
+        chip_8.emulate_cycle().unwrap();
+        assert_eq!((chip_8.pc(), chip_8.stack().len()), (0x202, 0));
+    }
+
+    // draws a solid 8x4 sprite at x, y, with clip_sprites set to clip
+    fn draw_block(x: u8, y: u8, clip: bool) -> Vec<u8> {
+        // I = 0x20A, V0 = x, V1 = y, D014, then the sprite's rows
+        let mut chip_8 = machine(&[
+            0xA2, 0x0A, 0x60, x, 0x61, y, 0xD0, 0x14, 0x12, 0x08, 0xFF, 0xFF, 0xFF, 0xFF,
+        ]);
+        chip_8.set_quirks(Quirks {
+            clip_sprites: clip,
+            ..Quirks::default()
+        });
+        for _ in 0..4 {
+            chip_8.emulate_cycle().unwrap();
+        }
+        chip_8.get_graphics_buffer()
+    }
+
+    fn lit(screen: &[u8], x: usize, y: usize) -> bool {
+        screen[x + y * DISPLAY_WIDTH] != 0
+    }
+
+    #[test]
+    fn sprites_start_wrapped_onto_the_screen() {
+        // 64 + 2, 32 + 1
+        let screen = draw_block(66, 33, true);
+        assert!(lit(&screen, 2, 1) && lit(&screen, 9, 4));
+        assert!(!lit(&screen, 1, 1) && !lit(&screen, 10, 1));
+        assert_eq!(screen.iter().filter(|pixel| **pixel != 0).count(), 32);
+    }
+
+    #[test]
+    fn sprites_wrap_at_the_edges() {
+        let screen = draw_block(60, 30, false);
+        assert!(lit(&screen, 63, 31) && lit(&screen, 0, 0) && lit(&screen, 3, 1));
+        assert_eq!(screen.iter().filter(|pixel| **pixel != 0).count(), 32);
+    }
+
+    #[test]
+    fn clip_sprites_cuts_them_off_at_the_edges() {
+        let screen = draw_block(60, 30, true);
+        assert!(lit(&screen, 63, 31) && !lit(&screen, 0, 0));
+        assert_eq!(screen.iter().filter(|pixel| **pixel != 0).count(), 8);
+    }
+
+    #[test]
+    fn font_address_masks_the_low_nibble() {
+        // V0 = 0x1A, F029
+        let mut chip_8 = machine(&[0x60, 0x1A, 0xF0, 0x29]);
+        chip_8.emulate_cycle().unwrap();
+        chip_8.emulate_cycle().unwrap();
+        assert_eq!(chip_8.i() as usize, FONT_BASE + 0xA * 5);
+        assert_eq!(chip_8.read_memory(chip_8.i()), 0xF0);
+    }
+
+    // runs program to its end with quirks
+    fn run_quirked(program: &[u8], quirks: Quirks) -> State {
+        let mut chip_8 = machine(program);
+        chip_8.set_quirks(quirks);
+        for _ in 0..program.len() / 2 {
+            chip_8.emulate_cycle().unwrap();
+        }
+        chip_8
+    }
+
+    #[test]
+    fn shift_vy_shifts_vy_into_vx() {
+        // V0 = 5, V1 = 0x10, 8016
+        let program = [0x60, 0x05, 0x61, 0x10, 0x80, 0x16];
+        let shift_vy = Quirks {
+            shift_vy: true,
+            ..Quirks::default()
+        };
+        assert_eq!(run_quirked(&program, shift_vy).registers()[0], 0x08);
+        let chip_8 = run_quirked(&program, Quirks::default());
+        assert_eq!((chip_8.registers()[0], chip_8.registers()[0xF]), (0x02, 1));
+    }
+
+    #[test]
+    fn load_store_increment_moves_i() {
+        // I = 0x300, F155
+        let program = [0xA3, 0x00, 0xF1, 0x55];
+        let increment = Quirks {
+            load_store_increment: true,
+            ..Quirks::default()
+        };
+        assert_eq!(run_quirked(&program, increment).i(), 0x302);
+        assert_eq!(run_quirked(&program, Quirks::default()).i(), 0x300);
+    }
+
+    #[test]
+    fn jump_vx_adds_vx_instead_of_v0() {
+        // V0 = 2, V1 = 4, B120
+        let program = [0x60, 0x02, 0x61, 0x04, 0xB1, 0x20];
+        let jump_vx = Quirks {
+            jump_vx: true,
+            ..Quirks::default()
+        };
+        assert_eq!(run_quirked(&program, jump_vx).pc(), 0x124);
+        assert_eq!(run_quirked(&program, Quirks::default()).pc(), 0x122);
+    }
+
+    #[test]
+    fn vf_reset_clears_vf_on_logic() {
+        // VF = 5, 8011
+        let program = [0x6F, 0x05, 0x80, 0x11];
+        let vf_reset = Quirks {
+            vf_reset: true,
+            ..Quirks::default()
+        };
+        assert_eq!(run_quirked(&program, vf_reset).registers()[0xF], 0);
+        assert_eq!(run_quirked(&program, Quirks::default()).registers()[0xF], 5);
+    }
+
+    #[test]
+    fn quirks_set_by_name() {
+        let mut quirks = Quirks::default();
+        quirks.set("shift=vy").unwrap();
+        quirks.set("clip = on").unwrap();
+        assert!(quirks.shift_vy && quirks.clip_sprites);
+        assert!(quirks.set("shift=vz").is_err());
+        assert!(quirks.set("wobble=on").is_err());
+        assert!(quirks.set("clip").is_err());
+    }
+
+    #[test]
+    fn quirk_bits_round_trip() {
+        for preset in Quirks::PRESETS {
+            let quirks = Quirks::preset(preset).unwrap();
+            assert_eq!(Quirks::from_bits(quirks.to_bits()), quirks);
+        }
+    }
+
+    // V0 = 5, I = 0x123, call 0x300, then loop
+    #[cfg(feature = "cdp1802")]
+    fn machine_code(routine: &[u8]) -> State {
+        let mut chip_8 = machine(&[0x60, 0x05, 0xA1, 0x23, 0x03, 0x00, 0x12, 0x06]);
+        chip_8.set_machine_code(true);
+        for (offset, byte) in routine.iter().enumerate() {
+            chip_8.write_memory(0x300 + offset as u16, *byte);
+        }
+        chip_8
+    }
+
+    #[cfg(feature = "cdp1802")]
+    #[test]
+    fn machine_code_returns_registers_and_display() {
+        let mut chip_8 = machine_code(&[
+            // V3 (R6) = V0 (R7) + 1
+            0x07, 0xFC, 0x01, 0x56, //
+            // I (RA) + 1
+            0x8A, 0xFC, 0x01, 0xAA, //
+            // R8 = 0xF00, the first display byte, lights the top left pixel
+            0xF8, 0x0F, 0xB8, 0xF8, 0x00, 0xA8, 0xF8, 0x80, 0x58, //
+            // SEP R4, back to the interpreter
+            0xD4,
+        ]);
+        for _ in 0..3 {
+            chip_8.emulate_cycle().unwrap();
+        }
+        assert_eq!(chip_8.registers()[0], 5);
+        assert_eq!(chip_8.registers()[3], 6);
+        assert_eq!(chip_8.i(), 0x124);
+        assert_eq!(chip_8.get_graphics_buffer()[0], 1);
+        assert_eq!(chip_8.get_graphics_buffer()[1..].iter().sum::<u8>(), 0);
+        assert_eq!(chip_8.pc(), 0x206);
+    }
+
+    #[cfg(feature = "cdp1802")]
+    #[test]
+    fn machine_code_that_never_returns_is_stuck() {
+        // BR 0x00, to itself
+        let mut chip_8 = machine_code(&[0x30, 0x00]);
+        chip_8.emulate_cycle().unwrap();
+        chip_8.emulate_cycle().unwrap();
+        assert_eq!(
+            chip_8.emulate_cycle(),
+            Err(Error::MachineCodeStuck { pc: 0x204 })
+        );
+    }
+
+    #[cfg(feature = "cdp1802")]
+    #[test]
+    fn machine_code_respects_write_protection() {
+        // R8 = 0, STR R8, SEP R4
+        let mut chip_8 = machine_code(&[0xF8, 0x00, 0xB8, 0xA8, 0x58, 0xD4]);
+        chip_8.set_write_protection(true);
+        let font = chip_8.read_memory(0x000);
+        chip_8.emulate_cycle().unwrap();
+        chip_8.emulate_cycle().unwrap();
+        assert_eq!(
+            chip_8.emulate_cycle(),
+            Err(Error::ProtectedWrite {
+                pc: 0x204,
+                address: 0x000
+            })
+        );
+        assert_eq!(chip_8.read_memory(0x000), font);
+    }
+
+    #[test]
+    fn timers_tick_when_the_clock_says() {
+        let clock = Manual::default();
+        // LD V0, 5, LD DT, V0, then loop
+        let mut chip_8 = machine(&[0x60, 0x05, 0xF0, 0x15, 0x12, 0x04]);
+        chip_8.set_clock(clock.clone());
+        for _ in 0..4 {
+            chip_8.emulate_cycle().unwrap();
+        }
+        assert_eq!(chip_8.delay_timer(), 5);
+        clock.advance(3);
+        chip_8.emulate_cycle().unwrap();
+        assert_eq!(chip_8.delay_timer(), 2);
+    }
+
+    #[test]
+    fn display_changes_are_reported_once() {
+        // I = the 0 digit, draw it at V0, V0 then clear the screen
+        let mut chip_8 = machine(&[0xA0, 0x00, 0xD0, 0x05, 0x00, 0xE0]);
+        assert!(chip_8.take_display_changes().is_empty());
+        chip_8.emulate_cycle().unwrap();
+        chip_8.emulate_cycle().unwrap();
+        let drawn = chip_8.take_display_changes();
+        assert_eq!(drawn.len(), 14);
+        assert!(drawn.contains(&(0, 0, true)));
+        assert!(drawn.iter().all(|(_, _, on)| *on));
+        assert!(chip_8.take_display_changes().is_empty());
+        chip_8.emulate_cycle().unwrap();
+        let cleared = chip_8.take_display_changes();
+        assert_eq!(cleared.len(), 14);
+        assert!(cleared.iter().all(|(_, _, on)| !*on));
+    }
+
+    #[test]
+    fn reset_restarts_the_loaded_program() {
+        // LD V0, 5
+        let mut chip_8 = machine(&[0x60, 0x05]);
+        chip_8.emulate_cycle().unwrap();
+        chip_8.write_memory(0x200, 0xFF);
+        chip_8.reset();
+        assert_eq!(chip_8.pc(), 0x200);
+        assert_eq!(chip_8.registers(), [0; 16]);
+        assert_eq!(chip_8.read_memory(0x200), 0x60);
+        chip_8.emulate_cycle().unwrap();
+        assert_eq!(chip_8.registers()[0], 5);
+    }
+
+    #[test]
+    fn load_rom_swaps_programs() {
+        let mut chip_8 = machine(&[0x60, 0x05]);
+        chip_8.set_stack_limit(4);
+        chip_8.emulate_cycle().unwrap();
+        // LD V1, 7
+        chip_8.load_rom(&[0x61, 0x07]).unwrap();
+        assert_eq!(chip_8.pc(), 0x200);
+        assert_eq!(chip_8.registers(), [0; 16]);
+        assert_eq!(chip_8.stack_limit(), 4);
+        chip_8.emulate_cycle().unwrap();
+        assert_eq!(chip_8.registers()[1], 7);
+        // reset goes back to the new program
+        chip_8.reset();
+        assert_eq!(chip_8.read_memory(0x200), 0x61);
+    }
+
+    #[test]
+    fn load_rom_refuses_programs_that_dont_fit() {
+        let mut chip_8 = machine(&[0x60, 0x05]);
+        chip_8.emulate_cycle().unwrap();
+        let rom = vec![0; DEFAULT_MEMORY_SIZE - 0x200 + 1];
+        assert_eq!(
+            chip_8.load_rom(&rom),
+            Err("The program is 3585 bytes, only 3584 fit".to_string())
+        );
+        assert_eq!(chip_8.pc(), 0x202);
+        assert_eq!(chip_8.registers()[0], 5);
+    }
+
+    #[test]
+    fn the_sound_sink_hears_the_buzzer_start_and_stop() {
+        let heard = Rc::new(RefCell::new(Vec::new()));
+        // LD V0, 2, LD ST, V0, then loop
+        let mut chip_8 = machine(&[0x60, 0x02, 0xF0, 0x18, 0x12, 0x04]);
+        let sink = heard.clone();
+        chip_8.set_sound_sink(move |on| sink.borrow_mut().push(on));
+        for _ in 0..4 {
+            chip_8.emulate_cycle().unwrap();
+        }
+        assert_eq!(*heard.borrow(), [true, false]);
+    }
+
+    #[test]
+    fn a_new_sound_sink_hears_a_buzzer_already_on() {
+        let heard = Rc::new(RefCell::new(Vec::new()));
+        let mut chip_8 = machine(&[]);
+        chip_8.set_sound_timer(10);
+        let sink = heard.clone();
+        chip_8.set_sound_sink(move |on| sink.borrow_mut().push(on));
+        chip_8.reset();
+        assert_eq!(*heard.borrow(), [true, false]);
+    }
+
+    #[test]
+    fn syscalls_go_to_the_handler() {
+        // SYS 0x123 twice
+        let mut chip_8 = machine(&[0x01, 0x23, 0x01, 0x23]);
+        chip_8.emulate_cycle().unwrap();
+        assert_eq!(chip_8.stats().unknown_opcodes, 1);
+        chip_8.on_syscall(|chip_8, address| chip_8.set_register(0, address as u8));
+        chip_8.emulate_cycle().unwrap();
+        assert_eq!(chip_8.registers()[0], 0x23);
+        assert_eq!(chip_8.pc(), 0x204);
+        assert_eq!(chip_8.stats().unknown_opcodes, 1);
+    }
+
+    #[test]
+    fn events_reach_every_listener() {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        // I = the 0 digit, draw it twice, clear the screen
+        let mut chip_8 = machine(&[0xA0, 0x00, 0xD0, 0x05, 0xD0, 0x05, 0x00, 0xE0]);
+        chip_8.set_delay_timer(1);
+        let listener = events.clone();
+        chip_8.subscribe(move |event| listener.borrow_mut().push(*event));
+        let count = Rc::new(RefCell::new(0));
+        let counter = count.clone();
+        chip_8.subscribe(move |_| *counter.borrow_mut() += 1);
+        for _ in 0..4 {
+            chip_8.emulate_cycle().unwrap();
+        }
+        let events = events.borrow();
+        assert_eq!(events.len(), *count.borrow());
+        assert_eq!(
+            events[..2],
+            [
+                Event::InstructionExecuted {
+                    pc: 0x200,
+                    opcode: 0xA000
+                },
+                Event::TimerExpired(Timer::Delay),
+            ]
+        );
+        assert!(events.contains(&Event::SpriteDrawn {
+            x: 0,
+            y: 0,
+            rows: 5,
+            collision: false
+        }));
+        assert!(events.contains(&Event::SpriteDrawn {
+            x: 0,
+            y: 0,
+            rows: 5,
+            collision: true
+        }));
+        assert!(events.contains(&Event::ScreenCleared));
+    }
+
+    #[cfg(feature = "cdp1802")]
+    #[test]
+    fn ef3_reads_the_latched_key() {
+        // OUT 2 latches the key R1 points at, B3 0x10 branches if it's held
+        let program = [0x62, 0x36, 0x10, 0x05];
+        for (keys, pc) in [(1 << 5, 0x10), (1 << 4, 0x03)] {
+            let mut memory = vec![Wrapping(0); 0x100];
+            for (offset, byte) in program.iter().enumerate() {
+                memory[offset] = Wrapping(*byte);
+            }
+            let mut vip = Vip {
+                memory: &mut memory,
+                keys,
+                key_latch: 0,
+                write_protection: false,
+                protected_write: None,
+            };
+            let mut cpu = Cdp1802::new();
+            cpu.x = 1;
+            cpu.r[1] = 0x03;
+            cpu.step(&mut vip);
+            cpu.step(&mut vip);
+            assert_eq!(vip.key_latch, 5);
+            assert_eq!(cpu.r[0], pc);
+        }
+    }
+}
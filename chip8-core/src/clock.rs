@@ -0,0 +1,128 @@
+// When the delay and sound timers tick. After every cycle State asks its
+// clock how many ticks are due and ticks the timers that many times.
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+pub trait Clock {
+    // ticks since the last call
+    fn ticks_due(&mut self) -> u32;
+}
+
+// A tick with every instruction, so timers run as fast as the program does.
+// Replays and lockstep comparisons depend on this.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PerCycle;
+
+impl Clock for PerCycle {
+    fn ticks_due(&mut self) -> u32 {
+        1
+    }
+}
+
+// Ticks only as often as it's advanced, for tests and for frontends calling
+// tick_timers themselves. Clones share their ticks, so one can be kept to
+// advance the clock given to State.
+#[derive(Debug, Default, Clone)]
+pub struct Manual {
+    due: Rc<Cell<u32>>,
+}
+
+impl Manual {
+    pub fn advance(&self, ticks: u32) {
+        self.due.set(self.due.get() + ticks);
+    }
+}
+
+impl Clock for Manual {
+    fn ticks_due(&mut self) -> u32 {
+        self.due.take()
+    }
+}
+
+// longer between cycles is a pause or a stall, which the timers sit out
+const MAX_GAP: Duration = Duration::from_millis(250);
+
+// 60 ticks a second of wall time by default, however many instructions that is
+#[derive(Debug, Clone)]
+pub struct RealTime {
+    tick: Duration,
+    last: Option<Instant>,
+    // time since the last tick
+    remainder: Duration,
+}
+
+impl RealTime {
+    pub fn new() -> Self {
+        Self::with_rate(60)
+    }
+
+    // rate ticks a second instead, for machines that ran their timers at 50
+    // or 100 Hz
+    pub fn with_rate(rate: u32) -> Self {
+        Self {
+            tick: Duration::from_secs(1) / rate.max(1),
+            last: None,
+            remainder: Duration::ZERO,
+        }
+    }
+}
+
+impl Default for RealTime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for RealTime {
+    fn ticks_due(&mut self) -> u32 {
+        let now = Instant::now();
+        let elapsed = match self.last.replace(now) {
+            Some(last) if now - last <= MAX_GAP => now - last,
+            _ => Duration::ZERO,
+        };
+        self.remainder += elapsed;
+        let ticks = (self.remainder.as_nanos() / self.tick.as_nanos()) as u32;
+        self.remainder -= self.tick * ticks;
+        ticks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn per_cycle_ticks_every_time() {
+        assert_eq!(PerCycle.ticks_due(), 1);
+        assert_eq!(PerCycle.ticks_due(), 1);
+    }
+
+    #[test]
+    fn manual_ticks_only_when_advanced() {
+        let advancer = Manual::default();
+        let mut clock = advancer.clone();
+        assert_eq!(clock.ticks_due(), 0);
+        advancer.advance(2);
+        advancer.advance(1);
+        assert_eq!(clock.ticks_due(), 3);
+        assert_eq!(clock.ticks_due(), 0);
+    }
+
+    #[test]
+    fn real_time_ticks_with_wall_time() {
+        let mut clock = RealTime::with_rate(1000);
+        assert_eq!(clock.ticks_due(), 0);
+        thread::sleep(Duration::from_millis(20));
+        assert!(clock.ticks_due() >= 20);
+    }
+
+    #[test]
+    fn real_time_sits_out_long_gaps() {
+        let mut clock = RealTime::with_rate(1000);
+        clock.ticks_due();
+        thread::sleep(MAX_GAP + Duration::from_millis(50));
+        assert_eq!(clock.ticks_due(), 0);
+    }
+}
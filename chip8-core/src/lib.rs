@@ -0,0 +1,16 @@
+// The CHIP-8 interpreter on its own, for programs embedding it. The SDL
+// frontend is chip8-sdl, the Frontend trait other displays implement is in
+// chip8-frontend and the assembler, disassembler and ROM info are in
+// chip8-tools, so using the core doesn't build any of them.
+// Without its default features it only runs programs given to load_buffer,
+// reading no files and asking the OS for no random seed, for wasm and
+// embedded builds.
+#[macro_use]
+pub mod log;
+#[cfg(feature = "cdp1802")]
+pub mod cdp1802;
+mod chip8;
+pub mod clock;
+pub mod font;
+
+pub use chip8::*;
@@ -0,0 +1,239 @@
+// Frontends plug in here. Anything that can show the display and report key
+// presses implements Frontend and is added to a Registry under a name;
+// --frontend NAME picks one, and run drives the machine for it.
+use crate::chip8::State;
+use crate::timing::{TimingModel, FRAME_RATE};
+use crate::vip;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub enum Event {
+    Key { key: u8, pressed: bool },
+    Quit,
+}
+
+pub trait Frontend {
+    // Shows the 64x32 display, one byte per pixel
+    fn draw(&mut self, screen: &[u8]) -> Result<(), String>;
+
+    // Input since the last call, polled once a frame
+    fn poll_events(&mut self) -> Result<Vec<Event>, String>;
+
+    // The buzzer starting or stopping
+    fn set_sound(&mut self, _on: bool) {}
+
+    // Runs chip_8 until the frontend quits, with the loop below unless the
+    // frontend has its own for more than showing the display and taking keys
+    fn run(
+        &mut self,
+        chip_8: &mut State,
+        timing: TimingModel,
+        timer_hz: Option<u32>,
+    ) -> Result<(), String> {
+        run(self, chip_8, timing, timer_hz)
+    }
+}
+
+struct Registration {
+    name: &'static str,
+    create: Box<dyn Fn() -> Result<Box<dyn Frontend>, String>>,
+}
+
+// The frontends a build can run. Default has the ones built into this crate,
+// each behind its own feature flag.
+pub struct Registry {
+    frontends: Vec<Registration>,
+}
+
+impl Registry {
+    pub fn empty() -> Self {
+        Self {
+            frontends: Vec::new(),
+        }
+    }
+
+    // Adds a frontend, replacing one already registered under the name
+    pub fn register(
+        &mut self,
+        name: &'static str,
+        create: impl Fn() -> Result<Box<dyn Frontend>, String> + 'static,
+    ) {
+        self.frontends.retain(|frontend| frontend.name != name);
+        self.frontends.push(Registration {
+            name,
+            create: Box::new(create),
+        });
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.frontends
+            .iter()
+            .map(|frontend| frontend.name)
+            .collect()
+    }
+
+    pub fn create(&self, name: &str) -> Result<Box<dyn Frontend>, String> {
+        match self.frontends.iter().find(|frontend| frontend.name == name) {
+            Some(frontend) => (frontend.create)(),
+            None => Err(format!(
+                "Unknown frontend {}, this build has: {}",
+                name,
+                self.names().join(" ")
+            )),
+        }
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        #[allow(unused_mut)]
+        let mut registry = Self::empty();
+        #[cfg(feature = "terminal")]
+        registry.register("terminal", crate::terminal::Terminal::create);
+        registry
+    }
+}
+
+// Runs chip_8 at the speed timing gives, 60 frames a second, until the frontend
+// quits. The timers tick timer_hz times a second, 60 if not given.
+pub fn run<F: Frontend + ?Sized>(
+    frontend: &mut F,
+    chip_8: &mut State,
+    timing: TimingModel,
+    timer_hz: Option<u32>,
+) -> Result<(), String> {
+    let frame_time = Duration::from_secs_f64(1.0 / FRAME_RATE as f64);
+    let mut next_frame = Instant::now();
+    let mut sounding = false;
+    // the display_generation on screen
+    let mut shown_generation = 0;
+    timing.configure(chip_8, Some(timer_hz.unwrap_or(60)));
+    let mut clock = vip::Clock::default();
+    let mut frame = 0;
+    loop {
+        for event in frontend.poll_events()? {
+            match event {
+                Event::Key { key, pressed } => chip_8.set_key(key as usize, pressed as u8),
+                Event::Quit => return Ok(()),
+            }
+        }
+        frame += 1;
+        let cycles = timing.cycles_for_frame(frame, 1);
+        if cycles.is_none() {
+            clock.start_frame();
+        }
+        // a faulted machine stays on screen, but stops executing
+        for _ in 0..cycles.unwrap_or(u32::MAX) {
+            if chip_8.error().is_some() || cycles.is_none() && !clock.fits(chip_8) {
+                break;
+            }
+            if let Err(error) = chip_8.emulate_cycle() {
+                error!("Emulation halted: {}", error);
+            }
+            clock.executed(chip_8);
+        }
+        let mut changed = chip_8.display_generation() != shown_generation;
+        if cycles.is_none() {
+            changed |= clock.end_frame(chip_8);
+            chip_8.tick_timers();
+        }
+        let beeping = chip_8.sound_timer() > 0;
+        if beeping != sounding {
+            frontend.set_sound(beeping);
+            sounding = beeping;
+        }
+        if changed {
+            match cycles {
+                Some(_) => frontend.draw(&chip_8.get_graphics_buffer())?,
+                // what the beam scanned
+                None => frontend.draw(clock.screen())?,
+            }
+            shown_generation = chip_8.display_generation();
+        }
+
+        next_frame += frame_time;
+        let now = Instant::now();
+        if next_frame > now {
+            thread::sleep(next_frame - now);
+        } else {
+            next_frame = now;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Blank;
+
+    impl Frontend for Blank {
+        fn draw(&mut self, _screen: &[u8]) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn poll_events(&mut self) -> Result<Vec<Event>, String> {
+            Ok(vec![Event::Quit])
+        }
+    }
+
+    #[test]
+    fn registered_frontends_can_be_created() {
+        let mut registry = Registry::empty();
+        registry.register("blank", || Ok(Box::new(Blank)));
+        assert_eq!(registry.names(), ["blank"]);
+        assert!(registry.create("blank").is_ok());
+        assert!(registry.create("missing").is_err());
+    }
+
+    #[test]
+    fn registering_a_name_again_replaces_it() {
+        let mut registry = Registry::empty();
+        registry.register("blank", || Err("old".to_string()));
+        registry.register("blank", || Ok(Box::new(Blank)));
+        assert_eq!(registry.names(), ["blank"]);
+        assert!(registry.create("blank").is_ok());
+    }
+
+    // runs a frame of its own and quits
+    struct Looping;
+
+    impl Frontend for Looping {
+        fn draw(&mut self, _screen: &[u8]) -> Result<(), String> {
+            Err("drawn".to_string())
+        }
+
+        fn poll_events(&mut self) -> Result<Vec<Event>, String> {
+            Err("polled".to_string())
+        }
+
+        fn run(
+            &mut self,
+            chip_8: &mut State,
+            _timing: TimingModel,
+            _timer_hz: Option<u32>,
+        ) -> Result<(), String> {
+            chip_8.emulate_cycle().map_err(|error| error.to_string())
+        }
+    }
+
+    #[test]
+    fn frontends_can_run_their_own_loop() {
+        let mut registry = Registry::empty();
+        registry.register("looping", || Ok(Box::new(Looping)));
+        let mut chip_8 = State::new();
+        chip_8.initialize();
+        let mut frontend = registry.create("looping").unwrap();
+        assert!(frontend
+            .run(&mut chip_8, TimingModel::default(), None)
+            .is_ok());
+        assert_eq!(chip_8.stats().cycles, 1);
+    }
+
+    #[test]
+    fn run_stops_when_the_frontend_quits() {
+        let mut chip_8 = State::new();
+        chip_8.initialize();
+        assert!(run(&mut Blank, &mut chip_8, TimingModel::default(), None).is_ok());
+    }
+}
@@ -0,0 +1,16 @@
+// What a frontend needs to run the CHIP-8 core: the Frontend trait, the
+// Registry of them --frontend picks from, and the loop driving the machine
+// at the speed a TimingModel gives. Crates with their own display implement
+// Frontend and register it next to the built in ones.
+#[macro_use]
+extern crate chip8_core;
+
+mod frontend;
+#[cfg(feature = "terminal")]
+pub mod terminal;
+pub mod timing;
+pub mod vip;
+
+use chip8_core as chip8;
+
+pub use frontend::*;
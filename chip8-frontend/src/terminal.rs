@@ -0,0 +1,150 @@
+// --frontend terminal, for machines without a display. The screen is drawn
+// with half block characters, two CHIP-8 rows per line, and keys are read
+// from a raw mode stdin on the usual 1234/QWER/ASDF/ZXCV block.
+// Esc or Ctrl-C quits.
+use crate::{Event, Frontend};
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+// terminals only report presses, so a key counts as held for this many
+// frames after its last press or repeat
+const HOLD_FRAMES: u8 = 8;
+
+// the physical block starting at 1, as CHIP-8 keys
+const KEYS: [(u8, u8); 16] = [
+    (b'1', 0x1),
+    (b'2', 0x2),
+    (b'3', 0x3),
+    (b'4', 0xC),
+    (b'q', 0x4),
+    (b'w', 0x5),
+    (b'e', 0x6),
+    (b'r', 0xD),
+    (b'a', 0x7),
+    (b's', 0x8),
+    (b'd', 0x9),
+    (b'f', 0xE),
+    (b'z', 0xA),
+    (b'x', 0x0),
+    (b'c', 0xB),
+    (b'v', 0xF),
+];
+
+pub struct Terminal {
+    input: Receiver<u8>,
+    // frames each key has left before it's released
+    held: [u8; 16],
+    // stty's description of the settings to restore
+    saved_mode: String,
+}
+
+impl Terminal {
+    pub fn create() -> Result<Box<dyn Frontend>, String> {
+        let saved_mode = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+        let (sender, input) = mpsc::channel();
+        thread::spawn(move || {
+            for byte in std::io::stdin().lock().bytes() {
+                let Ok(byte) = byte else { return };
+                if sender.send(byte).is_err() {
+                    return;
+                }
+            }
+        });
+        // the alternate screen keeps the shell's scrollback intact
+        print!("\x1b[?1049h\x1b[?25l\x1b[2J");
+        Ok(Box::new(Self {
+            input,
+            held: [0; 16],
+            saved_mode: saved_mode.trim().to_string(),
+        }))
+    }
+}
+
+impl Frontend for Terminal {
+    fn draw(&mut self, screen: &[u8]) -> Result<(), String> {
+        let mut frame = String::from("\x1b[H");
+        for row in screen.chunks(128) {
+            let (top, bottom) = row.split_at(64);
+            for (upper, lower) in top.iter().zip(bottom) {
+                frame.push(match (*upper != 0, *lower != 0) {
+                    (true, true) => '\u{2588}',
+                    (true, false) => '\u{2580}',
+                    (false, true) => '\u{2584}',
+                    (false, false) => ' ',
+                });
+            }
+            frame.push_str("\r\n");
+        }
+        let mut stdout = std::io::stdout().lock();
+        stdout
+            .write_all(frame.as_bytes())
+            .and_then(|_| stdout.flush())
+            .map_err(|op| op.to_string())
+    }
+
+    fn poll_events(&mut self) -> Result<Vec<Event>, String> {
+        let mut events = Vec::new();
+        let mut pressed = [false; 16];
+        while let Ok(byte) = self.input.try_recv() {
+            match byte {
+                // Esc, Ctrl-C
+                0x1B | 0x03 => events.push(Event::Quit),
+                _ => {
+                    let lower = byte.to_ascii_lowercase();
+                    if let Some((_, key)) = KEYS.iter().find(|(host, _)| *host == lower) {
+                        pressed[*key as usize] = true;
+                    }
+                }
+            }
+        }
+        for (key, frames) in self.held.iter_mut().enumerate() {
+            if pressed[key] {
+                if *frames == 0 {
+                    events.push(Event::Key {
+                        key: key as u8,
+                        pressed: true,
+                    });
+                }
+                *frames = HOLD_FRAMES;
+            } else if *frames > 0 {
+                *frames -= 1;
+                if *frames == 0 {
+                    events.push(Event::Key {
+                        key: key as u8,
+                        pressed: false,
+                    });
+                }
+            }
+        }
+        Ok(events)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = std::io::stdout().flush();
+        if let Err(error) = stty(&[&self.saved_mode]) {
+            warn!("Couldn't restore the terminal: {}", error);
+        }
+    }
+}
+
+// runs stty on the terminal stdin is connected to
+fn stty(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()
+        .map_err(|op| format!("Couldn't run stty: {}", op))?;
+    if !output.status.success() {
+        return Err(format!(
+            "stty failed, is stdin a terminal? {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
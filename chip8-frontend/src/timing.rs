@@ -0,0 +1,85 @@
+// How fast the machine runs. Every frontend runs 60 frames a second and asks
+// the model how many instructions go into each, so a speed means the same
+// thing whichever one is showing the screen.
+use crate::chip8::{clock, State};
+
+// the rate the timers count down at, and so the length of a frame
+pub const FRAME_RATE: u32 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimingModel {
+    // --ipf N, the same number of instructions every frame
+    FixedIpf(u32),
+    // --cps N, spread over the frames so that a second runs exactly N
+    CyclesPerSecond(u32),
+    // --timing vip, each instruction takes the time it took on the COSMAC VIP
+    VipAccurate,
+}
+
+impl Default for TimingModel {
+    fn default() -> Self {
+        Self::FixedIpf(1)
+    }
+}
+
+impl TimingModel {
+    // Instructions to run in frame number frame, multiplied by speed. None
+    // for VipAccurate, which runs until vip::Clock says the frame is over.
+    pub fn cycles_for_frame(&self, frame: u64, speed: u32) -> Option<u32> {
+        match *self {
+            Self::FixedIpf(ipf) => Some(ipf.saturating_mul(speed)),
+            Self::CyclesPerSecond(rate) => {
+                // this frame's share of the second, the remainders add up to whole cycles
+                // wide enough that no rate and speed overflow
+                let rate = rate as u128 * speed as u128;
+                let frame = (frame % FRAME_RATE as u64) as u128;
+                let total = |frame: u128| rate * frame / FRAME_RATE as u128;
+                Some(u32::try_from(total(frame + 1) - total(frame)).unwrap_or(u32::MAX))
+            }
+            Self::VipAccurate => None,
+        }
+    }
+
+    // For the window title, speed as in cycles_for_frame
+    pub fn describe(&self, speed: u32) -> String {
+        match *self {
+            Self::FixedIpf(ipf) => format!("{} ipf", ipf.saturating_mul(speed)),
+            Self::CyclesPerSecond(rate) => format!("{} Hz", rate as u64 * speed as u64),
+            Self::VipAccurate => "VIP timing".to_string(),
+        }
+    }
+
+    // The VIP's clock ticks the timers once a frame itself. Otherwise they
+    // tick in real time at timer_rate a second, or with every instruction
+    // without one for runs that have to be reproduced cycle for cycle.
+    pub fn configure(&self, chip_8: &mut State, timer_rate: Option<u32>) {
+        match (self, timer_rate) {
+            (Self::VipAccurate, _) => chip_8.set_clock(clock::Manual::default()),
+            (_, Some(rate)) => chip_8.set_clock(clock::RealTime::with_rate(rate)),
+            (_, None) => chip_8.set_clock(clock::PerCycle),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cycles_per_second_add_up_over_a_second() {
+        let model = TimingModel::CyclesPerSecond(1000);
+        let total: u32 = (0..FRAME_RATE as u64)
+            .map(|frame| model.cycles_for_frame(frame, 1).unwrap())
+            .sum();
+        assert_eq!(total, 1000);
+    }
+
+    #[test]
+    fn huge_speeds_saturate() {
+        let model = TimingModel::FixedIpf(u32::MAX / 2);
+        assert_eq!(model.cycles_for_frame(0, 3), Some(u32::MAX));
+        assert_eq!(model.describe(3), format!("{} ipf", u32::MAX));
+        let model = TimingModel::CyclesPerSecond(u32::MAX);
+        assert_eq!(model.cycles_for_frame(59, u32::MAX), Some(u32::MAX));
+    }
+}
@@ -0,0 +1,222 @@
+// --timing vip: runs as many instructions a frame as fit in the time the
+// COSMAC VIP's interpreter had, instead of a flat --ipf. Time is counted in
+// the 1802's machine cycles of 8 clocks, about 4.5us each. The costs are
+// approximations of the original interpreter's, close enough for ROMs that
+// were tuned to its speed.
+//
+// The picture is what the beam saw: every row of the screen is taken from the
+// machine as it was when that row was scanned, and a sprite's rows are drawn
+// one after another, so a sprite drawn while the beam passes is cut in two
+// the way it was on the VIP. Demos that race the beam depend on this.
+use crate::chip8::State;
+
+// 1.7609 MHz / 8 / 60 Hz, 262 lines of 14 machine cycles
+pub const FRAME_CYCLES: u32 = 3668;
+const LINE_CYCLES: u32 = 14;
+// the display interrupt routine at the start of every frame
+const INTERRUPT_CYCLES: u32 = 46;
+// the 128 lines showing the screen, four per row. While they're scanned the
+// display's DMA takes 8 of every 14 machine cycles.
+const DISPLAY_START: u32 = 4 * LINE_CYCLES;
+const DISPLAY_END: u32 = DISPLAY_START + 128 * LINE_CYCLES;
+const ROW_CYCLES: u32 = 4 * LINE_CYCLES;
+const DMA_FREE_CYCLES: u32 = 6;
+// DXYN's time before the first row is drawn
+const DRAW_SETUP_CYCLES: u32 = 68;
+
+const WIDTH: usize = 64;
+const HEIGHT: usize = 32;
+
+// Machine cycles the instruction at the program counter takes
+pub fn cost(state: &State) -> u32 {
+    let opcode = opcode(state);
+    let x = ((opcode >> 8) & 0xF) as u32;
+    match opcode & 0xF000 {
+        0x0000 if opcode == 0x00E0 => 24,
+        0x0000 | 0x1000 | 0x2000 | 0xB000 => 23,
+        0x3000 | 0x4000 | 0xA000 => 12,
+        0x5000 | 0x9000 | 0xE000 => 16,
+        0x6000 => 6,
+        0x7000 => 10,
+        0x8000 => 44,
+        0xC000 => 36,
+        0xD000 => DRAW_SETUP_CYCLES + (opcode & 0xF) as u32 * draw_row_cost(state, opcode),
+        _ => match opcode & 0xFF {
+            0x1E => 19,
+            0x29 => 20,
+            0x33 => 204,
+            // one pass per register
+            0x55 | 0x65 => 14 + 14 * (x + 1),
+            _ => 10,
+        },
+    }
+}
+
+// Drawing goes a byte at a time, a sprite that isn't byte aligned spans two
+// bytes per row and has to be shifted into place
+fn draw_row_cost(state: &State, opcode: u16) -> u32 {
+    let x = state.registers()[((opcode >> 8) & 0xF) as usize];
+    if x.is_multiple_of(8) {
+        46
+    } else {
+        70
+    }
+}
+
+fn opcode(state: &State) -> u16 {
+    ((state.read_memory(state.pc()) as u16) << 8)
+        | state.read_memory(state.pc().wrapping_add(1)) as u16
+}
+
+// The time after running cycles machine cycles from time, the DMA slows the
+// interpreter down while the screen is scanned
+fn advance(mut time: u32, mut cycles: u32) -> u32 {
+    if time < DISPLAY_START {
+        let step = cycles.min(DISPLAY_START - time);
+        time += step;
+        cycles -= step;
+    }
+    if cycles > 0 && time < DISPLAY_END {
+        let available = (DISPLAY_END - time) * DMA_FREE_CYCLES / LINE_CYCLES;
+        let step = cycles.min(available);
+        time += step * LINE_CYCLES / DMA_FREE_CYCLES;
+        cycles -= step;
+        if cycles > 0 {
+            time = time.max(DISPLAY_END);
+        }
+    }
+    time + cycles
+}
+
+// when the beam reaches a row of the screen
+fn scan_time(row: usize) -> u32 {
+    DISPLAY_START + row as u32 * ROW_CYCLES
+}
+
+// The instruction fits let start
+struct Pending {
+    start: u32,
+    cost: u32,
+    // for DXYN, the screen before it, the row its first row lands on and the
+    // time each of its rows takes
+    draw: Option<(Vec<u8>, usize, u32)>,
+}
+
+// Where in the current frame the interpreter and the beam are
+pub struct Clock {
+    // machine cycles since the frame started, past FRAME_CYCLES when the last
+    // instruction ran into the next frame
+    time: u32,
+    // DXYN waits for the display interrupt before drawing
+    waiting: bool,
+    pending: Option<Pending>,
+    // what the beam has shown, and how many rows of it this frame
+    screen: Vec<u8>,
+    scanned: usize,
+    changed: bool,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self {
+            time: 0,
+            waiting: false,
+            pending: None,
+            screen: vec![0; WIDTH * HEIGHT],
+            scanned: 0,
+            changed: false,
+        }
+    }
+}
+
+impl Clock {
+    // call at the start of every frame
+    pub fn start_frame(&mut self) {
+        self.time = self.time.saturating_sub(FRAME_CYCLES) + INTERRUPT_CYCLES;
+        self.scanned = 0;
+    }
+
+    // true if the next instruction starts this frame, call executed once it has run
+    pub fn fits(&mut self, state: &State) -> bool {
+        self.scan_until(state, self.time);
+        if self.time >= FRAME_CYCLES {
+            return false;
+        }
+        let opcode = opcode(state);
+        let mut draw = None;
+        if opcode & 0xF000 == 0xD000 {
+            if !self.waiting {
+                // waits out the rest of the frame, drawing starts with the next
+                self.waiting = true;
+                self.time = FRAME_CYCLES;
+                return false;
+            }
+            self.waiting = false;
+            let y = state.registers()[((opcode >> 4) & 0xF) as usize] as usize;
+            draw = Some((state.get_graphics_buffer(), y, draw_row_cost(state, opcode)));
+        }
+        self.pending = Some(Pending {
+            start: self.time,
+            cost: cost(state),
+            draw,
+        });
+        true
+    }
+
+    // Moves time past the instruction fits let start. Rows scanned while a
+    // sprite was being drawn only show the sprite's rows drawn by then.
+    pub fn executed(&mut self, state: &State) {
+        let Some(pending) = self.pending.take() else {
+            return;
+        };
+        self.time = advance(pending.start, pending.cost);
+        let Some((before, y, row_cost)) = pending.draw else {
+            return;
+        };
+        let after = state.get_graphics_buffer();
+        while self.scanned < HEIGHT && scan_time(self.scanned) < self.time.min(FRAME_CYCLES) {
+            let row = self.scanned;
+            // the sprite row that lands on this screen row
+            let sprite_row = (row + HEIGHT - y % HEIGHT) % HEIGHT;
+            let drawn = advance(
+                pending.start,
+                DRAW_SETUP_CYCLES + (sprite_row as u32 + 1) * row_cost,
+            );
+            let source = if drawn <= scan_time(row) {
+                &after
+            } else {
+                &before
+            };
+            self.scan_row(source);
+        }
+    }
+
+    // Scans the rest of the frame, true if the picture changed
+    pub fn end_frame(&mut self, state: &State) -> bool {
+        self.scan_until(state, FRAME_CYCLES);
+        std::mem::take(&mut self.changed)
+    }
+
+    // the picture the beam drew this frame
+    pub fn screen(&self) -> &[u8] {
+        &self.screen
+    }
+
+    fn scan_until(&mut self, state: &State, time: u32) {
+        if self.scanned == HEIGHT || scan_time(self.scanned) >= time {
+            return;
+        }
+        let screen = state.get_graphics_buffer();
+        while self.scanned < HEIGHT && scan_time(self.scanned) < time {
+            self.scan_row(&screen);
+        }
+    }
+
+    // the next row from screen
+    fn scan_row(&mut self, screen: &[u8]) {
+        let range = self.scanned * WIDTH..(self.scanned + 1) * WIDTH;
+        self.changed |= self.screen[range.clone()] != screen[range.clone()];
+        self.screen[range.clone()].copy_from_slice(&screen[range]);
+        self.scanned += 1;
+    }
+}
@@ -0,0 +1,196 @@
+// The buzzer, a square wave played while the sound timer is running. The tone
+// is made at whatever rate the output device runs at, band limited so it
+// doesn't alias, and faded in and out over a few milliseconds so starting
+// and stopping every few frames doesn't pop.
+use crate::wav::WavWriter;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::AudioSubsystem;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+const TONE_HZ: f32 = 440.0;
+const VOLUME: f32 = 0.15;
+// how long fading in or out takes
+const FADE_SECONDS: f32 = 0.005;
+// the sample rate of --record-audio files
+const RECORDING_RATE: u32 = 44100;
+
+// Makes the tone at a sample rate, for the device and for recordings
+struct Tone {
+    // the tone's position in its cycle, 0 to 1
+    phase: f32,
+    step: f32,
+    // the current volume, moving towards VOLUME or 0 by fade_step a sample
+    level: f32,
+    fade_step: f32,
+}
+
+impl Tone {
+    fn new(rate: f32) -> Self {
+        Self {
+            phase: 0.0,
+            step: TONE_HZ / rate,
+            level: 0.0,
+            fade_step: VOLUME / (FADE_SECONDS * rate),
+        }
+    }
+
+    // out holds channels samples for every moment
+    fn fill(&mut self, on: bool, out: &mut [f32], channels: usize) {
+        let target = if on { VOLUME } else { 0.0 };
+        for frame in out.chunks_mut(channels) {
+            self.level = if self.level < target {
+                (self.level + self.fade_step).min(target)
+            } else {
+                (self.level - self.fade_step).max(target)
+            };
+            let mut sample = if self.phase < 0.5 { 1.0 } else { -1.0 };
+            // smooths the two edges so the harmonics above the device's
+            // Nyquist frequency don't fold back as noise
+            sample += poly_blep(self.phase, self.step);
+            sample -= poly_blep((self.phase + 0.5) % 1.0, self.step);
+            frame.fill(sample * self.level);
+            self.phase = (self.phase + self.step) % 1.0;
+        }
+    }
+}
+
+struct SquareWave {
+    on: Arc<AtomicBool>,
+    channels: usize,
+    tone: Tone,
+}
+
+impl AudioCallback for SquareWave {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        let on = self.on.load(Ordering::Relaxed);
+        self.tone.fill(on, out, self.channels);
+    }
+}
+
+// The correction near an edge at phase 0 of a wave advancing step a sample
+fn poly_blep(phase: f32, step: f32) -> f32 {
+    if phase < step {
+        let t = phase / step;
+        2.0 * t - t * t - 1.0
+    } else if phase > 1.0 - step {
+        let t = (phase - 1.0) / step;
+        t * t + 2.0 * t + 1.0
+    } else {
+        0.0
+    }
+}
+
+// --bell, a sound sink ringing the terminal's bell when the buzzer stops,
+// for the terminal, headless runs and machines without audio
+pub fn terminal_bell(on: bool) {
+    if !on {
+        eprint!("\x07");
+    }
+}
+
+// The playback devices SDL knows, by name
+pub fn device_names(audio: &AudioSubsystem) -> Result<Vec<String>, String> {
+    let count = audio
+        .num_audio_playback_devices()
+        .ok_or("Couldn't list the audio devices")?;
+    (0..count)
+        .map(|index| audio.audio_playback_device_name(index))
+        .collect()
+}
+
+// The device after current, going through the system's default (None)
+// first and then every named one
+pub fn next_device(
+    audio: &AudioSubsystem,
+    current: Option<&str>,
+) -> Result<Option<String>, String> {
+    let names = device_names(audio)?;
+    let next = match current {
+        None => 0,
+        Some(current) => names
+            .iter()
+            .position(|name| name == current)
+            .map_or(names.len(), |index| index + 1),
+    };
+    Ok(names.get(next).cloned())
+}
+
+pub struct Buzzer {
+    on: Arc<AtomicBool>,
+    // None for the system's default
+    device: Option<String>,
+    // kept open for as long as the buzzer lives
+    _output: AudioDevice<SquareWave>,
+}
+
+impl Buzzer {
+    pub fn open(audio: &AudioSubsystem, device: Option<&str>) -> Result<Self, String> {
+        if let Some(device) = device {
+            let names = device_names(audio)?;
+            if !names.iter().any(|name| name == device) {
+                return Err(format!(
+                    "Unknown audio device {}, `chip8 audio-devices` lists them",
+                    device
+                ));
+            }
+        }
+        let on = Arc::new(AtomicBool::new(false));
+        // the device's own rate and channels, so SDL doesn't convert
+        let desired = AudioSpecDesired {
+            freq: None,
+            channels: None,
+            samples: None,
+        };
+        let output = audio.open_playback(device, &desired, |spec| SquareWave {
+            on: on.clone(),
+            channels: spec.channels.max(1) as usize,
+            tone: Tone::new(spec.freq as f32),
+        })?;
+        output.resume();
+        Ok(Self {
+            on,
+            device: device.map(str::to_string),
+            _output: output,
+        })
+    }
+
+    pub fn device(&self) -> Option<&str> {
+        self.device.as_deref()
+    }
+
+    pub fn set_on(&self, on: bool) {
+        self.on.store(on, Ordering::Relaxed);
+    }
+}
+
+// --record-audio PATH, the buzzer as it sounded over the whole session
+pub struct AudioRecording {
+    wav: WavWriter,
+    tone: Tone,
+    started: Instant,
+    written: u64,
+}
+
+impl AudioRecording {
+    pub fn create(path: &str) -> Result<Self, String> {
+        Ok(Self {
+            wav: WavWriter::create(path, RECORDING_RATE)?,
+            tone: Tone::new(RECORDING_RATE as f32),
+            started: Instant::now(),
+            written: 0,
+        })
+    }
+
+    // call once a frame with whether the buzzer sounded since the last call
+    pub fn record(&mut self, on: bool) -> Result<(), String> {
+        let due = (self.started.elapsed().as_secs_f64() * RECORDING_RATE as f64) as u64;
+        let mut samples = vec![0.0; (due - self.written) as usize];
+        self.tone.fill(on, &mut samples, 1);
+        self.written = due;
+        self.wav.write(&samples)
+    }
+}
@@ -0,0 +1,191 @@
+use crate::chip8::State;
+use crate::hash;
+use crate::headless;
+use std::collections::HashMap;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+pub struct Outcome {
+    pub rom: String,
+    // SHA-1 of the final display, None if the run panicked
+    pub display: Option<String>,
+    pub error: Option<String>,
+}
+
+// The .ch8 files in directory, sorted by name
+pub fn rom_paths(directory: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut paths: Vec<_> = fs::read_dir(directory)
+        .map_err(|op| op.to_string())?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("ch8"))
+        })
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+// Runs every .ch8 file in a directory headlessly, sorted by name. watch is
+// called after every frame with the ROM's name, its machine and the frames so far.
+pub fn run_directory(
+    directory: &Path,
+    frames: u64,
+    options: headless::Options,
+    watch: &mut dyn FnMut(&str, &State, u64),
+) -> Result<Vec<Outcome>, String> {
+    let mut outcomes = Vec::new();
+    for path in rom_paths(directory)? {
+        let rom = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let program = match fs::read(&path) {
+            Ok(program) => program,
+            Err(error) => {
+                outcomes.push(Outcome {
+                    rom,
+                    display: None,
+                    error: Some(error.to_string()),
+                });
+                continue;
+            }
+        };
+        let outcome = match panic::catch_unwind(AssertUnwindSafe(|| {
+            headless::run_watched(&program, frames, options, |chip_8, frame| {
+                watch(&rom, chip_8, frame)
+            })
+        })) {
+            Ok((chip_8, error)) => Outcome {
+                rom,
+                display: Some(hash::to_hex(&hash::sha1(&chip_8.get_graphics_buffer()))),
+                error,
+            },
+            Err(panic) => Outcome {
+                rom,
+                display: None,
+                error: Some(format!(
+                    "panic: {}",
+                    panic
+                        .downcast_ref::<&str>()
+                        .map(|message| message.to_string())
+                        .or_else(|| panic.downcast_ref::<String>().cloned())
+                        .unwrap_or_default()
+                )),
+            },
+        };
+        outcomes.push(outcome);
+    }
+    Ok(outcomes)
+}
+
+pub fn to_json(outcomes: &[Outcome]) -> String {
+    let entries: Vec<String> = outcomes
+        .iter()
+        .map(|outcome| {
+            format!(
+                "{{\"rom\": {}, \"display\": {}, \"error\": {}}}",
+                string(Some(&outcome.rom)),
+                string(outcome.display.as_deref()),
+                string(outcome.error.as_deref())
+            )
+        })
+        .collect();
+    format!("{{\"roms\": [\n{}\n]}}\n", entries.join(",\n"))
+}
+
+// "hash name" per ROM like sha1sum's output, with - for a run that panicked
+pub fn to_baseline(outcomes: &[Outcome]) -> String {
+    outcomes
+        .iter()
+        .map(|outcome| {
+            format!(
+                "{} {}\n",
+                outcome.display.as_deref().unwrap_or("-"),
+                outcome.rom
+            )
+        })
+        .collect()
+}
+
+// ROM name to display hash from a baseline written by to_baseline. Blank
+// lines and lines starting with # are skipped.
+pub fn read_baseline(baseline: &str) -> Result<HashMap<String, Option<String>>, String> {
+    let mut hashes = HashMap::new();
+    for (number, line) in baseline.lines().enumerate() {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (hash, rom) = line
+            .split_once(' ')
+            .ok_or_else(|| format!("Line {} of the baseline has no ROM name", number + 1))?;
+        let rom = rom.trim_start();
+        let display = match hash {
+            "-" => None,
+            hash if hash.len() == 40 && hash.chars().all(|c| c.is_ascii_hexdigit()) => {
+                Some(hash.to_ascii_lowercase())
+            }
+            _ => {
+                return Err(format!(
+                    "Line {} of the baseline has an invalid hash",
+                    number + 1
+                ))
+            }
+        };
+        if rom.is_empty() {
+            return Err(format!(
+                "Line {} of the baseline has no ROM name",
+                number + 1
+            ));
+        }
+        hashes.insert(rom.to_string(), display);
+    }
+    Ok(hashes)
+}
+
+fn string(value: Option<&str>) -> String {
+    match value {
+        Some(value) => format!(
+            "\"{}\"",
+            value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n")
+        ),
+        None => "null".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn baseline_round_trips() {
+        let outcomes = [
+            Outcome {
+                rom: "a game.ch8".to_string(),
+                display: Some("0123456789abcdef0123456789abcdef01234567".to_string()),
+                error: None,
+            },
+            Outcome {
+                rom: "crash.ch8".to_string(),
+                display: None,
+                error: Some("panic: oops".to_string()),
+            },
+        ];
+        let baseline = read_baseline(&to_baseline(&outcomes)).unwrap();
+        assert_eq!(baseline.len(), 2);
+        assert_eq!(baseline["a game.ch8"], outcomes[0].display);
+        assert_eq!(baseline["crash.ch8"], None);
+    }
+
+    #[test]
+    fn rejects_bad_baseline_lines() {
+        assert!(read_baseline("# comment\n\n").unwrap().is_empty());
+        assert!(read_baseline("nothash game.ch8\n").is_err());
+        assert!(read_baseline("0123456789abcdef0123456789abcdef01234567\n").is_err());
+    }
+}
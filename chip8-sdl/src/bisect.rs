@@ -0,0 +1,91 @@
+// Runs one ROM under two quirk profiles side by side, feeding both the same
+// replay inputs, to find the first cycle where they stop agreeing. Used by
+// `chip8 bisect` to answer "which quirk does this ROM need?".
+use crate::chip8::{Quirks, State};
+use crate::replay::Replay;
+use crate::statediff;
+
+pub struct Divergence {
+    pub cycle: u64,
+    // the instruction both machines executed when they split
+    pub pc: u16,
+    pub opcode: u16,
+    // the first machine's state, then the second's
+    pub differences: Vec<String>,
+}
+
+// The first cycle after which the machines differ, None if they agree for
+// cycles cycles or halt the same way
+pub fn find_divergence(
+    rom: &[u8],
+    replay: Option<&Replay>,
+    first: Quirks,
+    second: Quirks,
+    cycles: u64,
+) -> Result<Option<Divergence>, String> {
+    let mut machines = [State::new(), State::new()];
+    let seed = machines[0].seed();
+    for (chip_8, quirks) in machines.iter_mut().zip([first, second]) {
+        chip_8.initialize();
+        chip_8.load_buffer(rom);
+        chip_8.set_seed(seed);
+        if let Some(replay) = replay {
+            replay.configure(rom, chip_8)?;
+        }
+        chip_8.set_quirks(quirks);
+    }
+
+    for cycle in 0..cycles {
+        let pc = machines[0].pc();
+        for chip_8 in &mut machines {
+            if let Some(replay) = replay {
+                for input in replay.inputs_at(cycle) {
+                    chip_8.set_key(input.key as usize, input.pressed as u8);
+                }
+            }
+            // a halted machine stays as it is
+            let _ = chip_8.emulate_cycle();
+        }
+        let [a, b] = &machines;
+        if a.save_state() != b.save_state() || a.error() != b.error() {
+            let mut differences = statediff::diff(a, b);
+            if a.error() != b.error() {
+                differences.push(format!(
+                    "Error: {} -> {}",
+                    describe_error(a),
+                    describe_error(b)
+                ));
+            }
+            return Ok(Some(Divergence {
+                cycle,
+                pc,
+                opcode: a.opcode(),
+                differences,
+            }));
+        }
+        if a.error().is_some() {
+            break;
+        }
+    }
+    Ok(None)
+}
+
+// The quirk that changes what opcode does, if one does
+pub fn quirk_for(opcode: u16) -> Option<&'static str> {
+    let index = match opcode & 0xF00F {
+        0x8006 | 0x800E => 0,
+        0xF005 if matches!(opcode & 0xFF, 0x55 | 0x65) => 1,
+        _ if opcode & 0xF000 == 0xB000 => 2,
+        0x8001..=0x8003 => 3,
+        _ if opcode & 0xF000 == 0xD000 => 4,
+        _ => return None,
+    };
+    Some(Quirks::NAMES[index])
+}
+
+fn describe_error(chip_8: &State) -> String {
+    match chip_8.error() {
+        Some(error) => error.to_string(),
+        None => "none".to_string(),
+    }
+}
@@ -0,0 +1,92 @@
+// --cheats FILE, memory patches for freezing lives, score and the like. One
+// per line, # starts a comment:
+//
+//   2F0=03            written before every frame
+//   once 300=FF,00    written once, when the ROM starts
+//
+// Addresses and values are hex, several values go to consecutive addresses.
+use crate::chip8::State;
+use std::fs;
+
+#[derive(Debug)]
+pub struct Cheat {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    // false to keep writing it every frame
+    pub once: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct Cheats {
+    cheats: Vec<Cheat>,
+}
+
+impl Cheats {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut cheats = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let cheat = parse_cheat(line).map_err(|op| format!("line {}: {}", index + 1, op))?;
+            cheats.push(cheat);
+        }
+        Ok(Self { cheats })
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|op| format!("{}: {}", path, op))?;
+        Self::parse(&text).map_err(|op| format!("{}: {}", path, op))
+    }
+
+    // call once the ROM is loaded
+    pub fn apply_once(&self, chip_8: &mut State) {
+        for cheat in self.cheats.iter().filter(|cheat| cheat.once) {
+            chip_8.apply_patch(cheat.address, &cheat.bytes);
+        }
+    }
+
+    // call before every frame
+    pub fn apply_frame(&self, chip_8: &mut State) {
+        for cheat in self.cheats.iter().filter(|cheat| !cheat.once) {
+            chip_8.apply_patch(cheat.address, &cheat.bytes);
+        }
+    }
+}
+
+// [once] ADDRESS=VALUE[,VALUE...]
+fn parse_cheat(line: &str) -> Result<Cheat, String> {
+    let (once, patch) = match line.strip_prefix("once ") {
+        Some(patch) => (true, patch),
+        None => (false, line),
+    };
+    let (address, values) = patch
+        .split_once('=')
+        .ok_or_else(|| format!("expected address=value, not {}", line))?;
+    let address = hex(address.trim()).and_then(|address| {
+        u16::try_from(address).map_err(|_| format!("address {:X} is too big", address))
+    })?;
+    let bytes = values
+        .split(',')
+        .map(|value| {
+            hex(value.trim()).and_then(|value| {
+                u8::try_from(value).map_err(|_| format!("value {:X} doesn't fit a byte", value))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Cheat {
+        address,
+        bytes,
+        once,
+    })
+}
+
+// with or without 0x in front
+fn hex(text: &str) -> Result<u32, String> {
+    let digits = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+    u32::from_str_radix(digits, 16).map_err(|_| format!("invalid hex number {}", text))
+}
@@ -0,0 +1,245 @@
+// The user's own settings in ~/.config/chip8/config.toml, written back by
+// the emulator when they change. The same TOML subset as the per-game
+// sidecars, in [sections]:
+//
+//   [settings]
+//   save_on_exit = true       write changes made while running back here
+//   palette = ["#000000", "#FFFFFF"]
+//   speed = 2
+//   mute = false
+//   layout = "azerty"
+//   keymap = "5=Up,8=Down"   overrides on top of the layout, like --keymap
+//
+//   [macros]
+//   F9 = "frame 1: press 5; frame 4: release 5"
+//
+// The settings are the starting point a game's own and the command line's
+// override. A macro is an --input-script counting frames from when its
+// hotkey is pressed. They're recorded in the window by holding Shift with
+// the hotkey.
+use crate::inputscript::InputScript;
+use crate::sidecar;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Debug, Default)]
+pub struct Config {
+    pub save_on_exit: bool,
+    pub palette: Option<[[u8; 3]; 2]>,
+    // a speed multiplier
+    pub speed: Option<u32>,
+    pub mute: Option<bool>,
+    pub layout: Option<String>,
+    // key overrides like "5=Up,8=Down"
+    pub keymap: Option<String>,
+    // hotkey names and what they play
+    pub macros: Vec<(String, InputScript)>,
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+        let mut section = String::new();
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: String| format!("line {}: {}", index + 1, message);
+            let line = sidecar::strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line
+                .strip_prefix('[')
+                .and_then(|line| line.strip_suffix(']'))
+            {
+                section = name.trim().to_string();
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected key = value".to_string()))?;
+            let (key, value) = (key.trim(), value.trim());
+            let boolean = |value: &str| match value {
+                "true" => Ok(true),
+                "false" => Ok(false),
+                _ => Err(error(format!("{} takes true or false", key))),
+            };
+            match (section.as_str(), key) {
+                ("settings", "save_on_exit") => config.save_on_exit = boolean(value)?,
+                ("settings", "palette") => {
+                    config.palette = Some(sidecar::palette_value(value).map_err(error)?)
+                }
+                ("settings", "speed") => {
+                    config.speed = Some(
+                        value
+                            .parse()
+                            .map_err(|_| error(format!("invalid speed {}", value)))?,
+                    )
+                }
+                ("settings", "mute") => config.mute = Some(boolean(value)?),
+                ("settings", "layout") => {
+                    config.layout = Some(sidecar::string(value).map_err(error)?)
+                }
+                ("settings", "keymap") => {
+                    config.keymap = Some(sidecar::string(value).map_err(error)?)
+                }
+                ("macros", _) => {
+                    let script = InputScript::parse(&sidecar::string(value).map_err(error)?)
+                        .map_err(|op| error(format!("macro {}: {}", key, op)))?;
+                    config.macros.push((key.to_string(), script));
+                }
+                _ => return Err(error(format!("unknown setting {}", key))),
+            }
+        }
+        Ok(config)
+    }
+
+    // The config file's settings, the defaults if there isn't one
+    pub fn load() -> Result<Self, String> {
+        let path = config_path()?;
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+        let text = fs::read_to_string(&path).map_err(|op| format!("{}: {}", path.display(), op))?;
+        Self::parse(&text).map_err(|op| format!("{}: {}", path.display(), op))
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = config_path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|op| op.to_string())?;
+        }
+        fs::write(&path, self.to_text()).map_err(|op| format!("{}: {}", path.display(), op))
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        let mut settings = Vec::new();
+        if self.save_on_exit {
+            settings.push("save_on_exit = true".to_string());
+        }
+        if let Some(palette) = &self.palette {
+            settings.push(format!("palette = {}", sidecar::palette_text(palette)));
+        }
+        if let Some(speed) = self.speed {
+            settings.push(format!("speed = {}", speed));
+        }
+        if let Some(mute) = self.mute {
+            settings.push(format!("mute = {}", mute));
+        }
+        if let Some(layout) = &self.layout {
+            settings.push(format!("layout = \"{}\"", layout));
+        }
+        if let Some(keymap) = &self.keymap {
+            settings.push(format!("keymap = \"{}\"", keymap));
+        }
+        if !settings.is_empty() {
+            text.push_str("[settings]\n");
+            for line in settings {
+                text.push_str(&line);
+                text.push('\n');
+            }
+            text.push('\n');
+        }
+        if !self.macros.is_empty() {
+            text.push_str("[macros]\n");
+            for (hotkey, script) in &self.macros {
+                text.push_str(&format!("{} = \"{}\"\n", hotkey, script.to_text()));
+            }
+        }
+        text
+    }
+
+    pub fn macro_for(&self, hotkey: &str) -> Option<&InputScript> {
+        self.macros
+            .iter()
+            .find(|(name, _)| name == hotkey)
+            .map(|(_, script)| script)
+    }
+
+    // Binds script to hotkey, replacing what it played before
+    pub fn set_macro(&mut self, hotkey: &str, script: InputScript) {
+        self.macros.retain(|(name, _)| name != hotkey);
+        self.macros.push((hotkey.to_string(), script));
+    }
+}
+
+// e.g. ~/.config/chip8/config.toml
+fn config_path() -> Result<PathBuf, String> {
+    let directory = if let Some(path) = env::var_os("XDG_CONFIG_HOME") {
+        PathBuf::from(path).join("chip8")
+    } else if let Some(path) = env::var_os("APPDATA") {
+        PathBuf::from(path).join("chip8")
+    } else {
+        env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join(".config/chip8"))
+            .ok_or("No home directory to keep the config in")?
+    };
+    Ok(directory.join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let text = concat!(
+            "# mine\n",
+            "[settings]\n",
+            "save_on_exit = true\n",
+            "palette = [\"#002200\", \"#33FF66\"]  # green\n",
+            "speed = 2\n",
+            "mute = false\n",
+            "layout = \"azerty\"\n",
+            "keymap = \"5=Up,8=Down\"\n",
+            "\n",
+            "[macros]\n",
+            "F9 = \"frame 1: press 5; frame 4: release 5\"\n",
+        );
+        let config = Config::parse(text).unwrap();
+        assert!(config.save_on_exit);
+        assert_eq!(
+            config.palette,
+            Some([[0x00, 0x22, 0x00], [0x33, 0xFF, 0x66]])
+        );
+        assert_eq!((config.speed, config.mute), (Some(2), Some(false)));
+        assert_eq!(config.layout.as_deref(), Some("azerty"));
+        assert_eq!(config.keymap.as_deref(), Some("5=Up,8=Down"));
+        assert_eq!(config.macro_for("F9").unwrap().length(), 4);
+        assert!(config.macro_for("F11").is_none());
+
+        let again = Config::parse(&config.to_text()).unwrap();
+        assert_eq!(again.to_text(), config.to_text());
+        assert_eq!(Config::default().to_text(), "");
+    }
+
+    #[test]
+    fn rejects_malformed_settings() {
+        let error = |text: &str| Config::parse(text).err().unwrap();
+        assert_eq!(error("[settings]\nspeed"), "line 2: expected key = value");
+        assert_eq!(
+            error("[settings]\n\nspeed = fast"),
+            "line 3: invalid speed fast"
+        );
+        assert_eq!(
+            error("[settings]\nmute = yes"),
+            "line 2: mute takes true or false"
+        );
+        assert_eq!(
+            error("[settings]\nlayout = azerty"),
+            "line 2: expected a quoted string, not azerty"
+        );
+        assert_eq!(error("speed = 2"), "line 1: unknown setting speed");
+        assert!(Config::parse("[settings]\npalette = [\"#000000\"]").is_err());
+        assert!(Config::parse("[macros]\nF9 = \"sometime: press 5\"").is_err());
+    }
+
+    #[test]
+    fn setting_a_macro_replaces_the_old_one() {
+        let mut config = Config::default();
+        config.set_macro("F9", InputScript::parse("frame 1: press 5").unwrap());
+        config.set_macro("F9", InputScript::parse("frame 9: press 5").unwrap());
+        assert_eq!(config.macros.len(), 1);
+        assert_eq!(config.macro_for("F9").unwrap().length(), 9);
+    }
+}
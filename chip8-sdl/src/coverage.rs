@@ -0,0 +1,41 @@
+use crate::chip8::{COVERAGE_EXECUTED, COVERAGE_SPRITE};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+const ROW: usize = 64;
+
+// Writes a text map of the program area, one row of 64 bytes per line:
+// 'X' executed, 'S' read as sprite data, '#' both, '.' never touched
+pub fn export(path: &str, coverage: &[u8]) -> Result<(), String> {
+    let mut out = BufWriter::new(File::create(path).map_err(|op| op.to_string())?);
+    let end = coverage
+        .iter()
+        .rposition(|flags| *flags != 0)
+        .map_or(0x200, |last| (last / ROW + 1) * ROW)
+        .max(0x200);
+
+    let executed = count(&coverage[0x200..end], COVERAGE_EXECUTED);
+    let sprite = count(&coverage[0x200..end], COVERAGE_SPRITE);
+    writeln!(out, "executed bytes: {}", executed).map_err(|op| op.to_string())?;
+    writeln!(out, "sprite bytes:   {}", sprite).map_err(|op| op.to_string())?;
+
+    for start in (0x200..end).step_by(ROW) {
+        let row: String = coverage[start..start + ROW]
+            .iter()
+            .map(
+                |flags| match (flags & COVERAGE_EXECUTED, flags & COVERAGE_SPRITE) {
+                    (0, 0) => '.',
+                    (_, 0) => 'X',
+                    (0, _) => 'S',
+                    _ => '#',
+                },
+            )
+            .collect();
+        writeln!(out, "{:#05X} {}", start, row).map_err(|op| op.to_string())?;
+    }
+    out.flush().map_err(|op| op.to_string())
+}
+
+fn count(coverage: &[u8], flag: u8) -> usize {
+    coverage.iter().filter(|flags| **flags & flag != 0).count()
+}
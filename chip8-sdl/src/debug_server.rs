@@ -0,0 +1,526 @@
+// --debug-server ADDRESS, the debugger over a WebSocket for browser and editor
+// UIs. One client is served at a time, a new one takes over from the last.
+// Requests are JSON objects with a cmd and an optional id that's copied into
+// the reply:
+//
+//   {"cmd":"pause"} {"cmd":"continue"} {"cmd":"step"}
+//   {"cmd":"regs"}                            pc, opcode, v, i, dt, st and stack
+//   {"cmd":"memory","address":512,"length":16}
+//   {"cmd":"break","address":"main"}          an address or a label
+//   {"cmd":"delete","address":512}
+//   {"cmd":"breakpoints"}
+//
+// Errors are replied as {"error":"..."}. The server also sends
+// {"event":"stopped","reason":"breakpoint","pc":512} whenever execution stops
+// and {"event":"frame","pixels":"..."} whenever the display changes, the
+// pixels as hex with one bit per pixel, 8 bytes a row, leftmost pixel in the
+// high bit.
+use crate::chip8::State;
+use crate::debugger::Command;
+use crate::hash;
+use crate::symbols::Symbols;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeSet;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+// RFC 6455 appends this to the client's key in the handshake
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+// the handshake's request line and headers together
+const MAX_HEADER: u64 = 0x2000;
+// requests are small, anything bigger isn't from a debugger UI
+const MAX_MESSAGE: u64 = 0x10000;
+// how long a client may take to send each part of its handshake
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+enum Incoming {
+    Connected(TcpStream),
+    Text(String),
+    Ping(Vec<u8>),
+    Closed,
+}
+
+pub struct DebugServer {
+    // what each connection, numbered as they arrive, sent
+    incoming: Receiver<(usize, Incoming)>,
+    client: Option<(usize, TcpStream)>,
+    symbols: Symbols,
+    breakpoints: BTreeSet<u16>,
+    // skips the next check, so resuming from a breakpoint doesn't stop right away
+    resuming: bool,
+    // the display the client was last sent
+    last_frame: Vec<u8>,
+}
+
+impl DebugServer {
+    pub fn bind(address: &str, symbols: Symbols) -> Result<Self, String> {
+        let listener = TcpListener::bind(address)
+            .map_err(|op| format!("Couldn't listen on {}: {}", address, op))?;
+        let (sender, incoming) = mpsc::channel();
+        thread::spawn(move || {
+            for (id, stream) in listener.incoming().enumerate() {
+                let Ok(stream) = stream else { continue };
+                // a thread each, so a client that's slow to shake hands
+                // doesn't hold up the next
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    if let Err(error) = serve(stream, id, &sender) {
+                        warn!("Debug client: {}", error);
+                    }
+                    let _ = sender.send((id, Incoming::Closed));
+                });
+            }
+        });
+        info!("Debug server listening on ws://{}", address);
+        Ok(Self {
+            incoming,
+            client: None,
+            symbols,
+            breakpoints: BTreeSet::new(),
+            resuming: false,
+            last_frame: Vec::new(),
+        })
+    }
+
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    // call when execution resumes without a request from the client
+    pub fn resume(&mut self) {
+        self.resuming = true;
+    }
+
+    // call before each cycle, true when execution should stop
+    pub fn should_break(&mut self, state: &State) -> bool {
+        !std::mem::take(&mut self.resuming) && self.breakpoints.contains(&state.pc())
+    }
+
+    // Tells the client execution stopped and where
+    pub fn stopped(&mut self, state: &State, reason: &str) {
+        self.send(&json!({"event": "stopped", "reason": reason, "pc": state.pc()}));
+    }
+
+    // call once a frame, the client is only sent displays that changed
+    pub fn send_frame(&mut self, screen: &[u8]) {
+        if self.client.is_none() || self.last_frame == screen {
+            return;
+        }
+        self.last_frame = screen.to_vec();
+        let packed: Vec<u8> = screen
+            .chunks(8)
+            .map(|pixels| {
+                pixels
+                    .iter()
+                    .fold(0, |byte, pixel| byte << 1 | (*pixel != 0) as u8)
+            })
+            .collect();
+        self.send(&json!({"event": "frame", "pixels": hash::to_hex(&packed)}));
+    }
+
+    // Answers the client's requests since the last call, up to the first one
+    // that needs the frontend
+    pub fn poll(&mut self, state: &mut State) -> Option<Command> {
+        while let Ok((id, incoming)) = self.incoming.try_recv() {
+            // anything else from a client that was taken over from is dropped
+            let current = self
+                .client
+                .as_ref()
+                .is_some_and(|(client, _)| *client == id);
+            match incoming {
+                Incoming::Connected(stream) => {
+                    info!("Debug client connected");
+                    if let Some((_, previous)) = self.client.replace((id, stream)) {
+                        info!("Debug client replaced by the new one");
+                        let _ = previous.shutdown(Shutdown::Both);
+                    }
+                    self.last_frame.clear();
+                }
+                Incoming::Closed => {
+                    if current {
+                        self.client = None;
+                        info!("Debug client disconnected");
+                    }
+                }
+                _ if !current => {}
+                Incoming::Ping(payload) => self.write_frame(0xA, &payload),
+                Incoming::Text(request) => {
+                    let request = serde_json::from_str::<Value>(&request)
+                        .map_err(|op| format!("Invalid JSON: {}", op));
+                    let id = request
+                        .as_ref()
+                        .ok()
+                        .and_then(|request| request.get("id"))
+                        .and_then(Value::as_u64);
+                    let (mut reply, command) = match request
+                        .and_then(|request| self.execute(&request, state))
+                    {
+                        Ok((reply, command)) => (reply, command),
+                        Err(error) => (Map::from_iter([("error".to_string(), json!(error))]), None),
+                    };
+                    if let Some(id) = id {
+                        reply.insert("id".to_string(), json!(id));
+                    }
+                    self.send(&Value::Object(reply));
+                    if let Some(command) = command {
+                        if command != Command::Pause {
+                            self.resuming = true;
+                        }
+                        return Some(command);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    // the reply's fields, and what the frontend has to do
+    fn execute(
+        &mut self,
+        request: &Value,
+        state: &mut State,
+    ) -> Result<(Map<String, Value>, Option<Command>), String> {
+        let cmd = request
+            .get("cmd")
+            .and_then(Value::as_str)
+            .ok_or("Missing cmd")?;
+        let command = match cmd {
+            "pause" => Some(Command::Pause),
+            "continue" => Some(Command::Continue),
+            "step" => Some(Command::Step),
+            "regs" => {
+                let pc = state.pc();
+                let opcode = ((state.read_memory(pc) as u16) << 8)
+                    | state.read_memory(pc.wrapping_add(1)) as u16;
+                return Ok((
+                    fields(json!({
+                        "pc": pc,
+                        "opcode": opcode,
+                        "v": state.registers(),
+                        "i": state.i(),
+                        "dt": state.delay_timer(),
+                        "st": state.sound_timer(),
+                        "stack": state.stack(),
+                    })),
+                    None,
+                ));
+            }
+            "memory" => {
+                let address = self.location(request, state)?;
+                let length = request
+                    .get("length")
+                    .ok_or("Missing length")?
+                    .as_u64()
+                    .ok_or("Invalid length")?
+                    .min((state.memory_size() - address as usize) as u64);
+                let bytes: Vec<u8> = (address as usize..address as usize + length as usize)
+                    .map(|address| state.read_memory(address as u16))
+                    .collect();
+                return Ok((fields(json!({"address": address, "bytes": bytes})), None));
+            }
+            "break" => {
+                self.breakpoints.insert(self.location(request, state)?);
+                None
+            }
+            "delete" => {
+                let address = self.location(request, state)?;
+                if !self.breakpoints.remove(&address) {
+                    return Err(format!("No breakpoint at {:#05X}", address));
+                }
+                None
+            }
+            "breakpoints" => {
+                return Ok((fields(json!({"breakpoints": self.breakpoints})), None));
+            }
+            command => return Err(format!("Unknown cmd {}", command)),
+        };
+        Ok((fields(json!({"ok": true})), command))
+    }
+
+    // the request's address, a number or a label inside state's memory
+    fn location(&self, request: &Value, state: &State) -> Result<u16, String> {
+        let value = request.get("address").ok_or("Missing address")?;
+        let address = match value {
+            Value::Number(number) => number.as_u64().and_then(|number| number.try_into().ok()),
+            Value::String(text) => match text.strip_prefix("0x").or(text.strip_prefix("0X")) {
+                Some(hex) => u16::from_str_radix(hex, 16).ok(),
+                None => text.parse().ok().or_else(|| self.symbols.address(text)),
+            },
+            _ => None,
+        };
+        address
+            .filter(|address| (*address as usize) < state.memory_size())
+            .ok_or_else(|| format!("Unknown location {}", value))
+    }
+
+    fn send(&mut self, message: &Value) {
+        self.write_frame(0x1, message.to_string().as_bytes());
+    }
+
+    // a client that can't be written to is dropped, the reader notices too
+    fn write_frame(&mut self, opcode: u8, payload: &[u8]) {
+        let Some((_, client)) = &mut self.client else {
+            return;
+        };
+        let mut frame = vec![0x80 | opcode];
+        match payload.len() {
+            length @ 0..=125 => frame.push(length as u8),
+            length @ 126..=0xFFFF => {
+                frame.push(126);
+                frame.extend_from_slice(&(length as u16).to_be_bytes());
+            }
+            length => {
+                frame.push(127);
+                frame.extend_from_slice(&(length as u64).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(payload);
+        if client.write_all(&frame).is_err() {
+            self.client = None;
+        }
+    }
+}
+
+// Does the handshake, then passes the client's messages on until it leaves
+fn serve(stream: TcpStream, id: usize, sender: &Sender<(usize, Incoming)>) -> Result<(), String> {
+    stream
+        .set_read_timeout(Some(READ_TIMEOUT))
+        .map_err(|op| op.to_string())?;
+    // the limit is the handshake's until it's done
+    let mut reader = BufReader::new(
+        stream
+            .try_clone()
+            .map_err(|op| op.to_string())?
+            .take(MAX_HEADER),
+    );
+    let mut key = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).map_err(|op| op.to_string())? == 0 {
+            if reader.get_ref().limit() == 0 {
+                return Err(format!("Handshake over {} bytes", MAX_HEADER));
+            }
+            return Err("Closed during the handshake".to_string());
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value.trim().to_string());
+            }
+        }
+    }
+    let key = key.ok_or("Not a WebSocket request")?;
+    // a debugger may sit idle for as long as it likes once connected
+    reader.get_mut().set_limit(u64::MAX);
+    stream.set_read_timeout(None).map_err(|op| op.to_string())?;
+    let accept = base64(&hash::sha1(format!("{}{}", key, HANDSHAKE_GUID).as_bytes()));
+    let mut writer = stream;
+    write!(
+        writer,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        accept
+    )
+    .map_err(|op| op.to_string())?;
+    if sender.send((id, Incoming::Connected(writer))).is_err() {
+        return Ok(());
+    }
+
+    // fragments of a message that isn't finished yet
+    let mut message = Vec::new();
+    loop {
+        let mut header = [0u8; 2];
+        if reader.read_exact(&mut header).is_err() {
+            return Ok(());
+        }
+        let last = header[0] & 0x80 != 0;
+        let opcode = header[0] & 0x0F;
+        let mut length = (header[1] & 0x7F) as u64;
+        if length == 126 {
+            let mut bytes = [0u8; 2];
+            reader.read_exact(&mut bytes).map_err(|op| op.to_string())?;
+            length = u16::from_be_bytes(bytes) as u64;
+        } else if length == 127 {
+            let mut bytes = [0u8; 8];
+            reader.read_exact(&mut bytes).map_err(|op| op.to_string())?;
+            length = u64::from_be_bytes(bytes);
+        }
+        // fragments count towards their message's size
+        if message.len() as u64 + length > MAX_MESSAGE {
+            return Err(format!("Message of over {} bytes is too long", MAX_MESSAGE));
+        }
+        let mut mask = [0u8; 4];
+        if header[1] & 0x80 != 0 {
+            reader.read_exact(&mut mask).map_err(|op| op.to_string())?;
+        }
+        let mut payload = vec![0u8; length as usize];
+        reader
+            .read_exact(&mut payload)
+            .map_err(|op| op.to_string())?;
+        for (index, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[index % 4];
+        }
+        let incoming = match opcode {
+            0x0 | 0x1 => {
+                message.extend_from_slice(&payload);
+                if !last {
+                    continue;
+                }
+                Incoming::Text(String::from_utf8_lossy(&std::mem::take(&mut message)).into_owned())
+            }
+            0x8 => return Ok(()),
+            0x9 => Incoming::Ping(payload),
+            _ => continue,
+        };
+        if sender.send((id, incoming)).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+// the fields of a reply built with json!
+fn fields(reply: Value) -> Map<String, Value> {
+    match reply {
+        Value::Object(fields) => fields,
+        _ => Map::new(),
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::new();
+    for chunk in bytes.chunks(3) {
+        let value = chunk.iter().enumerate().fold(0u32, |value, (index, byte)| {
+            value | (*byte as u32) << (16 - index * 8)
+        });
+        for index in 0..4 {
+            if index <= chunk.len() {
+                text.push(ALPHABET[(value >> (18 - index * 6)) as usize & 0x3F] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::XO_CHIP_MEMORY_SIZE;
+
+    // serves one connection that's sent request, returning serve's result
+    fn serve_request(request: Vec<u8>) -> (Result<(), String>, Receiver<(usize, Incoming)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        // written from a thread, the server only reads as it goes
+        let writer = thread::spawn(move || {
+            let _ = client.write_all(&request);
+            client
+        });
+        let (sender, incoming) = mpsc::channel();
+        let result = serve(stream, 0, &sender);
+        drop(writer.join());
+        (result, incoming)
+    }
+
+    const HANDSHAKE: &[u8] =
+        b"GET / HTTP/1.1\r\nSec-WebSocket-Key: x3JJHMbDL1EzLkh9GBhXDw==\r\n\r\n";
+
+    // an unmasked frame, 127 and a 64 bit length whatever its size
+    fn frame(first_byte: u8, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![first_byte, 127];
+        frame.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    #[test]
+    fn passes_messages_on() {
+        let mut request = HANDSHAKE.to_vec();
+        request.extend(frame(0x01, b"{\"cmd\":"));
+        request.extend(frame(0x80, b"\"regs\"}"));
+        request.extend(frame(0x88, &[]));
+        let (result, incoming) = serve_request(request);
+        assert!(result.is_ok());
+        assert!(matches!(incoming.recv(), Ok((0, Incoming::Connected(_)))));
+        match incoming.recv() {
+            Ok((0, Incoming::Text(text))) => assert_eq!(text, "{\"cmd\":\"regs\"}"),
+            _ => panic!("expected the message"),
+        }
+    }
+
+    #[test]
+    fn rejects_long_handshakes() {
+        let mut request = b"GET / HTTP/1.1\r\nX-Padding: ".to_vec();
+        request.extend(std::iter::repeat_n(b'a', MAX_HEADER as usize));
+        request.extend(b"\r\n\r\n");
+        let (result, incoming) = serve_request(request);
+        assert!(result.unwrap_err().contains("Handshake over"));
+        assert!(incoming.try_recv().is_err());
+    }
+
+    #[test]
+    fn gives_up_on_silent_clients() {
+        let (result, incoming) = serve_request(b"GET / HTTP/1.1\r\n".to_vec());
+        assert!(result.is_err());
+        assert!(incoming.try_recv().is_err());
+    }
+
+    #[test]
+    fn limits_fragmented_messages() {
+        let half = vec![b' '; MAX_MESSAGE as usize / 2 + 1];
+        let mut request = HANDSHAKE.to_vec();
+        request.extend(frame(0x01, &half));
+        request.extend(frame(0x00, &half));
+        let (result, _) = serve_request(request);
+        assert!(result.unwrap_err().contains("too long"));
+    }
+
+    fn server() -> DebugServer {
+        DebugServer::bind("127.0.0.1:0", Symbols::parse("0x0300 main\n").unwrap()).unwrap()
+    }
+
+    fn execute(server: &mut DebugServer, request: &str, state: &mut State) -> Value {
+        let request = serde_json::from_str(request).unwrap();
+        let (reply, _) = server.execute(&request, state).unwrap();
+        Value::Object(reply)
+    }
+
+    #[test]
+    fn regs_reads_the_opcode_across_the_top_of_memory() {
+        let mut state = State::new();
+        state.set_memory_size(XO_CHIP_MEMORY_SIZE).unwrap();
+        state.write_memory(0xFFFF, 0x12);
+        state.write_memory(0x0000, 0x34);
+        state.set_pc(0xFFFF);
+        let reply = execute(&mut server(), r#"{"cmd": "regs"}"#, &mut state);
+        assert_eq!(reply["opcode"], 0x1234);
+    }
+
+    #[test]
+    fn addresses_may_be_numbers_hex_or_labels() {
+        let mut server = server();
+        let mut state = State::new();
+        for address in ["512", r#""0x200""#, r#""main""#] {
+            let request = format!(r#"{{"cmd": "break", "address": {}}}"#, address);
+            assert_eq!(execute(&mut server, &request, &mut state)["ok"], true);
+        }
+        let reply = execute(&mut server, r#"{"cmd":"breakpoints"}"#, &mut state);
+        assert_eq!(reply["breakpoints"], json!([0x200, 0x300]));
+    }
+
+    #[test]
+    fn memory_stops_at_the_end() {
+        let mut state = State::new();
+        let request = r#"{"cmd": "memory", "address": 4094, "length": 16}"#;
+        let reply = execute(&mut server(), request, &mut state);
+        assert_eq!(reply["bytes"].as_array().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,612 @@
+// Console debugger for --debug. Commands are read from standard input while
+// the window keeps running; the machine stops at breakpoints and caught events,
+// and while it's stopped the console can inspect and step it.
+use crate::asm::ORIGIN;
+use crate::chip8::{State, DISPLAY_HEIGHT, DISPLAY_WIDTH, XO_CHIP_MEMORY_SIZE};
+use crate::disasm;
+use crate::symbols::Symbols;
+use std::collections::{BTreeSet, VecDeque};
+use std::io::BufRead;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+// how many instructions stepback can undo
+const HISTORY_LENGTH: usize = 10_000;
+
+const HELP: &str = "\
+break LOCATION    stop when execution reaches LOCATION, an address or a label
+delete LOCATION   remove a breakpoint
+catch EVENT       stop before every EVENT: draw, sound, key-wait or self-modify
+uncatch EVENT     stop catching EVENT
+breakpoints       list the breakpoints and caught events
+continue          resume execution
+pause             stop execution
+step              execute one instruction
+stepback [N]      undo the last N instructions, 1 by default
+next              execute one instruction, running a whole subroutine it calls
+finish            run until the current subroutine returns
+until LOCATION    run at full speed until execution reaches LOCATION
+run N             run N instructions at full speed
+regs              show the registers
+peek LOCATION [N] show N bytes of memory from LOCATION, 16 by default
+poke LOCATION B.. write bytes to memory from LOCATION
+setreg REG VALUE  set V0-VF, I, DT or ST
+setpc LOCATION    continue execution from LOCATION
+backtrace         show the subroutine calls that led here
+help              show this list";
+
+// Events that can stop execution wherever they happen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Event {
+    // DXYN
+    Draw,
+    // FX18
+    Sound,
+    // FX0A, when it starts waiting
+    KeyWait,
+    // FX33 or FX55 writing over the program's instructions
+    SelfModify,
+}
+
+const EVENTS: [(&str, Event); 4] = [
+    ("draw", Event::Draw),
+    ("sound", Event::Sound),
+    ("key-wait", Event::KeyWait),
+    ("self-modify", Event::SelfModify),
+];
+
+// Undoes one instruction: what it could change, as it was before. Memory
+// and the display are only kept for the instructions that write them, so an
+// instruction costs the same to record whatever the memory size.
+struct Undo {
+    // V0-VF, I, pc and the opcode, laid out as in the saved state
+    registers: Vec<u8>,
+    // address and value of every byte the instruction can write
+    memory: Vec<(usize, u8)>,
+    display: Option<Vec<u8>>,
+    timers: [u8; 2],
+    stack: Vec<u16>,
+}
+
+impl Undo {
+    // call before state executes its next instruction
+    fn new(state: &State) -> Self {
+        let opcode = opcode_at(state, state.pc());
+        let mut registers = state.registers().to_vec();
+        registers.extend_from_slice(&state.i().to_le_bytes());
+        registers.extend_from_slice(&state.pc().to_le_bytes());
+        registers.extend_from_slice(&state.opcode().to_le_bytes());
+        let size = state.memory_size();
+        let from_i = |length: usize| {
+            (0..length)
+                .map(|offset| (state.i() as usize + offset) % size)
+                .collect()
+        };
+        let written: Vec<usize> = match opcode & 0xF0FF {
+            _ if opcode == 0x00E0 || opcode == 0x00EE => Vec::new(),
+            // 1802 machine code can write anywhere
+            _ if opcode & 0xF000 == 0x0000 => (0..size).collect(),
+            0xF033 => from_i(3),
+            0xF055 => from_i(((opcode & 0x0F00) >> 8) as usize + 1),
+            _ => Vec::new(),
+        };
+        let draws = opcode & 0xF000 == 0xD000 || opcode & 0xF000 == 0x0000 && opcode != 0x00EE;
+        Self {
+            registers,
+            memory: written
+                .into_iter()
+                .map(|address| (address, state.read_memory(address as u16)))
+                .collect(),
+            display: draws.then(|| state.get_graphics_buffer()),
+            timers: [state.delay_timer(), state.sound_timer()],
+            stack: state.stack().to_vec(),
+        }
+    }
+
+    // Rewinds data, saved by State::save_state with memory_size bytes of memory
+    fn undo(&self, data: &mut Vec<u8>, memory_size: usize) {
+        for (address, value) in &self.memory {
+            data[*address] = *value;
+        }
+        let display = memory_size + self.registers.len();
+        data[memory_size..display].copy_from_slice(&self.registers);
+        if let Some(pixels) = &self.display {
+            data[display..display + pixels.len()].copy_from_slice(pixels);
+        }
+        data.truncate(display + DISPLAY_WIDTH * DISPLAY_HEIGHT);
+        data.extend_from_slice(&self.timers);
+        data.extend_from_slice(&(self.stack.len() as u16).to_le_bytes());
+        for address in &self.stack {
+            data.extend_from_slice(&address.to_le_bytes());
+        }
+    }
+}
+
+// What the frontend has to do for a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Continue,
+    // continue without the frame rate limiting the speed
+    Run,
+    Pause,
+    Step,
+}
+
+pub struct Debugger {
+    symbols: Symbols,
+    breakpoints: BTreeSet<u16>,
+    catches: BTreeSet<Event>,
+    // true for every address an instruction of the program covers, as far
+    // as the largest memory goes
+    code: Vec<bool>,
+    lines: Receiver<String>,
+    // skips the next check, so resuming from a breakpoint doesn't stop right away
+    resuming: bool,
+    // where the last cycle started, FX0A repeats itself while it waits
+    last_pc: Option<u16>,
+    // next and finish run until the stack is back down to this depth
+    return_depth: Option<usize>,
+    // until and run stop here, breakpoints still stop them earlier
+    until: Option<u16>,
+    remaining: Option<u64>,
+    // how to undo the instruction about to execute
+    pending: Option<Undo>,
+    history: VecDeque<Undo>,
+}
+
+impl Debugger {
+    pub fn new(rom: &[u8], symbols: Symbols) -> Self {
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else { return };
+                if sender.send(line).is_err() {
+                    return;
+                }
+            }
+        });
+        println!("Debugger ready, type help for a list of commands");
+        let mut debugger = Self {
+            symbols: Symbols::default(),
+            breakpoints: BTreeSet::new(),
+            catches: BTreeSet::new(),
+            code: Vec::new(),
+            lines,
+            resuming: false,
+            last_pc: None,
+            return_depth: None,
+            until: None,
+            remaining: None,
+            pending: None,
+            history: VecDeque::new(),
+        };
+        debugger.set_program(rom, symbols);
+        debugger
+    }
+
+    // call when a different program is loaded, breakpoints stay where they are
+    pub fn set_program(&mut self, rom: &[u8], symbols: Symbols) {
+        self.symbols = symbols;
+        self.history.clear();
+        self.code = vec![false; XO_CHIP_MEMORY_SIZE];
+        for (offset, _) in disasm::find_code(rom, ORIGIN)
+            .iter()
+            .enumerate()
+            .filter(|(_, code)| **code)
+        {
+            let address = ORIGIN as usize + offset;
+            self.code[address % XO_CHIP_MEMORY_SIZE] = true;
+            self.code[(address + 1) % XO_CHIP_MEMORY_SIZE] = true;
+        }
+    }
+
+    pub fn has_breakpoint(&self, address: u16) -> bool {
+        self.breakpoints.contains(&address)
+    }
+
+    // true if there's a breakpoint at address now
+    pub fn toggle_breakpoint(&mut self, address: u16) -> bool {
+        if self.breakpoints.remove(&address) {
+            return false;
+        }
+        self.breakpoints.insert(address);
+        true
+    }
+
+    // call when execution resumes without a debugger command
+    pub fn resume(&mut self) {
+        self.resuming = true;
+    }
+
+    // call before each cycle, true when execution should stop
+    pub fn should_break(&mut self, state: &State) -> bool {
+        self.pending = Some(Undo::new(state));
+        let pc = state.pc();
+        let repeated = self.last_pc.replace(pc) == Some(pc);
+        let finished_run = match &mut self.remaining {
+            Some(0) => true,
+            Some(remaining) => {
+                *remaining -= 1;
+                false
+            }
+            None => false,
+        };
+        if std::mem::take(&mut self.resuming) {
+            return false;
+        }
+        if self.breakpoints.contains(&pc) {
+            println!("Breakpoint");
+        } else if self
+            .return_depth
+            .is_some_and(|depth| state.stack().len() <= depth)
+        {
+            println!("Returned");
+        } else if self.until == Some(pc) {
+            println!("Reached");
+        } else if finished_run {
+            println!("Run finished");
+        } else if let Some(event) = self.caught_event(state, repeated) {
+            let (name, _) = EVENTS.iter().find(|(_, other)| *other == event).unwrap();
+            println!("Caught {}", name);
+        } else {
+            return false;
+        }
+        // whatever stopped execution ends a next, finish, until or run
+        self.cancel_targets();
+        self.show_location(state);
+        true
+    }
+
+    fn cancel_targets(&mut self) {
+        self.return_depth = None;
+        self.until = None;
+        self.remaining = None;
+    }
+
+    // call after each cycle, records how to undo it
+    pub fn record(&mut self) {
+        let Some(undo) = self.pending.take() else {
+            return;
+        };
+        if self.history.len() == HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+        self.history.push_back(undo);
+    }
+
+    // the caught event the next instruction causes, if any
+    fn caught_event(&self, state: &State, repeated: bool) -> Option<Event> {
+        let opcode = opcode_at(state, state.pc());
+        let event = match opcode & 0xF0FF {
+            _ if opcode & 0xF000 == 0xD000 => Event::Draw,
+            0xF018 => Event::Sound,
+            0xF00A if !repeated => Event::KeyWait,
+            0xF033 | 0xF055 => {
+                let length = if opcode & 0xFF == 0x33 {
+                    3
+                } else {
+                    ((opcode & 0x0F00) >> 8) + 1
+                };
+                let mut written = (0..length)
+                    .map(|offset| (state.i() as usize + offset as usize) % state.memory_size());
+                if !written.any(|address| self.code[address]) {
+                    return None;
+                }
+                Event::SelfModify
+            }
+            _ => return None,
+        };
+        Some(event).filter(|event| self.catches.contains(event))
+    }
+
+    // Runs commands typed since the last call, up to the first one that
+    // needs the frontend
+    pub fn poll(&mut self, state: &mut State) -> Option<Command> {
+        while let Ok(line) = self.lines.try_recv() {
+            match self.execute(line.trim(), state) {
+                Ok(Some(command)) => {
+                    if command != Command::Pause {
+                        self.resuming = true;
+                    }
+                    if matches!(command, Command::Pause | Command::Step) {
+                        self.cancel_targets();
+                    }
+                    return Some(command);
+                }
+                Ok(None) => {}
+                Err(error) => println!("{}", error),
+            }
+        }
+        None
+    }
+
+    pub fn show_location(&self, state: &State) {
+        let pc = state.pc();
+        let opcode = opcode_at(state, pc);
+        println!(
+            "{}  {:04X}  {}",
+            self.symbols.describe(pc),
+            opcode,
+            disasm::disassemble(opcode)
+        );
+    }
+
+    fn execute(&mut self, line: &str, state: &mut State) -> Result<Option<Command>, String> {
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            return Ok(None);
+        };
+        match command {
+            "break" | "b" => {
+                let address = self.location(words.next(), state)?;
+                self.breakpoints.insert(address);
+                println!("Breakpoint at {}", self.symbols.describe(address));
+            }
+            "delete" | "d" => {
+                let address = self.location(words.next(), state)?;
+                if !self.breakpoints.remove(&address) {
+                    return Err(format!(
+                        "No breakpoint at {}",
+                        self.symbols.describe(address)
+                    ));
+                }
+            }
+            "catch" | "uncatch" => {
+                let name = words.next().ok_or("Missing event")?;
+                let (_, event) = EVENTS
+                    .iter()
+                    .find(|(other, _)| *other == name)
+                    .ok_or_else(|| format!("Unknown event {}", name))?;
+                if command == "catch" {
+                    self.catches.insert(*event);
+                } else {
+                    self.catches.remove(event);
+                }
+            }
+            "breakpoints" => {
+                for address in &self.breakpoints {
+                    println!("{}", self.symbols.describe(*address));
+                }
+                for (name, event) in EVENTS {
+                    if self.catches.contains(&event) {
+                        println!("catch {}", name);
+                    }
+                }
+            }
+            "continue" | "c" => return Ok(Some(Command::Continue)),
+            "pause" => return Ok(Some(Command::Pause)),
+            "step" | "s" => return Ok(Some(Command::Step)),
+            "stepback" | "sb" => {
+                let count = match words.next() {
+                    Some(count) => count
+                        .parse()
+                        .map_err(|_| format!("Invalid count {}", count))?,
+                    None => 1,
+                };
+                if count > self.history.len() {
+                    return Err(format!(
+                        "Only {} instructions can be undone",
+                        self.history.len()
+                    ));
+                }
+                let mut data = state.save_state();
+                for _ in 0..count {
+                    let undo = self.history.pop_back().unwrap();
+                    undo.undo(&mut data, state.memory_size());
+                }
+                state.load_state(&data)?;
+                return Ok(Some(Command::Pause));
+            }
+            "next" | "n" => {
+                if opcode_at(state, state.pc()) & 0xF000 != 0x2000 {
+                    return Ok(Some(Command::Step));
+                }
+                self.return_depth = Some(state.stack().len());
+                return Ok(Some(Command::Continue));
+            }
+            "until" => {
+                self.until = Some(self.location(words.next(), state)?);
+                return Ok(Some(Command::Run));
+            }
+            "run" => {
+                let count = words.next().ok_or("Missing instruction count")?;
+                let count: u64 = count
+                    .parse()
+                    .map_err(|_| format!("Invalid count {}", count))?;
+                if count == 0 {
+                    return Ok(None);
+                }
+                self.remaining = Some(count);
+                return Ok(Some(Command::Run));
+            }
+            "finish" => {
+                let depth = state.stack().len();
+                if depth == 0 {
+                    return Err("Not in a subroutine".to_string());
+                }
+                self.return_depth = Some(depth - 1);
+                return Ok(Some(Command::Continue));
+            }
+            "regs" | "r" => {
+                self.show_location(state);
+                let registers = state.registers();
+                for (row, chunk) in registers.chunks(8).enumerate() {
+                    let values: Vec<String> = chunk
+                        .iter()
+                        .enumerate()
+                        .map(|(index, value)| format!("V{:X}={:02X}", row * 8 + index, value))
+                        .collect();
+                    println!("{}", values.join(" "));
+                }
+                println!(
+                    "I={:03X} DT={:02X} ST={:02X}",
+                    state.i(),
+                    state.delay_timer(),
+                    state.sound_timer()
+                );
+            }
+            "peek" => {
+                let address = self.location(words.next(), state)?;
+                let count = match words.next() {
+                    Some(count) => number(count)?,
+                    None => 16,
+                };
+                for row in (0..count).step_by(16) {
+                    let start = address.wrapping_add(row as u16);
+                    let bytes: Vec<String> = (row..count.min(row + 16))
+                        .map(|offset| {
+                            format!(
+                                "{:02X}",
+                                state.read_memory(address.wrapping_add(offset as u16))
+                            )
+                        })
+                        .collect();
+                    println!(
+                        "{:03X}  {}",
+                        start as usize % state.memory_size(),
+                        bytes.join(" ")
+                    );
+                }
+            }
+            "poke" => {
+                let address = self.location(words.next(), state)?;
+                let bytes = words
+                    .map(|word| {
+                        u8::try_from(number(word)?).map_err(|_| format!("{} isn't a byte", word))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                if bytes.is_empty() {
+                    return Err("Missing bytes".to_string());
+                }
+                for (offset, byte) in bytes.iter().enumerate() {
+                    state.write_memory(address.wrapping_add(offset as u16), *byte);
+                }
+            }
+            "setreg" => {
+                let register = words.next().ok_or("Missing register")?.to_ascii_lowercase();
+                let word = words.next().ok_or("Missing value")?;
+                let value = number(word)?;
+                let byte = || u8::try_from(value).map_err(|_| format!("{} isn't a byte", word));
+                match register.as_str() {
+                    "i" if value <= 0xFFFF => state.set_i(value as u16),
+                    "i" => return Err(format!("{} doesn't fit I", word)),
+                    "dt" => state.set_delay_timer(byte()?),
+                    "st" => state.set_sound_timer(byte()?),
+                    _ => {
+                        let index = register
+                            .strip_prefix('v')
+                            .filter(|digit| digit.len() == 1)
+                            .and_then(|digit| u8::from_str_radix(digit, 16).ok())
+                            .ok_or_else(|| format!("Unknown register {}", register))?;
+                        state.set_register(index as usize, byte()?);
+                    }
+                }
+            }
+            "setpc" => {
+                state.set_pc(self.location(words.next(), state)?);
+                self.show_location(state);
+            }
+            "backtrace" | "bt" => {
+                for frame in backtrace(state, &self.symbols) {
+                    println!("{}", frame);
+                }
+            }
+            "help" => println!("{}", HELP),
+            _ => return Err(format!("Unknown command {}, try help", command)),
+        }
+        Ok(None)
+    }
+
+    // a label, 0x hex or decimal address inside state's memory
+    fn location(&self, word: Option<&str>, state: &State) -> Result<u16, String> {
+        let word = word.ok_or("Missing location")?;
+        if let Some(address) = self.symbols.address(word) {
+            return Ok(address);
+        }
+        let address = match word.strip_prefix("0x").or(word.strip_prefix("0X")) {
+            Some(hex) => u16::from_str_radix(hex, 16).ok(),
+            None => word.parse().ok(),
+        };
+        address
+            .filter(|address| (*address as usize) < state.memory_size())
+            .ok_or_else(|| format!("Unknown location {}", word))
+    }
+}
+
+// 0x hex or decimal
+fn number(word: &str) -> Result<u32, String> {
+    match word.strip_prefix("0x").or(word.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => word.parse().ok(),
+    }
+    .ok_or_else(|| format!("Invalid number {}", word))
+}
+
+// The current instruction then each call that's still waiting to return,
+// innermost first
+pub fn backtrace(state: &State, symbols: &Symbols) -> Vec<String> {
+    std::iter::once(state.pc())
+        .chain(state.stack().iter().rev().copied())
+        .enumerate()
+        .map(|(frame, address)| {
+            let opcode = opcode_at(state, address);
+            format!(
+                "#{:<2} {}  {:04X}  {}",
+                frame,
+                symbols.describe(address),
+                opcode,
+                disasm::disassemble(opcode)
+            )
+        })
+        .collect()
+}
+
+fn opcode_at(state: &State, address: u16) -> u16 {
+    ((state.read_memory(address) as u16) << 8) | state.read_memory(address.wrapping_add(1)) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // runs program's first count instructions recording them, then steps
+    // back over all of them
+    fn steps_back(program: &[u8], memory_size: usize, count: usize) {
+        let mut state = State::new();
+        state.set_memory_size(memory_size).unwrap();
+        state.initialize();
+        state.load_buffer(program);
+        state.set_register(3, 0x7B);
+        let before = state.save_state();
+        let mut history = VecDeque::new();
+        for _ in 0..count {
+            let undo = Undo::new(&state);
+            state.emulate_cycle().unwrap();
+            history.push_back(undo);
+        }
+        assert_ne!(state.save_state(), before);
+        let mut data = state.save_state();
+        while let Some(undo) = history.pop_back() {
+            undo.undo(&mut data, state.memory_size());
+        }
+        assert_eq!(data, before);
+    }
+
+    #[test]
+    fn undoes_stores_at_the_end_of_memory() {
+        // I = 0xFFE, store V0-V3 and the BCD of V3, draw and call, wrapping
+        // around 4K and going past it in 64K
+        let program = [0xAF, 0xFE, 0xF3, 0x55, 0xF3, 0x33, 0xD0, 0x15, 0x22, 0x10];
+        steps_back(&program, crate::chip8::DEFAULT_MEMORY_SIZE, 5);
+        steps_back(&program, XO_CHIP_MEMORY_SIZE, 5);
+    }
+
+    #[test]
+    fn undoes_returns_and_clears() {
+        // call 0x206, which clears the screen after a draw and returns
+        let program = [
+            0x22, 0x06, 0x00, 0x00, 0x00, 0x00, 0xD0, 0x05, 0x00, 0xE0, 0x00, 0xEE,
+        ];
+        steps_back(&program, crate::chip8::DEFAULT_MEMORY_SIZE, 4);
+    }
+}
@@ -0,0 +1,39 @@
+; Shown when the emulator is started without a ROM.
+; Draws a CHIP-8 banner, then shows the hex digit of each key pressed.
+
+        CLS
+        LD V0, 14       ; banner x
+        LD V1, 8        ; banner y
+        LD V2, 0        ; letters drawn
+        LD V3, 5        ; bytes per letter
+        LD I, letters
+banner:
+        DRW V0, V1, 5
+        ADD V0, 6
+        ADD I, V3
+        ADD V2, 1
+        SE V2, 6
+        JP banner
+
+        LD V4, 0        ; last key shown
+        LD V6, 30       ; digit x
+        LD V7, 20       ; digit y
+        LD V8, 0        ; set once a digit is on screen
+keys:
+        LD V5, K
+        LD F, V4        ; erase the last digit
+        SE V8, 0
+        DRW V6, V7, 5
+        LD F, V5
+        DRW V6, V7, 5
+        LD V4, V5
+        LD V8, 1
+        JP keys
+
+letters:
+        DB 0x70, 0x80, 0x80, 0x80, 0x70 ; C
+        DB 0x88, 0x88, 0xF8, 0x88, 0x88 ; H
+        DB 0x70, 0x20, 0x20, 0x20, 0x70 ; I
+        DB 0xF0, 0x88, 0xF0, 0x80, 0x80 ; P
+        DB 0x00, 0x00, 0x70, 0x00, 0x00 ; -
+        DB 0x70, 0x88, 0x70, 0x88, 0x70 ; 8
@@ -0,0 +1,48 @@
+// -d/--echo, the instructions printed to stdout as they run. A program runs
+// hundreds a frame, more than a terminal keeps up with, so only the last
+// LINES of each frame are printed, after a count of the ones skipped.
+use crate::disasm;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+
+const LINES: usize = 32;
+
+#[derive(Default)]
+pub struct Echo {
+    // address and opcode
+    recent: VecDeque<(u16, u16)>,
+    skipped: u64,
+}
+
+impl Echo {
+    pub fn push(&mut self, pc: u16, opcode: u16) {
+        if self.recent.len() == LINES {
+            self.recent.pop_front();
+            self.skipped += 1;
+        }
+        self.recent.push_back((pc, opcode));
+    }
+
+    // call once a frame
+    pub fn flush(&mut self) -> Result<(), String> {
+        if self.recent.is_empty() {
+            return Ok(());
+        }
+        let mut out = io::stdout().lock();
+        if self.skipped > 0 {
+            writeln!(out, "... {} skipped", self.skipped).map_err(|op| op.to_string())?;
+        }
+        for (pc, opcode) in self.recent.drain(..) {
+            writeln!(
+                out,
+                "{:#05X}: {:04X}  {}",
+                pc,
+                opcode,
+                disasm::disassemble(opcode)
+            )
+            .map_err(|op| op.to_string())?;
+        }
+        self.skipped = 0;
+        out.flush().map_err(|op| op.to_string())
+    }
+}
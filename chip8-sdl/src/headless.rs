@@ -0,0 +1,103 @@
+use crate::audio;
+use crate::chip8::State;
+use crate::inputscript::InputScript;
+
+// How a headless run is set up. Runs with the same options end the same way,
+// the seed isn't picked at random.
+#[derive(Clone, Copy)]
+pub struct Options {
+    // --seed, for CXNN
+    pub seed: u64,
+    // --ipf, instructions per 60Hz frame
+    pub ipf: u32,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        // the SDL frontend's default of one cycle per frame
+        Self { seed: 0, ipf: 1 }
+    }
+}
+
+// Runs a program without any display or input for a number of frames,
+// returning the machine and the error that halted it, if any
+pub fn run(rom: &[u8], frames: u64, options: Options) -> (State, Option<String>) {
+    run_watched(rom, frames, options, |_, _| {})
+}
+
+// run, calling watch after every frame with the machine and the frames so far
+pub fn run_watched(
+    rom: &[u8],
+    frames: u64,
+    options: Options,
+    watch: impl FnMut(&State, u64),
+) -> (State, Option<String>) {
+    run_frames(rom, frames, options, |_, _| {}, watch)
+}
+
+// run, pressing keys as input says and ringing the terminal's bell when the
+// buzzer stops if bell is set
+pub fn run_with_input(
+    rom: &[u8],
+    frames: u64,
+    options: Options,
+    input: &InputScript,
+    bell: bool,
+) -> (State, Option<String>) {
+    let press = |chip_8: &mut State, frame| {
+        if bell && frame == 1 {
+            chip_8.set_sound_sink(audio::terminal_bell);
+        }
+        for input in input.inputs_at(frame) {
+            chip_8.set_key(input.key as usize, input.pressed as u8);
+        }
+    };
+    run_frames(rom, frames, options, press, |_, _| {})
+}
+
+fn run_frames(
+    rom: &[u8],
+    frames: u64,
+    options: Options,
+    mut before: impl FnMut(&mut State, u64),
+    mut watch: impl FnMut(&State, u64),
+) -> (State, Option<String>) {
+    let mut chip_8 = State::new();
+    chip_8.set_seed(options.seed);
+    chip_8.initialize();
+    chip_8.load_buffer(rom);
+    for frame in 1..=frames {
+        before(&mut chip_8, frame);
+        for _ in 0..options.ipf {
+            if let Err(error) = chip_8.emulate_cycle() {
+                return (chip_8, Some(error.to_string()));
+            }
+        }
+        watch(&chip_8, frame);
+    }
+    (chip_8, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_repeat() {
+        // C0FF then 1202, a random number and a loop
+        let rom = [0xC0, 0xFF, 0x12, 0x02];
+        let options = Options { seed: 7, ipf: 3 };
+        let (first, _) = run(&rom, 2, options);
+        let (second, _) = run(&rom, 2, options);
+        assert_eq!(first.save_state(), second.save_state());
+    }
+
+    #[test]
+    fn ipf_sets_the_cycles_per_frame() {
+        // 7001 then 1200, adding one to V0 every cycle
+        let rom = [0x70, 0x01, 0x12, 0x00];
+        let options = Options { seed: 0, ipf: 10 };
+        let (chip_8, _) = run(&rom, 3, options);
+        assert_eq!(chip_8.registers()[0], 15);
+    }
+}
@@ -0,0 +1,114 @@
+// --heatmap or Ctrl+H, memory drawn 64 bytes a row over the left of the
+// display, each byte colored by how it's used: green for executed, blue for
+// read and red for written, brighter the more often. Code, data tables and
+// scratch RAM stand apart, and mixes show up as mixed colors.
+use crate::chip8::{Accesses, State};
+use crate::ui::draw_text;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+const COLUMNS: usize = 64;
+const SCALE: u32 = 2;
+const LEGEND_HEIGHT: u32 = 7 * SCALE + 4;
+
+pub struct Heatmap {
+    pub enabled: bool,
+}
+
+impl Heatmap {
+    pub fn new() -> Self {
+        Self { enabled: false }
+    }
+
+    // Turns it on or off, counting starts over each time it's shown
+    pub fn toggle(&mut self, chip_8: &mut State) {
+        self.enabled = !self.enabled;
+        chip_8.set_access_counting(self.enabled);
+    }
+
+    pub fn draw(
+        &self,
+        canvas: &mut Canvas<Window>,
+        area: Rect,
+        chip_8: &State,
+    ) -> Result<(), String> {
+        let Some(accesses) = chip_8.accesses().filter(|_| self.enabled) else {
+            return Ok(());
+        };
+        // memory past what fits a pixel a row is merged into fewer rows
+        let height = area.height().saturating_sub(LEGEND_HEIGHT).max(1);
+        let rows = accesses.len().div_ceil(COLUMNS);
+        let rows_per_line = rows.div_ceil(height as usize);
+        let lines = rows.div_ceil(rows_per_line);
+        let cell = (height / lines as u32)
+            .min(area.width() / COLUMNS as u32)
+            .max(1);
+
+        let mut most = Accesses::default();
+        for access in accesses {
+            most.reads = most.reads.max(access.reads);
+            most.writes = most.writes.max(access.writes);
+            most.executes = most.executes.max(access.executes);
+        }
+        let (left, top) = (area.x(), area.y());
+        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        canvas.fill_rect(Rect::new(
+            left,
+            top,
+            cell * COLUMNS as u32,
+            cell * lines as u32 + LEGEND_HEIGHT,
+        ))?;
+        for line in 0..lines {
+            for column in 0..COLUMNS {
+                // the bytes in this column of each merged row
+                let mut total = Accesses::default();
+                for row in line * rows_per_line..(line + 1) * rows_per_line {
+                    if let Some(access) = accesses.get(row * COLUMNS + column) {
+                        total.reads = total.reads.saturating_add(access.reads);
+                        total.writes = total.writes.saturating_add(access.writes);
+                        total.executes = total.executes.saturating_add(access.executes);
+                    }
+                }
+                if total == Accesses::default() {
+                    continue;
+                }
+                canvas.set_draw_color(Color::RGB(
+                    level(total.writes, most.writes),
+                    level(total.executes, most.executes),
+                    level(total.reads, most.reads),
+                ));
+                canvas.fill_rect(Rect::new(
+                    left + (column as u32 * cell) as i32,
+                    top + (line as u32 * cell) as i32,
+                    cell,
+                    cell,
+                ))?;
+            }
+        }
+
+        let y = top + (lines as u32 * cell) as i32 + 2;
+        let mut x = left;
+        for (label, color) in [
+            ("EXEC", Color::RGB(0, 255, 0)),
+            ("READ", Color::RGB(0, 0, 255)),
+            ("WRITE", Color::RGB(255, 0, 0)),
+        ] {
+            draw_text(canvas, label, x, y, SCALE, color)?;
+            x += ((label.len() + 1) * 4) as i32 * SCALE as i32;
+        }
+        Ok(())
+    }
+}
+
+// count's brightness out of most, on a log scale so addresses touched a few
+// times still show next to a loop run millions of times
+fn level(count: u32, most: u32) -> u8 {
+    if count == 0 {
+        return 0;
+    }
+    // the dimmest touched byte is still visible
+    let fraction = (count as f32).ln_1p() / (most.max(count) as f32).ln_1p();
+    (64.0 + fraction * 191.0) as u8
+}
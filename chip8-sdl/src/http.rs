@@ -0,0 +1,225 @@
+// A small HTTP/1.1 server for the control and monitoring endpoints. Each
+// connection gets a thread, up to MAX_CONNECTIONS at once, that reads one
+// request and hands it to whoever owns the machine, which answers it the next
+// time it polls.
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryIter};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// enough for any ROM
+const MAX_BODY: usize = 0x10000;
+// the request line and headers together
+const MAX_HEADER: u64 = 0x2000;
+// connections being served at once, more are turned away
+const MAX_CONNECTIONS: usize = 16;
+// how long a connection waits for the machine to answer
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(5);
+// how long a client may take to send each part of its request
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    // after the ?, empty without one
+    pub query: String,
+    pub body: Vec<u8>,
+    reply: Sender<Vec<u8>>,
+}
+
+impl Request {
+    pub fn respond(self, status: u16, content_type: &str, body: &[u8]) {
+        let reason = match status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            503 => "Service Unavailable",
+            _ => "Error",
+        };
+        let mut response = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            status,
+            reason,
+            content_type,
+            body.len()
+        )
+        .into_bytes();
+        response.extend_from_slice(body);
+        // the connection may have timed out already
+        let _ = self.reply.send(response);
+    }
+
+    pub fn text(self, status: u16, text: &str) {
+        self.respond(status, "text/plain; charset=utf-8", text.as_bytes());
+    }
+
+    // the value of name=value in the query
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        self.query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    }
+}
+
+pub struct Server {
+    requests: Receiver<Request>,
+}
+
+impl Server {
+    pub fn bind(address: &str) -> Result<Self, String> {
+        let listener = TcpListener::bind(address)
+            .map_err(|op| format!("Couldn't listen on {}: {}", address, op))?;
+        let (sender, requests) = mpsc::channel();
+        let connections = Arc::new(AtomicUsize::new(0));
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                if connections.load(Ordering::Relaxed) >= MAX_CONNECTIONS {
+                    let _ = stream.write_all(
+                        b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    );
+                    continue;
+                }
+                connections.fetch_add(1, Ordering::Relaxed);
+                let sender = sender.clone();
+                let connections = connections.clone();
+                thread::spawn(move || {
+                    if let Err(error) = serve(stream, &sender) {
+                        debug!("HTTP client: {}", error);
+                    }
+                    connections.fetch_sub(1, Ordering::Relaxed);
+                });
+            }
+        });
+        Ok(Self { requests })
+    }
+
+    // the requests that arrived since the last call
+    pub fn requests(&self) -> TryIter<'_, Request> {
+        self.requests.try_iter()
+    }
+}
+
+fn serve(mut stream: TcpStream, sender: &Sender<Request>) -> Result<(), String> {
+    stream
+        .set_read_timeout(Some(READ_TIMEOUT))
+        .map_err(|op| op.to_string())?;
+    // the limit is the header's until the body's length is known
+    let mut reader = BufReader::new(
+        stream
+            .try_clone()
+            .map_err(|op| op.to_string())?
+            .take(MAX_HEADER),
+    );
+    let mut request_line = String::new();
+    reader
+        .read_line(&mut request_line)
+        .map_err(|op| op.to_string())?;
+    let mut words = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (words.next(), words.next()) else {
+        return Err(format!("Invalid request line {}", request_line.trim()));
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let mut length = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).map_err(|op| op.to_string())? == 0 {
+            if reader.get_ref().limit() == 0 {
+                return Err(format!("Headers over {} bytes", MAX_HEADER));
+            }
+            return Err("Closed in the headers".to_string());
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("Invalid Content-Length {}", value.trim()))?;
+            }
+        }
+    }
+    if length > MAX_BODY {
+        return Err(format!("Body of {} bytes is too long", length));
+    }
+    let mut body = vec![0; length];
+    reader.get_mut().set_limit(length as u64);
+    reader.read_exact(&mut body).map_err(|op| op.to_string())?;
+
+    let (reply, answer) = mpsc::channel();
+    sender
+        .send(Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            query: query.to_string(),
+            body,
+            reply,
+        })
+        .map_err(|op| op.to_string())?;
+    let response = answer
+        .recv_timeout(ANSWER_TIMEOUT)
+        .map_err(|_| "The emulator didn't answer".to_string())?;
+    stream.write_all(&response).map_err(|op| op.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // serves one connection that's sent request, returning serve's result
+    fn serve_request(request: Vec<u8>) -> (Result<(), String>, Receiver<Request>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        // the client may be cut off before it's done
+        let _ = client.write_all(&request);
+        let (sender, requests) = mpsc::channel();
+        (serve(stream, &sender), requests)
+    }
+
+    #[test]
+    fn reads_a_request() {
+        let request = b"POST /rom?x=1 HTTP/1.1\r\nContent-Length: 2\r\n\r\nhi".to_vec();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        client.write_all(&request).unwrap();
+        let (sender, requests) = mpsc::channel();
+        let server = thread::spawn(move || serve(stream, &sender));
+        let request = requests.recv().unwrap();
+        assert_eq!(
+            (request.method.as_str(), request.path.as_str()),
+            ("POST", "/rom")
+        );
+        assert_eq!(request.parameter("x"), Some("1"));
+        assert_eq!(request.body, b"hi");
+        request.text(200, "ok");
+        assert!(server.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn rejects_long_headers() {
+        let mut request = b"GET / HTTP/1.1\r\nX-Padding: ".to_vec();
+        request.extend(std::iter::repeat_n(b'a', MAX_HEADER as usize));
+        request.extend(b"\r\n\r\n");
+        let (result, requests) = serve_request(request);
+        assert!(result.unwrap_err().contains("Headers over"));
+        assert!(requests.try_recv().is_err());
+    }
+
+    #[test]
+    fn gives_up_on_silent_clients() {
+        let (result, _) = serve_request(b"GET / HTTP/1.1\r\n".to_vec());
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,101 @@
+// --http ADDRESS, a REST API for test rigs and demos driven by other programs:
+//
+//   GET  /state                the registers, timers, stack and cycle count as JSON
+//   GET  /screenshot?scale=N   the display as a PNG, N pixels per CHIP-8 pixel
+//   POST /keys/K/down          press key K, 0-F
+//   POST /keys/K/up            release it
+//   POST /rom                  restart with the ROM in the body
+use crate::chip8::State;
+use crate::http::Server;
+use crate::png;
+
+// keeps screenshots a reasonable size
+const MAX_SCALE: usize = 20;
+
+pub struct HttpApi {
+    server: Server,
+}
+
+impl HttpApi {
+    pub fn bind(address: &str) -> Result<Self, String> {
+        let server = Server::bind(address)?;
+        info!("HTTP API listening on http://{}", address);
+        Ok(Self { server })
+    }
+
+    // Answers the requests since the last call, returns a ROM the frontend
+    // should restart with
+    pub fn poll(&mut self, state: &mut State) -> Option<Vec<u8>> {
+        let mut rom = None;
+        for request in self.server.requests() {
+            // responding consumes the request
+            let path = request.path.clone();
+            let path: Vec<&str> = path.trim_matches('/').split('/').collect();
+            match (request.method.as_str(), path.as_slice()) {
+                ("GET", ["state"]) => {
+                    let json = state_json(state);
+                    request.respond(200, "application/json", json.as_bytes());
+                }
+                ("GET", ["screenshot"]) => {
+                    let scale = match request.parameter("scale").map(str::to_string) {
+                        Some(scale) => match scale.parse() {
+                            Ok(scale @ 1..=MAX_SCALE) => scale,
+                            _ => {
+                                request.text(400, &format!("Invalid scale {}", scale));
+                                continue;
+                            }
+                        },
+                        None => 1,
+                    };
+                    let image = png::encode(&state.get_graphics_buffer(), 64, scale);
+                    request.respond(200, "image/png", &image);
+                }
+                ("POST", ["keys", key, action @ ("down" | "up")]) => {
+                    match u8::from_str_radix(key, 16).ok().filter(|key| *key < 16) {
+                        Some(key) => {
+                            state.set_key(key as usize, (*action == "down") as u8);
+                            request.text(200, "OK");
+                        }
+                        None => request.text(400, &format!("Invalid key {}", key)),
+                    }
+                }
+                ("POST", ["rom"]) => {
+                    let space = state.memory_size() - 0x200;
+                    if request.body.is_empty() || request.body.len() > space {
+                        let message = format!("A ROM can't be {} bytes", request.body.len());
+                        request.text(400, &message);
+                    } else {
+                        let message = format!("Loaded {} bytes", request.body.len());
+                        rom = Some(request.body.clone());
+                        request.text(200, &message);
+                    }
+                }
+                (_, ["state" | "screenshot" | "rom"] | ["keys", ..]) => {
+                    request.text(405, "Method not allowed")
+                }
+                _ => request.text(404, "Not found"),
+            }
+        }
+        rom
+    }
+}
+
+fn state_json(state: &State) -> String {
+    let v: Vec<String> = state.registers().iter().map(u8::to_string).collect();
+    let stack: Vec<String> = state.stack().iter().map(u16::to_string).collect();
+    let error = match state.error() {
+        Some(error) => format!("\"{}\"", error),
+        None => "null".to_string(),
+    };
+    format!(
+        "{{\"pc\":{},\"i\":{},\"v\":[{}],\"dt\":{},\"st\":{},\"stack\":[{}],\"cycles\":{},\"error\":{}}}",
+        state.pc(),
+        state.i(),
+        v.join(","),
+        state.delay_timer(),
+        state.sound_timer(),
+        stack.join(","),
+        state.stats().cycles,
+        error
+    )
+}
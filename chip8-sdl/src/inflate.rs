@@ -0,0 +1,264 @@
+// Decompresses zlib streams (RFC 1950/1951), enough to read the PNGs other
+// tools write. No checks beyond what's needed to not run off the end.
+
+struct Bits<'a> {
+    data: &'a [u8],
+    position: usize,
+    bit: u32,
+}
+
+impl Bits<'_> {
+    fn bit(&mut self) -> Result<u32, String> {
+        let byte = *self
+            .data
+            .get(self.position)
+            .ok_or("Compressed data ends early")?;
+        let value = (byte >> self.bit) & 1;
+        self.bit += 1;
+        if self.bit == 8 {
+            self.bit = 0;
+            self.position += 1;
+        }
+        Ok(value as u32)
+    }
+
+    // count bits, least significant first
+    fn bits(&mut self, count: u32) -> Result<u32, String> {
+        let mut value = 0;
+        for index in 0..count {
+            value |= self.bit()? << index;
+        }
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        if self.bit != 0 {
+            self.bit = 0;
+            self.position += 1;
+        }
+    }
+}
+
+// A canonical Huffman code, as the number of codes of each length and the
+// symbols in code order
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0; 16];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0; 16];
+        for length in 1..16 {
+            offsets[length] = offsets[length - 1] + counts[length - 1];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, length) in lengths.iter().enumerate() {
+            if *length != 0 {
+                symbols[offsets[*length as usize] as usize] = symbol as u16;
+                offsets[*length as usize] += 1;
+            }
+        }
+        Self { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Result<u16, String> {
+        // the first code of each length follows on from the last length's
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..16 {
+            code |= bits.bit()? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("Invalid Huffman code".to_string())
+    }
+}
+
+const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// the order code length code lengths come in
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+pub fn zlib_decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() < 2 || data[0] & 0xF != 8 {
+        return Err("Not a zlib stream".to_string());
+    }
+    let mut bits = Bits {
+        data: &data[2..],
+        position: 0,
+        bit: 0,
+    };
+    let mut out = Vec::new();
+    loop {
+        let last = bits.bit()? == 1;
+        match bits.bits(2)? {
+            0 => {
+                bits.align();
+                let start = bits.position;
+                let header = bits
+                    .data
+                    .get(start..start + 4)
+                    .ok_or("Compressed data ends early")?;
+                let length = u16::from_le_bytes([header[0], header[1]]) as usize;
+                let block = bits
+                    .data
+                    .get(start + 4..start + 4 + length)
+                    .ok_or("Compressed data ends early")?;
+                out.extend_from_slice(block);
+                bits.position = start + 4 + length;
+            }
+            1 => {
+                let mut lengths = [0; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+                let literals = Huffman::new(&lengths);
+                let distances = Huffman::new(&[5; 30]);
+                inflate_block(&mut bits, &mut out, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut bits)?;
+                inflate_block(&mut bits, &mut out, &literals, &distances)?;
+            }
+            _ => return Err("Invalid block type".to_string()),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn dynamic_codes(bits: &mut Bits) -> Result<(Huffman, Huffman), String> {
+    let literal_count = bits.bits(5)? as usize + 257;
+    let distance_count = bits.bits(5)? as usize + 1;
+    let code_count = bits.bits(4)? as usize + 4;
+    let mut code_lengths = [0; 19];
+    for index in CODE_LENGTH_ORDER.iter().take(code_count) {
+        code_lengths[*index] = bits.bits(3)? as u8;
+    }
+    let code = Huffman::new(&code_lengths);
+    let mut lengths = Vec::new();
+    while lengths.len() < literal_count + distance_count {
+        let (value, repeat) = match code.decode(bits)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (
+                *lengths.last().ok_or("Repeat with no length before it")?,
+                3 + bits.bits(2)?,
+            ),
+            17 => (0, 3 + bits.bits(3)?),
+            _ => (0, 11 + bits.bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ))
+}
+
+fn inflate_block(
+    bits: &mut Bits,
+    out: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), String> {
+    loop {
+        let symbol = literals.decode(bits)? as usize;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let index = symbol - 257;
+                if index >= LENGTH_BASES.len() {
+                    return Err("Invalid length".to_string());
+                }
+                let length =
+                    LENGTH_BASES[index] as usize + bits.bits(LENGTH_EXTRA[index] as u32)? as usize;
+                let index = distances.decode(bits)? as usize;
+                if index >= DISTANCE_BASES.len() {
+                    return Err("Invalid distance".to_string());
+                }
+                let distance = DISTANCE_BASES[index] as usize
+                    + bits.bits(DISTANCE_EXTRA[index] as u32)? as usize;
+                if distance > out.len() {
+                    return Err("Distance before the start of the data".to_string());
+                }
+                let start = out.len() - distance;
+                for offset in 0..length {
+                    out.push(out[start + offset]);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(&hex[index..index + 2], 16).unwrap())
+            .collect()
+    }
+
+    // what zlib.compress writes at levels 0 and 9
+    #[test]
+    fn reads_stored_and_fixed_blocks() {
+        let stored = bytes("7801011400ebff48656c6c6f2c2048656c6c6f2c2048656c6c6f21463e0696");
+        let fixed = bytes("78daf348cdc9c9d751f040a21401463e0696");
+        for data in [stored, fixed] {
+            assert_eq!(zlib_decompress(&data).unwrap(), b"Hello, Hello, Hello!");
+        }
+    }
+
+    #[test]
+    fn reads_dynamic_blocks() {
+        let data = bytes(concat!(
+            "78da55d0410e03210805d0ab700441440da799a45d74d94e7aff0157fca5e487",
+            "ffa4d1fdfd5fbff78b3e373527ae6f7692fa56a75edfdb49216f4ea30e643859",
+            "1df4484c58193b561d5894ec3a58a1e006352da128954c0196353671475fb431",
+            "92778818d1a966c37f640ae0b27217d0bb64e3c6ffe60d41afa917d0ab660a4f",
+            "bd6297807e48340ae8c70c9780de522fa0b79302fd3cbb403f4f239e3e5d0f0a",
+            "b5a1ee",
+        ));
+        let expected: String = (0..30)
+            .map(|n| format!("{} squared is {}; ", n, n * n))
+            .collect();
+        assert_eq!(zlib_decompress(&data).unwrap(), expected.as_bytes());
+    }
+
+    #[test]
+    fn rejects_truncated_streams() {
+        let fixed = bytes("78daf348cdc9c9d751f040a21401463e0696");
+        assert!(zlib_decompress(&fixed[..8]).is_err());
+        assert!(zlib_decompress(b"PK").is_err());
+    }
+}
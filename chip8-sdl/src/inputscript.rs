@@ -0,0 +1,109 @@
+// --input-script FILE, key presses at set frames for automated runs of
+// interactive ROMs, simpler to write by hand than a replay:
+//
+//   frame 120: press 5; frame 130: release 5
+//
+// Statements are split by ; or new lines and # starts a comment. Keys are
+// the keypad's hex digits, frames count from 1 and an input takes effect
+// before its frame runs.
+use std::fs;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Input {
+    pub frame: u64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct InputScript {
+    // in frame order
+    inputs: Vec<Input>,
+}
+
+impl InputScript {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut inputs = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap();
+            for statement in line.split(';').map(str::trim) {
+                if statement.is_empty() {
+                    continue;
+                }
+                let input = parse_statement(statement)
+                    .map_err(|op| format!("line {}: {}", index + 1, op))?;
+                inputs.push(input);
+            }
+        }
+        // stable, so inputs on the same frame keep their order
+        inputs.sort_by_key(|input| input.frame);
+        Ok(Self { inputs })
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|op| format!("{}: {}", path, op))?;
+        Self::parse(&text).map_err(|op| format!("{}: {}", path, op))
+    }
+
+    pub fn inputs_at(&self, frame: u64) -> impl Iterator<Item = &Input> {
+        self.inputs.iter().filter(move |input| input.frame == frame)
+    }
+
+    pub fn from_inputs(mut inputs: Vec<Input>) -> Self {
+        inputs.sort_by_key(|input| input.frame);
+        Self { inputs }
+    }
+
+    // the frame of the last input, 0 without any
+    pub fn length(&self) -> u64 {
+        self.inputs.last().map_or(0, |input| input.frame)
+    }
+
+    // One line, in the format parse reads
+    pub fn to_text(&self) -> String {
+        let statements: Vec<String> = self
+            .inputs
+            .iter()
+            .map(|input| {
+                format!(
+                    "frame {}: {} {:X}",
+                    input.frame,
+                    if input.pressed { "press" } else { "release" },
+                    input.key
+                )
+            })
+            .collect();
+        statements.join("; ")
+    }
+}
+
+// frame N: press K
+fn parse_statement(statement: &str) -> Result<Input, String> {
+    let (frame, action) = statement
+        .strip_prefix("frame")
+        .and_then(|rest| rest.split_once(':'))
+        .ok_or_else(|| format!("expected frame N: press/release K, not {}", statement))?;
+    let frame = frame
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid frame {}", frame.trim()))?;
+    let mut words = action.split_whitespace();
+    let pressed = match words.next() {
+        Some("press") => true,
+        Some("release") => false,
+        _ => return Err(format!("expected press or release in {}", statement)),
+    };
+    let key = words.next().ok_or("missing key")?;
+    let key = u8::from_str_radix(key, 16)
+        .ok()
+        .filter(|key| *key < 16)
+        .ok_or_else(|| format!("invalid key {}, keys are 0 to F", key))?;
+    if let Some(extra) = words.next() {
+        return Err(format!("unexpected {}", extra));
+    }
+    Ok(Input {
+        frame,
+        key,
+        pressed,
+    })
+}
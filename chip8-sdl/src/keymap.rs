@@ -0,0 +1,208 @@
+use sdl2::keyboard::{Keycode, Scancode};
+
+// CHIP-8 keys in the order of the physical 4x4 block starting at 1 on a keyboard
+const KEYPAD: [u8; 16] = [
+    0x1, 0x2, 0x3, 0xC, //
+    0x4, 0x5, 0x6, 0xD, //
+    0x7, 0x8, 0x9, 0xE, //
+    0xA, 0x0, 0xB, 0xF,
+];
+
+// the same block by physical position
+const SCANCODES: [Scancode; 16] = [
+    Scancode::Num1,
+    Scancode::Num2,
+    Scancode::Num3,
+    Scancode::Num4,
+    Scancode::Q,
+    Scancode::W,
+    Scancode::E,
+    Scancode::R,
+    Scancode::A,
+    Scancode::S,
+    Scancode::D,
+    Scancode::F,
+    Scancode::Z,
+    Scancode::X,
+    Scancode::C,
+    Scancode::V,
+];
+
+pub const LAYOUTS: [&str; 6] = [
+    "qwerty", "azerty", "qwertz", "dvorak", "colemak", "scancode",
+];
+
+// Which host keys press which CHIP-8 key. Keycodes are checked before scancodes.
+#[derive(Clone)]
+pub struct Keymap {
+    keycodes: Vec<(Keycode, u8)>,
+    scancodes: Vec<(Scancode, u8)>,
+}
+
+impl Keymap {
+    // The number row is always matched by position since AZERTY needs shift for
+    // digits, the letter rows follow the named layout
+    pub fn preset(layout: &str) -> Result<Self, String> {
+        use Keycode::*;
+        let letters = match layout.to_ascii_lowercase().as_str() {
+            "qwerty" => [Q, W, E, R, A, S, D, F, Z, X, C, V],
+            "azerty" => [A, Z, E, R, Q, S, D, F, W, X, C, V],
+            "qwertz" => [Q, W, E, R, A, S, D, F, Y, X, C, V],
+            "dvorak" => [Quote, Comma, Period, P, A, O, E, U, Semicolon, Q, J, K],
+            "colemak" => [Q, W, F, P, A, R, S, T, Z, X, C, V],
+            "scancode" => {
+                return Ok(Self {
+                    keycodes: Vec::new(),
+                    scancodes: SCANCODES.into_iter().zip(KEYPAD).collect(),
+                })
+            }
+            _ => {
+                return Err(format!(
+                    "Unknown layout {}, expected one of {}",
+                    layout,
+                    LAYOUTS.join(", ")
+                ))
+            }
+        };
+        Ok(Self {
+            keycodes: letters
+                .into_iter()
+                .zip(KEYPAD[4..].iter().copied())
+                .collect(),
+            scancodes: SCANCODES[..4]
+                .iter()
+                .copied()
+                .zip(KEYPAD[..4].iter().copied())
+                .collect(),
+        })
+    }
+
+    // Makes keycode the only host key for a CHIP-8 key
+    pub fn set(&mut self, key: u8, keycode: Keycode) {
+        self.keycodes
+            .retain(|(host, chip_8)| *chip_8 != key && *host != keycode);
+        self.scancodes.retain(|(_, chip_8)| *chip_8 != key);
+        self.keycodes.push((keycode, key));
+    }
+
+    // Applies overrides like "5=Up,8=Down,7=Left,9=Right", keys are SDL key names
+    pub fn apply_overrides(&mut self, overrides: &str) -> Result<(), String> {
+        for pair in overrides.split(',').filter(|pair| !pair.trim().is_empty()) {
+            let (key, name) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid key mapping {}, expected KEY=NAME", pair))?;
+            let key = u8::from_str_radix(key.trim(), 16)
+                .ok()
+                .filter(|key| *key <= 0xF)
+                .ok_or_else(|| format!("Invalid CHIP-8 key {}", key))?;
+            let keycode = Keycode::from_name(name.trim())
+                .ok_or_else(|| format!("Unknown key name {}", name))?;
+            self.set(key, keycode);
+        }
+        Ok(())
+    }
+
+    pub fn lookup(&self, keycode: Option<Keycode>, scancode: Option<Scancode>) -> Option<u8> {
+        keycode
+            .and_then(|keycode| find(&self.keycodes, keycode))
+            .or_else(|| scancode.and_then(|scancode| find(&self.scancodes, scancode)))
+    }
+}
+
+// Overrides as one, a later mapping for a key replacing an earlier one. None
+// if there are none.
+pub fn merge_overrides<'a>(overrides: impl IntoIterator<Item = &'a String>) -> Option<String> {
+    let mut merged: Vec<(&str, &str)> = Vec::new();
+    for pair in overrides
+        .into_iter()
+        .flat_map(|overrides| overrides.split(','))
+    {
+        if let Some((key, name)) = pair.split_once('=') {
+            let (key, name) = (key.trim(), name.trim());
+            merged.retain(|(earlier, _)| !earlier.eq_ignore_ascii_case(key));
+            merged.push((key, name));
+        }
+    }
+    if merged.is_empty() {
+        return None;
+    }
+    let pairs: Vec<String> = merged
+        .iter()
+        .map(|(key, name)| format!("{}={}", key, name))
+        .collect();
+    Some(pairs.join(","))
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::preset("qwerty").unwrap()
+    }
+}
+
+fn find<T: PartialEq>(map: &[(T, u8)], key: T) -> Option<u8> {
+    map.iter()
+        .find(|(host, _)| *host == key)
+        .map(|(_, chip_8)| *chip_8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_map_the_letter_rows_by_layout() {
+        let qwerty = Keymap::default();
+        assert_eq!(qwerty.lookup(Some(Keycode::Q), None), Some(0x4));
+        assert_eq!(qwerty.lookup(Some(Keycode::V), None), Some(0xF));
+        let azerty = Keymap::preset("AZERTY").unwrap();
+        assert_eq!(azerty.lookup(Some(Keycode::A), None), Some(0x4));
+        assert_eq!(azerty.lookup(Some(Keycode::W), None), Some(0xA));
+        assert!(Keymap::preset("workman").is_err());
+    }
+
+    #[test]
+    fn the_number_row_is_matched_by_position() {
+        let keymap = Keymap::preset("azerty").unwrap();
+        assert_eq!(
+            keymap.lookup(Some(Keycode::Ampersand), Some(Scancode::Num1)),
+            Some(0x1)
+        );
+        assert_eq!(keymap.lookup(None, Some(Scancode::Num4)), Some(0xC));
+        assert_eq!(keymap.lookup(None, Some(Scancode::Q)), None);
+        let scancode = Keymap::preset("scancode").unwrap();
+        assert_eq!(
+            scancode.lookup(Some(Keycode::Q), Some(Scancode::V)),
+            Some(0xF)
+        );
+    }
+
+    #[test]
+    fn set_replaces_every_host_key() {
+        let mut keymap = Keymap::default();
+        keymap.set(0x1, Keycode::Q);
+        assert_eq!(keymap.lookup(Some(Keycode::Q), None), Some(0x1));
+        assert_eq!(keymap.lookup(None, Some(Scancode::Num1)), None);
+    }
+
+    #[test]
+    fn parses_overrides() {
+        let mut keymap = Keymap::default();
+        keymap.apply_overrides("5=Up, a=Space,").unwrap();
+        assert_eq!(keymap.lookup(Some(Keycode::Up), None), Some(0x5));
+        assert_eq!(keymap.lookup(Some(Keycode::Space), None), Some(0xA));
+        assert_eq!(keymap.lookup(Some(Keycode::W), None), None);
+        assert!(keymap.apply_overrides("5").is_err());
+        assert!(keymap.apply_overrides("10=Up").is_err());
+        assert!(keymap.apply_overrides("g=Up").is_err());
+    }
+
+    #[test]
+    fn merged_overrides_keep_the_last_mapping_of_a_key() {
+        let overrides = ["5=Up, a=Space".to_string(), "A=Return,8=Down".to_string()];
+        assert_eq!(
+            merge_overrides(&overrides).as_deref(),
+            Some("5=Up,A=Return,8=Down")
+        );
+        assert_eq!(merge_overrides(&[]), None);
+    }
+}
@@ -0,0 +1,816 @@
+use std::cell::Cell;
+use std::env::args;
+use std::fs;
+
+#[macro_use]
+extern crate chip8_core;
+
+mod audio;
+mod batch;
+mod bisect;
+mod cheats;
+mod config;
+mod coverage;
+#[cfg(feature = "debug-server")]
+mod debug_server;
+mod debugger;
+mod echo;
+mod headless;
+mod heatmap;
+#[cfg(any(feature = "http-api", feature = "stats-server"))]
+mod http;
+#[cfg(feature = "http-api")]
+mod http_api;
+mod inflate;
+mod inputscript;
+mod keymap;
+mod menu;
+mod overlay;
+mod pausemenu;
+mod png;
+mod render;
+mod replay;
+mod savestate;
+mod screencheck;
+#[cfg(feature = "scripting")]
+mod script;
+mod sdl_frontend;
+mod sidecar;
+mod statediff;
+#[cfg(feature = "stats-server")]
+mod stats_server;
+mod theme;
+mod touch;
+mod trace;
+mod ui;
+mod wav;
+mod window;
+mod zip;
+
+use chip8_core as chip8;
+use chip8_core::log;
+use chip8_frontend::{timing, vip};
+use chip8_tools::{
+    asm, assemble_file, commands, disasm, hash, is_octo, quirkdetect, read_rom, symbols,
+};
+
+// window pixels per CHIP-8 pixel unless --scale says otherwise
+const DEFAULT_SCALE: u32 = 10;
+// how long bisect runs without a replay to say how long
+const BISECT_CYCLES: u64 = 100_000;
+// off and on pixels unless a game's settings choose others
+const DEFAULT_PALETTE: [[u8; 3]; 2] = [[0, 0, 0], [255, 255, 255]];
+// run when no ROM is given
+const DEMO: &str = include_str!("demo.asm");
+
+pub fn main() -> Result<(), String> {
+    match args().nth(1).as_deref() {
+        Some("batch") => return batch_command(args().skip(2)),
+        Some("headless") => return headless_command(args().skip(2)),
+        Some("lockstep") => return lockstep_command(args().skip(2)),
+        Some("slots") => return slots_command(args().skip(2)),
+        Some("diff-state") => return diff_state_command(args().skip(2)),
+        Some("bisect") => return bisect_command(args().skip(2)),
+        Some("audio-devices") => return audio_devices_command(),
+        Some("themes") => return themes_command(),
+        // the tools moved to chip8-tools, still run here for old scripts
+        Some("disasm") => return commands::disasm(args().skip(2)),
+        Some("asm") => return commands::asm(args().skip(2)),
+        Some("info") => return commands::info(args().skip(2)),
+        _ => {}
+    }
+
+    let mut chip_8 = chip8::State::new();
+
+    chip_8.initialize();
+
+    let mut game = None;
+    let mut tracer = None;
+    let mut coverage_path = None;
+    let mut autosave = false;
+    let mut seed = None;
+    let mut record_path = None;
+    let mut replay_path = None;
+    // the user's settings, which everything else overrides
+    let config = config::Config::load()?;
+    // the preset keymap started from, for the pause menu
+    let mut layout = config
+        .layout
+        .clone()
+        .unwrap_or_else(|| "qwerty".to_string());
+    let mut keymap = keymap::Keymap::preset(&layout)?;
+    let mut key_overrides = Vec::new();
+    let mut touch_layout = touch::TouchLayout::default();
+    let mut timing = timing::TimingModel::default();
+    let mut palette = config.palette.unwrap_or(DEFAULT_PALETTE);
+    // the buzzer stays silent
+    let mut mute = config.mute.unwrap_or(false);
+    // settings given as flags, which win over the game's own
+    let mut timing_chosen = false;
+    let mut layout_chosen = false;
+    let mut palette_chosen = false;
+    // lit pixels outlined in the off color so neighbors stay apart
+    let mut pixel_outlines = false;
+    // lines between the pixels for counting them, toggled with Ctrl+G
+    let mut grid = false;
+    let mut magnifier = ui::Magnifier::new();
+    let mut debug_overlay = overlay::DebugOverlay::new();
+    let mut heatmap = heatmap::Heatmap::new();
+    let mut vsync = false;
+    // 0 runs as fast as possible, unset is 60 or, with vsync pacing the
+    // frames, 0
+    let mut fps_limit = None;
+    let mut scale = DEFAULT_SCALE;
+    let mut fullscreen = false;
+    // the terminal's bell for the buzzer when there's no audio
+    let mut bell = false;
+    let mut slow_motion = 1;
+    // slow motion slows the timers down too instead of keeping them at full speed
+    let mut scale_timers = false;
+    // how many times a second the timers tick, apart from the frame rate
+    let mut timer_hz = None;
+    let mut compare = None;
+    let mut debug = false;
+    let mut symbols_path = None;
+    let mut script_path = None;
+    // the full SDL window unless a frontend is asked for, --frontend sdl is a
+    // plain one
+    let mut frontend_name = None;
+    let mut debug_server_address = None;
+    let mut http_address = None;
+    // quirks from --quirks win over detected ones
+    let mut quirks_chosen = false;
+    // --quirk name=value, applied on top of whichever quirks end up chosen
+    let mut quirk_overrides = Vec::new();
+    let mut detect_quirks = false;
+    let mut echo = false;
+    let mut stats_address = None;
+    // the system's default unless one is named
+    let mut audio_device = None;
+    let mut audio_record_path = None;
+    let mut input_script = None;
+    let mut cheats = None;
+    // every presented frame is written here as a numbered PNG
+    let mut dump_directory = None;
+    // chip8 dev source.s8 assembles and runs the source, reloading it on save
+    let dev = args().nth(1).as_deref() == Some("dev");
+    let mut args = args().skip(if dev { 2 } else { 1 });
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--protect-memory" => chip_8.set_write_protection(true),
+            "--memory-size" => {
+                let size = match args.next().ok_or("Missing memory size")?.as_str() {
+                    "4k" => chip8::DEFAULT_MEMORY_SIZE,
+                    "64k" => chip8::XO_CHIP_MEMORY_SIZE,
+                    size => size.parse().map_err(|_| "Invalid memory size")?,
+                };
+                chip_8.set_memory_size(size)?;
+            }
+            "--address-faults" => chip_8.set_address_wrapping(false),
+            "--font" => {
+                let name = args.next().ok_or("Missing font")?;
+                // a bundled font, or a file holding one
+                let font = chip8::font::named(&name).or_else(|error| match fs::read(&name) {
+                    Ok(bytes) => chip8::font::from_bytes(&bytes),
+                    Err(_) => Err(error),
+                })?;
+                chip_8.set_font(font);
+            }
+            "--machine-code" => {
+                #[cfg(feature = "cdp1802")]
+                chip_8.set_machine_code(true);
+                #[cfg(not(feature = "cdp1802"))]
+                return Err("--machine-code needs the cdp1802 feature".to_string());
+            }
+            "--profile" => chip_8.set_profiling(true),
+            "--autosave" => autosave = true,
+            "--seed" => {
+                seed = Some(
+                    args.next()
+                        .ok_or("Missing seed")?
+                        .parse()
+                        .map_err(|_| "Invalid seed")?,
+                );
+            }
+            "--layout" => {
+                layout = args.next().ok_or("Missing layout")?;
+                keymap = keymap::Keymap::preset(&layout)?;
+                layout_chosen = true;
+            }
+            "--keymap" => key_overrides.push(args.next().ok_or("Missing key mapping")?),
+            "--ipf" => {
+                timing = timing::TimingModel::FixedIpf(
+                    args.next()
+                        .ok_or("Missing instructions per frame")?
+                        .parse()
+                        .map_err(|_| "Invalid instructions per frame")?,
+                );
+                timing_chosen = true;
+            }
+            "--cps" => {
+                timing = timing::TimingModel::CyclesPerSecond(
+                    args.next()
+                        .ok_or("Missing cycles per second")?
+                        .parse()
+                        .map_err(|_| "Invalid cycles per second")?,
+                );
+                timing_chosen = true;
+            }
+            "--cheats" => {
+                cheats = Some(cheats::Cheats::load(
+                    &args.next().ok_or("Missing cheat file")?,
+                )?);
+            }
+            "--input-script" => {
+                input_script = Some(inputscript::InputScript::load(
+                    &args.next().ok_or("Missing input script")?,
+                )?);
+            }
+            "--dump-frames" => {
+                dump_directory = Some(args.next().ok_or("Missing frame directory")?);
+            }
+            "--record-audio" => {
+                audio_record_path = Some(args.next().ok_or("Missing WAV path")?);
+            }
+            "--audio-device" => {
+                audio_device = Some(args.next().ok_or("Missing audio device")?);
+            }
+            "--timing" => {
+                timing = match args.next().as_deref() {
+                    Some("ipf") => timing::TimingModel::default(),
+                    Some("vip") => timing::TimingModel::VipAccurate,
+                    _ => return Err("--timing takes ipf or vip".to_string()),
+                };
+                timing_chosen = true;
+            }
+            "--vsync" => {
+                vsync = match args.next().as_deref() {
+                    Some("on") => true,
+                    Some("off") => false,
+                    _ => return Err("--vsync takes on or off".to_string()),
+                };
+            }
+            "--fps-limit" => {
+                fps_limit = Some(
+                    args.next()
+                        .ok_or("Missing frame rate limit")?
+                        .parse()
+                        .map_err(|_| "Invalid frame rate limit")?,
+                );
+            }
+            "--scale" => {
+                scale = args
+                    .next()
+                    .ok_or("Missing scale")?
+                    .parse()
+                    .map_err(|_| "Invalid scale")?;
+                if scale == 0 {
+                    return Err("The scale has to be at least 1".to_string());
+                }
+            }
+            "--fullscreen" => fullscreen = true,
+            "--theme" => {
+                palette = theme::named(&args.next().ok_or("Missing theme")?)?;
+                palette_chosen = true;
+            }
+            "--pixel-outlines" => pixel_outlines = true,
+            "--grid" => grid = true,
+            "--magnifier" => magnifier.enabled = true,
+            "--debug-overlay" => debug_overlay.enabled = true,
+            "--heatmap" => heatmap.toggle(&mut chip_8),
+            "--bell" => bell = true,
+            "--mute" => mute = true,
+            "--slow-motion" => {
+                slow_motion = match args.next().as_deref() {
+                    Some("1") => 1,
+                    Some("0.5") => 2,
+                    Some("0.25") => 4,
+                    _ => return Err("--slow-motion takes 1, 0.5 or 0.25".to_string()),
+                };
+            }
+            "--scale-timers" => scale_timers = true,
+            "--timer-hz" => {
+                let hz: u32 = args
+                    .next()
+                    .ok_or("Missing timer rate")?
+                    .parse()
+                    .map_err(|_| "Invalid timer rate")?;
+                if hz == 0 {
+                    return Err("The timer rate has to be at least 1".to_string());
+                }
+                timer_hz = Some(hz);
+            }
+            "--quirks" => {
+                chip_8.set_quirks(chip8::Quirks::preset(
+                    &args.next().ok_or("Missing quirk preset")?,
+                )?);
+                quirks_chosen = true;
+            }
+            "--quirk" => {
+                let setting = args.next().ok_or("Missing quirk=value")?;
+                // checked now so a typo stops the run straight away
+                chip8::Quirks::default().set(&setting)?;
+                quirk_overrides.push(setting);
+            }
+            "--detect-quirks" => detect_quirks = true,
+            "--compare" => {
+                compare = Some(chip8::Quirks::preset(
+                    &args.next().ok_or("Missing quirk preset")?,
+                )?);
+            }
+            "--debug" => debug = true,
+            "-d" | "--echo" => echo = true,
+            "--symbols" => symbols_path = Some(args.next().ok_or("Missing symbol file")?),
+            "--script" => script_path = Some(args.next().ok_or("Missing script path")?),
+            "--frontend" => frontend_name = Some(args.next().ok_or("Missing frontend")?),
+            "--http" => http_address = Some(args.next().ok_or("Missing HTTP address")?),
+            "--stats-server" => {
+                stats_address = Some(args.next().ok_or("Missing stats server address")?)
+            }
+            "--debug-server" => {
+                debug_server_address = Some(args.next().ok_or("Missing debug server address")?)
+            }
+            "--touch-layout" => {
+                touch_layout =
+                    touch::TouchLayout::parse(&args.next().ok_or("Missing touch layout")?)?;
+            }
+            "--save-replay" => record_path = Some(args.next().ok_or("Missing replay path")?),
+            "--play-replay" => replay_path = Some(args.next().ok_or("Missing replay path")?),
+            "--log-level" => {
+                log::set_max_level(args.next().ok_or("Missing log level")?.parse()?);
+            }
+            "--coverage" => {
+                coverage_path = Some(args.next().ok_or("Missing coverage output path")?);
+                chip_8.set_coverage(true);
+            }
+            "--trace-json" => {
+                tracer = Some(trace::JsonTracer::create(
+                    &args.next().ok_or("Missing trace output path")?,
+                )?);
+            }
+            _ => game = Some(arg),
+        }
+    }
+    if debug && game.as_deref() == Some("-") {
+        return Err(
+            "The debugger reads commands from stdin, so the ROM can't come from it".to_string(),
+        );
+    }
+    // the sidecar's speed multiplier, applied once the speed hotkeys exist
+    let mut game_speed = None;
+    let mut game_overrides = None;
+    if let Some(path) = &game {
+        if let Some(settings) = sidecar::Settings::find(path)? {
+            info!("Using the settings in {}.toml", path);
+            if let (Some(ipf), false) = (settings.ipf, timing_chosen) {
+                timing = timing::TimingModel::FixedIpf(ipf);
+            }
+            if let (Some(quirks), false) = (settings.quirks, quirks_chosen) {
+                chip_8.set_quirks(quirks);
+                quirks_chosen = true;
+            }
+            if let (Some(colors), false) = (settings.palette, palette_chosen) {
+                palette = colors;
+                palette_chosen = true;
+            }
+            game_speed = settings.speed;
+            if let (Some(name), false) = (settings.layout, layout_chosen) {
+                keymap = keymap::Keymap::preset(&name)?;
+                layout = name;
+            }
+            game_overrides = settings.keymap;
+        }
+    }
+    // applied after the loop so they win over any --layout, the config's
+    // first, then the game's, then the command line's
+    for overrides in config
+        .keymap
+        .iter()
+        .chain(&game_overrides)
+        .chain(&key_overrides)
+    {
+        keymap.apply_overrides(overrides)?;
+    }
+    // the user's own, without the game's, for saving back to the config
+    let user_overrides = keymap::merge_overrides(config.keymap.iter().chain(&key_overrides));
+
+    // the VIP's timers tick with its display, at its rate
+    if timer_hz.is_some() && timing == timing::TimingModel::VipAccurate {
+        return Err("--timer-hz can't be used with --timing vip".to_string());
+    }
+
+    // the full window unless a frontend is asked for, --frontend sdl is a
+    // plain one
+    let name = frontend_name.unwrap_or_else(|| "window".to_string());
+    if echo && name != "window" {
+        return Err(format!("--echo prints over the {} frontend", name));
+    }
+    let mut frontends = chip8_frontend::Registry::default();
+    let sdl_keymap = keymap.clone();
+    frontends.register("sdl", move || {
+        sdl_frontend::SdlFrontend::create(sdl_keymap.clone(), palette, scale)
+    });
+    let full = name == "window";
+    // the window loads the game itself, it may open a menu to pick one
+    let options = Cell::new(full.then(|| window::Options {
+        game: game.clone(),
+        dev,
+        config,
+        layout,
+        keymap,
+        user_overrides,
+        touch_layout,
+        palette,
+        mute,
+        game_speed,
+        palette_chosen,
+        quirks_chosen,
+        quirk_overrides: quirk_overrides.clone(),
+        detect_quirks,
+        pixel_outlines,
+        grid,
+        magnifier,
+        debug_overlay,
+        heatmap,
+        vsync,
+        fps_limit: fps_limit.unwrap_or(if vsync { 0 } else { 60 }),
+        scale,
+        fullscreen,
+        bell,
+        slow_motion,
+        scale_timers,
+        compare,
+        debug,
+        echo,
+        seed,
+        autosave,
+        symbols_path,
+        script_path,
+        debug_server_address,
+        http_address,
+        stats_address,
+        audio_device,
+        audio_record_path,
+        input_script,
+        cheats,
+        dump_directory,
+        record_path,
+        replay_path,
+        coverage_path,
+        tracer,
+    }));
+    frontends.register("window", move || {
+        let options = options.take().ok_or("The window is already open")?;
+        window::FullWindow::create(options)
+    });
+    let mut frontend = frontends.create(&name)?;
+    if !full {
+        override_quirks(&mut chip_8, &quirk_overrides)?;
+        let rom = match &game {
+            Some(path) if dev || is_octo(path) => assemble_file(path)?.0,
+            Some(path) => read_rom(path)?,
+            None => asm::assemble(DEMO)?.0,
+        };
+        chip_8.load_buffer(&rom);
+        if let Some(seed) = seed {
+            chip_8.set_seed(seed);
+        }
+        if bell {
+            chip_8.set_sound_sink(audio::terminal_bell);
+        }
+    }
+    frontend.run(&mut chip_8, timing, timer_hz)
+}
+
+// chip8 themes, what --theme takes and how far apart each theme's colors are
+fn themes_command() -> Result<(), String> {
+    for (name, palette) in theme::THEMES {
+        println!("{:14} {:.1}:1", name, theme::contrast_ratio(&palette));
+    }
+    Ok(())
+}
+
+// chip8 audio-devices, the names --audio-device takes
+fn audio_devices_command() -> Result<(), String> {
+    let audio = sdl2::init()?.audio()?;
+    for name in audio::device_names(&audio)? {
+        println!("{}", name);
+    }
+    Ok(())
+}
+
+// --quirk settings, in the order they were given
+fn override_quirks(chip_8: &mut chip8::State, overrides: &[String]) -> Result<(), String> {
+    let mut quirks = chip_8.quirks();
+    for setting in overrides {
+        quirks.set(setting)?;
+    }
+    chip_8.set_quirks(quirks);
+    Ok(())
+}
+
+#[cfg(feature = "stats-server")]
+fn bind_stats_server(address: Option<&str>) -> Result<Option<stats_server::StatsServer>, String> {
+    address.map(stats_server::StatsServer::bind).transpose()
+}
+
+#[cfg(not(feature = "stats-server"))]
+fn bind_stats_server(address: Option<&str>) -> Result<(), String> {
+    match address {
+        Some(_) => Err("--stats-server needs the stats-server feature".to_string()),
+        None => Ok(()),
+    }
+}
+
+// chip8 batch roms/ [--frames 600] [--seed N] [--ipf N] [--report report.json]
+// [--baseline baseline.txt] [--save-baseline baseline.txt]
+// chip8 headless game.ch8 [--frames N] [--seed N] [--ipf N]
+// [--expect-screen reference.png|.txt] [--input-script keys.txt] [--bell]
+// prints the screen the program ends on, or fails if it isn't the reference
+fn headless_command(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let mut path = None;
+    let mut frames = 600;
+    let mut reference = None;
+    let mut input = inputscript::InputScript::default();
+    let mut bell = false;
+    let mut options = headless::Options::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => {
+                frames = args
+                    .next()
+                    .ok_or("Missing frame count")?
+                    .parse()
+                    .map_err(|_| "Invalid frame count")?
+            }
+            "--seed" => {
+                options.seed = args
+                    .next()
+                    .ok_or("Missing seed")?
+                    .parse()
+                    .map_err(|_| "Invalid seed")?
+            }
+            "--ipf" => {
+                options.ipf = args
+                    .next()
+                    .ok_or("Missing instructions per frame")?
+                    .parse()
+                    .map_err(|_| "Invalid instructions per frame")?
+            }
+            "--expect-screen" => reference = Some(args.next().ok_or("Missing reference screen")?),
+            "--input-script" => {
+                input = inputscript::InputScript::load(&args.next().ok_or("Missing input script")?)?
+            }
+            "--bell" => bell = true,
+            _ => path = Some(arg),
+        }
+    }
+    let rom = read_rom(&path.ok_or("No ROM provided")?)?;
+    let (chip_8, error) = headless::run_with_input(&rom, frames, options, &input, bell);
+    if let Some(error) = error {
+        warn!("Emulation halted: {}", error);
+    }
+    let screen = chip_8.get_graphics_buffer();
+    let Some(reference) = reference else {
+        print!("{}", screencheck::to_text(&screen));
+        return Ok(());
+    };
+    let expected = screencheck::load(&reference)?;
+    let (differences, text) = screencheck::compare(&expected, &screen);
+    if differences > 0 {
+        // + should be on, - should be off
+        print!("{}", text);
+        return Err(format!(
+            "{} pixel(s) differ from {} after {} frames",
+            differences, reference, frames
+        ));
+    }
+    println!("Screen matches {}", reference);
+    Ok(())
+}
+
+fn batch_command(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let mut directory = None;
+    let mut frames = 600;
+    let mut report = None;
+    let mut baseline = None;
+    let mut save_baseline = None;
+    let mut stats_address = None;
+    let mut options = headless::Options::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => {
+                frames = args
+                    .next()
+                    .ok_or("Missing frame count")?
+                    .parse()
+                    .map_err(|_| "Invalid frame count")?
+            }
+            "--seed" => {
+                options.seed = args
+                    .next()
+                    .ok_or("Missing seed")?
+                    .parse()
+                    .map_err(|_| "Invalid seed")?
+            }
+            "--ipf" => {
+                options.ipf = args
+                    .next()
+                    .ok_or("Missing instructions per frame")?
+                    .parse()
+                    .map_err(|_| "Invalid instructions per frame")?
+            }
+            "--report" => report = Some(args.next().ok_or("Missing report path")?),
+            "--baseline" => baseline = Some(args.next().ok_or("Missing baseline path")?),
+            "--save-baseline" => save_baseline = Some(args.next().ok_or("Missing baseline path")?),
+            "--stats-server" => {
+                stats_address = Some(args.next().ok_or("Missing stats server address")?)
+            }
+            _ => directory = Some(arg),
+        }
+    }
+    let directory = directory.ok_or("No ROM directory provided")?;
+    let directory = std::path::Path::new(&directory);
+
+    #[cfg(feature = "stats-server")]
+    let mut watch = {
+        let mut stats_server = bind_stats_server(stats_address.as_deref())?;
+        let total = batch::rom_paths(directory)?.len();
+        let mut current = String::new();
+        let mut started = 0;
+        move |rom: &str, chip_8: &chip8::State, frame: u64| {
+            let Some(stats_server) = &mut stats_server else {
+                return;
+            };
+            if rom != current {
+                current = rom.to_string();
+                started += 1;
+                stats_server.set_info("rom", current.clone());
+                stats_server.set_info("roms", format!("{}/{}", started, total));
+            }
+            stats_server.poll(chip_8, frame);
+        }
+    };
+    #[cfg(not(feature = "stats-server"))]
+    let mut watch = {
+        bind_stats_server(stats_address.as_deref())?;
+        |_: &str, _: &chip8::State, _: u64| {}
+    };
+    let outcomes = batch::run_directory(directory, frames, options, &mut watch)?;
+
+    let baseline = match baseline {
+        Some(path) => Some(batch::read_baseline(
+            &fs::read_to_string(path).map_err(|op| op.to_string())?,
+        )?),
+        None => None,
+    };
+    let mut regressions = 0;
+    for outcome in &outcomes {
+        let status = match baseline.as_ref().map(|baseline| baseline.get(&outcome.rom)) {
+            None => "DONE",
+            Some(None) => "NEW ",
+            Some(Some(expected)) if *expected == outcome.display => "PASS",
+            Some(Some(_)) => {
+                regressions += 1;
+                "FAIL"
+            }
+        };
+        println!(
+            "{} {} {}",
+            status,
+            outcome.rom,
+            outcome.error.as_deref().unwrap_or("ok")
+        );
+    }
+    if let Some(path) = report {
+        fs::write(path, batch::to_json(&outcomes)).map_err(|op| op.to_string())?;
+    }
+    if let Some(path) = save_baseline {
+        fs::write(path, batch::to_baseline(&outcomes)).map_err(|op| op.to_string())?;
+    }
+    if regressions > 0 {
+        return Err(format!("{} ROM(s) differ from the baseline", regressions));
+    }
+    Ok(())
+}
+
+// chip8 lockstep game.ch8 reference.jsonl
+fn lockstep_command(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let rom = fs::read(args.next().ok_or("No ROM provided")?).map_err(|op| op.to_string())?;
+    let reference = fs::read_to_string(args.next().ok_or("No reference trace provided")?)
+        .map_err(|op| op.to_string())?;
+    let mut chip_8 = chip8::State::new();
+    chip_8.initialize();
+    chip_8.load_buffer(&rom);
+    match trace::lockstep(&mut chip_8, &reference)? {
+        Some(report) => Err(report),
+        None => {
+            println!("No divergence from the reference trace");
+            Ok(())
+        }
+    }
+}
+
+// chip8 slots game.ch8
+fn slots_command(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let rom = fs::read(args.next().ok_or("No ROM provided")?).map_err(|op| op.to_string())?;
+    for slot in 1..=savestate::SLOTS {
+        match savestate::preview(&rom, slot) {
+            Ok(preview) => println!("{}", preview),
+            Err(_) => println!("Slot {}: empty\n", slot),
+        }
+    }
+    Ok(())
+}
+
+// chip8 diff-state a.state b.state
+fn diff_state_command(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let mut states = Vec::new();
+    for _ in 0..2 {
+        let path = args.next().ok_or("Two save states are needed")?;
+        let mut chip_8 = chip8::State::new();
+        chip_8.initialize();
+        savestate::read_state(std::path::Path::new(&path), &mut chip_8)
+            .map_err(|op| format!("{}: {}", path, op))?;
+        states.push(chip_8);
+    }
+    let lines = statediff::diff(&states[0], &states[1]);
+    if lines.is_empty() {
+        println!("The states are identical");
+    }
+    for line in lines {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+// chip8 bisect game.ch8 chip8 schip [--replay run.c8rp] [--cycles N]
+fn bisect_command(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let mut positional = Vec::new();
+    let mut replay_path = None;
+    let mut cycles = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--replay" => replay_path = Some(args.next().ok_or("Missing replay path")?),
+            "--cycles" => {
+                cycles = Some(
+                    args.next()
+                        .ok_or("Missing cycle count")?
+                        .parse()
+                        .map_err(|_| "Invalid cycle count")?,
+                )
+            }
+            _ => positional.push(arg),
+        }
+    }
+    let [path, first, second] = positional.as_slice() else {
+        return Err("Expected a ROM and two quirk presets".to_string());
+    };
+    let rom = read_rom(path)?;
+    let (first, second) = (
+        chip8::Quirks::preset(first)?,
+        chip8::Quirks::preset(second)?,
+    );
+    let replay = match replay_path {
+        Some(path) => Some(replay::Replay::from_bytes(
+            &fs::read(&path).map_err(|op| format!("{}: {}", path, op))?,
+        )?),
+        None => None,
+    };
+    let cycles = cycles
+        .or(replay.as_ref().map(|replay| replay.cycles))
+        .unwrap_or(BISECT_CYCLES);
+
+    let Some(divergence) = bisect::find_divergence(&rom, replay.as_ref(), first, second, cycles)?
+    else {
+        println!("No divergence in {} cycles", cycles);
+        return Ok(());
+    };
+    println!(
+        "Diverged at cycle {}: {:#05X}  {:04X}  {}",
+        divergence.cycle,
+        divergence.pc,
+        divergence.opcode,
+        disasm::disassemble(divergence.opcode)
+    );
+    if let Some(quirk) = bisect::quirk_for(divergence.opcode) {
+        println!("The instruction depends on the {} quirk", quirk);
+    }
+    for line in &divergence.differences {
+        println!("  {}", line);
+    }
+    // each quirk the presets disagree on, changed on its own
+    println!("Quirks that differ:");
+    let differing = first.to_bits() ^ second.to_bits();
+    for (bit, name) in chip8::Quirks::NAMES.iter().enumerate() {
+        if differing & 1 << bit == 0 {
+            continue;
+        }
+        let alone = chip8::Quirks::from_bits(first.to_bits() ^ 1 << bit);
+        match bisect::find_divergence(&rom, replay.as_ref(), first, alone, cycles)? {
+            Some(divergence) => println!("  {}: diverges at cycle {}", name, divergence.cycle),
+            None => println!("  {}: no effect in {} cycles", name, cycles),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,218 @@
+// Boot menu shown when a directory or an archive of several ROMs is given.
+// Keypad 2/8 (or the arrow keys) move, 5/6 (or Enter) launch.
+// Each ROM is shown with a thumbnail of its display after running briefly,
+// cached under the data directory and generated in the background.
+use crate::batch;
+use crate::hash;
+use crate::headless;
+use crate::keymap::Keymap;
+use crate::savestate;
+use crate::ui;
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use sdl2::EventPump;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+const SCALE: u32 = 3;
+const HEADER_HEIGHT: u32 = 9 * SCALE;
+// a thumbnail is drawn at one window pixel per CHIP-8 pixel
+const ROW_HEIGHT: u32 = 32 + 4;
+// a second at ten instructions per frame, enough for most title screens
+const THUMBNAIL_FRAMES: u64 = 60 * 10;
+
+pub struct Menu {
+    // file names and contents
+    roms: Vec<(String, Vec<u8>)>,
+    // the display of each ROM once it's known
+    thumbnails: Vec<Option<Vec<u8>>>,
+    // thumbnails that weren't cached arrive here as they're generated
+    generated: Receiver<(usize, Vec<u8>)>,
+    selected: usize,
+}
+
+impl Menu {
+    pub fn scan(directory: &str) -> Result<Self, String> {
+        let roms = batch::rom_paths(Path::new(directory))?
+            .into_iter()
+            .map(|path| {
+                let rom = fs::read(&path).map_err(|op| op.to_string())?;
+                Ok((path.to_string_lossy().into_owned(), rom))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        if roms.is_empty() {
+            return Err(format!("No .ch8 files in {}", directory));
+        }
+        Ok(Self::new(roms))
+    }
+
+    pub fn new(roms: Vec<(String, Vec<u8>)>) -> Self {
+        let mut thumbnails = Vec::with_capacity(roms.len());
+        let mut missing = Vec::new();
+        for (index, (_, rom)) in roms.iter().enumerate() {
+            let cached = thumbnail_path(rom)
+                .ok()
+                .and_then(|path| fs::read(path).ok())
+                .filter(|screen| screen.len() == 64 * 32);
+            if cached.is_none() {
+                missing.push((index, rom.clone()));
+            }
+            thumbnails.push(cached);
+        }
+        let (sender, generated) = mpsc::channel();
+        thread::spawn(move || {
+            for (index, rom) in missing {
+                if let Some(screen) = generate_thumbnail(&rom) {
+                    if sender.send((index, screen)).is_err() {
+                        // the menu is gone
+                        return;
+                    }
+                }
+            }
+        });
+
+        Self {
+            roms,
+            thumbnails,
+            generated,
+            selected: 0,
+        }
+    }
+
+    // Runs until a ROM is picked, None if the window was closed first
+    pub fn choose(
+        mut self,
+        canvas: &mut Canvas<Window>,
+        event_pump: &mut EventPump,
+        keymap: &Keymap,
+        area: Rect,
+    ) -> Result<Option<(String, Vec<u8>)>, String> {
+        loop {
+            while let Ok((index, screen)) = self.generated.try_recv() {
+                self.thumbnails[index] = Some(screen);
+            }
+            self.draw(canvas, area)?;
+            for event in event_pump.poll_iter() {
+                match event {
+                    Event::Quit { .. } => return Ok(None),
+                    Event::KeyDown {
+                        keycode, scancode, ..
+                    } => {
+                        let key = keymap.lookup(keycode, scancode);
+                        if keycode == Some(Keycode::Up) || key == Some(0x2) {
+                            self.selected = self.selected.saturating_sub(1);
+                        } else if keycode == Some(Keycode::Down) || key == Some(0x8) {
+                            self.selected = (self.selected + 1).min(self.roms.len() - 1);
+                        } else if keycode == Some(Keycode::Return)
+                            || key == Some(0x5)
+                            || key == Some(0x6)
+                        {
+                            return Ok(Some(self.roms.swap_remove(self.selected)));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
+        }
+    }
+
+    fn draw(&self, canvas: &mut Canvas<Window>, area: Rect) -> Result<(), String> {
+        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        canvas.clear();
+        let x = area.x() + (2 * SCALE) as i32;
+        ui::draw_text(
+            canvas,
+            "SELECT A ROM",
+            x,
+            area.y() + (2 * SCALE) as i32,
+            SCALE,
+            Color::RGB(255, 255, 0),
+        )?;
+
+        // keep the selection on screen, scrolling a page at a time
+        let rows = ((area.height().saturating_sub(HEADER_HEIGHT) / ROW_HEIGHT) as usize).max(1);
+        let first = self.selected / rows * rows;
+        let text_x = x + 64 + (2 * SCALE) as i32;
+        let columns = ((area.right() - text_x) as u32 / (4 * SCALE)) as usize;
+        for (row, (file_name, _)) in self.roms.iter().enumerate().skip(first).take(rows) {
+            let y = area.y() + (HEADER_HEIGHT + (row - first) as u32 * ROW_HEIGHT) as i32;
+            let color = if row == self.selected {
+                canvas.set_draw_color(Color::RGB(200, 200, 200));
+                canvas.fill_rect(Rect::new(area.x(), y, area.width(), ROW_HEIGHT))?;
+                Color::RGB(0, 0, 0)
+            } else {
+                Color::RGB(255, 255, 255)
+            };
+
+            let thumbnail = Rect::new(x, y + 2, 64, 32);
+            canvas.set_draw_color(Color::RGB(40, 40, 40));
+            canvas.fill_rect(thumbnail)?;
+            if let Some(screen) = &self.thumbnails[row] {
+                let pixels: Vec<Rect> = screen
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, pixel)| **pixel == 1)
+                    .map(|(index, _)| {
+                        Rect::new(
+                            thumbnail.x() + (index % 64) as i32,
+                            thumbnail.y() + (index / 64) as i32,
+                            1,
+                            1,
+                        )
+                    })
+                    .collect();
+                canvas.set_draw_color(Color::RGB(255, 255, 255));
+                canvas.fill_rects(&pixels)?;
+            }
+
+            let name: String = Path::new(file_name)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_uppercase())
+                .unwrap_or_default()
+                .chars()
+                .take(columns)
+                .collect();
+            let text_y = y + ((ROW_HEIGHT - 5 * SCALE) / 2) as i32;
+            ui::draw_text(canvas, &name, text_x, text_y, SCALE, color)?;
+        }
+        canvas.present();
+        Ok(())
+    }
+}
+
+// Thumbnails are keyed by the ROM's hash like save states
+fn thumbnail_path(rom: &[u8]) -> Result<PathBuf, String> {
+    Ok(savestate::data_dir()?
+        .join("thumbnails")
+        .join(format!("{}.bin", hash::to_hex(&hash::sha1(rom)))))
+}
+
+// Runs rom headlessly and caches its display, None if it panicked
+fn generate_thumbnail(rom: &[u8]) -> Option<Vec<u8>> {
+    let screen = panic::catch_unwind(AssertUnwindSafe(|| {
+        headless::run(rom, THUMBNAIL_FRAMES, headless::Options::default())
+            .0
+            .get_graphics_buffer()
+    }))
+    .ok()?;
+    match thumbnail_path(rom) {
+        Ok(path) => {
+            let written =
+                fs::create_dir_all(path.parent().unwrap()).and_then(|_| fs::write(&path, &screen));
+            if let Err(error) = written {
+                warn!("Couldn't cache thumbnail {}: {}", path.display(), error);
+            }
+        }
+        Err(error) => warn!("Couldn't cache thumbnail: {}", error),
+    }
+    Some(screen)
+}
@@ -0,0 +1,319 @@
+// The debug overlay, --debug-overlay or Ctrl+D: panels over the bottom right
+// of the display showing parts of the machine as it runs. History is kept
+// while it's hidden so it's there as soon as it's shown.
+use crate::chip8::State;
+use crate::symbols::Symbols;
+use crate::ui::{draw_text, KEYPAD_LAYOUT};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use std::collections::VecDeque;
+
+const SCALE: u32 = 2;
+const LINE_HEIGHT: u32 = 7 * SCALE;
+const WIDTH: u32 = 3 * HISTORY as u32;
+// frames of history
+const HISTORY: usize = 64;
+const SPARKLINE_HEIGHT: u32 = 16;
+// return addresses listed, from the top of the stack
+const STACK_ROWS: usize = 6;
+// frames a push or pop stays highlighted
+const FLASH_FRAMES: u8 = 20;
+// the keypad's cells, square
+const KEY_SIZE: u32 = 14;
+// frames a key is held before it looks stuck, five seconds
+const STUCK_FRAMES: u32 = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StackChange {
+    Push,
+    Pop,
+}
+
+pub struct DebugOverlay {
+    pub enabled: bool,
+    // the delay and sound timers, oldest first
+    timers: VecDeque<(u8, u8)>,
+    stack: Vec<u16>,
+    stack_limit: usize,
+    // the last push or pop and the frames left to highlight it
+    stack_change: Option<(StackChange, u8)>,
+    symbols: Symbols,
+    // the keys the core sees as down, and for how many frames each has been
+    keys: u16,
+    held: [u32; 16],
+}
+
+impl DebugOverlay {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            timers: VecDeque::with_capacity(HISTORY),
+            stack: Vec::new(),
+            stack_limit: 0,
+            stack_change: None,
+            symbols: Symbols::default(),
+            keys: 0,
+            held: [0; 16],
+        }
+    }
+
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    // Call once a frame, true when the overlay needs drawing again
+    pub fn sample(&mut self, chip_8: &State) -> bool {
+        let timers = (chip_8.delay_timer(), chip_8.sound_timer());
+        let mut changed = self.timers.back() != Some(&timers);
+        if self.timers.len() == HISTORY {
+            self.timers.pop_front();
+        }
+        self.timers.push_back(timers);
+
+        // only what's left at the end of the frame is seen, a call and its
+        // return in the same frame don't show
+        let stack = chip_8.stack();
+        if stack != self.stack {
+            let change = if stack.len() < self.stack.len() {
+                StackChange::Pop
+            } else {
+                StackChange::Push
+            };
+            self.stack_change = Some((change, FLASH_FRAMES));
+            self.stack = stack.to_vec();
+            changed = true;
+        } else if let Some((change, frames)) = self.stack_change {
+            // fading out, then drawn once more without it
+            self.stack_change = (frames > 1).then_some((change, frames - 1));
+            changed = true;
+        }
+        changed |= self.stack_limit != chip_8.stack_limit();
+        self.stack_limit = chip_8.stack_limit();
+
+        let keys = chip_8.keys();
+        changed |= keys != self.keys;
+        self.keys = keys;
+        for (key, held) in self.held.iter_mut().enumerate() {
+            if keys & (1 << key) == 0 {
+                *held = 0;
+            } else {
+                *held += 1;
+                // the seconds shown once it looks stuck
+                changed |= *held >= STUCK_FRAMES && *held % 60 == 0;
+            }
+        }
+        self.enabled && changed
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas<Window>, area: Rect) -> Result<(), String> {
+        if !self.enabled {
+            return Ok(());
+        }
+        let height = Self::keypad_height() + Self::stack_height() + 2 * Self::timer_height();
+        let left = area.right() - WIDTH as i32 - 8;
+        let top = area.bottom() - height as i32 - 8;
+        canvas.set_draw_color(Color::RGB(40, 40, 40));
+        canvas.fill_rect(Rect::new(left - 4, top - 4, WIDTH + 8, height + 8))?;
+        self.draw_keypad(canvas, left, top)?;
+        let top = top + Self::keypad_height() as i32;
+        self.draw_stack(canvas, left, top)?;
+        let top = top + Self::stack_height() as i32;
+        self.draw_timer(canvas, left, top, "DT", |(delay, _)| delay)?;
+        self.draw_timer(
+            canvas,
+            left,
+            top + Self::timer_height() as i32,
+            "ST",
+            |(_, sound)| sound,
+        )
+    }
+
+    fn keypad_height() -> u32 {
+        LINE_HEIGHT + 4 * KEY_SIZE + 4
+    }
+
+    // The keys the core sees as down, laid out like the VIP's keypad, the
+    // ones held long enough to look stuck in orange. Beside it the key bits
+    // as set_keys takes them.
+    fn draw_keypad(&self, canvas: &mut Canvas<Window>, left: i32, top: i32) -> Result<(), String> {
+        draw_text(
+            canvas,
+            "KEYPAD",
+            left,
+            top,
+            SCALE,
+            Color::RGB(255, 255, 255),
+        )?;
+        let grid_top = top + LINE_HEIGHT as i32;
+        for (index, key) in KEYPAD_LAYOUT.iter().enumerate() {
+            let held = self.held[*key as usize];
+            let (background, text) = if held >= STUCK_FRAMES {
+                (Color::RGB(255, 160, 0), Color::RGB(0, 0, 0))
+            } else if held > 0 {
+                (Color::RGB(80, 255, 80), Color::RGB(0, 0, 0))
+            } else {
+                (Color::RGB(80, 80, 80), Color::RGB(255, 255, 255))
+            };
+            let x = left + (index as u32 % 4 * KEY_SIZE) as i32;
+            let y = grid_top + (index as u32 / 4 * KEY_SIZE) as i32;
+            canvas.set_draw_color(background);
+            canvas.fill_rect(Rect::new(x, y, KEY_SIZE - 2, KEY_SIZE - 2))?;
+            draw_text(canvas, &format!("{:X}", key), x + 3, y + 2, SCALE, text)?;
+        }
+        let x = left + (4 * KEY_SIZE) as i32 + 4;
+        draw_text(
+            canvas,
+            &format!("{:016b}", self.keys),
+            x,
+            grid_top,
+            SCALE,
+            Color::RGB(255, 255, 255),
+        )?;
+        let longest = self.held.iter().copied().max().unwrap_or(0);
+        if longest >= STUCK_FRAMES {
+            let y = grid_top + LINE_HEIGHT as i32;
+            let text = format!("HELD {}S", longest / 60);
+            draw_text(canvas, &text, x, y, SCALE, Color::RGB(255, 160, 0))?;
+        }
+        Ok(())
+    }
+
+    fn stack_height() -> u32 {
+        (STACK_ROWS as u32 + 2) * LINE_HEIGHT
+    }
+
+    // The depth against the limit, then the return addresses from the top
+    // down with the newest flashing green when pushed or the top red when
+    // something was popped off it
+    fn draw_stack(&self, canvas: &mut Canvas<Window>, left: i32, top: i32) -> Result<(), String> {
+        let depth = self.stack.len();
+        // two calls from overflowing
+        let near_full = depth + 2 >= self.stack_limit;
+        let flash = self.stack_change.map(|(change, _)| change);
+        let header_color = if near_full {
+            Color::RGB(255, 80, 80)
+        } else {
+            Color::RGB(255, 255, 255)
+        };
+        draw_text(
+            canvas,
+            &format!("STACK {}/{}", depth, self.stack_limit),
+            left,
+            top,
+            SCALE,
+            header_color,
+        )?;
+        // one segment per slot
+        let bar_top = top + LINE_HEIGHT as i32 - 4;
+        let slot = WIDTH / self.stack_limit.max(1) as u32;
+        for index in 0..self.stack_limit {
+            canvas.set_draw_color(if index >= depth {
+                Color::RGB(80, 80, 80)
+            } else if near_full {
+                Color::RGB(255, 80, 80)
+            } else {
+                Color::RGB(0, 200, 255)
+            });
+            canvas.fill_rect(Rect::new(
+                left + (index as u32 * slot) as i32,
+                bar_top,
+                slot.saturating_sub(1).max(1),
+                SCALE * 2,
+            ))?;
+        }
+
+        if depth == 0 {
+            let color = if flash == Some(StackChange::Pop) {
+                Color::RGB(255, 80, 80)
+            } else {
+                Color::RGB(128, 128, 128)
+            };
+            let y = top + LINE_HEIGHT as i32 + 4;
+            return draw_text(canvas, "EMPTY", left, y, SCALE, color);
+        }
+        let columns = (WIDTH / (4 * SCALE)) as usize;
+        for (row, address) in self.stack.iter().rev().take(STACK_ROWS).enumerate() {
+            // where it returns to, after the call
+            let text = format!(
+                "{:X} {}",
+                depth - 1 - row,
+                self.symbols.describe(address.wrapping_add(2))
+            );
+            let color = match flash {
+                Some(StackChange::Push) if row == 0 => Color::RGB(80, 255, 80),
+                Some(StackChange::Pop) if row == 0 => Color::RGB(255, 80, 80),
+                _ => Color::RGB(255, 255, 255),
+            };
+            let y = top + ((row + 1) as u32 * LINE_HEIGHT) as i32 + 4;
+            let text: String = text.chars().take(columns).collect();
+            draw_text(canvas, &text, left, y, SCALE, color)?;
+        }
+        if depth > STACK_ROWS {
+            let y = top + ((STACK_ROWS + 1) as u32 * LINE_HEIGHT) as i32 + 4;
+            let text = format!("+{} more", depth - STACK_ROWS);
+            draw_text(canvas, &text, left, y, SCALE, Color::RGB(128, 128, 128))?;
+        }
+        Ok(())
+    }
+
+    fn timer_height() -> u32 {
+        LINE_HEIGHT + SCALE * 3 + SPARKLINE_HEIGHT + 4
+    }
+
+    // The timer's value, a bar shrinking as it counts down and its history,
+    // all scaled to the largest value in the history so short waits show too
+    fn draw_timer(
+        &self,
+        canvas: &mut Canvas<Window>,
+        left: i32,
+        top: i32,
+        name: &str,
+        value: impl Fn((u8, u8)) -> u8,
+    ) -> Result<(), String> {
+        let values: Vec<u32> = self
+            .timers
+            .iter()
+            .map(|&timers| value(timers) as u32)
+            .collect();
+        let current = values.last().copied().unwrap_or(0);
+        let most = values.iter().copied().max().unwrap_or(0).max(1);
+        let color = if current > 0 {
+            Color::RGB(255, 255, 0)
+        } else {
+            Color::RGB(128, 128, 128)
+        };
+        draw_text(
+            canvas,
+            &format!("{} {:02X} {:3}", name, current, current),
+            left,
+            top,
+            SCALE,
+            color,
+        )?;
+
+        let bar_top = top + LINE_HEIGHT as i32;
+        canvas.set_draw_color(Color::RGB(80, 80, 80));
+        canvas.fill_rect(Rect::new(left, bar_top, WIDTH, SCALE * 2))?;
+        if current > 0 {
+            canvas.set_draw_color(color);
+            canvas.fill_rect(Rect::new(left, bar_top, WIDTH * current / most, SCALE * 2))?;
+        }
+
+        let bottom = bar_top + (SCALE * 3 + SPARKLINE_HEIGHT) as i32;
+        canvas.set_draw_color(Color::RGB(0, 200, 255));
+        for (index, value) in values.iter().enumerate() {
+            let height = SPARKLINE_HEIGHT * value / most;
+            if height > 0 {
+                canvas.fill_rect(Rect::new(
+                    left + index as i32 * 3,
+                    bottom - height as i32,
+                    3,
+                    height,
+                ))?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,241 @@
+// The menu Esc opens over the display, so the emulator can be driven without
+// the command line once it's running. The machine stays paused while it's
+// open. Up/Down move, Left/Right change a setting, Enter picks and Esc goes
+// back a page or closes it.
+use crate::batch;
+use crate::ui;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use std::path::{Path, PathBuf};
+
+const SCALE: u32 = 3;
+const ROW_HEIGHT: u32 = 5 * SCALE + 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Setting {
+    Palette,
+    Speed,
+    Sound,
+    Quirks,
+    Keymap,
+}
+
+const SETTINGS: [(Setting, &str); 5] = [
+    (Setting::Palette, "PALETTE"),
+    (Setting::Speed, "SPEED"),
+    (Setting::Sound, "SOUND"),
+    (Setting::Quirks, "QUIRKS"),
+    (Setting::Keymap, "KEYMAP"),
+];
+
+// What the frontend should do about a key press
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Resume,
+    Reset,
+    Load(String),
+    // a setting moved a step forwards (1) or backwards (-1)
+    Adjust(Setting, i32),
+    // keep the current settings for the running ROM
+    SaveForGame,
+    Quit,
+}
+
+// The settings' current values, shown next to their names
+pub struct Values {
+    pub palette: String,
+    pub speed: String,
+    pub sound: String,
+    pub quirks: String,
+    pub keymap: String,
+}
+
+impl Values {
+    fn get(&self, setting: Setting) -> &str {
+        match setting {
+            Setting::Palette => &self.palette,
+            Setting::Speed => &self.speed,
+            Setting::Sound => &self.sound,
+            Setting::Quirks => &self.quirks,
+            Setting::Keymap => &self.keymap,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Page {
+    Main,
+    Settings,
+    // the ROMs in the directory the game came from
+    Roms(Vec<PathBuf>),
+}
+
+const MAIN_ITEMS: [&str; 5] = ["RESUME", "RESET", "LOAD ROM", "SETTINGS", "QUIT"];
+
+pub struct PauseMenu {
+    page: Option<Page>,
+    selected: usize,
+    directory: PathBuf,
+}
+
+impl PauseMenu {
+    // Load ROM lists the .ch8 files next to game
+    pub fn new(game: &str) -> Self {
+        let directory = Path::new(game)
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .map_or_else(|| PathBuf::from("."), Path::to_path_buf);
+        Self {
+            page: None,
+            selected: 0,
+            directory,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.page.is_some()
+    }
+
+    pub fn open(&mut self) {
+        self.show(Page::Main);
+    }
+
+    fn show(&mut self, page: Page) {
+        self.page = Some(page);
+        self.selected = 0;
+    }
+
+    fn rows(&self) -> usize {
+        match &self.page {
+            None => 0,
+            Some(Page::Main) => MAIN_ITEMS.len(),
+            // the settings, saving them for the game and Back
+            Some(Page::Settings) => SETTINGS.len() + 2,
+            Some(Page::Roms(roms)) => roms.len() + 1,
+        }
+    }
+
+    pub fn key(&mut self, keycode: Option<Keycode>) -> Option<Action> {
+        let page = self.page.clone()?;
+        let rows = self.rows();
+        match keycode? {
+            Keycode::Up => self.selected = (self.selected + rows - 1) % rows,
+            Keycode::Down => self.selected = (self.selected + 1) % rows,
+            Keycode::Escape if page == Page::Main => return self.close(Action::Resume),
+            Keycode::Escape => self.show(Page::Main),
+            Keycode::Left | Keycode::Right if page == Page::Settings => {
+                let step = if keycode == Some(Keycode::Left) {
+                    -1
+                } else {
+                    1
+                };
+                return SETTINGS
+                    .get(self.selected)
+                    .map(|(setting, _)| Action::Adjust(*setting, step));
+            }
+            Keycode::Return | Keycode::KpEnter => return self.choose(page),
+            _ => {}
+        }
+        None
+    }
+
+    fn choose(&mut self, page: Page) -> Option<Action> {
+        match page {
+            Page::Main => match self.selected {
+                0 => self.close(Action::Resume),
+                1 => self.close(Action::Reset),
+                2 => {
+                    let roms = batch::rom_paths(&self.directory).unwrap_or_default();
+                    self.show(Page::Roms(roms));
+                    None
+                }
+                3 => {
+                    self.show(Page::Settings);
+                    None
+                }
+                _ => self.close(Action::Quit),
+            },
+            Page::Settings => match SETTINGS.get(self.selected) {
+                Some((setting, _)) => Some(Action::Adjust(*setting, 1)),
+                None if self.selected == SETTINGS.len() => Some(Action::SaveForGame),
+                None => {
+                    self.show(Page::Main);
+                    None
+                }
+            },
+            Page::Roms(roms) => match roms.get(self.selected) {
+                Some(path) => self.close(Action::Load(path.to_string_lossy().into_owned())),
+                None => {
+                    self.show(Page::Main);
+                    None
+                }
+            },
+        }
+    }
+
+    fn close(&mut self, action: Action) -> Option<Action> {
+        self.page = None;
+        Some(action)
+    }
+
+    // Draws the open page over area
+    pub fn draw(
+        &self,
+        canvas: &mut Canvas<Window>,
+        area: Rect,
+        values: &Values,
+    ) -> Result<(), String> {
+        let Some(page) = &self.page else {
+            return Ok(());
+        };
+        let (title, labels): (&str, Vec<String>) = match page {
+            Page::Main => (
+                "PAUSED",
+                MAIN_ITEMS.iter().map(|item| item.to_string()).collect(),
+            ),
+            Page::Settings => (
+                "SETTINGS",
+                SETTINGS
+                    .iter()
+                    .map(|(setting, name)| format!("{}: {}", name, values.get(*setting)))
+                    .chain(["SAVE FOR THIS GAME".to_string(), "BACK".to_string()])
+                    .collect(),
+            ),
+            Page::Roms(roms) => (
+                "LOAD ROM",
+                roms.iter()
+                    .map(|path| {
+                        path.file_name()
+                            .map_or_else(String::new, |name| name.to_string_lossy().into_owned())
+                    })
+                    .chain(["BACK".to_string()])
+                    .collect(),
+            ),
+        };
+
+        canvas.set_draw_color(Color::RGB(20, 20, 20));
+        canvas.fill_rect(area)?;
+        let left = area.x() + ROW_HEIGHT as i32;
+        let top = area.y() + ROW_HEIGHT as i32 / 2;
+        ui::draw_text(canvas, title, left, top, SCALE, Color::RGB(128, 128, 128))?;
+        // the rows under the title that fit, scrolled to keep the selection
+        // in view
+        let fits = ((area.height() / ROW_HEIGHT) as usize)
+            .saturating_sub(2)
+            .max(1);
+        let first = self.selected.saturating_sub(fits - 1);
+        for (row, label) in labels.iter().enumerate().skip(first).take(fits) {
+            let color = if row == self.selected {
+                Color::RGB(255, 255, 0)
+            } else {
+                Color::RGB(255, 255, 255)
+            };
+            let y = top + ((row - first + 1) as u32 * ROW_HEIGHT) as i32;
+            ui::draw_text(canvas, label, left, y, SCALE, color)?;
+        }
+        Ok(())
+    }
+}
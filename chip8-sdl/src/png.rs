@@ -0,0 +1,221 @@
+// Writes the display as a grayscale or two color PNG, with the image data in
+// stored (uncompressed) deflate blocks so no compressor is needed, and reads
+// PNGs back for comparing screens against
+use crate::hash;
+use crate::inflate;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+// the most a stored deflate block holds
+const BLOCK_LENGTH: usize = 0xFFFF;
+
+// screen is one byte per pixel, width wide, each pixel becomes a scale by
+// scale square. Used by --http, which a build may not have.
+#[allow(dead_code)]
+pub fn encode(screen: &[u8], width: usize, scale: usize) -> Vec<u8> {
+    encode_image(screen, width, scale, None)
+}
+
+// The same in the off and on colors of palette
+pub fn encode_colored(
+    screen: &[u8],
+    width: usize,
+    scale: usize,
+    palette: &[[u8; 3]; 2],
+) -> Vec<u8> {
+    encode_image(screen, width, scale, Some(palette))
+}
+
+fn encode_image(
+    screen: &[u8],
+    width: usize,
+    scale: usize,
+    palette: Option<&[[u8; 3]; 2]>,
+) -> Vec<u8> {
+    let height = screen.len() / width;
+    let mut raw = Vec::new();
+    for row in screen.chunks(width) {
+        let mut line = vec![0];
+        for pixel in row {
+            let value = match (*pixel != 0, palette) {
+                // an index into the palette
+                (on, Some(_)) => on as u8,
+                (true, None) => 0xFF,
+                (false, None) => 0,
+            };
+            line.extend(std::iter::repeat_n(value, scale));
+        }
+        for _ in 0..scale {
+            raw.extend_from_slice(&line);
+        }
+    }
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&((width * scale) as u32).to_be_bytes());
+    header.extend_from_slice(&((height * scale) as u32).to_be_bytes());
+    // 8 bit grayscale or palette indices, default compression, filter and
+    // no interlacing
+    let color_type = if palette.is_some() { 3 } else { 0 };
+    header.extend_from_slice(&[8, color_type, 0, 0, 0]);
+
+    let mut png = SIGNATURE.to_vec();
+    chunk(&mut png, b"IHDR", &header);
+    if let Some(palette) = palette {
+        chunk(&mut png, b"PLTE", palette.as_flattened());
+    }
+    chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = hash::crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = data.chunks(BLOCK_LENGTH).collect();
+    for (index, block) in blocks.iter().enumerate() {
+        stream.push((index == blocks.len() - 1) as u8);
+        let length = block.len() as u16;
+        stream.extend_from_slice(&length.to_le_bytes());
+        stream.extend_from_slice(&(!length).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    stream.extend_from_slice(&(b << 16 | a).to_be_bytes());
+    stream
+}
+
+// Reads a non-interlaced PNG of any color type, as its width and the
+// brightness of every pixel from 0 to 255
+pub fn decode(data: &[u8]) -> Result<(usize, Vec<u8>), String> {
+    if !data.starts_with(&SIGNATURE) {
+        return Err("Not a PNG".to_string());
+    }
+    let mut header = None;
+    let mut palette = Vec::new();
+    let mut compressed = Vec::new();
+    let mut rest = &data[SIGNATURE.len()..];
+    while rest.len() >= 12 {
+        let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+        let kind = &rest[4..8];
+        let body = rest.get(8..8 + length).ok_or("PNG ends early")?;
+        match kind {
+            b"IHDR" if length >= 13 => header = Some(body.to_vec()),
+            b"PLTE" => palette = body.to_vec(),
+            b"IDAT" => compressed.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+        rest = rest.get(12 + length..).ok_or("PNG ends early")?;
+    }
+    let header = header.ok_or("PNG has no header")?;
+    let width = u32::from_be_bytes(header[0..4].try_into().unwrap()) as usize;
+    let height = u32::from_be_bytes(header[4..8].try_into().unwrap()) as usize;
+    let (depth, color_type, interlace) = (header[8] as usize, header[9], header[12]);
+    if interlace != 0 {
+        return Err("Interlaced PNGs aren't supported".to_string());
+    }
+    let channels = match color_type {
+        0 | 3 => 1,
+        2 => 3,
+        4 => 2,
+        6 => 4,
+        _ => return Err(format!("Unknown PNG color type {}", color_type)),
+    };
+    let pixel_bits = channels * depth;
+    // filters work on whole bytes, a pixel smaller than one counts as one
+    let pixel_bytes = pixel_bits.div_ceil(8);
+    let line_length = (width * pixel_bits).div_ceil(8);
+    let raw = inflate::zlib_decompress(&compressed)?;
+    if raw.len() < (line_length + 1) * height {
+        return Err("PNG image data is too short".to_string());
+    }
+
+    let mut previous = vec![0; line_length];
+    let mut brightness = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let start = y * (line_length + 1);
+        let filter = raw[start];
+        let mut line = raw[start + 1..start + 1 + line_length].to_vec();
+        for index in 0..line_length {
+            let left = if index >= pixel_bytes {
+                line[index - pixel_bytes]
+            } else {
+                0
+            };
+            let up = previous[index];
+            let up_left = if index >= pixel_bytes {
+                previous[index - pixel_bytes]
+            } else {
+                0
+            };
+            let predicted = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return Err(format!("Unknown PNG filter {}", filter)),
+            };
+            line[index] = line[index].wrapping_add(predicted);
+        }
+        for x in 0..width {
+            // the most significant 8 bits of each channel
+            let sample = |channel: usize| -> u8 {
+                let bit = (x * channels + channel) * depth;
+                match depth {
+                    8 | 16 => line[bit / 8],
+                    _ => {
+                        let value = (line[bit / 8] >> (8 - depth - bit % 8)) & ((1 << depth) - 1);
+                        if color_type == 3 {
+                            value
+                        } else {
+                            (value as u32 * 255 / ((1 << depth) - 1)) as u8
+                        }
+                    }
+                }
+            };
+            let [red, green, blue] = match color_type {
+                0 | 4 => [sample(0); 3],
+                3 => {
+                    let index = sample(0) as usize * 3;
+                    let color = palette
+                        .get(index..index + 3)
+                        .ok_or("PNG color outside its palette")?;
+                    [color[0], color[1], color[2]]
+                }
+                _ => [sample(0), sample(1), sample(2)],
+            };
+            brightness
+                .push(((red as u32 * 299 + green as u32 * 587 + blue as u32 * 114) / 1000) as u8);
+        }
+        previous = line;
+    }
+    Ok((width, brightness))
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let (to_left, to_up, to_up_left) = (
+        (estimate - left as i16).abs(),
+        (estimate - up as i16).abs(),
+        (estimate - up_left as i16).abs(),
+    );
+    if to_left <= to_up && to_left <= to_up_left {
+        left
+    } else if to_up <= to_up_left {
+        up
+    } else {
+        up_left
+    }
+}
@@ -0,0 +1,96 @@
+// Drawing a CHIP-8 display into an SDL canvas, for the full window and
+// --frontend sdl alike.
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, TextureCreator};
+use sdl2::video::{Window, WindowContext};
+
+// On high-DPI displays the window is sized in logical pixels but drawn at the
+// display's resolution, so drawing is scaled to keep using logical pixels.
+// Returns the scale factor.
+pub fn fit_output(canvas: &mut Canvas<Window>) -> Result<f32, String> {
+    let (width, _) = canvas.window().size();
+    let (output_width, _) = canvas.output_size()?;
+    let ratio = output_width as f32 / width as f32;
+    canvas.set_scale(ratio, ratio)?;
+    Ok(ratio)
+}
+
+// Draws a CHIP-8 screen into area in the off and on colors of palette,
+// pixels that differ from other are shown in red. With outlines, lit pixels
+// big enough for it get an edge in the off color.
+pub fn draw_display(
+    canvas: &mut Canvas<Window>,
+    texture_creator: &TextureCreator<WindowContext>,
+    screen: &[u8],
+    other: Option<&[u8]>,
+    palette: &[[u8; 3]; 2],
+    outlines: bool,
+    area: Rect,
+) -> Result<(), String> {
+    let mut texture = texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGB24, 64, 32)
+        .map_err(|op| op.to_string())?;
+    texture.with_lock(Rect::new(0, 0, 64, 32), |buffer, _| {
+        for (index, value) in screen.iter().enumerate() {
+            let different = other.is_some_and(|other| other[index] != *value);
+            let color = match (*value, different) {
+                (_, true) => [255, 0, 0],
+                (1, false) => palette[1],
+                _ => palette[0],
+            };
+            buffer[index * 3..index * 3 + 3].copy_from_slice(&color);
+        }
+    })?;
+    canvas.copy(&texture, None, area)?;
+
+    let (width, height) = (area.width() as i32, area.height() as i32);
+    if !outlines || width < 64 * 3 || height < 32 * 3 {
+        return Ok(());
+    }
+    let edges: Vec<Rect> = screen
+        .iter()
+        .enumerate()
+        .filter(|(_, value)| **value == 1)
+        .map(|(index, _)| {
+            let (x, y) = ((index % 64) as i32, (index / 64) as i32);
+            let left = area.x() + width * x / 64;
+            let top = area.y() + height * y / 32;
+            Rect::new(
+                left,
+                top,
+                (area.x() + width * (x + 1) / 64 - left) as u32,
+                (area.y() + height * (y + 1) / 32 - top) as u32,
+            )
+        })
+        .collect();
+    let [red, green, blue] = palette[0];
+    canvas.set_draw_color(Color::RGB(red, green, blue));
+    canvas.draw_rects(&edges)
+}
+
+// Lines between the pixels of a display drawn into area, a quarter of the
+// way from the off color to the on one. Left out while pixels are too small
+// for lines between them to leave anything to see.
+pub fn draw_grid(
+    canvas: &mut Canvas<Window>,
+    area: Rect,
+    palette: &[[u8; 3]; 2],
+) -> Result<(), String> {
+    let (width, height) = (area.width() as i32, area.height() as i32);
+    if width < 64 * 4 || height < 32 * 4 {
+        return Ok(());
+    }
+    let [off, on] = palette.map(|color| color.map(|channel| channel as u16));
+    let mix = |channel: usize| ((off[channel] * 3 + on[channel]) / 4) as u8;
+    canvas.set_draw_color(Color::RGB(mix(0), mix(1), mix(2)));
+    for x in 1..64 {
+        let left = area.x() + width * x / 64;
+        canvas.draw_line((left, area.y()), (left, area.bottom() - 1))?;
+    }
+    for y in 1..32 {
+        let top = area.y() + height * y / 32;
+        canvas.draw_line((area.x(), top), (area.right() - 1, top))?;
+    }
+    Ok(())
+}
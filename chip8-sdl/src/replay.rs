@@ -0,0 +1,244 @@
+// Replay files: everything needed to reproduce a run exactly.
+//
+// "C8RP", version u16, ROM SHA-1, seed u64, stack limit u16, quirk bits u8,
+// memory size u32, cycle count u64, SHA-1 of the final save state, input
+// count u32, then per input in cycle order: cycle u64, key u8, pressed u8. All
+// integers little endian. Time is counted in executed cycles so pausing or
+// changing speed while recording doesn't matter.
+use crate::chip8::{Quirks, State};
+use crate::hash;
+
+const MAGIC: &[u8; 4] = b"C8RP";
+const VERSION: u16 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Input {
+    // the cycle the change takes effect before
+    pub cycle: u64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+pub struct Replay {
+    pub rom_hash: [u8; 20],
+    pub seed: u64,
+    pub stack_limit: u16,
+    pub quirks: Quirks,
+    pub memory_size: u32,
+    pub cycles: u64,
+    pub final_state: [u8; 20],
+    pub inputs: Vec<Input>,
+}
+
+impl Replay {
+    // Starts recording a run of rom on a freshly initialized chip_8
+    pub fn new(rom: &[u8], chip_8: &State) -> Self {
+        Self {
+            rom_hash: hash::sha1(rom),
+            seed: chip_8.seed(),
+            stack_limit: chip_8.stack_limit() as u16,
+            quirks: chip_8.quirks(),
+            memory_size: chip_8.memory_size() as u32,
+            cycles: 0,
+            final_state: [0; 20],
+            inputs: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, cycle: u64, key: u8, pressed: bool) {
+        self.inputs.push(Input {
+            cycle,
+            key,
+            pressed,
+        });
+    }
+
+    pub fn finish(&mut self, cycles: u64, chip_8: &State) {
+        self.cycles = cycles;
+        self.final_state = hash::sha1(&chip_8.save_state());
+    }
+
+    // Applies the recorded settings before the run starts
+    pub fn configure(&self, rom: &[u8], chip_8: &mut State) -> Result<(), String> {
+        if hash::sha1(rom) != self.rom_hash {
+            return Err("The replay was recorded with a different ROM".to_string());
+        }
+        if chip_8.memory_size() != self.memory_size as usize {
+            return Err(format!(
+                "The replay was recorded with {} bytes of memory, run it with --memory-size {}",
+                self.memory_size, self.memory_size
+            ));
+        }
+        chip_8.set_stack_limit(self.stack_limit as usize);
+        chip_8.set_quirks(self.quirks);
+        chip_8.set_seed(self.seed);
+        Ok(())
+    }
+
+    // a binary search, the inputs are in cycle order
+    pub fn inputs_at(&self, cycle: u64) -> impl Iterator<Item = &Input> {
+        let start = self.inputs.partition_point(|input| input.cycle < cycle);
+        self.inputs[start..]
+            .iter()
+            .take_while(move |input| input.cycle == cycle)
+    }
+
+    // true if chip_8 ended up exactly where the recording did
+    pub fn verify(&self, chip_8: &State) -> bool {
+        hash::sha1(&chip_8.save_state()) == self.final_state
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&VERSION.to_le_bytes());
+        data.extend_from_slice(&self.rom_hash);
+        data.extend_from_slice(&self.seed.to_le_bytes());
+        data.extend_from_slice(&self.stack_limit.to_le_bytes());
+        data.push(self.quirks.to_bits());
+        data.extend_from_slice(&self.memory_size.to_le_bytes());
+        data.extend_from_slice(&self.cycles.to_le_bytes());
+        data.extend_from_slice(&self.final_state);
+        data.extend_from_slice(&(self.inputs.len() as u32).to_le_bytes());
+        for input in &self.inputs {
+            data.extend_from_slice(&input.cycle.to_le_bytes());
+            data.push(input.key);
+            data.push(input.pressed as u8);
+        }
+        data
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, String> {
+        let mut offset = 0;
+        let mut take = |length: usize| -> Result<&[u8], String> {
+            let bytes = data
+                .get(offset..offset + length)
+                .ok_or("Truncated replay file")?;
+            offset += length;
+            Ok(bytes)
+        };
+
+        if take(4)? != MAGIC {
+            return Err("Not a replay file".to_string());
+        }
+        let version = u16::from_le_bytes(take(2)?.try_into().unwrap());
+        if version != VERSION {
+            return Err(format!("Unsupported replay version {}", version));
+        }
+        let rom_hash = take(20)?.try_into().unwrap();
+        let seed = u64::from_le_bytes(take(8)?.try_into().unwrap());
+        let stack_limit = u16::from_le_bytes(take(2)?.try_into().unwrap());
+        let quirks = Quirks::from_bits(take(1)?[0]);
+        let memory_size = u32::from_le_bytes(take(4)?.try_into().unwrap());
+        let cycles = u64::from_le_bytes(take(8)?.try_into().unwrap());
+        let final_state = take(20)?.try_into().unwrap();
+        let count = u32::from_le_bytes(take(4)?.try_into().unwrap());
+        let mut inputs = Vec::new();
+        for _ in 0..count {
+            let cycle = u64::from_le_bytes(take(8)?.try_into().unwrap());
+            let input = take(2)?;
+            if inputs.last().is_some_and(|last: &Input| last.cycle > cycle) {
+                return Err("Corrupt replay file".to_string());
+            }
+            inputs.push(Input {
+                cycle,
+                key: input[0],
+                pressed: input[1] != 0,
+            });
+        }
+        Ok(Self {
+            rom_hash,
+            seed,
+            stack_limit,
+            quirks,
+            memory_size,
+            cycles,
+            final_state,
+            inputs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::XO_CHIP_MEMORY_SIZE;
+
+    fn recorded() -> Replay {
+        let mut replay = Replay::new(&[0x12, 0x00], &State::new());
+        replay.seed = 0x1234_5678_9ABC;
+        replay.quirks = Quirks::preset("schip").unwrap();
+        replay.record(3, 0x5, true);
+        replay.record(3, 0xA, true);
+        replay.record(40, 0x5, false);
+        replay.cycles = 100;
+        replay.final_state = [7; 20];
+        replay
+    }
+
+    #[test]
+    fn round_trips() {
+        let replay = recorded();
+        let read = Replay::from_bytes(&replay.to_bytes()).unwrap();
+        assert_eq!(read.rom_hash, replay.rom_hash);
+        assert_eq!(read.seed, replay.seed);
+        assert_eq!(read.stack_limit, replay.stack_limit);
+        assert_eq!(read.quirks, replay.quirks);
+        assert_eq!(read.cycles, 100);
+        assert_eq!(read.final_state, [7; 20]);
+        assert_eq!(read.inputs, replay.inputs);
+        assert_eq!(read.inputs_at(3).count(), 2);
+        assert_eq!(
+            read.inputs_at(40).collect::<Vec<_>>(),
+            vec![&replay.inputs[2]]
+        );
+        assert_eq!(read.inputs_at(4).count(), 0);
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        let data = recorded().to_bytes();
+        assert_eq!(
+            Replay::from_bytes(&data[..data.len() - 1]).err(),
+            Some("Truncated replay file".to_string())
+        );
+        assert_eq!(
+            Replay::from_bytes(b"C8SS").err(),
+            Some("Not a replay file".to_string())
+        );
+        let mut future = data.clone();
+        future[4] = VERSION as u8 + 1;
+        assert!(Replay::from_bytes(&future).is_err());
+        let mut old = data.clone();
+        old[4] = VERSION as u8 - 1;
+        assert_eq!(
+            Replay::from_bytes(&old).err(),
+            Some(format!("Unsupported replay version {}", VERSION - 1))
+        );
+        // the first input's cycle, after the second's
+        let mut unordered = data.clone();
+        let first = data.len() - 3 * 10;
+        unordered[first] = 4;
+        assert_eq!(
+            Replay::from_bytes(&unordered).err(),
+            Some("Corrupt replay file".to_string())
+        );
+    }
+
+    #[test]
+    fn memory_size_round_trips() {
+        let mut chip_8 = State::new();
+        chip_8.set_memory_size(XO_CHIP_MEMORY_SIZE).unwrap();
+        let replay = Replay::new(&[0x12, 0x00], &chip_8);
+        let replay = Replay::from_bytes(&replay.to_bytes()).unwrap();
+        assert_eq!(replay.memory_size as usize, XO_CHIP_MEMORY_SIZE);
+        assert!(replay.configure(&[0x12, 0x00], &mut chip_8).is_ok());
+    }
+
+    #[test]
+    fn rejects_a_different_memory_size() {
+        let mut chip_8 = State::new();
+        chip_8.set_memory_size(XO_CHIP_MEMORY_SIZE).unwrap();
+        let replay = Replay::new(&[0x12, 0x00], &chip_8);
+        assert!(replay.configure(&[0x12, 0x00], &mut State::new()).is_err());
+    }
+}
@@ -0,0 +1,273 @@
+use crate::chip8::State;
+use crate::hash;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const SLOTS: u8 = 4;
+
+// State files start with "C8ST", version u16, the unix time they were saved
+// u64, the machine's memory size u32, compression u8, the state's length u32,
+// then the state from State::save_state, zstd compressed with the zstd
+// feature.
+const MAGIC: &[u8; 4] = b"C8ST";
+const VERSION: u16 = 2;
+const HEADER_LENGTH: usize = 23;
+const UNCOMPRESSED: u8 = 0;
+const ZSTD: u8 = 2;
+#[cfg(feature = "zstd")]
+const ZSTD_LEVEL: i32 = 3;
+
+// Where per-user emulator data lives, e.g. ~/.local/share/chip8
+pub fn data_dir() -> Result<PathBuf, String> {
+    if let Some(path) = env::var_os("XDG_DATA_HOME") {
+        return Ok(PathBuf::from(path).join("chip8"));
+    }
+    if let Some(path) = env::var_os("APPDATA") {
+        return Ok(PathBuf::from(path).join("chip8"));
+    }
+    env::var_os("HOME")
+        .map(|home| PathBuf::from(home).join(".local/share/chip8"))
+        .ok_or_else(|| "No home directory to store data in".to_string())
+}
+
+// States are kept per ROM, keyed by its hash so renamed files still match
+pub fn state_dir(rom: &[u8]) -> Result<PathBuf, String> {
+    Ok(data_dir()?
+        .join("states")
+        .join(hash::to_hex(&hash::sha1(rom))))
+}
+
+pub fn slot_path(rom: &[u8], slot: u8) -> Result<PathBuf, String> {
+    Ok(state_dir(rom)?.join(format!("slot{}.state", slot)))
+}
+
+pub fn autosave_path(rom: &[u8]) -> Result<PathBuf, String> {
+    Ok(state_dir(rom)?.join("autosave.state"))
+}
+
+pub fn save_slot(chip_8: &State, rom: &[u8], slot: u8) -> Result<(), String> {
+    write_state(&slot_path(rom, slot)?, chip_8)
+}
+
+pub fn load_slot(chip_8: &mut State, rom: &[u8], slot: u8) -> Result<u64, String> {
+    read_state(&slot_path(rom, slot)?, chip_8)
+}
+
+pub fn write_state(path: &Path, chip_8: &State) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|op| op.to_string())?;
+    }
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    fs::write(path, encode_state(chip_8, timestamp)).map_err(|op| op.to_string())
+}
+
+// Returns when the state was saved
+pub fn read_state(path: &Path, chip_8: &mut State) -> Result<u64, String> {
+    decode_state(&fs::read(path).map_err(|op| op.to_string())?, chip_8)
+}
+
+pub fn encode_state(chip_8: &State, timestamp: u64) -> Vec<u8> {
+    let state = chip_8.save_state();
+    let mut data = MAGIC.to_vec();
+    data.extend_from_slice(&VERSION.to_le_bytes());
+    data.extend_from_slice(&timestamp.to_le_bytes());
+    data.extend_from_slice(&(chip_8.memory_size() as u32).to_le_bytes());
+    let (compression, body) = compress(&state);
+    data.push(compression);
+    data.extend_from_slice(&(state.len() as u32).to_le_bytes());
+    data.extend(body);
+    data
+}
+
+// Returns when the state was saved
+pub fn decode_state(data: &[u8], chip_8: &mut State) -> Result<u64, String> {
+    if !data.starts_with(MAGIC) {
+        return Err("Not a save state".to_string());
+    }
+    if data.len() < HEADER_LENGTH {
+        return Err("Truncated save state".to_string());
+    }
+    let version = u16::from_le_bytes(data[4..6].try_into().unwrap());
+    if version != VERSION {
+        return Err(format!(
+            "The save state is version {}, this build only reads {}",
+            version, VERSION
+        ));
+    }
+    let timestamp = u64::from_le_bytes(data[6..14].try_into().unwrap());
+    let memory_size = u32::from_le_bytes(data[14..18].try_into().unwrap()) as usize;
+    check_memory_size(memory_size, chip_8)?;
+    let length = u32::from_le_bytes(data[19..23].try_into().unwrap()) as usize;
+    // the stack is saved as deep as it was, so at most a full one more than
+    // this machine's state
+    if length > chip_8.save_state().len() + 2 * chip_8.stack_limit() {
+        return Err("Corrupt save state".to_string());
+    }
+    let body = &data[HEADER_LENGTH..];
+    let state = match data[18] {
+        UNCOMPRESSED => body.to_vec(),
+        #[cfg(feature = "zstd")]
+        ZSTD => zstd::bulk::decompress(body, length).map_err(|_| "Corrupt save state")?,
+        #[cfg(not(feature = "zstd"))]
+        ZSTD => {
+            return Err(
+                "The save state is zstd compressed, this build has no zstd feature".to_string(),
+            )
+        }
+        compression => return Err(format!("Unknown save state compression {}", compression)),
+    };
+    if state.len() != length {
+        return Err("Corrupt save state".to_string());
+    }
+    chip_8.load_state(&state)?;
+    Ok(timestamp)
+}
+
+fn check_memory_size(size: usize, chip_8: &State) -> Result<(), String> {
+    if size != chip_8.memory_size() {
+        return Err(format!(
+            "The save state is from a machine with {} bytes of memory, this one has {}",
+            size,
+            chip_8.memory_size()
+        ));
+    }
+    Ok(())
+}
+
+// the compression byte and the compressed state
+#[cfg(feature = "zstd")]
+fn compress(state: &[u8]) -> (u8, Vec<u8>) {
+    match zstd::bulk::compress(state, ZSTD_LEVEL) {
+        Ok(compressed) => (ZSTD, compressed),
+        Err(_) => (UNCOMPRESSED, state.to_vec()),
+    }
+}
+
+#[cfg(not(feature = "zstd"))]
+fn compress(state: &[u8]) -> (u8, Vec<u8>) {
+    (UNCOMPRESSED, state.to_vec())
+}
+
+// Timestamp and a half resolution text thumbnail of a slot's display
+pub fn preview(rom: &[u8], slot: u8) -> Result<String, String> {
+    let mut chip_8 = State::new();
+    let timestamp = load_slot(&mut chip_8, rom, slot)?;
+    let gfx = chip_8.get_graphics_buffer();
+    let mut text = format!("Slot {}: saved {}\n", slot, format_timestamp(timestamp));
+    for y in (0..32).step_by(4) {
+        let row: String = (0..64)
+            .step_by(2)
+            .map(|x| {
+                let lit =
+                    (0..4).any(|dy| gfx[x + (y + dy) * 64] != 0 || gfx[x + 1 + (y + dy) * 64] != 0);
+                if lit {
+                    '#'
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        text += &row;
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+// YYYY-MM-DD HH:MM:SS UTC
+pub fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64;
+    let seconds = timestamp % 86400;
+    // civil-from-days, proleptic Gregorian calendar
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::{DEFAULT_MEMORY_SIZE, XO_CHIP_MEMORY_SIZE};
+
+    fn machine(memory_size: usize) -> State {
+        let mut chip_8 = State::new();
+        chip_8.set_memory_size(memory_size).unwrap();
+        chip_8.initialize();
+        chip_8
+    }
+
+    #[test]
+    fn round_trips() {
+        let chip_8 = machine(XO_CHIP_MEMORY_SIZE);
+        let data = encode_state(&chip_8, 1234);
+        let mut loaded = machine(XO_CHIP_MEMORY_SIZE);
+        assert_eq!(decode_state(&data, &mut loaded), Ok(1234));
+        assert_eq!(loaded.save_state(), chip_8.save_state());
+    }
+
+    #[test]
+    fn compresses() {
+        let chip_8 = machine(XO_CHIP_MEMORY_SIZE);
+        let (compression, body) = compress(&chip_8.save_state());
+        if cfg!(feature = "zstd") {
+            assert_eq!(compression, ZSTD);
+            assert!(body.len() < chip_8.save_state().len() / 10);
+        } else {
+            assert_eq!(compression, UNCOMPRESSED);
+        }
+    }
+
+    #[test]
+    fn round_trips_a_deep_stack() {
+        // CALL 0x200 until the stack is full
+        let mut chip_8 = machine(DEFAULT_MEMORY_SIZE);
+        chip_8.load_buffer(&[0x22, 0x00]);
+        while chip_8.emulate_cycle().is_ok() {}
+        let data = encode_state(&chip_8, 0);
+        let mut loaded = machine(DEFAULT_MEMORY_SIZE);
+        assert_eq!(decode_state(&data, &mut loaded), Ok(0));
+        assert_eq!(loaded.save_state(), chip_8.save_state());
+    }
+
+    #[test]
+    fn rejects_oversized_lengths() {
+        let mut data = encode_state(&machine(DEFAULT_MEMORY_SIZE), 0);
+        data[19..23].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(
+            decode_state(&data, &mut machine(DEFAULT_MEMORY_SIZE)),
+            Err("Corrupt save state".to_string())
+        );
+        assert!(decode_state(
+            &data[..HEADER_LENGTH - 1],
+            &mut machine(DEFAULT_MEMORY_SIZE)
+        )
+        .is_err());
+        assert!(decode_state(&[0; 64], &mut machine(DEFAULT_MEMORY_SIZE)).is_err());
+    }
+
+    #[test]
+    fn rejects_a_different_memory_size() {
+        let data = encode_state(&machine(XO_CHIP_MEMORY_SIZE), 0);
+        assert!(decode_state(&data, &mut machine(DEFAULT_MEMORY_SIZE)).is_err());
+        let data = encode_state(&machine(DEFAULT_MEMORY_SIZE), 0);
+        assert!(decode_state(&data, &mut machine(XO_CHIP_MEMORY_SIZE)).is_err());
+    }
+}
@@ -0,0 +1,99 @@
+// Reference screens for `chip8 headless --expect-screen`, so a ROM project's
+// CI can check the display a program ends on. A reference is text art, 32
+// lines of # for on and . for off, or a PNG of the screen at any whole scale
+// and in any two colors.
+use crate::png;
+use std::fs;
+
+const WIDTH: usize = 64;
+const HEIGHT: usize = 32;
+
+// A screen, one byte per pixel
+pub fn load(path: &str) -> Result<Vec<u8>, String> {
+    let data = fs::read(path).map_err(|op| format!("{}: {}", path, op))?;
+    let screen = if path.to_lowercase().ends_with(".png") {
+        from_png(&data)
+    } else {
+        from_text(&String::from_utf8_lossy(&data))
+    };
+    screen.map_err(|op| format!("{}: {}", path, op))
+}
+
+pub fn from_text(text: &str) -> Result<Vec<u8>, String> {
+    let lines: Vec<&str> = text.trim_end().lines().collect();
+    if lines.len() != HEIGHT {
+        return Err(format!("expected {} lines, found {}", HEIGHT, lines.len()));
+    }
+    let mut screen = vec![0; WIDTH * HEIGHT];
+    for (y, line) in lines.iter().enumerate() {
+        // trailing off pixels may be left out
+        for (x, character) in line.trim_end().chars().enumerate() {
+            if x >= WIDTH {
+                return Err(format!("line {} is longer than {}", y + 1, WIDTH));
+            }
+            screen[y * WIDTH + x] = match character {
+                '#' | '\u{2588}' | 'X' | '1' => 1,
+                '.' | ' ' | '0' => 0,
+                _ => return Err(format!("line {}: unknown pixel {:?}", y + 1, character)),
+            };
+        }
+    }
+    Ok(screen)
+}
+
+pub fn to_text(screen: &[u8]) -> String {
+    let mut text = String::new();
+    for row in screen.chunks(WIDTH) {
+        text.extend(row.iter().map(|pixel| if *pixel != 0 { '#' } else { '.' }));
+        text.push('\n');
+    }
+    text
+}
+
+fn from_png(data: &[u8]) -> Result<Vec<u8>, String> {
+    let (width, brightness) = png::decode(data)?;
+    let height = brightness.len() / width.max(1);
+    let scale = width / WIDTH;
+    if scale == 0 || width != WIDTH * scale || height != HEIGHT * scale {
+        return Err(format!(
+            "a {}x{} image isn't the screen at a whole scale",
+            width, height
+        ));
+    }
+    // the darker of the two colors is off, whichever they are
+    let darkest = *brightness.iter().min().unwrap();
+    let brightest = *brightness.iter().max().unwrap();
+    let threshold = if darkest == brightest {
+        128
+    } else {
+        (darkest as u16 + brightest as u16).div_ceil(2) as u8
+    };
+    let mut screen = vec![0; WIDTH * HEIGHT];
+    for (index, pixel) in screen.iter_mut().enumerate() {
+        // the middle of each scaled up pixel
+        let x = (index % WIDTH) * scale + scale / 2;
+        let y = (index / WIDTH) * scale + scale / 2;
+        *pixel = (brightness[y * width + x] >= threshold) as u8;
+    }
+    Ok(screen)
+}
+
+// The text art of actual, with pixels that should be on marked + and ones
+// that should be off marked -, and how many differ
+pub fn compare(expected: &[u8], actual: &[u8]) -> (usize, String) {
+    let mut differences = 0;
+    let mut text = String::new();
+    for (index, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+        text.push(match (*expected != 0, *actual != 0) {
+            (true, true) => '#',
+            (false, false) => '.',
+            (true, false) => '+',
+            (false, true) => '-',
+        });
+        differences += (*expected != 0) as usize ^ (*actual != 0) as usize;
+        if index % WIDTH == WIDTH - 1 {
+            text.push('\n');
+        }
+    }
+    (differences, text)
+}
@@ -0,0 +1,402 @@
+// Hooks for --script, a small built-in language for cheats, automated tests
+// and instrumentation. One hook per line, # starts a comment:
+//
+//   frame: STATEMENT; STATEMENT     runs once per frame
+//   step: STATEMENT                 runs before every instruction
+//   at LOCATION: STATEMENT          runs before the instruction at LOCATION
+//
+// Statements are `PLACE = EXPRESSION` where PLACE is V0-VF, I, DT, ST or
+// [ADDRESS], `print VALUE...`, `assert CONDITION`, which stops the
+// emulator when it fails, and `if CONDITION then STATEMENT`. A condition is
+// two expressions compared with == != < > <= or >=. Expressions combine
+// numbers, labels, V0-VF, I, DT, ST, PC and [ADDRESS] with + - & | and ^,
+// left to right. Tokens are separated by spaces: `V3 = V3 + 1`.
+use crate::chip8::State;
+use crate::symbols::Symbols;
+use std::fs;
+
+#[derive(Debug, Clone)]
+enum Value {
+    Number(u16),
+    V(usize),
+    I,
+    Dt,
+    St,
+    Pc,
+    Memory(Box<Value>),
+}
+
+#[derive(Debug, Clone)]
+struct Expression {
+    text: String,
+    first: Value,
+    rest: Vec<(char, Value)>,
+}
+
+#[derive(Debug, Clone)]
+struct Condition {
+    left: Expression,
+    operator: String,
+    right: Expression,
+}
+
+#[derive(Debug, Clone)]
+enum Statement {
+    Assign(Value, Expression),
+    Print(Vec<Expression>),
+    Assert(Condition),
+    If(Condition, Box<Statement>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Hook {
+    Frame,
+    Step,
+    At(u16),
+}
+
+pub struct Script {
+    hooks: Vec<(Hook, Vec<Statement>, usize)>,
+}
+
+impl Script {
+    pub fn read(path: &str, symbols: &Symbols) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|op| format!("{}: {}", path, op))?;
+        Self::parse(&text, symbols).map_err(|op| format!("{}: {}", path, op))
+    }
+
+    fn parse(text: &str, symbols: &Symbols) -> Result<Self, String> {
+        let mut hooks = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: String| format!("line {}: {}", line_number, message);
+            let (hook, body) = line
+                .split_once(':')
+                .ok_or_else(|| error("expected frame:, step: or at LOCATION:".to_string()))?;
+            let hook = match hook.split_whitespace().collect::<Vec<_>>().as_slice() {
+                ["frame"] => Hook::Frame,
+                ["step"] => Hook::Step,
+                ["at", location] => Hook::At(
+                    address(location, symbols)
+                        .ok_or_else(|| error(format!("unknown location {}", location)))?,
+                ),
+                _ => return Err(error(format!("unknown hook {}", hook.trim()))),
+            };
+            let statements = body
+                .split(';')
+                .filter(|statement| !statement.trim().is_empty())
+                .map(|statement| {
+                    let words: Vec<&str> = statement.split_whitespace().collect();
+                    parse_statement(&words, symbols).map_err(error)
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            hooks.push((hook, statements, line_number));
+        }
+        Ok(Self { hooks })
+    }
+
+    pub fn frame(&self, state: &mut State) -> Result<(), String> {
+        self.run(state, |hook| hook == Hook::Frame)
+    }
+
+    // call before each cycle
+    pub fn before_instruction(&self, state: &mut State) -> Result<(), String> {
+        let pc = state.pc();
+        self.run(state, |hook| hook == Hook::Step || hook == Hook::At(pc))
+    }
+
+    fn run(&self, state: &mut State, runs: impl Fn(Hook) -> bool) -> Result<(), String> {
+        for (hook, statements, line) in &self.hooks {
+            if !runs(*hook) {
+                continue;
+            }
+            for statement in statements {
+                execute(statement, state).map_err(|op| format!("script line {}: {}", line, op))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_statement(words: &[&str], symbols: &Symbols) -> Result<Statement, String> {
+    match words {
+        ["print", expressions @ ..] if !expressions.is_empty() => Ok(Statement::Print(
+            expressions
+                .iter()
+                .map(|word| parse_expression(&[word], symbols))
+                .collect::<Result<_, _>>()?,
+        )),
+        ["assert", condition @ ..] => Ok(Statement::Assert(parse_condition(condition, symbols)?)),
+        ["if", rest @ ..] => {
+            let then = rest
+                .iter()
+                .position(|word| *word == "then")
+                .ok_or("if without then")?;
+            Ok(Statement::If(
+                parse_condition(&rest[..then], symbols)?,
+                Box::new(parse_statement(&rest[then + 1..], symbols)?),
+            ))
+        }
+        [place, "=", expression @ ..] => {
+            let place = parse_value(place, symbols)?;
+            if matches!(place, Value::Number(_) | Value::Pc) {
+                return Err("only V0-VF, I, DT, ST and memory can be assigned".to_string());
+            }
+            Ok(Statement::Assign(
+                place,
+                parse_expression(expression, symbols)?,
+            ))
+        }
+        _ => Err(format!("invalid statement {}", words.join(" "))),
+    }
+}
+
+fn parse_condition(words: &[&str], symbols: &Symbols) -> Result<Condition, String> {
+    let split = words
+        .iter()
+        .position(|word| ["==", "!=", "<", ">", "<=", ">="].contains(word))
+        .ok_or("expected a comparison")?;
+    Ok(Condition {
+        left: parse_expression(&words[..split], symbols)?,
+        operator: words[split].to_string(),
+        right: parse_expression(&words[split + 1..], symbols)?,
+    })
+}
+
+fn parse_expression(words: &[&str], symbols: &Symbols) -> Result<Expression, String> {
+    let (first, rest) = words.split_first().ok_or("missing expression")?;
+    if rest.len() % 2 != 0 {
+        return Err(format!("incomplete expression {}", words.join(" ")));
+    }
+    Ok(Expression {
+        text: words.join(" "),
+        first: parse_value(first, symbols)?,
+        rest: rest
+            .chunks(2)
+            .map(|pair| match pair[0] {
+                "+" | "-" | "&" | "|" | "^" => Ok((
+                    pair[0].chars().next().unwrap(),
+                    parse_value(pair[1], symbols)?,
+                )),
+                operator => Err(format!("unknown operator {}", operator)),
+            })
+            .collect::<Result<_, String>>()?,
+    })
+}
+
+fn parse_value(word: &str, symbols: &Symbols) -> Result<Value, String> {
+    if let Some(inner) = word
+        .strip_prefix('[')
+        .and_then(|word| word.strip_suffix(']'))
+    {
+        return Ok(Value::Memory(Box::new(parse_value(inner, symbols)?)));
+    }
+    let upper = word.to_ascii_uppercase();
+    Ok(match upper.as_str() {
+        "I" => Value::I,
+        "DT" => Value::Dt,
+        "ST" => Value::St,
+        "PC" => Value::Pc,
+        _ => match upper
+            .strip_prefix('V')
+            .filter(|digit| digit.len() == 1)
+            .and_then(|digit| usize::from_str_radix(digit, 16).ok())
+        {
+            Some(register) => Value::V(register),
+            None => Value::Number(
+                address(word, symbols).ok_or_else(|| format!("unknown value {}", word))?,
+            ),
+        },
+    })
+}
+
+// a label, 0x hex or decimal number
+fn address(word: &str, symbols: &Symbols) -> Option<u16> {
+    symbols.address(word).or_else(
+        || match word.strip_prefix("0x").or(word.strip_prefix("0X")) {
+            Some(hex) => u16::from_str_radix(hex, 16).ok(),
+            None => word.parse().ok(),
+        },
+    )
+}
+
+fn execute(statement: &Statement, state: &mut State) -> Result<(), String> {
+    match statement {
+        Statement::Assign(place, expression) => {
+            let value = evaluate(expression, state);
+            match place {
+                Value::V(register) => state.set_register(*register, value as u8),
+                // I reaches as far as memory does
+                Value::I => state.set_i((value as usize % state.memory_size()) as u16),
+                Value::Dt => state.set_delay_timer(value as u8),
+                Value::St => state.set_sound_timer(value as u8),
+                Value::Memory(address) => {
+                    let address = value_of(address, state);
+                    state.write_memory(address, value as u8);
+                }
+                Value::Number(_) | Value::Pc => unreachable!(),
+            }
+        }
+        Statement::Print(expressions) => {
+            let values: Vec<String> = expressions
+                .iter()
+                .map(|expression| format!("{}={:#X}", expression.text, evaluate(expression, state)))
+                .collect();
+            println!("{}", values.join(" "));
+        }
+        Statement::Assert(condition) => {
+            if !holds(condition, state) {
+                return Err(format!(
+                    "assertion failed: {} {} {} ({:#X} and {:#X}) at {:#05X}",
+                    condition.left.text,
+                    condition.operator,
+                    condition.right.text,
+                    evaluate(&condition.left, state),
+                    evaluate(&condition.right, state),
+                    state.pc()
+                ));
+            }
+        }
+        Statement::If(condition, statement) => {
+            if holds(condition, state) {
+                execute(statement, state)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn holds(condition: &Condition, state: &State) -> bool {
+    let left = evaluate(&condition.left, state);
+    let right = evaluate(&condition.right, state);
+    match condition.operator.as_str() {
+        "==" => left == right,
+        "!=" => left != right,
+        "<" => left < right,
+        ">" => left > right,
+        "<=" => left <= right,
+        _ => left >= right,
+    }
+}
+
+fn evaluate(expression: &Expression, state: &State) -> u16 {
+    expression.rest.iter().fold(
+        value_of(&expression.first, state),
+        |total, (operator, value)| {
+            let value = value_of(value, state);
+            match operator {
+                '+' => total.wrapping_add(value),
+                '-' => total.wrapping_sub(value),
+                '&' => total & value,
+                '|' => total | value,
+                _ => total ^ value,
+            }
+        },
+    )
+}
+
+fn value_of(value: &Value, state: &State) -> u16 {
+    match value {
+        Value::Number(number) => *number,
+        Value::V(register) => state.registers()[*register] as u16,
+        Value::I => state.i(),
+        Value::Dt => state.delay_timer() as u16,
+        Value::St => state.sound_timer() as u16,
+        Value::Pc => state.pc(),
+        Value::Memory(address) => state.read_memory(value_of(address, state)) as u16,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // LD V0, 1 then loop, with the loop labelled
+    const PROGRAM: [u8; 4] = [0x60, 0x01, 0x12, 0x02];
+
+    fn machine() -> State {
+        let mut chip_8 = State::new();
+        chip_8.initialize();
+        chip_8.load_buffer(&PROGRAM);
+        chip_8
+    }
+
+    fn symbols() -> Symbols {
+        Symbols::parse("0x0202 loop").unwrap()
+    }
+
+    fn error(text: &str) -> String {
+        Script::parse(text, &symbols()).err().unwrap()
+    }
+
+    #[test]
+    fn reports_errors_with_their_line() {
+        assert_eq!(
+            error("# comment\n\nframe: V0 = 1\nevery: V0 = 1"),
+            "line 4: unknown hook every"
+        );
+        assert_eq!(
+            error("at nowhere: V0 = 1"),
+            "line 1: unknown location nowhere"
+        );
+        assert_eq!(
+            error("frame V0 = 1"),
+            "line 1: expected frame:, step: or at LOCATION:"
+        );
+        assert_eq!(
+            error("frame: PC = 2"),
+            "line 1: only V0-VF, I, DT, ST and memory can be assigned"
+        );
+        assert_eq!(
+            error("step: V0 = V1 *"),
+            "line 1: incomplete expression V1 *"
+        );
+        assert_eq!(error("step: assert V0"), "line 1: expected a comparison");
+        assert_eq!(error("step: if V0 == 1 V1 = 2"), "line 1: if without then");
+    }
+
+    #[test]
+    fn assigns_registers_and_memory() {
+        let script = Script::parse(
+            "frame: V3 = 0x10 + 2 ^ 1; I = loop; [0x300] = V3 | 0x80; DT = 5",
+            &symbols(),
+        )
+        .unwrap();
+        let mut chip_8 = machine();
+        script.frame(&mut chip_8).unwrap();
+        assert_eq!(chip_8.registers()[3], 0x13);
+        assert_eq!(chip_8.i(), 0x202);
+        assert_eq!(chip_8.read_memory(0x300), 0x93);
+        assert_eq!(chip_8.delay_timer(), 5);
+    }
+
+    #[test]
+    fn failed_assertions_stop_the_script() {
+        let script = Script::parse("frame: assert V0 == 1", &symbols()).unwrap();
+        let mut chip_8 = machine();
+        assert_eq!(
+            script.frame(&mut chip_8),
+            Err("script line 1: assertion failed: V0 == 1 (0x0 and 0x1) at 0x200".to_string())
+        );
+        chip_8.set_register(0, 1);
+        assert_eq!(script.frame(&mut chip_8), Ok(()));
+    }
+
+    #[test]
+    fn at_hooks_run_at_their_label() {
+        let script = Script::parse("at loop: V1 = V1 + 1\nstep: V2 = V2 + 1", &symbols()).unwrap();
+        let mut chip_8 = machine();
+        for _ in 0..3 {
+            script.before_instruction(&mut chip_8).unwrap();
+            chip_8.emulate_cycle().unwrap();
+        }
+        // the first instruction is at 0x200, then the loop twice
+        assert_eq!(chip_8.registers()[1], 2);
+        assert_eq!(chip_8.registers()[2], 3);
+        assert_eq!(chip_8.registers()[0], 1);
+    }
+}
@@ -0,0 +1,128 @@
+// --frontend sdl, a plain SDL window run through the Frontend trait like any
+// other frontend. The full window with the debugger, menus and overlays is
+// window.rs, what runs without --frontend.
+use crate::audio::Buzzer;
+use crate::keymap::Keymap;
+use crate::render;
+use chip8_frontend::{Event, Frontend};
+use sdl2::event::Event as SdlEvent;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, TextureCreator};
+use sdl2::video::{Window, WindowContext};
+use sdl2::EventPump;
+
+pub struct SdlFrontend {
+    canvas: Canvas<Window>,
+    texture_creator: TextureCreator<WindowContext>,
+    event_pump: EventPump,
+    keymap: Keymap,
+    palette: [[u8; 3]; 2],
+    scale: u32,
+    // None without a sound device
+    buzzer: Option<Buzzer>,
+}
+
+impl SdlFrontend {
+    pub fn create(
+        keymap: Keymap,
+        palette: [[u8; 3]; 2],
+        scale: u32,
+    ) -> Result<Box<dyn Frontend>, String> {
+        sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", "nearest");
+        let sdl_context = sdl2::init()?;
+        let video_subsystem = sdl_context.video()?;
+        let buzzer = match sdl_context
+            .audio()
+            .and_then(|audio| Buzzer::open(&audio, None))
+        {
+            Ok(buzzer) => Some(buzzer),
+            Err(error) => {
+                warn!("No sound: {}", error);
+                None
+            }
+        };
+        let window = video_subsystem
+            .window("CHIP-8", 64 * scale, 32 * scale)
+            .position_centered()
+            .build()
+            .map_err(|op| op.to_string())?;
+        let canvas = window
+            .into_canvas()
+            .present_vsync()
+            .build()
+            .map_err(|op| op.to_string())?;
+        let texture_creator = canvas.texture_creator();
+        let event_pump = sdl_context.event_pump()?;
+        Ok(Box::new(Self {
+            canvas,
+            texture_creator,
+            event_pump,
+            keymap,
+            palette,
+            scale,
+            buzzer,
+        }))
+    }
+}
+
+impl Frontend for SdlFrontend {
+    fn draw(&mut self, screen: &[u8]) -> Result<(), String> {
+        let [red, green, blue] = self.palette[0];
+        self.canvas.set_draw_color(Color::RGB(red, green, blue));
+        self.canvas.clear();
+        render::draw_display(
+            &mut self.canvas,
+            &self.texture_creator,
+            screen,
+            None,
+            &self.palette,
+            false,
+            Rect::new(0, 0, 64 * self.scale, 32 * self.scale),
+        )?;
+        self.canvas.present();
+        Ok(())
+    }
+
+    fn poll_events(&mut self) -> Result<Vec<Event>, String> {
+        let mut events = Vec::new();
+        for event in self.event_pump.poll_iter() {
+            match event {
+                SdlEvent::Quit { .. }
+                | SdlEvent::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => events.push(Event::Quit),
+                SdlEvent::KeyDown {
+                    keycode,
+                    scancode,
+                    repeat: false,
+                    ..
+                } => {
+                    if let Some(key) = self.keymap.lookup(keycode, scancode) {
+                        events.push(Event::Key { key, pressed: true });
+                    }
+                }
+                SdlEvent::KeyUp {
+                    keycode, scancode, ..
+                } => {
+                    if let Some(key) = self.keymap.lookup(keycode, scancode) {
+                        events.push(Event::Key {
+                            key,
+                            pressed: false,
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(events)
+    }
+
+    fn set_sound(&mut self, on: bool) {
+        if let Some(buzzer) = &self.buzzer {
+            buzzer.set_on(on);
+        }
+    }
+}
@@ -0,0 +1,306 @@
+// Per-game settings from a game.ch8.toml next to the ROM, read automatically.
+// Flags on the command line still win. A small subset of TOML, one
+// `key = value` per line, # starts a comment:
+//
+//   ipf = 15
+//   speed = 2                 a multiple of ipf, like the +/- hotkeys
+//   quirks = "schip"          a preset, then single quirks on top of it
+//   shift_vy = true
+//   palette = ["#002200", "#33FF66"]   off and on pixels
+//   layout = "azerty"
+//   keymap = "5=Up,8=Down"
+//
+// The pause menu's "save for this game" writes the same format to the data
+// directory, keyed by the ROM's hash, for ROMs whose directory isn't ours to
+// write to. A file next to the ROM wins over it.
+use crate::chip8::Quirks;
+use crate::hash;
+use crate::savestate;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Default)]
+pub struct Settings {
+    pub ipf: Option<u32>,
+    pub speed: Option<u32>,
+    pub quirks: Option<Quirks>,
+    // the colors of off and on pixels
+    pub palette: Option<[[u8; 3]; 2]>,
+    pub layout: Option<String>,
+    pub keymap: Option<String>,
+}
+
+impl Settings {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut settings = Self::default();
+        // single quirks are applied after the whole file so the preset can come later
+        let mut single_quirks = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: String| format!("line {}: {}", index + 1, message);
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected key = value".to_string()))?;
+            let (key, value) = (key.trim(), value.trim());
+            match key {
+                "ipf" => {
+                    settings.ipf = Some(
+                        value
+                            .parse()
+                            .map_err(|_| error(format!("invalid ipf {}", value)))?,
+                    )
+                }
+                "speed" => {
+                    settings.speed = Some(
+                        value
+                            .parse()
+                            .map_err(|_| error(format!("invalid speed {}", value)))?,
+                    )
+                }
+                "quirks" => {
+                    settings.quirks =
+                        Some(Quirks::preset(&string(value).map_err(error)?).map_err(error)?)
+                }
+                "palette" => settings.palette = Some(palette_value(value).map_err(error)?),
+                "layout" => settings.layout = Some(string(value).map_err(error)?),
+                "keymap" => settings.keymap = Some(string(value).map_err(error)?),
+                _ => match Quirks::NAMES.iter().position(|name| *name == key) {
+                    Some(bit) => {
+                        let on = match value {
+                            "true" => true,
+                            "false" => false,
+                            _ => return Err(error(format!("{} takes true or false", key))),
+                        };
+                        single_quirks.push((bit, on));
+                    }
+                    None => return Err(error(format!("unknown setting {}", key))),
+                },
+            }
+        }
+        if !single_quirks.is_empty() {
+            let mut bits = settings.quirks.unwrap_or_default().to_bits();
+            for (bit, on) in single_quirks {
+                bits = bits & !(1 << bit) | (on as u8) << bit;
+            }
+            settings.quirks = Some(Quirks::from_bits(bits));
+        }
+        Ok(settings)
+    }
+
+    // The settings next to a ROM file, if there are any
+    pub fn find(rom_path: &str) -> Result<Option<Self>, String> {
+        let path = sidecar_path(rom_path);
+        if !Path::new(rom_path).is_file() || !path.is_file() {
+            return Ok(None);
+        }
+        let text = fs::read_to_string(&path).map_err(|op| format!("{}: {}", path.display(), op))?;
+        Self::parse(&text)
+            .map(Some)
+            .map_err(|op| format!("{}: {}", path.display(), op))
+    }
+
+    // The settings saved from the pause menu for a ROM, if there are any
+    pub fn saved(rom: &[u8]) -> Result<Option<Self>, String> {
+        let path = saved_path(rom)?;
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let text = fs::read_to_string(&path).map_err(|op| format!("{}: {}", path.display(), op))?;
+        Self::parse(&text)
+            .map(Some)
+            .map_err(|op| format!("{}: {}", path.display(), op))
+    }
+
+    pub fn save(&self, rom: &[u8]) -> Result<(), String> {
+        let path = saved_path(rom)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|op| op.to_string())?;
+        }
+        fs::write(&path, self.to_text()).map_err(|op| format!("{}: {}", path.display(), op))
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        if let Some(ipf) = self.ipf {
+            text += &format!("ipf = {}\n", ipf);
+        }
+        if let Some(speed) = self.speed {
+            text += &format!("speed = {}\n", speed);
+        }
+        if let Some(quirks) = self.quirks {
+            // a preset when there is one, otherwise every quirk on its own
+            match Quirks::PRESETS
+                .into_iter()
+                .find(|name| Quirks::preset(name) == Ok(quirks))
+            {
+                Some(name) => text += &format!("quirks = \"{}\"\n", name),
+                None => {
+                    for (bit, name) in Quirks::NAMES.iter().enumerate() {
+                        let on = quirks.to_bits() & (1 << bit) != 0;
+                        text += &format!("{} = {}\n", name, on);
+                    }
+                }
+            }
+        }
+        if let Some(palette) = &self.palette {
+            text += &format!("palette = {}\n", palette_text(palette));
+        }
+        if let Some(layout) = &self.layout {
+            text += &format!("layout = \"{}\"\n", layout);
+        }
+        if let Some(keymap) = &self.keymap {
+            text += &format!("keymap = \"{}\"\n", keymap);
+        }
+        text
+    }
+}
+
+// game.ch8 -> game.ch8.toml
+fn sidecar_path(rom_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.toml", rom_path))
+}
+
+// e.g. ~/.local/share/chip8/games/<sha1>.toml
+fn saved_path(rom: &[u8]) -> Result<PathBuf, String> {
+    Ok(savestate::data_dir()?
+        .join("games")
+        .join(format!("{}.toml", hash::to_hex(&hash::sha1(rom)))))
+}
+
+// the line up to a # that isn't inside a string
+pub fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (index, character) in line.char_indices() {
+        match character {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+pub fn string(value: &str) -> Result<String, String> {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .map(str::to_string)
+        .ok_or_else(|| format!("expected a quoted string, not {}", value))
+}
+
+// ["#off", "#on"]
+pub fn palette_value(value: &str) -> Result<[[u8; 3]; 2], String> {
+    let colors = value
+        .strip_prefix('[')
+        .and_then(|value| value.strip_suffix(']'))
+        .ok_or("palette takes [\"#off\", \"#on\"]")?
+        .split(',')
+        .map(|color| color_value(color.trim()))
+        .collect::<Result<Vec<_>, _>>()?;
+    colors
+        .try_into()
+        .map_err(|_| "palette takes two colors".to_string())
+}
+
+// the other way around, for writing a palette back
+pub fn palette_text(palette: &[[u8; 3]; 2]) -> String {
+    let [off, on] =
+        palette.map(|[red, green, blue]| format!("\"#{:02X}{:02X}{:02X}\"", red, green, blue));
+    format!("[{}, {}]", off, on)
+}
+
+// "#RRGGBB"
+fn color_value(value: &str) -> Result<[u8; 3], String> {
+    let text = string(value)?;
+    let digits = text
+        .strip_prefix('#')
+        .filter(|digits| digits.len() == 6)
+        .ok_or_else(|| format!("invalid color {}, expected \"#RRGGBB\"", text))?;
+    let mut color = [0; 3];
+    for (index, channel) in color.iter_mut().enumerate() {
+        *channel = u8::from_str_radix(&digits[index * 2..index * 2 + 2], 16)
+            .map_err(|_| format!("invalid color {}", text))?;
+    }
+    Ok(color)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let text = concat!(
+            "ipf = 15\n",
+            "speed = 2   # twice as fast\n",
+            "quirks = \"schip\"\n",
+            "palette = [\"#002200\", \"#33FF66\"]\n",
+            "layout = \"azerty\"\n",
+            "keymap = \"5=Up,8=Down\"\n",
+        );
+        let settings = Settings::parse(text).unwrap();
+        assert_eq!((settings.ipf, settings.speed), (Some(15), Some(2)));
+        assert_eq!(settings.quirks, Some(Quirks::preset("schip").unwrap()));
+        assert_eq!(
+            settings.palette,
+            Some([[0x00, 0x22, 0x00], [0x33, 0xFF, 0x66]])
+        );
+        assert_eq!(settings.layout.as_deref(), Some("azerty"));
+        assert_eq!(settings.keymap.as_deref(), Some("5=Up,8=Down"));
+        assert_eq!(settings.to_text(), text.replace("   # twice as fast", ""));
+    }
+
+    #[test]
+    fn single_quirks_apply_on_top_of_the_preset() {
+        let settings = Settings::parse("shift_vy = true\nquirks = \"schip\"").unwrap();
+        let quirks = settings.quirks.unwrap();
+        assert_eq!(
+            quirks,
+            Quirks {
+                shift_vy: true,
+                ..Quirks::preset("schip").unwrap()
+            }
+        );
+        // no preset matches, so every quirk is written out
+        assert!(settings.to_text().contains("jump_vx = true\n"));
+        let again = Settings::parse(&settings.to_text()).unwrap();
+        assert_eq!(again.quirks, Some(quirks));
+    }
+
+    #[test]
+    fn rejects_malformed_settings() {
+        let error = |text: &str| Settings::parse(text).err().unwrap();
+        assert_eq!(error("\nipf"), "line 2: expected key = value");
+        assert_eq!(error("ipf = many"), "line 1: invalid ipf many");
+        assert_eq!(error("volume = 11"), "line 1: unknown setting volume");
+        assert_eq!(
+            error(&format!("{} = maybe", Quirks::NAMES[0])),
+            format!("line 1: {} takes true or false", Quirks::NAMES[0])
+        );
+        assert_eq!(
+            error("layout = \"azerty"),
+            "line 1: expected a quoted string, not \"azerty"
+        );
+        assert_eq!(
+            error("palette = [\"#000000\", \"#FFF\"]"),
+            "line 1: invalid color #FFF, expected \"#RRGGBB\""
+        );
+        assert_eq!(
+            error("palette = [\"#000000\"]"),
+            "line 1: palette takes two colors"
+        );
+        assert!(Settings::parse("quirks = \"nonsense\"").is_err());
+    }
+
+    #[test]
+    fn comments_stop_outside_strings() {
+        assert_eq!(
+            strip_comment("palette = [\"#000000\", \"#FFFFFF\"] # x"),
+            "palette = [\"#000000\", \"#FFFFFF\"] "
+        );
+        assert_eq!(strip_comment("# all of it"), "");
+    }
+}
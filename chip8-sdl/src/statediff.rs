@@ -0,0 +1,139 @@
+// Differences between two machine states, one line per difference, for
+// `chip8 diff-state`
+use crate::chip8::State;
+
+// differing bytes at most this far apart are reported as one range
+const RANGE_GAP: usize = 4;
+
+pub fn diff(a: &State, b: &State) -> Vec<String> {
+    let mut lines = Vec::new();
+    if a.pc() != b.pc() {
+        lines.push(format!("PC: {:#05X} -> {:#05X}", a.pc(), b.pc()));
+    }
+    if a.i() != b.i() {
+        lines.push(format!("I: {:#05X} -> {:#05X}", a.i(), b.i()));
+    }
+    for (index, (x, y)) in a.registers().iter().zip(b.registers()).enumerate() {
+        if *x != y {
+            lines.push(format!("V{:X}: {:#04X} -> {:#04X}", index, x, y));
+        }
+    }
+    if a.delay_timer() != b.delay_timer() {
+        lines.push(format!("DT: {} -> {}", a.delay_timer(), b.delay_timer()));
+    }
+    if a.sound_timer() != b.sound_timer() {
+        lines.push(format!("ST: {} -> {}", a.sound_timer(), b.sound_timer()));
+    }
+    if a.stack() != b.stack() {
+        lines.push(format!(
+            "Stack: {} -> {}",
+            stack(a.stack()),
+            stack(b.stack())
+        ));
+    }
+
+    if a.memory_size() != b.memory_size() {
+        lines.push(format!(
+            "Memory size: {} -> {}",
+            a.memory_size(),
+            b.memory_size()
+        ));
+    }
+    // the memory both have
+    let size = a.memory_size().min(b.memory_size());
+    for (start, end) in ranges(size, |address| {
+        a.read_memory(address as u16) != b.read_memory(address as u16)
+    }) {
+        let bytes = |state: &State| -> String {
+            (start..=end)
+                .map(|address| format!("{:02X}", state.read_memory(address as u16)))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        if end - start < 8 {
+            lines.push(format!(
+                "Memory {:#05X}-{:#05X}: {} -> {}",
+                start,
+                end,
+                bytes(a),
+                bytes(b)
+            ));
+        } else {
+            lines.push(format!(
+                "Memory {:#05X}-{:#05X}: {} bytes",
+                start,
+                end,
+                end - start + 1
+            ));
+        }
+    }
+
+    let (screen_a, screen_b) = (a.get_graphics_buffer(), b.get_graphics_buffer());
+    let pixels = screen_a
+        .iter()
+        .zip(&screen_b)
+        .filter(|(x, y)| x != y)
+        .count();
+    if pixels > 0 {
+        // - only lit in the first state, + only in the second
+        lines.push(format!("Display: {} pixels differ", pixels));
+        for row in 0..32 {
+            let line: String = (row * 64..row * 64 + 64)
+                .map(|index| match (screen_a[index], screen_b[index]) {
+                    (0, 0) => '.',
+                    (_, 0) => '-',
+                    (0, _) => '+',
+                    _ => '#',
+                })
+                .collect();
+            lines.push(line);
+        }
+    }
+    lines
+}
+
+fn stack(addresses: &[u16]) -> String {
+    let addresses: Vec<String> = addresses
+        .iter()
+        .map(|address| format!("{:#05X}", address))
+        .collect();
+    format!("[{}]", addresses.join(", "))
+}
+
+// Inclusive address ranges below size where differs is true
+fn ranges(size: usize, differs: impl Fn(usize) -> bool) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for address in (0..size).filter(|address| differs(*address)) {
+        match ranges.last_mut() {
+            Some((_, end)) if address - *end <= RANGE_GAP => *end = address,
+            _ => ranges.push((address, address)),
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::XO_CHIP_MEMORY_SIZE;
+
+    #[test]
+    fn reports_memory_past_4k() {
+        let mut a = State::new();
+        a.set_memory_size(XO_CHIP_MEMORY_SIZE).unwrap();
+        let mut b = State::new();
+        b.set_memory_size(XO_CHIP_MEMORY_SIZE).unwrap();
+        b.write_memory(0x8000, 0xAA);
+        assert_eq!(diff(&a, &b), ["Memory 0x8000-0x8000: 00 -> AA"]);
+    }
+
+    #[test]
+    fn reports_different_memory_sizes() {
+        let mut a = State::new();
+        a.initialize();
+        let mut b = State::new();
+        b.initialize();
+        b.set_memory_size(XO_CHIP_MEMORY_SIZE).unwrap();
+        assert_eq!(diff(&a, &b), ["Memory size: 4096 -> 65536"]);
+    }
+}
@@ -0,0 +1,86 @@
+// --stats-server ADDRESS, live numbers for dashboards watching long runs.
+// GET /stats answers with one `name value` pair per line: the rates over the
+// last second, the machine's timers and counters, and whatever the runner
+// adds with set_info, such as batch progress.
+use crate::chip8::State;
+use crate::http::Server;
+use std::time::{Duration, Instant};
+
+// how often the rates are recalculated
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+pub struct StatsServer {
+    server: Server,
+    window_start: Instant,
+    // the counts when the window started
+    window_cycles: u64,
+    window_frames: u64,
+    instruction_rate: f64,
+    frame_rate: f64,
+    info: Vec<(String, String)>,
+}
+
+impl StatsServer {
+    pub fn bind(address: &str) -> Result<Self, String> {
+        let server = Server::bind(address)?;
+        info!("Stats served on http://{}/stats", address);
+        Ok(Self {
+            server,
+            window_start: Instant::now(),
+            window_cycles: 0,
+            window_frames: 0,
+            instruction_rate: 0.0,
+            frame_rate: 0.0,
+            info: Vec::new(),
+        })
+    }
+
+    // an extra line for the report, replacing one with the same name
+    pub fn set_info(&mut self, name: &str, value: String) {
+        match self.info.iter_mut().find(|(other, _)| other == name) {
+            Some((_, old)) => *old = value,
+            None => self.info.push((name.to_string(), value)),
+        }
+    }
+
+    // call once a frame, frames counts the frames since chip_8 started
+    pub fn poll(&mut self, chip_8: &State, frames: u64) {
+        let cycles = chip_8.stats().cycles;
+        // a restarted machine counts from zero again
+        if cycles < self.window_cycles || frames < self.window_frames {
+            self.window_cycles = cycles;
+            self.window_frames = frames;
+        }
+        let elapsed = self.window_start.elapsed();
+        if elapsed >= RATE_WINDOW {
+            let seconds = elapsed.as_secs_f64();
+            self.instruction_rate = (cycles - self.window_cycles) as f64 / seconds;
+            self.frame_rate = (frames - self.window_frames) as f64 / seconds;
+            self.window_start = Instant::now();
+            self.window_cycles = cycles;
+            self.window_frames = frames;
+        }
+
+        for request in self.server.requests() {
+            if request.path != "/stats" {
+                request.text(404, "Not found");
+                continue;
+            }
+            let mut report = format!(
+                "instructions_per_second {:.1}\nframes_per_second {:.1}\ncycles {}\nframes {}\ndelay_timer {}\nsound_timer {}\nunknown_opcodes {}\nhalted {}\n",
+                self.instruction_rate,
+                self.frame_rate,
+                cycles,
+                frames,
+                chip_8.delay_timer(),
+                chip_8.sound_timer(),
+                chip_8.stats().unknown_opcodes,
+                chip_8.error().is_some() as u8
+            );
+            for (name, value) in &self.info {
+                report.push_str(&format!("{} {}\n", name, value));
+            }
+            request.text(200, &report);
+        }
+    }
+}
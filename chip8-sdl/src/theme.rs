@@ -0,0 +1,47 @@
+// --theme, display colors picked for low vision and colorblind players. Every
+// theme's on and off colors are at least 7:1 apart, the WCAG's enhanced
+// contrast level, `chip8 themes` lists them with their ratios.
+
+// name, then the off and on colors
+pub const THEMES: [(&str, [[u8; 3]; 2]); 5] = [
+    ("default", [[0, 0, 0], [255, 255, 255]]),
+    ("inverted", [[255, 255, 255], [0, 0, 0]]),
+    ("high-contrast", [[0, 0, 0], [255, 255, 0]]),
+    ("amber", [[0, 0, 0], [255, 176, 0]]),
+    // blue and orange, told apart with any kind of color blindness
+    ("colorblind", [[0, 26, 51], [230, 159, 0]]),
+];
+
+pub fn named(name: &str) -> Result<[[u8; 3]; 2], String> {
+    THEMES
+        .iter()
+        .find(|(theme, _)| theme.eq_ignore_ascii_case(name))
+        .map(|(_, palette)| *palette)
+        .ok_or_else(|| {
+            let names: Vec<_> = THEMES.iter().map(|(theme, _)| *theme).collect();
+            format!(
+                "Unknown theme {}, expected one of {}",
+                name,
+                names.join(", ")
+            )
+        })
+}
+
+// The WCAG contrast ratio between the two colors, from 1 to 21
+pub fn contrast_ratio(palette: &[[u8; 3]; 2]) -> f32 {
+    let [off, on] = palette.map(luminance);
+    (off.max(on) + 0.05) / (off.min(on) + 0.05)
+}
+
+// relative luminance of an sRGB color
+fn luminance(color: [u8; 3]) -> f32 {
+    let [red, green, blue] = color.map(|channel| {
+        let channel = channel as f32 / 255.0;
+        if channel <= 0.04045 {
+            channel / 12.92
+        } else {
+            ((channel + 0.055) / 1.055).powf(2.4)
+        }
+    });
+    0.2126 * red + 0.7152 * green + 0.0722 * blue
+}
@@ -0,0 +1,43 @@
+// Maps touches on the display to keypad keys through a grid described like
+// "123C/456D/789E/A0BF": rows separated by '/', one hex key or '.' per cell
+pub struct TouchLayout {
+    rows: Vec<Vec<Option<u8>>>,
+}
+
+impl TouchLayout {
+    pub fn parse(layout: &str) -> Result<Self, String> {
+        let rows: Vec<Vec<Option<u8>>> = layout
+            .split('/')
+            .map(|row| {
+                row.chars()
+                    .map(|cell| match cell {
+                        '.' => Ok(None),
+                        _ => cell
+                            .to_digit(16)
+                            .map(|key| Some(key as u8))
+                            .ok_or_else(|| format!("Invalid touch layout cell {}", cell)),
+                    })
+                    .collect()
+            })
+            .collect::<Result<_, _>>()?;
+        if rows.is_empty() || rows.iter().any(|row| row.is_empty()) {
+            return Err(format!("Invalid touch layout {}", layout));
+        }
+        Ok(Self { rows })
+    }
+
+    // x and y are 0.0-1.0 across the touched area
+    pub fn key_at(&self, x: f32, y: f32) -> Option<u8> {
+        if !(0.0..1.0).contains(&x) || !(0.0..1.0).contains(&y) {
+            return None;
+        }
+        let row = &self.rows[(y * self.rows.len() as f32) as usize];
+        row[(x * row.len() as f32) as usize]
+    }
+}
+
+impl Default for TouchLayout {
+    fn default() -> Self {
+        Self::parse("123C/456D/789E/A0BF").unwrap()
+    }
+}
@@ -0,0 +1,292 @@
+use crate::chip8::State;
+use crate::hash;
+use crate::symbols::Symbols;
+use serde_json::Value;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+// Writes one JSON object per executed cycle, only registers that changed are listed
+// and the display hash is only included after instructions that change the screen.
+// Instructions at a labelled address name the label.
+pub struct JsonTracer {
+    out: BufWriter<File>,
+    cycle: u64,
+    pc: u16,
+    registers: [u8; 16],
+    symbols: Symbols,
+}
+
+impl JsonTracer {
+    pub fn create(path: &str) -> Result<Self, String> {
+        Ok(Self {
+            out: BufWriter::new(File::create(path).map_err(|op| op.to_string())?),
+            cycle: 0,
+            pc: 0,
+            registers: [0; 16],
+            symbols: Symbols::default(),
+        })
+    }
+
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    // call before emulate_cycle
+    pub fn before(&mut self, state: &State) {
+        self.pc = state.pc();
+        self.registers = state.registers();
+    }
+
+    // call after emulate_cycle
+    pub fn after(&mut self, state: &State) -> Result<(), String> {
+        let mut changed = Vec::new();
+        for (index, (old, new)) in self.registers.iter().zip(state.registers()).enumerate() {
+            if *old != new {
+                changed.push(format!("\"V{:X}\":{}", index, new));
+            }
+        }
+        let display = if changes_display(state.opcode()) {
+            format!(",\"display\":\"{}\"", display_hash(state))
+        } else {
+            String::new()
+        };
+        let label = match self.symbols.name(self.pc) {
+            Some(name) => format!(
+                ",\"label\":\"{}\"",
+                name.replace('\\', "\\\\").replace('"', "\\\"")
+            ),
+            None => String::new(),
+        };
+        writeln!(
+            self.out,
+            "{{\"cycle\":{},\"pc\":{}{},\"opcode\":{},\"v\":{{{}}},\"i\":{},\"dt\":{},\"st\":{}{}}}",
+            self.cycle,
+            self.pc,
+            label,
+            state.opcode(),
+            changed.join(","),
+            state.i(),
+            state.delay_timer(),
+            state.sound_timer(),
+            display
+        )
+        .map_err(|op| op.to_string())?;
+        self.cycle += 1;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), String> {
+        self.out.flush().map_err(|op| op.to_string())
+    }
+}
+
+fn changes_display(opcode: u16) -> bool {
+    opcode == 0x00E0 || opcode & 0xF000 == 0xD000
+}
+
+fn display_hash(state: &State) -> String {
+    hash::to_hex(&hash::sha1(&state.get_graphics_buffer()))
+}
+
+// One line of a trace written by JsonTracer
+pub struct Record {
+    pub pc: u16,
+    pub opcode: u16,
+    // only the registers that changed during the cycle
+    pub registers: Vec<(usize, u8)>,
+    pub i: u16,
+    pub dt: u8,
+    pub st: u8,
+    pub display: Option<String>,
+}
+
+impl Record {
+    // Reads the fields by key, in whatever order the record has them
+    pub fn parse(value: &Value) -> Result<Self, String> {
+        let number = |name: &str, max: u64| -> Result<u64, String> {
+            value
+                .get(name)
+                .ok_or(format!("Missing {}", name))?
+                .as_u64()
+                .filter(|number| *number <= max)
+                .ok_or(format!("Invalid {}", name))
+        };
+
+        let mut registers = Vec::new();
+        if let Some(changed) = value.get("v") {
+            for (name, value) in changed.as_object().ok_or("Invalid registers")? {
+                let index = name
+                    .strip_prefix('V')
+                    .filter(|digit| digit.len() == 1)
+                    .and_then(|digit| usize::from_str_radix(digit, 16).ok())
+                    .ok_or(format!("Invalid register {}", name))?;
+                let value = value
+                    .as_u64()
+                    .and_then(|value| u8::try_from(value).ok())
+                    .ok_or(format!("Invalid value for {}", name))?;
+                registers.push((index, value));
+            }
+        }
+
+        let display = match value.get("display") {
+            Some(display) => Some(display.as_str().ok_or("Invalid display")?.to_string()),
+            None => None,
+        };
+
+        Ok(Self {
+            pc: number("pc", 0xFFFF)? as u16,
+            opcode: number("opcode", 0xFFFF)? as u16,
+            registers,
+            i: number("i", 0xFFFF)? as u16,
+            dt: number("dt", 0xFF)? as u8,
+            st: number("st", 0xFF)? as u8,
+            display,
+        })
+    }
+}
+
+// Executes chip_8 one cycle per reference record and stops at the first cycle
+// whose outcome differs, returning a description of both states
+pub fn lockstep(chip_8: &mut State, reference: &str) -> Result<Option<String>, String> {
+    let mut expected = [0u8; 16];
+    // records are counted rather than lines, so a formatted trace works too
+    let records = serde_json::Deserializer::from_str(reference).into_iter::<Value>();
+    for (cycle, record) in records.enumerate() {
+        let record = record
+            .map_err(|op| op.to_string())
+            .and_then(|record| Record::parse(&record))
+            .map_err(|op| format!("record {}: {}", cycle + 1, op))?;
+        for (index, value) in &record.registers {
+            expected[*index] = *value;
+        }
+
+        let pc = chip_8.pc();
+        let halted = chip_8.emulate_cycle().err();
+        let mut differences = Vec::new();
+        if pc != record.pc {
+            differences.push("PC");
+        }
+        if chip_8.opcode() != record.opcode {
+            differences.push("opcode");
+        }
+        if chip_8.registers() != expected {
+            differences.push("V registers");
+        }
+        if chip_8.i() != record.i {
+            differences.push("I");
+        }
+        if chip_8.delay_timer() != record.dt || chip_8.sound_timer() != record.st {
+            differences.push("timers");
+        }
+        if let Some(display) = &record.display {
+            if *display != display_hash(chip_8) {
+                differences.push("display");
+            }
+        }
+        if halted.is_some() {
+            differences.push("halted");
+        }
+        if differences.is_empty() {
+            continue;
+        }
+
+        let mut report = format!("Diverged at cycle {}: {}\n", cycle, differences.join(", "));
+        report += &format!(
+            "  reference: pc={:#05X} opcode={:04X} I={:#05X} DT={} ST={} V={:02X?}\n",
+            record.pc, record.opcode, record.i, record.dt, record.st, expected
+        );
+        report += &format!(
+            "  emulator:  pc={:#05X} opcode={:04X} I={:#05X} DT={} ST={} V={:02X?}",
+            pc,
+            chip_8.opcode(),
+            chip_8.i(),
+            chip_8.delay_timer(),
+            chip_8.sound_timer(),
+            chip_8.registers()
+        );
+        if let Some(error) = halted {
+            report += &format!("\n  emulator halted: {}", error);
+        }
+        return Ok(Some(report));
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // LD V0, 5, ADD V0, 3 then loop
+    const PROGRAM: [u8; 6] = [0x60, 0x05, 0x70, 0x03, 0x12, 0x04];
+    const REFERENCE: &str = concat!(
+        "{\"cycle\":0,\"pc\":512,\"opcode\":24581,\"v\":{\"V0\":5},\"i\":0,\"dt\":0,\"st\":0}\n",
+        "{\"cycle\":1,\"pc\":514,\"opcode\":28675,\"v\":{\"V0\":8},\"i\":0,\"dt\":0,\"st\":0}\n",
+    );
+
+    fn parse(line: &str) -> Result<Record, String> {
+        Record::parse(&serde_json::from_str(line).map_err(|op| op.to_string())?)
+    }
+
+    fn machine() -> State {
+        let mut chip_8 = State::new();
+        chip_8.initialize();
+        chip_8.load_buffer(&PROGRAM);
+        chip_8
+    }
+
+    #[test]
+    fn parses_records() {
+        let record = parse(concat!(
+            "{\"cycle\":7,\"pc\":520,\"label\":\"draw\",\"opcode\":53829,",
+            "\"v\":{\"VA\":12,\"VF\":1},\"i\":768,\"dt\":3,\"st\":0,\"display\":\"0a1b\"}"
+        ))
+        .unwrap();
+        assert_eq!(record.pc, 520);
+        assert_eq!(record.opcode, 0xD245);
+        assert_eq!(record.registers, vec![(0xA, 12), (0xF, 1)]);
+        assert_eq!((record.i, record.dt, record.st), (768, 3, 0));
+        assert_eq!(record.display.as_deref(), Some("0a1b"));
+    }
+
+    #[test]
+    fn rejects_missing_fields() {
+        let error = parse("{\"cycle\":0,\"pc\":512,\"v\":{},\"i\":0,\"dt\":0,\"st\":0}");
+        assert_eq!(error.err(), Some("Missing opcode".to_string()));
+        assert!(parse("{\"pc\":512,\"opcode\":0,\"v\":{\"VG\":1}").is_err());
+    }
+
+    #[test]
+    fn parses_formatted_records() {
+        let record = parse(concat!(
+            "{\n  \"cycle\": 7,\n  \"pc\": 520,\n  \"label\": \"\\\"i\\\": 1\",\n",
+            "  \"opcode\": 53829,\n  \"v\": { \"VA\": 12 },\n",
+            "  \"i\": 768,\n  \"dt\": 3,\n  \"st\": 0\n}"
+        ))
+        .unwrap();
+        assert_eq!((record.pc, record.opcode), (520, 0xD245));
+        assert_eq!(record.registers, vec![(0xA, 12)]);
+        assert_eq!((record.i, record.dt, record.st), (768, 3, 0));
+        assert_eq!(record.display, None);
+    }
+
+    #[test]
+    fn lockstep_follows_a_matching_trace() {
+        assert_eq!(lockstep(&mut machine(), REFERENCE), Ok(None));
+    }
+
+    #[test]
+    fn lockstep_reports_the_first_difference() {
+        let reference = REFERENCE.replace("\"V0\":8", "\"V0\":9");
+        let report = lockstep(&mut machine(), &reference).unwrap().unwrap();
+        assert!(report.starts_with("Diverged at cycle 1: V registers\n"));
+    }
+
+    #[test]
+    fn lockstep_counts_records_rather_than_lines() {
+        let reference = REFERENCE
+            .replace(",", ",\n  ")
+            .replace("\"V0\":8", "\"V0\":9");
+        let report = lockstep(&mut machine(), &reference).unwrap().unwrap();
+        assert!(report.starts_with("Diverged at cycle 1: V registers\n"));
+    }
+}
@@ -0,0 +1,618 @@
+use crate::chip8::State;
+use crate::disasm;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use std::time::{Duration, Instant};
+
+// 3x5 glyphs, one row per byte with the leftmost pixel in bit 2
+const GLYPHS: [(char, [u8; 5]); 53] = [
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b111, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b010, 0b010, 0b010]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+    (' ', [0b000, 0b000, 0b000, 0b000, 0b000]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    (',', [0b000, 0b000, 0b000, 0b010, 0b100]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    ('_', [0b000, 0b000, 0b000, 0b000, 0b111]),
+    ('+', [0b000, 0b010, 0b111, 0b010, 0b000]),
+    ('=', [0b000, 0b111, 0b000, 0b111, 0b000]),
+    ('/', [0b001, 0b001, 0b010, 0b100, 0b100]),
+    ('%', [0b101, 0b001, 0b010, 0b100, 0b101]),
+    ('(', [0b010, 0b100, 0b100, 0b100, 0b010]),
+    (')', [0b010, 0b001, 0b001, 0b001, 0b010]),
+    ('[', [0b110, 0b100, 0b100, 0b100, 0b110]),
+    (']', [0b011, 0b001, 0b001, 0b001, 0b011]),
+    ('!', [0b010, 0b010, 0b010, 0b000, 0b010]),
+    ('?', [0b111, 0b001, 0b010, 0b000, 0b010]),
+    ('#', [0b101, 0b111, 0b101, 0b111, 0b101]),
+];
+
+fn glyph(c: char) -> [u8; 5] {
+    let c = c.to_ascii_uppercase();
+    GLYPHS
+        .iter()
+        .find(|(glyph, _)| *glyph == c)
+        .or_else(|| GLYPHS.iter().find(|(glyph, _)| *glyph == '?'))
+        .map(|(_, rows)| *rows)
+        .unwrap()
+}
+
+// Width in pixels of text drawn at scale
+pub fn text_width(text: &str, scale: u32) -> u32 {
+    (text.chars().count() as u32 * 4).saturating_sub(1) * scale
+}
+
+pub fn draw_text(
+    canvas: &mut Canvas<Window>,
+    text: &str,
+    x: i32,
+    y: i32,
+    scale: u32,
+    color: Color,
+) -> Result<(), String> {
+    canvas.set_draw_color(color);
+    for (index, c) in text.chars().enumerate() {
+        let left = x + (index as u32 * 4 * scale) as i32;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for column in 0..3 {
+                if bits & (0b100 >> column) != 0 {
+                    canvas.fill_rect(Rect::new(
+                        left + (column * scale) as i32,
+                        y + (row as u32 * scale) as i32,
+                        scale,
+                        scale,
+                    ))?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Keys in the order they're laid out on the original keypad
+pub const KEYPAD_LAYOUT: [u8; 16] = [
+    0x1, 0x2, 0x3, 0xC, //
+    0x4, 0x5, 0x6, 0xD, //
+    0x7, 0x8, 0x9, 0xE, //
+    0xA, 0x0, 0xB, 0xF,
+];
+
+// A clickable 4x4 keypad drawn under the display
+pub struct Keypad {
+    pub visible: bool,
+    // the key held down with the mouse, released with the button
+    pub held: Option<u8>,
+}
+
+impl Keypad {
+    pub const HEIGHT: u32 = 160;
+
+    pub fn new() -> Self {
+        Self {
+            visible: false,
+            held: None,
+        }
+    }
+
+    fn cell(index: usize, area: Rect) -> Rect {
+        let width = area.width() / 4;
+        let height = area.height() / 4;
+        Rect::new(
+            area.x() + (index % 4) as i32 * width as i32 + 2,
+            area.y() + (index / 4) as i32 * height as i32 + 2,
+            width - 4,
+            height - 4,
+        )
+    }
+
+    pub fn key_at(&self, area: Rect, x: i32, y: i32) -> Option<u8> {
+        if !self.visible {
+            return None;
+        }
+        (0..16)
+            .find(|index| Self::cell(*index, area).contains_point((x, y)))
+            .map(|index| KEYPAD_LAYOUT[index])
+    }
+
+    // Pressed keys are the ones the core currently sees as down
+    pub fn draw(
+        &self,
+        canvas: &mut Canvas<Window>,
+        area: Rect,
+        chip_8: &State,
+    ) -> Result<(), String> {
+        if !self.visible {
+            return Ok(());
+        }
+        for (index, key) in KEYPAD_LAYOUT.iter().enumerate() {
+            let cell = Self::cell(index, area);
+            let pressed = chip_8.is_key_pressed(*key as usize);
+            canvas.set_draw_color(if pressed {
+                Color::RGB(200, 200, 200)
+            } else {
+                Color::RGB(60, 60, 60)
+            });
+            canvas.fill_rect(cell)?;
+            let label = format!("{:X}", key);
+            let scale = 4;
+            draw_text(
+                canvas,
+                &label,
+                cell.center().x() - (text_width(&label, scale) / 2) as i32,
+                cell.center().y() - (5 * scale / 2) as i32,
+                scale,
+                if pressed {
+                    Color::RGB(0, 0, 0)
+                } else {
+                    Color::RGB(255, 255, 255)
+                },
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Short status messages drawn over the top left of the display
+pub struct Osd {
+    message: Option<(String, Instant)>,
+}
+
+impl Osd {
+    const DURATION: Duration = Duration::from_secs(2);
+    const SCALE: u32 = 3;
+
+    pub fn new() -> Self {
+        Self { message: None }
+    }
+
+    pub fn show(&mut self, message: impl Into<String>) {
+        self.message = Some((message.into(), Instant::now()));
+    }
+
+    // true while a message is on screen, or just expired and needs erasing
+    pub fn needs_redraw(&mut self) -> bool {
+        match &self.message {
+            Some((_, shown)) if shown.elapsed() > Self::DURATION => {
+                self.message = None;
+                true
+            }
+            Some(_) => true,
+            None => false,
+        }
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas<Window>) -> Result<(), String> {
+        if let Some((message, _)) = &self.message {
+            let padding = Self::SCALE * 2;
+            canvas.set_draw_color(Color::RGB(40, 40, 40));
+            canvas.fill_rect(Rect::new(
+                4,
+                4,
+                text_width(message, Self::SCALE) + padding * 2,
+                5 * Self::SCALE + padding * 2,
+            ))?;
+            draw_text(
+                canvas,
+                message,
+                (4 + padding) as i32,
+                (4 + padding) as i32,
+                Self::SCALE,
+                Color::RGB(255, 255, 0),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    V(u8),
+    I,
+    Pc,
+    Delay,
+    Sound,
+}
+
+impl Register {
+    fn name(self) -> String {
+        match self {
+            Register::V(index) => format!("V{:X}", index),
+            Register::I => "I".to_string(),
+            Register::Pc => "PC".to_string(),
+            Register::Delay => "DT".to_string(),
+            Register::Sound => "ST".to_string(),
+        }
+    }
+
+    // hex digits the value is shown with
+    fn digits(self) -> usize {
+        match self {
+            Register::I | Register::Pc => 3,
+            _ => 2,
+        }
+    }
+
+    fn read(self, chip_8: &State) -> u16 {
+        match self {
+            Register::V(index) => chip_8.registers()[index as usize] as u16,
+            Register::I => chip_8.i(),
+            Register::Pc => chip_8.pc(),
+            Register::Delay => chip_8.delay_timer() as u16,
+            Register::Sound => chip_8.sound_timer() as u16,
+        }
+    }
+
+    fn write(self, chip_8: &mut State, value: u16) {
+        match self {
+            Register::V(index) => chip_8.set_register(index as usize, value as u8),
+            Register::I => chip_8.set_i(value),
+            Register::Pc => chip_8.set_pc(value),
+            Register::Delay => chip_8.set_delay_timer(value as u8),
+            Register::Sound => chip_8.set_sound_timer(value as u8),
+        }
+    }
+}
+
+// The registers drawn over the display while paused. Clicking one edits it:
+// type hex digits, Enter writes the value into the machine, Escape cancels.
+pub struct RegisterPanel {
+    // the register and the digits typed so far
+    editing: Option<(Register, String)>,
+}
+
+impl RegisterPanel {
+    const SCALE: u32 = 3;
+    const LINE_HEIGHT: u32 = 7 * Self::SCALE;
+    const COLUMN_WIDTH: u32 = 8 * 4 * Self::SCALE;
+
+    pub fn new() -> Self {
+        Self { editing: None }
+    }
+
+    // each register and where it's drawn: V0-V7 and V8-VF side by side, then
+    // I and PC, then the timers
+    fn layout(area: Rect) -> Vec<(Register, Rect)> {
+        let mut registers: Vec<(Register, u32, u32)> = (0..16)
+            .map(|index| (Register::V(index), index as u32 / 8, index as u32 % 8))
+            .collect();
+        registers.extend([
+            (Register::I, 0, 8),
+            (Register::Pc, 1, 8),
+            (Register::Delay, 0, 9),
+            (Register::Sound, 1, 9),
+        ]);
+        let left = area.right() - 2 * Self::COLUMN_WIDTH as i32 - 4;
+        registers
+            .into_iter()
+            .map(|(register, column, row)| {
+                let rect = Rect::new(
+                    left + (column * Self::COLUMN_WIDTH) as i32,
+                    area.y() + 4 + (row * Self::LINE_HEIGHT) as i32,
+                    Self::COLUMN_WIDTH,
+                    Self::LINE_HEIGHT,
+                );
+                (register, rect)
+            })
+            .collect()
+    }
+
+    // true if the click landed on a register, which is then being edited
+    pub fn click(&mut self, area: Rect, x: i32, y: i32) -> bool {
+        let hit = Self::layout(area)
+            .into_iter()
+            .find(|(_, rect)| rect.contains_point((x, y)));
+        self.editing = hit.map(|(register, _)| (register, String::new()));
+        hit.is_some()
+    }
+
+    // true if the key went to the register being edited
+    pub fn key(&mut self, keycode: Option<Keycode>, chip_8: &mut State) -> bool {
+        let (Some((register, typed)), Some(keycode)) = (&mut self.editing, keycode) else {
+            return false;
+        };
+        match keycode {
+            Keycode::Return | Keycode::KpEnter => {
+                if let Ok(value) = u16::from_str_radix(typed, 16) {
+                    register.write(chip_8, value);
+                }
+                self.editing = None;
+            }
+            Keycode::Escape => self.editing = None,
+            Keycode::Backspace => {
+                typed.pop();
+            }
+            _ => {
+                // 0-9 on either the number row or keypad, and A-F
+                let name = keycode.name();
+                let digit = name.strip_prefix("Keypad ").unwrap_or(&name);
+                let digit = Some(digit)
+                    .filter(|digit| digit.len() == 1)
+                    .and_then(|digit| digit.chars().next())
+                    .filter(char::is_ascii_hexdigit);
+                match digit {
+                    Some(digit) if typed.len() < register.digits() => typed.push(digit),
+                    Some(_) => {}
+                    None => return false,
+                }
+            }
+        }
+        true
+    }
+
+    pub fn draw(
+        &self,
+        canvas: &mut Canvas<Window>,
+        area: Rect,
+        chip_8: &State,
+    ) -> Result<(), String> {
+        let layout = Self::layout(area);
+        let (first, last) = (layout[0].1, layout[layout.len() - 1].1);
+        canvas.set_draw_color(Color::RGB(40, 40, 40));
+        canvas.fill_rect(Rect::new(
+            first.x() - 4,
+            first.y() - 4,
+            2 * Self::COLUMN_WIDTH + 4,
+            (last.bottom() - first.y()) as u32 + 4,
+        ))?;
+        for (register, rect) in layout {
+            let (value, color) = match &self.editing {
+                Some((editing, typed)) if *editing == register => (
+                    format!("{:_<width$}", typed, width = register.digits()),
+                    Color::RGB(255, 255, 0),
+                ),
+                _ => (
+                    format!(
+                        "{:0width$X}",
+                        register.read(chip_8),
+                        width = register.digits()
+                    ),
+                    Color::RGB(255, 255, 255),
+                ),
+            };
+            let text = format!("{}={}", register.name(), value);
+            draw_text(canvas, &text, rect.x(), rect.y(), Self::SCALE, color)?;
+        }
+        Ok(())
+    }
+}
+
+// The instructions around the pc, drawn over the display while paused. Lines
+// can be selected by clicking or with the arrow keys to toggle breakpoints.
+pub struct DisassemblyPane {
+    // the view is centered on this instead of the pc once something's selected
+    selected: Option<u16>,
+}
+
+impl DisassemblyPane {
+    const SCALE: u32 = 3;
+    const LINE_HEIGHT: u32 = 7 * Self::SCALE;
+    // e.g. 2A4  D015  DRW V0, V1, 5
+    const WIDTH: u32 = 24 * 4 * Self::SCALE;
+
+    pub fn new() -> Self {
+        Self { selected: None }
+    }
+
+    // go back to following the pc
+    pub fn follow(&mut self) {
+        self.selected = None;
+    }
+
+    // the selected line's address, the pc's when none is
+    pub fn selection(&self, chip_8: &State) -> u16 {
+        self.selected.unwrap_or(chip_8.pc())
+    }
+
+    // moves the selection by instructions, negative is up
+    pub fn scroll(&mut self, chip_8: &State, instructions: i32) {
+        let address = self.selection(chip_8) as i32 + instructions * 2;
+        self.selected = Some(address.rem_euclid(chip_8.memory_size() as i32) as u16);
+    }
+
+    // each line's address and where it's drawn, wrapping around memory_size
+    fn layout(area: Rect, center: u16, memory_size: usize) -> Vec<(u16, Rect)> {
+        let rows = ((area.height() - 8) / Self::LINE_HEIGHT).max(1);
+        let first = center as i32 - (rows / 2 * 2) as i32;
+        (0..rows)
+            .map(|row| {
+                let address = (first + row as i32 * 2).rem_euclid(memory_size as i32) as u16;
+                let rect = Rect::new(
+                    area.x() + 4,
+                    area.y() + 4 + (row * Self::LINE_HEIGHT) as i32,
+                    Self::WIDTH,
+                    Self::LINE_HEIGHT,
+                );
+                (address, rect)
+            })
+            .collect()
+    }
+
+    // the address of the line clicked, which is then selected
+    pub fn click(&mut self, area: Rect, x: i32, y: i32, chip_8: &State) -> Option<u16> {
+        let (address, _) = Self::layout(area, self.selection(chip_8), chip_8.memory_size())
+            .into_iter()
+            .find(|(_, rect)| rect.contains_point((x, y)))?;
+        self.selected = Some(address);
+        Some(address)
+    }
+
+    pub fn draw(
+        &self,
+        canvas: &mut Canvas<Window>,
+        area: Rect,
+        chip_8: &State,
+        breakpoint: impl Fn(u16) -> bool,
+    ) -> Result<(), String> {
+        let layout = Self::layout(area, self.selection(chip_8), chip_8.memory_size());
+        let (first, last) = (layout[0].1, layout[layout.len() - 1].1);
+        canvas.set_draw_color(Color::RGB(40, 40, 40));
+        canvas.fill_rect(Rect::new(
+            first.x() - 4,
+            first.y() - 4,
+            Self::WIDTH + 4,
+            (last.bottom() - first.y()) as u32 + 4,
+        ))?;
+        for (address, rect) in layout {
+            if self.selected == Some(address) {
+                canvas.set_draw_color(Color::RGB(80, 80, 80));
+                canvas.fill_rect(Rect::new(
+                    rect.x() - 2,
+                    rect.y() - 2,
+                    rect.width(),
+                    rect.height(),
+                ))?;
+            }
+            let opcode = (chip_8.read_memory(address) as u16) << 8
+                | chip_8.read_memory(address.wrapping_add(1)) as u16;
+            let text = format!(
+                "{:03X}  {:04X}  {}",
+                address,
+                opcode,
+                disasm::disassemble(opcode)
+            );
+            let color = match (address == chip_8.pc(), breakpoint(address)) {
+                (true, true) => Color::RGB(255, 160, 0),
+                (true, false) => Color::RGB(255, 255, 0),
+                (false, true) => Color::RGB(255, 80, 80),
+                (false, false) => Color::RGB(255, 255, 255),
+            };
+            draw_text(canvas, &text, rect.x(), rect.y(), Self::SCALE, color)?;
+        }
+        Ok(())
+    }
+}
+
+// A zoomed inset of the pixels around the mouse, with the one under it
+// outlined and its coordinates, for lining up sprites
+pub struct Magnifier {
+    pub enabled: bool,
+    // where the mouse last was in the window
+    mouse: Option<(i32, i32)>,
+}
+
+impl Magnifier {
+    // pixels shown either side of the one under the mouse
+    const RADIUS: i32 = 4;
+    // window pixels per magnified pixel
+    const ZOOM: u32 = 12;
+    const SCALE: u32 = 2;
+
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            mouse: None,
+        }
+    }
+
+    // true when the inset needs drawing again
+    pub fn hover(&mut self, x: i32, y: i32) -> bool {
+        self.mouse = Some((x, y));
+        self.enabled
+    }
+
+    // Draws the inset next to the mouse if it's over screen, drawn into area
+    pub fn draw(
+        &self,
+        canvas: &mut Canvas<Window>,
+        area: Rect,
+        screen: &[u8],
+        palette: &[[u8; 3]; 2],
+    ) -> Result<(), String> {
+        let Some((x, y)) = self
+            .mouse
+            .filter(|(x, y)| self.enabled && area.contains_point((*x, *y)))
+        else {
+            return Ok(());
+        };
+        let column = (x - area.x()) * 64 / area.width() as i32;
+        let row = (y - area.y()) * 32 / area.height() as i32;
+        let label = format!("{},{}", column, row);
+
+        // beside the mouse, on whichever side has room
+        let side = (2 * Self::RADIUS + 1) as u32 * Self::ZOOM;
+        let height = side + 5 * Self::SCALE + 4;
+        let left = if x + 16 + side as i32 <= area.right() {
+            x + 16
+        } else {
+            x - 16 - side as i32
+        };
+        let top = if y + 16 + height as i32 <= area.bottom() {
+            y + 16
+        } else {
+            y - 16 - height as i32
+        };
+        canvas.set_draw_color(Color::RGB(40, 40, 40));
+        canvas.fill_rect(Rect::new(left - 2, top - 2, side + 4, height + 4))?;
+        for dy in -Self::RADIUS..=Self::RADIUS {
+            for dx in -Self::RADIUS..=Self::RADIUS {
+                let (pixel_x, pixel_y) = (column + dx, row + dy);
+                // off the edge of the screen stays the background
+                if !(0..64).contains(&pixel_x) || !(0..32).contains(&pixel_y) {
+                    continue;
+                }
+                let [red, green, blue] =
+                    palette[screen[(pixel_y * 64 + pixel_x) as usize] as usize];
+                canvas.set_draw_color(Color::RGB(red, green, blue));
+                canvas.fill_rect(Rect::new(
+                    left + (dx + Self::RADIUS) * Self::ZOOM as i32,
+                    top + (dy + Self::RADIUS) * Self::ZOOM as i32,
+                    Self::ZOOM,
+                    Self::ZOOM,
+                ))?;
+            }
+        }
+        let center = Self::RADIUS * Self::ZOOM as i32;
+        canvas.set_draw_color(Color::RGB(255, 0, 0));
+        canvas.draw_rect(Rect::new(
+            left + center,
+            top + center,
+            Self::ZOOM,
+            Self::ZOOM,
+        ))?;
+        draw_text(
+            canvas,
+            &label,
+            left,
+            top + side as i32 + 2,
+            Self::SCALE,
+            Color::RGB(255, 255, 0),
+        )
+    }
+}
@@ -0,0 +1,61 @@
+// Writes mono 16 bit WAV files. The header's lengths are brought up to date
+// after every write, so the file plays even if the emulator never gets to
+// close it.
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+
+const HEADER_LENGTH: u32 = 44;
+
+pub struct WavWriter {
+    file: BufWriter<File>,
+    rate: u32,
+    samples: u32,
+}
+
+impl WavWriter {
+    pub fn create(path: &str, rate: u32) -> Result<Self, String> {
+        let file = File::create(path).map_err(|op| format!("{}: {}", path, op))?;
+        let mut writer = Self {
+            file: BufWriter::new(file),
+            rate,
+            samples: 0,
+        };
+        writer.write_header().map_err(|op| op.to_string())?;
+        Ok(writer)
+    }
+
+    // samples from -1 to 1
+    pub fn write(&mut self, samples: &[f32]) -> Result<(), String> {
+        self.file
+            .seek(SeekFrom::End(0))
+            .map_err(|op| op.to_string())?;
+        for sample in samples {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.file
+                .write_all(&value.to_le_bytes())
+                .map_err(|op| op.to_string())?;
+        }
+        self.samples += samples.len() as u32;
+        self.write_header().map_err(|op| op.to_string())
+    }
+
+    fn write_header(&mut self) -> std::io::Result<()> {
+        let data_length = self.samples * 2;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(b"RIFF")?;
+        self.file
+            .write_all(&(HEADER_LENGTH - 8 + data_length).to_le_bytes())?;
+        self.file.write_all(b"WAVEfmt ")?;
+        // PCM, one channel, the rate, bytes a second, bytes a sample and bits a sample
+        self.file.write_all(&16u32.to_le_bytes())?;
+        self.file.write_all(&1u16.to_le_bytes())?;
+        self.file.write_all(&1u16.to_le_bytes())?;
+        self.file.write_all(&self.rate.to_le_bytes())?;
+        self.file.write_all(&(self.rate * 2).to_le_bytes())?;
+        self.file.write_all(&2u16.to_le_bytes())?;
+        self.file.write_all(&16u16.to_le_bytes())?;
+        self.file.write_all(b"data")?;
+        self.file.write_all(&data_length.to_le_bytes())?;
+        self.file.flush()
+    }
+}
@@ -0,0 +1,2038 @@
+// The full window, what runs without --frontend: the display with the
+// debugger panes, pause menu, keypad and overlays around it, replays,
+// comparisons and servers, and the hotkeys for all of them. It runs its own
+// loop through Frontend::run, the plain frontends share chip8-frontend's.
+#[cfg(feature = "debug-server")]
+use crate::debug_server;
+#[cfg(feature = "http-api")]
+use crate::http_api;
+#[cfg(feature = "scripting")]
+use crate::script;
+#[cfg(feature = "stats-server")]
+use crate::stats_server;
+use crate::{
+    asm, audio, cheats, chip8, config, coverage, debugger, disasm, echo, hash, heatmap,
+    inputscript, keymap, log, menu, overlay, pausemenu, png, quirkdetect, render, replay,
+    savestate, sidecar, symbols, theme, timing, touch, trace, ui, vip, zip,
+};
+use chip8::State;
+use chip8_frontend::{Event, Frontend};
+use chip8_tools::{assemble_file, is_octo, read_rom};
+use sdl2::event::Event as SdlEvent;
+use sdl2::keyboard::{Keycode, Mod, Scancode};
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, TextureCreator};
+use sdl2::video::{Window, WindowContext};
+use sdl2::{AudioSubsystem, EventPump, EventSubsystem};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{IsTerminal, Write};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use timing::TimingModel;
+
+// how much faster the emulator runs while turbo is held
+const TURBO_FACTOR: u32 = 8;
+// multipliers of the speed the +/- hotkeys step through
+const SPEED_STEPS: [u32; 7] = [1, 2, 3, 4, 6, 8, 16];
+// what the slow motion hotkey cycles through, as divisors of the speed
+const SLOW_MOTION_STEPS: [u32; 3] = [1, 2, 4];
+// cycles per frame while the debugger runs to a target
+const DEBUGGER_RUN_CYCLES: u32 = 10_000;
+// the hotkeys macros are bound to, Shift with one records it
+const MACRO_KEYS: [(Keycode, &str); 3] = [
+    (Keycode::F9, "F9"),
+    (Keycode::F11, "F11"),
+    (Keycode::F12, "F12"),
+];
+
+// What the command line, the settings and a sidecar next to the ROM chose
+pub struct Options {
+    // a ROM, Octo source, archive or directory of ROMs, the demo if None
+    pub game: Option<String>,
+    // assembles the game and reloads it on save
+    pub dev: bool,
+    // the user's settings, which everything else overrides
+    pub config: config::Config,
+    // the preset keymap started from, for the pause menu
+    pub layout: String,
+    pub keymap: keymap::Keymap,
+    // the config's and --keymap's overrides, saved back to the config
+    pub user_overrides: Option<String>,
+    pub touch_layout: touch::TouchLayout,
+    pub palette: [[u8; 3]; 2],
+    // the buzzer stays silent
+    pub mute: bool,
+    // the sidecar's speed multiplier
+    pub game_speed: Option<u32>,
+    // settings given as flags, which win over the game's own
+    pub palette_chosen: bool,
+    pub quirks_chosen: bool,
+    // --quirk name=value, applied on top of whichever quirks end up chosen
+    pub quirk_overrides: Vec<String>,
+    pub detect_quirks: bool,
+    // lit pixels outlined in the off color so neighbors stay apart
+    pub pixel_outlines: bool,
+    // lines between the pixels for counting them, toggled with Ctrl+G
+    pub grid: bool,
+    pub magnifier: ui::Magnifier,
+    pub debug_overlay: overlay::DebugOverlay,
+    pub heatmap: heatmap::Heatmap,
+    pub vsync: bool,
+    // 0 runs as fast as possible
+    pub fps_limit: u32,
+    pub scale: u32,
+    pub fullscreen: bool,
+    // the terminal's bell for the buzzer when there's no audio
+    pub bell: bool,
+    // a divisor of the speed, one of SLOW_MOTION_STEPS
+    pub slow_motion: u32,
+    // slow motion slows the timers down too instead of keeping them at full speed
+    pub scale_timers: bool,
+    // a second machine with these quirks, shown on the right
+    pub compare: Option<chip8::Quirks>,
+    pub debug: bool,
+    pub echo: bool,
+    pub seed: Option<u64>,
+    pub autosave: bool,
+    pub symbols_path: Option<String>,
+    pub script_path: Option<String>,
+    pub debug_server_address: Option<String>,
+    pub http_address: Option<String>,
+    pub stats_address: Option<String>,
+    // the system's default unless one is named
+    pub audio_device: Option<String>,
+    pub audio_record_path: Option<String>,
+    pub input_script: Option<inputscript::InputScript>,
+    pub cheats: Option<cheats::Cheats>,
+    // every presented frame is written here as a numbered PNG
+    pub dump_directory: Option<String>,
+    pub record_path: Option<String>,
+    pub replay_path: Option<String>,
+    pub coverage_path: Option<String>,
+    pub tracer: Option<trace::JsonTracer>,
+}
+
+// A program to restart with, its symbols, the message to log and the game's
+// name if the game changed
+type NewProgram = (Vec<u8>, symbols::Symbols, String, Option<String>);
+
+pub struct FullWindow {
+    canvas: Canvas<Window>,
+    texture_creator: TextureCreator<WindowContext>,
+    event_pump: EventPump,
+    // the pause menu quits through the same Event::Quit as closing the window
+    events: EventSubsystem,
+    // a machine without sound still runs, unless a device was asked for
+    audio: Result<AudioSubsystem, String>,
+    buzzer: Option<audio::Buzzer>,
+    audio_recording: Option<audio::AudioRecording>,
+    bell: bool,
+
+    vsync: bool,
+    // in fullscreen the displays are centred with black bars around them
+    fullscreen: bool,
+    scale: u32,
+    window_width: u32,
+    display_width: u32,
+    display_height: u32,
+    left: i32,
+    top: i32,
+    keypad_area: Rect,
+
+    palette: [[u8; 3]; 2],
+    pixel_outlines: bool,
+    grid: bool,
+    magnifier: ui::Magnifier,
+    debug_overlay: overlay::DebugOverlay,
+    heatmap: heatmap::Heatmap,
+    keypad: ui::Keypad,
+    osd: ui::Osd,
+    // the registers, editable while paused
+    register_panel: ui::RegisterPanel,
+    register_panel_shown: bool,
+    disassembly: ui::DisassemblyPane,
+    pause_menu: pausemenu::PauseMenu,
+    title: String,
+    dump_directory: Option<String>,
+    frames: u64,
+    // set when something besides the CHIP-8 display needs repainting
+    redraw: bool,
+    // the display_generation of each machine on screen
+    shown_generations: (u64, Option<u64>),
+    // the key each finger on the screen is holding down
+    fingers: HashMap<i64, u8>,
+    touch_layout: touch::TouchLayout,
+    mute: bool,
+
+    config: config::Config,
+    layout: String,
+    keymap: keymap::Keymap,
+    user_overrides: Option<String>,
+    // what the settings started as, once the game's own are applied
+    starting_settings: ([[u8; 3]; 2], usize, bool, String),
+    // the hotkey, the frame it started on and the keys pressed since
+    macro_recording: Option<(&'static str, u64, Vec<inputscript::Input>)>,
+    macro_playing: Option<(&'static str, u64)>,
+
+    game: Option<String>,
+    dev: bool,
+    rom: Vec<u8>,
+    file_name: String,
+    game_name: String,
+    game_speed: Option<u32>,
+    symbols: symbols::Symbols,
+    symbols_path: Option<String>,
+    palette_chosen: bool,
+    quirks_chosen: bool,
+    quirk_overrides: Vec<String>,
+    detect_quirks: bool,
+    seed: Option<u64>,
+    autosave: bool,
+    script_path: Option<String>,
+    #[cfg(feature = "scripting")]
+    script: Option<script::Script>,
+    debug_server_address: Option<String>,
+    #[cfg(feature = "debug-server")]
+    debug_server: Option<debug_server::DebugServer>,
+    http_address: Option<String>,
+    #[cfg(feature = "http-api")]
+    http_api: Option<http_api::HttpApi>,
+    stats_address: Option<String>,
+    #[cfg(feature = "stats-server")]
+    stats_server: Option<stats_server::StatsServer>,
+    tracer: Option<trace::JsonTracer>,
+    coverage_path: Option<String>,
+    record_path: Option<String>,
+    replay_path: Option<String>,
+    player: Option<replay::Replay>,
+    recorder: Option<replay::Replay>,
+    cheats: Option<cheats::Cheats>,
+    input_script: Option<inputscript::InputScript>,
+    compare: Option<chip8::Quirks>,
+    // a second machine fed the same input, shown on the right for comparison
+    mirror: Option<State>,
+    diverged: bool,
+    debug: bool,
+    debugger: Option<debugger::Debugger>,
+    echo: Option<Rc<RefCell<echo::Echo>>>,
+    draw_counts: Rc<RefCell<DrawCounts>>,
+
+    timing: TimingModel,
+    timer_hz: Option<u32>,
+    // timers tick in real time unless replays, scaled timers or comparisons
+    // need them ticking with cycles
+    real_time_timers: bool,
+    scale_timers: bool,
+    vip_clock: vip::Clock,
+    // frames the machine has run, for spreading --cps over them and for
+    // --input-script
+    timing_frame: u64,
+    slow_motion: u32,
+    // cycles owed to the next frame in slow motion
+    slow_cycles: u32,
+    speed: usize,
+    turbo: bool,
+    paused: bool,
+    // the debugger's until and run go as fast as they can
+    running_to_target: bool,
+    started: Instant,
+    frame_time: Option<Duration>,
+    next_frame: Instant,
+    // the source being developed and when it was last changed
+    watched: Option<(String, Option<std::time::SystemTime>)>,
+    // a ROM dropped on the window, loaded at the start of the next frame
+    dropped_program: Option<NewProgram>,
+}
+
+impl FullWindow {
+    pub fn create(options: Options) -> Result<Box<dyn Frontend>, String> {
+        // touches are handled as touches, not as clicks on the keypad
+        sdl2::hint::set("SDL_TOUCH_MOUSE_EVENTS", "0");
+        // scaled up CHIP-8 pixels should stay sharp
+        sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", "nearest");
+        let sdl_context = sdl2::init()?;
+        let video_subsystem = sdl_context.video()?;
+        let audio = sdl_context.audio();
+        let buzzer = match audio
+            .as_ref()
+            .map_err(|op| op.clone())
+            .and_then(|audio| audio::Buzzer::open(audio, options.audio_device.as_deref()))
+        {
+            Ok(buzzer) => Some(buzzer),
+            Err(error) if options.audio_device.is_some() => return Err(error),
+            Err(error) => {
+                warn!("No sound: {}", error);
+                None
+            }
+        };
+        let audio_recording = options
+            .audio_record_path
+            .as_deref()
+            .map(audio::AudioRecording::create)
+            .transpose()?;
+        if let Some(directory) = &options.dump_directory {
+            fs::create_dir_all(directory).map_err(|op| format!("{}: {}", directory, op))?;
+        }
+
+        let screens = if options.compare.is_some() { 2 } else { 1 };
+        let mut scale = options.scale;
+        let mut fullscreen_size = None;
+        match video_subsystem.desktop_display_mode(0) {
+            Ok(desktop) => {
+                let fits = (desktop.w as u32 / (64 * screens))
+                    .min(desktop.h as u32 / 32)
+                    .max(1);
+                if options.fullscreen {
+                    // the biggest whole number scale, so every pixel is the same size
+                    scale = fits;
+                    fullscreen_size = Some((desktop.w as u32, desktop.h as u32));
+                } else if scale > fits {
+                    // a window bigger than the desktop can't be shown whole
+                    warn!("Scale {} doesn't fit on the desktop, using {}", scale, fits);
+                    scale = fits;
+                }
+            }
+            Err(error) if options.fullscreen => {
+                warn!(
+                    "Couldn't get the desktop size, starting windowed: {}",
+                    error
+                );
+            }
+            Err(_) => {}
+        }
+        let display_width = 64 * scale;
+        let display_height = 32 * scale;
+        let content_width = display_width * screens;
+        let (window_width, window_height) =
+            fullscreen_size.unwrap_or((content_width, display_height));
+        let left = ((window_width - content_width) / 2) as i32;
+        let top = ((window_height - display_height) / 2) as i32;
+
+        let mut window = video_subsystem.window("CHIP-8", window_width, window_height);
+        window.position_centered().allow_highdpi();
+        if fullscreen_size.is_some() {
+            window.fullscreen_desktop();
+        }
+        let window = window.build().map_err(|op| op.to_string())?;
+
+        let mut canvas = window.into_canvas();
+        if options.vsync {
+            canvas = canvas.present_vsync();
+        }
+        let mut canvas = canvas.build().map_err(|op| op.to_string())?;
+        let ratio = render::fit_output(&mut canvas)?;
+        info!("Display scale factor {}", ratio);
+
+        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        canvas.clear();
+        canvas.present();
+
+        let texture_creator = canvas.texture_creator();
+        let event_pump = sdl_context.event_pump()?;
+        let events = sdl_context.event()?;
+
+        // the window grows to fit the keypad, except in fullscreen where it
+        // covers the bottom of the screen
+        let keypad_area = if fullscreen_size.is_some() {
+            Rect::new(
+                left,
+                (window_height - ui::Keypad::HEIGHT) as i32,
+                content_width,
+                ui::Keypad::HEIGHT,
+            )
+        } else {
+            Rect::new(0, display_height as i32, window_width, ui::Keypad::HEIGHT)
+        };
+        let speed = options
+            .game_speed
+            .or(options.config.speed)
+            .and_then(|speed| SPEED_STEPS.iter().position(|step| *step == speed))
+            .unwrap_or(0);
+        let frame_time = if options.fps_limit > 0 {
+            Some(Duration::from_secs_f64(1.0 / options.fps_limit as f64))
+        } else {
+            None
+        };
+
+        Ok(Box::new(Self {
+            canvas,
+            texture_creator,
+            event_pump,
+            events,
+            audio,
+            buzzer,
+            audio_recording,
+            bell: options.bell,
+            vsync: options.vsync,
+            fullscreen: fullscreen_size.is_some(),
+            scale,
+            window_width,
+            display_width,
+            display_height,
+            left,
+            top,
+            keypad_area,
+            palette: options.palette,
+            pixel_outlines: options.pixel_outlines,
+            grid: options.grid,
+            magnifier: options.magnifier,
+            debug_overlay: options.debug_overlay,
+            heatmap: options.heatmap,
+            keypad: ui::Keypad::new(),
+            osd: ui::Osd::new(),
+            register_panel: ui::RegisterPanel::new(),
+            register_panel_shown: false,
+            disassembly: ui::DisassemblyPane::new(),
+            pause_menu: pausemenu::PauseMenu::new(""),
+            title: String::new(),
+            dump_directory: options.dump_directory,
+            frames: 0,
+            redraw: false,
+            shown_generations: (0, None),
+            fingers: HashMap::new(),
+            touch_layout: options.touch_layout,
+            mute: options.mute,
+            starting_settings: (options.palette, speed, options.mute, String::new()),
+            config: options.config,
+            layout: options.layout,
+            keymap: options.keymap,
+            user_overrides: options.user_overrides,
+            macro_recording: None,
+            macro_playing: None,
+            game: options.game,
+            dev: options.dev,
+            rom: Vec::new(),
+            file_name: String::new(),
+            game_name: String::new(),
+            game_speed: options.game_speed,
+            symbols: symbols::Symbols::default(),
+            symbols_path: options.symbols_path,
+            palette_chosen: options.palette_chosen,
+            quirks_chosen: options.quirks_chosen,
+            quirk_overrides: options.quirk_overrides,
+            detect_quirks: options.detect_quirks,
+            seed: options.seed,
+            autosave: options.autosave,
+            script_path: options.script_path,
+            #[cfg(feature = "scripting")]
+            script: None,
+            debug_server_address: options.debug_server_address,
+            #[cfg(feature = "debug-server")]
+            debug_server: None,
+            http_address: options.http_address,
+            #[cfg(feature = "http-api")]
+            http_api: None,
+            stats_address: options.stats_address,
+            #[cfg(feature = "stats-server")]
+            stats_server: None,
+            tracer: options.tracer,
+            coverage_path: options.coverage_path,
+            record_path: options.record_path,
+            replay_path: options.replay_path,
+            player: None,
+            recorder: None,
+            cheats: options.cheats,
+            input_script: options.input_script,
+            compare: options.compare,
+            mirror: None,
+            diverged: false,
+            debug: options.debug,
+            debugger: None,
+            echo: options
+                .echo
+                .then(|| Rc::new(RefCell::new(echo::Echo::default()))),
+            draw_counts: Rc::new(RefCell::new(DrawCounts::default())),
+            timing: TimingModel::default(),
+            timer_hz: None,
+            real_time_timers: true,
+            scale_timers: options.scale_timers,
+            vip_clock: vip::Clock::default(),
+            timing_frame: 0,
+            slow_motion: options.slow_motion,
+            slow_cycles: 0,
+            speed,
+            turbo: false,
+            paused: false,
+            running_to_target: false,
+            started: Instant::now(),
+            frame_time,
+            next_frame: Instant::now(),
+            watched: None,
+            dropped_program: None,
+        }))
+    }
+
+    fn display_area(&self) -> Rect {
+        Rect::new(self.left, self.top, self.display_width, self.display_height)
+    }
+
+    // The game's file name and contents, picked from a menu for a directory
+    // or an archive with several ROMs. None if the menu was closed first.
+    fn open_game(&mut self) -> Result<Option<(String, Vec<u8>)>, String> {
+        let menu_area = Rect::new(
+            self.left,
+            self.top,
+            self.window_width - 2 * self.left as u32,
+            self.display_height,
+        );
+        let entry = match self.game.take() {
+            Some(path) if std::path::Path::new(&path).is_dir() => {
+                let menu = menu::Menu::scan(&path)?;
+                menu.choose(
+                    &mut self.canvas,
+                    &mut self.event_pump,
+                    &self.keymap,
+                    menu_area,
+                )?
+            }
+            Some(path) if path.to_ascii_lowercase().ends_with(".zip") => {
+                let mut roms = zip::roms(&read_rom(&path)?)?;
+                match roms.len() {
+                    0 => return Err(format!("No .ch8 files in {}", path)),
+                    1 => Some(roms.remove(0)),
+                    _ => menu::Menu::new(roms).choose(
+                        &mut self.canvas,
+                        &mut self.event_pump,
+                        &self.keymap,
+                        menu_area,
+                    )?,
+                }
+            }
+            // Octo sources run directly, without a separate assemble step
+            Some(path) if self.dev || is_octo(&path) => {
+                let (rom, symbols) = assemble_file(&path)?;
+                self.symbols = symbols;
+                Some((path, rom))
+            }
+            Some(path) => {
+                let rom = read_rom(&path)?;
+                if path == "-" {
+                    Some(("stdin".to_string(), rom))
+                } else {
+                    Some((path, rom))
+                }
+            }
+            None => {
+                info!("No ROM given, running the demo");
+                let (rom, symbols) = asm::assemble(crate::DEMO)?;
+                self.symbols = symbols;
+                Some(("demo".to_string(), rom))
+            }
+        };
+        Ok(entry)
+    }
+
+    // Loads the game into chip_8 and sets up everything watching it. false
+    // if no game was picked.
+    fn start(
+        &mut self,
+        chip_8: &mut State,
+        timing: TimingModel,
+        timer_hz: Option<u32>,
+    ) -> Result<bool, String> {
+        if self.bell && self.buzzer.is_none() {
+            chip_8.set_sound_sink(audio::terminal_bell);
+        }
+        // labels come with sources that are assembled here
+        let Some((file_name, rom)) = self.open_game()? else {
+            return Ok(false);
+        };
+        self.symbols = match &self.symbols_path {
+            Some(path) => symbols::Symbols::read(std::path::Path::new(path))?,
+            None if !self.symbols.is_empty() => std::mem::take(&mut self.symbols),
+            None => symbols::Symbols::find(&file_name)?.unwrap_or_default(),
+        };
+        if let Some(tracer) = &mut self.tracer {
+            tracer.set_symbols(self.symbols.clone());
+        }
+        self.debug_overlay.set_symbols(self.symbols.clone());
+        // read once the labels it can refer to are known
+        #[cfg(feature = "scripting")]
+        if let Some(path) = &self.script_path {
+            self.script = Some(script::Script::read(path, &self.symbols)?);
+        }
+        #[cfg(not(feature = "scripting"))]
+        if self.script_path.is_some() {
+            return Err("--script needs the scripting feature".to_string());
+        }
+        #[cfg(feature = "debug-server")]
+        if let Some(address) = &self.debug_server_address {
+            self.debug_server = Some(debug_server::DebugServer::bind(
+                address,
+                self.symbols.clone(),
+            )?);
+        }
+        #[cfg(not(feature = "debug-server"))]
+        if self.debug_server_address.is_some() {
+            return Err("--debug-server needs the debug-server feature".to_string());
+        }
+        #[cfg(feature = "http-api")]
+        if let Some(address) = &self.http_address {
+            self.http_api = Some(http_api::HttpApi::bind(address)?);
+        }
+        #[cfg(not(feature = "http-api"))]
+        if self.http_address.is_some() {
+            return Err("--http needs the http-api feature".to_string());
+        }
+        #[cfg(feature = "stats-server")]
+        {
+            self.stats_server = crate::bind_stats_server(self.stats_address.as_deref())?;
+        }
+        #[cfg(not(feature = "stats-server"))]
+        crate::bind_stats_server(self.stats_address.as_deref())?;
+        // enough to tell exactly which ROM a bug report is about
+        info!(
+            "Loaded {} bytes from {}, SHA-1 {}, CRC32 {:08x}",
+            rom.len(),
+            file_name,
+            hash::to_hex(&hash::sha1(&rom)),
+            hash::crc32(&rom)
+        );
+        self.game_name = rom_name(&file_name);
+        // saved from the pause menu, under the flags and a sidecar next to the ROM
+        if let Some(settings) = sidecar::Settings::saved(&rom)? {
+            info!("Using the settings saved for this game");
+            if let (Some(quirks), false) = (settings.quirks, self.quirks_chosen) {
+                chip_8.set_quirks(quirks);
+                self.quirks_chosen = true;
+            }
+            if let (Some(colors), false) = (settings.palette, self.palette_chosen) {
+                self.palette = colors;
+            }
+            if let Some(step) = self
+                .game_speed
+                .or(settings.speed)
+                .and_then(|saved| SPEED_STEPS.iter().position(|step| *step == saved))
+            {
+                self.speed = step;
+            }
+        }
+        if !self.quirks_chosen {
+            let detection = quirkdetect::detect(&rom, asm::ORIGIN);
+            if let Some(quirks) = detection.quirks {
+                let name = detection.preset.unwrap_or("custom");
+                for reason in &detection.reasons {
+                    debug!("{}", reason);
+                }
+                if self.detect_quirks {
+                    info!(
+                        "Using the {} quirks the ROM looks written for: {:?}",
+                        name, quirks
+                    );
+                    chip_8.set_quirks(quirks);
+                } else {
+                    info!(
+                        "The ROM looks written for the {} quirks, --detect-quirks applies them",
+                        name
+                    );
+                }
+            }
+        }
+        crate::override_quirks(chip_8, &self.quirk_overrides)?;
+        chip_8.load_buffer(&rom);
+        if let Some(seed) = self.seed {
+            chip_8.set_seed(seed);
+        }
+        if let Some(path) = &self.replay_path {
+            let replay = replay::Replay::from_bytes(&fs::read(path).map_err(|op| op.to_string())?)?;
+            replay.configure(&rom, chip_8)?;
+            self.player = Some(replay);
+        }
+        self.recorder = self
+            .record_path
+            .as_ref()
+            .map(|_| replay::Replay::new(&rom, chip_8));
+        if self.autosave {
+            let path = savestate::autosave_path(&rom)?;
+            if path.exists() && confirm("Resume where you left off?") {
+                let timestamp = savestate::read_state(&path, chip_8)?;
+                info!(
+                    "Resumed from autosave ({})",
+                    savestate::format_timestamp(timestamp)
+                );
+            }
+        }
+        if let Some(cheats) = &self.cheats {
+            // a replay can't be reproduced with memory changing under it
+            if self.replay_path.is_some() || self.record_path.is_some() {
+                return Err("Cheats can't be used with replays".to_string());
+            }
+            // after any autosave is resumed, which would undo them
+            cheats.apply_once(chip_8);
+        }
+        if let Some(quirks) = self.compare {
+            let mut mirror = State::new();
+            mirror.initialize();
+            mirror.set_stack_limit(chip_8.stack_limit());
+            mirror.set_memory_size(chip_8.memory_size())?;
+            mirror.set_address_wrapping(chip_8.address_wrapping());
+            mirror.set_font(*chip_8.font());
+            #[cfg(feature = "cdp1802")]
+            mirror.set_machine_code(chip_8.machine_code());
+            mirror.set_quirks(quirks);
+            mirror.load_state(&chip_8.save_state())?;
+            mirror.set_seed(chip_8.seed());
+            self.mirror = Some(mirror);
+        }
+        // replays count time in cycles, which VIP timing doesn't tick the timers by
+        if timing == TimingModel::VipAccurate && (self.player.is_some() || self.recorder.is_some())
+        {
+            return Err("Replays can't be used with --timing vip".to_string());
+        }
+        self.real_time_timers = !self.scale_timers
+            && self.player.is_none()
+            && self.recorder.is_none()
+            && self.mirror.is_none();
+        if timer_hz.is_some() && !self.real_time_timers {
+            return Err(
+                "--timer-hz can't be used with replays, --scale-timers or --compare".to_string(),
+            );
+        }
+        timing.configure(
+            chip_8,
+            self.real_time_timers.then(|| timer_hz.unwrap_or(60)),
+        );
+        if let Some(mirror) = &mut self.mirror {
+            timing.configure(mirror, None);
+        }
+        self.timing = timing;
+        self.timer_hz = timer_hz;
+
+        {
+            let draw_counts = self.draw_counts.clone();
+            chip_8.subscribe(move |event| {
+                let mut counts = draw_counts.borrow_mut();
+                match event {
+                    chip8::Event::SpriteDrawn { collision, .. } => {
+                        counts.sprites += 1;
+                        counts.collisions += *collision as u64;
+                    }
+                    chip8::Event::ScreenCleared => counts.clears += 1,
+                    _ => {}
+                }
+            });
+        }
+        if let Some(echo) = &self.echo {
+            let echo = echo.clone();
+            chip_8.subscribe(move |event| {
+                if let chip8::Event::InstructionExecuted { pc, opcode } = event {
+                    echo.borrow_mut().push(*pc, *opcode);
+                }
+            });
+        }
+        if log::enabled(log::Level::Trace) {
+            chip_8.subscribe(|event| match event {
+                chip8::Event::InstructionExecuted { pc, opcode } => {
+                    trace!(
+                        "{:#05X}: {:04X}  {}",
+                        pc,
+                        opcode,
+                        disasm::disassemble(*opcode)
+                    )
+                }
+                event => trace!("{:?}", event),
+            });
+        }
+        // started after the autosave prompt, which reads stdin too
+        if self.debug {
+            self.debugger = Some(debugger::Debugger::new(&rom, self.symbols.clone()));
+        }
+        if self.dev {
+            let modified = modified_time(&file_name);
+            self.watched = Some((file_name.clone(), modified));
+        }
+        // what the settings started as, only changes made while running are saved
+        self.starting_settings = (self.palette, self.speed, self.mute, self.layout.clone());
+        self.pause_menu = pausemenu::PauseMenu::new(&file_name);
+        self.rom = rom;
+        self.file_name = file_name;
+        self.started = Instant::now();
+        self.next_frame = Instant::now();
+        Ok(true)
+    }
+
+    // One frame: running the machine, drawing and handling input. false once
+    // the window is closed.
+    fn frame(&mut self, chip_8: &mut State) -> Result<bool, String> {
+        self.load_new_program(chip_8)?;
+        let (steps, command) = self.poll_debuggers(chip_8);
+
+        #[cfg(feature = "scripting")]
+        if let (Some(script), false) = (&self.script, self.paused || self.pause_menu.is_open()) {
+            script.frame(chip_8)?;
+        }
+
+        let clocked = self.run_cycles(chip_8, steps)?;
+
+        if let (Some(debugger), 1..) = (&self.debugger, steps) {
+            debugger.show_location(chip_8);
+        }
+        // the panes follow steps and console commands while paused
+        if self.paused && (steps > 0 || command.is_some()) {
+            self.disassembly.follow();
+            self.redraw = true;
+        }
+        #[cfg(feature = "debug-server")]
+        if let Some(debug_server) = &mut self.debug_server {
+            if steps > 0 {
+                debug_server.stopped(chip_8, "step");
+            }
+            debug_server.send_frame(&chip_8.get_graphics_buffer());
+        }
+
+        if let Some(echo) = &self.echo {
+            echo.borrow_mut().flush()?;
+        }
+        self.redraw |= self.debug_overlay.sample(chip_8);
+        // the counts change with every instruction
+        self.redraw |= self.heatmap.enabled && !self.paused && !self.pause_menu.is_open();
+
+        if let Some(mirror) = &self.mirror {
+            let different = chip_8.get_graphics_buffer() != mirror.get_graphics_buffer();
+            if different && !self.diverged {
+                info!("Screens diverged at cycle {}", chip_8.stats().cycles);
+                self.osd.show("Screens diverged");
+            }
+            self.diverged = different;
+        }
+
+        self.update_title(chip_8)?;
+        let sounding = chip_8.sound_timer() > 0 && !self.paused && !self.pause_menu.is_open();
+        if let Some(buzzer) = &self.buzzer {
+            buzzer.set_on(sounding && !self.mute);
+        }
+        if let Some(recording) = &mut self.audio_recording {
+            recording.record(sounding)?;
+        }
+
+        self.redraw |= self.osd.needs_redraw();
+        self.redraw |= self.paused != self.register_panel_shown;
+        let generations = (
+            chip_8.display_generation(),
+            self.mirror
+                .as_ref()
+                .map(|mirror| mirror.display_generation()),
+        );
+        // with vsync presenting is what paces the frames, so every one is drawn
+        if generations != self.shown_generations || self.redraw || self.vsync {
+            // VIP timing shows what the beam scanned, sprites drawn as it passed included
+            let screen = if clocked {
+                self.vip_clock.screen().to_vec()
+            } else {
+                chip_8.get_graphics_buffer()
+            };
+            self.draw_window(chip_8, &screen)?;
+            self.shown_generations = generations;
+            self.redraw = false;
+        }
+
+        let events: Vec<SdlEvent> = self.event_pump.poll_iter().collect();
+        for event in events {
+            if !self.handle_event(chip_8, event)? {
+                return Ok(false);
+            }
+        }
+
+        // software limiter, a frame that ran long isn't made up for
+        if let Some(frame_time) = self.frame_time {
+            self.next_frame += frame_time;
+            let now = Instant::now();
+            if self.next_frame > now {
+                std::thread::sleep(self.next_frame - now);
+            } else {
+                self.next_frame = now;
+            }
+        }
+        Ok(true)
+    }
+
+    // A source saved while developing it, a program sent over HTTP or one
+    // dropped on the window or picked from the pause menu
+    fn load_new_program(&mut self, chip_8: &mut State) -> Result<(), String> {
+        let mut new_program = self.dropped_program.take();
+        if let Some((source, last_modified)) = &mut self.watched {
+            let modified = modified_time(source);
+            if modified != *last_modified {
+                *last_modified = modified;
+                // settings live outside the machine, so only the program restarts
+                match assemble_file(source) {
+                    Ok((program, new_symbols)) => {
+                        let message = format!("Reloaded {}", source);
+                        new_program = Some((program, new_symbols, message, None))
+                    }
+                    Err(error) => {
+                        error!("{}", error);
+                        self.osd.show("Assembly failed");
+                    }
+                }
+            }
+        }
+        #[cfg(feature = "http-api")]
+        if let Some(http_api) = &mut self.http_api {
+            if let Some(program) = http_api.poll(chip_8) {
+                let message = format!("Loaded {} bytes over HTTP", program.len());
+                new_program = Some((program, symbols::Symbols::default(), message, None));
+            }
+        }
+        #[cfg(feature = "stats-server")]
+        if let Some(stats_server) = &mut self.stats_server {
+            stats_server.poll(chip_8, self.frames);
+        }
+        // a program that doesn't fit leaves the old one running
+        let Some((program, new_symbols, message, name)) = new_program else {
+            return Ok(());
+        };
+        if let Err(error) = chip_8.load_rom(&program) {
+            error!("{}", error);
+            self.osd.show("Program too big");
+            return Ok(());
+        }
+        self.rom = program;
+        if let Some(name) = name {
+            self.game_name = name;
+            // a different game, which may have had settings saved for it
+            match sidecar::Settings::saved(&self.rom) {
+                Ok(Some(settings)) => {
+                    if let (Some(quirks), false) = (settings.quirks, self.quirks_chosen) {
+                        chip_8.set_quirks(quirks);
+                    }
+                    if let (Some(colors), false) = (settings.palette, self.palette_chosen) {
+                        self.palette = colors;
+                    }
+                    if let Some(step) = settings
+                        .speed
+                        .and_then(|saved| SPEED_STEPS.iter().position(|step| *step == saved))
+                    {
+                        self.speed = step;
+                    }
+                }
+                Ok(None) => {}
+                Err(error) => error!("{}", error),
+            }
+        }
+        self.symbols = new_symbols;
+        if let Some(tracer) = &mut self.tracer {
+            tracer.set_symbols(self.symbols.clone());
+        }
+        self.debug_overlay.set_symbols(self.symbols.clone());
+        if let Some(debugger) = &mut self.debugger {
+            debugger.set_program(&self.rom, self.symbols.clone());
+        }
+        #[cfg(feature = "debug-server")]
+        if let Some(debug_server) = &mut self.debug_server {
+            debug_server.set_symbols(self.symbols.clone());
+        }
+        if let Some(mirror) = &mut self.mirror {
+            mirror.load_rom(&self.rom)?;
+        }
+        if let Some(cheats) = &self.cheats {
+            cheats.apply_once(chip_8);
+            if let Some(mirror) = &mut self.mirror {
+                cheats.apply_once(mirror);
+            }
+        }
+        if let Some(recorder) = &mut self.recorder {
+            warn!("The program changed, restarting the replay recording");
+            *recorder = replay::Replay::new(&self.rom, chip_8);
+        }
+        info!("{}", message);
+        self.osd.show("Reloaded");
+        self.redraw = true;
+        Ok(())
+    }
+
+    // Commands from the console debugger or the debug server, and how many
+    // instructions to step even while paused
+    fn poll_debuggers(&mut self, chip_8: &mut State) -> (u32, Option<debugger::Command>) {
+        let mut steps = 0;
+        let mut command = None;
+        if let Some(debugger) = &mut self.debugger {
+            command = debugger.poll(chip_8);
+        }
+        #[cfg(feature = "debug-server")]
+        if let (Some(debug_server), None) = (&mut self.debug_server, command) {
+            command = debug_server.poll(chip_8);
+        }
+        // both debuggers skip their breakpoint at the pc when either resumes
+        if command.is_some_and(|command| command != debugger::Command::Pause) {
+            self.resume_debuggers();
+        }
+        match command {
+            Some(debugger::Command::Continue) => {
+                self.paused = false;
+                self.running_to_target = false;
+                self.osd.show("Resumed");
+            }
+            Some(debugger::Command::Run) => {
+                self.paused = false;
+                self.running_to_target = true;
+                self.osd.show("Running");
+            }
+            Some(debugger::Command::Pause) => {
+                self.paused = true;
+                self.running_to_target = false;
+                if let Some(debugger) = &self.debugger {
+                    debugger.show_location(chip_8);
+                }
+                #[cfg(feature = "debug-server")]
+                if let Some(debug_server) = &mut self.debug_server {
+                    debug_server.stopped(chip_8, "pause");
+                }
+                self.osd.show("Paused");
+            }
+            Some(debugger::Command::Step) => {
+                self.paused = true;
+                self.running_to_target = false;
+                steps = 1;
+            }
+            None => {}
+        }
+        (steps, command)
+    }
+
+    fn resume_debuggers(&mut self) {
+        if let Some(debugger) = &mut self.debugger {
+            debugger.resume();
+        }
+        #[cfg(feature = "debug-server")]
+        if let Some(debug_server) = &mut self.debug_server {
+            debug_server.resume();
+        }
+    }
+
+    // Runs the frame's cycles, and steps more on top. true if VIP timing ran
+    // them until the frame's time was up.
+    fn run_cycles(&mut self, chip_8: &mut State, steps: u32) -> Result<bool, String> {
+        let cycles = if self.paused || self.pause_menu.is_open() {
+            Some(0)
+        } else if self.running_to_target {
+            Some(DEBUGGER_RUN_CYCLES)
+        } else {
+            let factor = if self.turbo { TURBO_FACTOR } else { 1 };
+            self.timing_frame += 1;
+            if let Some(cheats) = &self.cheats {
+                cheats.apply_frame(chip_8);
+                if let Some(mirror) = &mut self.mirror {
+                    cheats.apply_frame(mirror);
+                }
+            }
+            // a replay's own inputs win
+            if let (Some(script), None) = (&self.input_script, &self.player) {
+                for input in script.inputs_at(self.timing_frame) {
+                    press_key(chip_8, &mut self.recorder, input.key, input.pressed);
+                }
+            }
+            if let Some((hotkey, start)) = self.macro_playing {
+                let frame = self.timing_frame - start;
+                match self.config.macro_for(hotkey) {
+                    Some(script) if frame <= script.length() && self.player.is_none() => {
+                        for input in script.inputs_at(frame) {
+                            press_key(chip_8, &mut self.recorder, input.key, input.pressed);
+                        }
+                    }
+                    _ => self.macro_playing = None,
+                }
+            }
+            self.timing
+                .cycles_for_frame(self.timing_frame, SPEED_STEPS[self.speed] * factor)
+        };
+        // VIP timing runs until the frame's time is up instead of a count
+        let clocked = cycles.is_none();
+        if clocked {
+            self.vip_clock.start_frame();
+        }
+        let full_speed = cycles.unwrap_or(0);
+        self.slow_cycles += full_speed;
+        let budget = if clocked {
+            u32::MAX
+        } else {
+            self.slow_cycles / self.slow_motion + steps
+        };
+        self.slow_cycles %= self.slow_motion;
+        for _ in 0..budget {
+            if let Some(replay) = &self.player {
+                let cycle = chip_8.stats().cycles;
+                if cycle == replay.cycles {
+                    if replay.verify(chip_8) {
+                        info!("Replay finished and verified after {} cycles", cycle);
+                        self.osd.show("Replay verified");
+                    } else {
+                        error!("Replay desynced, the final state doesn't match the recording");
+                        self.osd.show("Replay desynced");
+                    }
+                    self.player = None;
+                } else {
+                    for input in replay.inputs_at(cycle) {
+                        chip_8.set_key(input.key as usize, input.pressed as u8);
+                    }
+                }
+            }
+            // a faulted machine stays on screen, but stops executing
+            if chip_8.error().is_some() {
+                break;
+            }
+            if clocked && !self.vip_clock.fits(chip_8) {
+                break;
+            }
+            if self.should_break(chip_8) {
+                self.paused = true;
+                self.running_to_target = false;
+                self.osd.show("Breakpoint");
+                break;
+            }
+            #[cfg(feature = "scripting")]
+            if let Some(script) = &self.script {
+                script.before_instruction(chip_8)?;
+            }
+            if let Some(tracer) = &mut self.tracer {
+                tracer.before(chip_8);
+            }
+            let result = chip_8.emulate_cycle();
+            if clocked {
+                self.vip_clock.executed(chip_8);
+            }
+            if let Some(debugger) = &mut self.debugger {
+                debugger.record();
+            }
+            match result {
+                Ok(()) => {
+                    if let Some(tracer) = &mut self.tracer {
+                        tracer.after(chip_8)?;
+                    }
+                }
+                Err(error) => {
+                    error!("Emulation halted: {}", error);
+                    for frame in debugger::backtrace(chip_8, &self.symbols) {
+                        error!("  {}", frame);
+                    }
+                    self.osd.show("Halted");
+                }
+            }
+            if let Some(mirror) = &mut self.mirror {
+                for key in 0..16 {
+                    mirror.set_key(key, chip_8.is_key_pressed(key) as u8);
+                }
+                if mirror.error().is_none() {
+                    if let Err(error) = mirror.emulate_cycle() {
+                        error!("Comparison instance halted: {}", error);
+                    }
+                }
+            }
+        }
+
+        // timers ticking once per cycle for a comparison are kept at full speed
+        // by making up the ticks of the cycles slow motion skipped. Replays
+        // depend on timers only ticking with cycles, so they're always scaled
+        // while one is in use.
+        if clocked {
+            self.redraw |= self.vip_clock.end_frame(chip_8);
+            chip_8.tick_timers();
+            if let Some(mirror) = &mut self.mirror {
+                mirror.tick_timers();
+            }
+        } else if !self.real_time_timers
+            && !self.scale_timers
+            && self.player.is_none()
+            && self.recorder.is_none()
+        {
+            for _ in budget..full_speed {
+                chip_8.tick_timers();
+                if let Some(mirror) = &mut self.mirror {
+                    mirror.tick_timers();
+                }
+            }
+        }
+        Ok(clocked)
+    }
+
+    // true if either debugger has a breakpoint at the next instruction
+    fn should_break(&mut self, chip_8: &State) -> bool {
+        let stop = self
+            .debugger
+            .as_mut()
+            .is_some_and(|debugger| debugger.should_break(chip_8));
+        #[cfg(feature = "debug-server")]
+        if let Some(debug_server) = &mut self.debug_server {
+            let stop = debug_server.should_break(chip_8) || stop;
+            if stop {
+                debug_server.stopped(chip_8, "breakpoint");
+            }
+            return stop;
+        }
+        stop
+    }
+
+    fn update_title(&mut self, chip_8: &State) -> Result<(), String> {
+        let status = if chip_8.error().is_some() {
+            " [HALTED]"
+        } else if self.paused || self.pause_menu.is_open() {
+            " [PAUSED]"
+        } else if self.turbo {
+            " [TURBO]"
+        } else if self.slow_motion == 2 {
+            " [SLOW 0.5x]"
+        } else if self.slow_motion == 4 {
+            " [SLOW 0.25x]"
+        } else {
+            ""
+        };
+        let title = format!(
+            "CHIP-8 \u{2014} {} ({}){}",
+            self.game_name,
+            self.timing.describe(SPEED_STEPS[self.speed]),
+            status
+        );
+        if title != self.title {
+            self.canvas
+                .window_mut()
+                .set_title(&title)
+                .map_err(|op| op.to_string())?;
+            self.title = title;
+        }
+        Ok(())
+    }
+
+    // The displays and everything drawn over and around them
+    fn draw_window(&mut self, chip_8: &State, screen: &[u8]) -> Result<(), String> {
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        self.canvas.clear();
+        let area = self.display_area();
+        match &self.mirror {
+            Some(mirror) => {
+                let other = mirror.get_graphics_buffer();
+                let second = Rect::new(
+                    self.left + self.display_width as i32,
+                    self.top,
+                    self.display_width,
+                    self.display_height,
+                );
+                for (area, screen, other) in [(area, screen, &other[..]), (second, &other, screen)]
+                {
+                    render::draw_display(
+                        &mut self.canvas,
+                        &self.texture_creator,
+                        screen,
+                        Some(other),
+                        &self.palette,
+                        self.pixel_outlines,
+                        area,
+                    )?;
+                    if self.grid {
+                        render::draw_grid(&mut self.canvas, area, &self.palette)?;
+                    }
+                }
+                self.magnifier
+                    .draw(&mut self.canvas, area, screen, &self.palette)?;
+                self.magnifier
+                    .draw(&mut self.canvas, second, &other, &self.palette)?;
+            }
+            None => {
+                render::draw_display(
+                    &mut self.canvas,
+                    &self.texture_creator,
+                    screen,
+                    None,
+                    &self.palette,
+                    self.pixel_outlines,
+                    area,
+                )?;
+                if self.grid {
+                    render::draw_grid(&mut self.canvas, area, &self.palette)?;
+                }
+                self.magnifier
+                    .draw(&mut self.canvas, area, screen, &self.palette)?;
+            }
+        }
+        // under the disassembly, which takes the same side
+        self.heatmap.draw(&mut self.canvas, area, chip_8)?;
+        if self.paused {
+            let debugger = &self.debugger;
+            self.disassembly
+                .draw(&mut self.canvas, area, chip_8, |address| {
+                    debugger
+                        .as_ref()
+                        .is_some_and(|debugger| debugger.has_breakpoint(address))
+                })?;
+            self.register_panel.draw(&mut self.canvas, area, chip_8)?;
+        } else {
+            self.disassembly.follow();
+        }
+        self.debug_overlay.draw(&mut self.canvas, area)?;
+        self.register_panel_shown = self.paused;
+        let values = pausemenu::Values {
+            palette: theme::THEMES
+                .iter()
+                .find(|(_, colors)| *colors == self.palette)
+                .map_or("custom", |(name, _)| name)
+                .to_string(),
+            speed: format!("{}x", SPEED_STEPS[self.speed]),
+            sound: if self.mute { "off" } else { "on" }.to_string(),
+            quirks: chip8::Quirks::PRESETS
+                .into_iter()
+                .find(|name| chip8::Quirks::preset(name) == Ok(chip_8.quirks()))
+                .unwrap_or("custom")
+                .to_string(),
+            keymap: self.layout.clone(),
+        };
+        self.pause_menu.draw(&mut self.canvas, area, &values)?;
+        self.keypad
+            .draw(&mut self.canvas, self.keypad_area, chip_8)?;
+        self.osd.draw(&mut self.canvas)?;
+        self.canvas.present();
+        if let Some(directory) = &self.dump_directory {
+            let path = format!("{}/{:06}.png", directory, self.frames);
+            let image = png::encode_colored(screen, 64, self.scale as usize, &self.palette);
+            if let Err(error) = fs::write(&path, image) {
+                error!("Couldn't dump frame {}: {}", path, error);
+                self.dump_directory = None;
+            }
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    // false when the window is closing
+    fn handle_event(&mut self, chip_8: &mut State, event: SdlEvent) -> Result<bool, String> {
+        match event {
+            SdlEvent::Quit { .. } => return Ok(false),
+            SdlEvent::KeyDown {
+                keycode,
+                scancode,
+                keymod,
+                repeat,
+                ..
+            } => self.key_down(chip_8, keycode, scancode, keymod, repeat)?,
+            SdlEvent::KeyUp {
+                keycode, scancode, ..
+            } => {
+                if keycode == Some(Keycode::Tab) {
+                    self.turbo = false;
+                    self.osd.show(format!("Speed {}x", SPEED_STEPS[self.speed]));
+                }
+                if let Some(key) = self.keymap.lookup(keycode, scancode) {
+                    if self.player.is_none() {
+                        press_key(chip_8, &mut self.recorder, key, false);
+                        self.redraw |= self.keypad.visible;
+                        record_macro_key(&mut self.macro_recording, self.timing_frame, key, false);
+                    }
+                }
+            }
+            SdlEvent::FingerDown {
+                finger_id, x, y, ..
+            }
+            | SdlEvent::FingerMotion {
+                finger_id, x, y, ..
+            } => {
+                let (width, height) = self.canvas.window().size();
+                let (px, py) = ((x * width as f32) as i32, (y * height as f32) as i32);
+                let key = self.keypad.key_at(self.keypad_area, px, py).or_else(|| {
+                    self.touch_layout.key_at(
+                        (px - self.left) as f32 / self.display_width as f32,
+                        (py - self.top) as f32 / self.display_height as f32,
+                    )
+                });
+                if self.player.is_none() && self.fingers.get(&finger_id).copied() != key {
+                    if let Some(old) = self.fingers.remove(&finger_id) {
+                        press_key(chip_8, &mut self.recorder, old, false);
+                    }
+                    if let Some(key) = key {
+                        press_key(chip_8, &mut self.recorder, key, true);
+                        self.fingers.insert(finger_id, key);
+                    }
+                    self.redraw |= self.keypad.visible;
+                }
+            }
+            SdlEvent::FingerUp { finger_id, .. } => {
+                if let Some(key) = self.fingers.remove(&finger_id) {
+                    press_key(chip_8, &mut self.recorder, key, false);
+                    self.redraw |= self.keypad.visible;
+                }
+            }
+            SdlEvent::MouseButtonDown {
+                mouse_btn: MouseButton::Left,
+                x,
+                y,
+                ..
+            } => {
+                let area = self.display_area();
+                if self.paused && self.register_panel.click(area, x, y) {
+                    self.redraw = true;
+                } else if let Some(address) = self
+                    .paused
+                    .then(|| self.disassembly.click(area, x, y, chip_8))
+                    .flatten()
+                {
+                    self.osd
+                        .show(toggle_breakpoint(&mut self.debugger, address));
+                    self.redraw = true;
+                } else if let Some(key) = self.keypad.key_at(self.keypad_area, x, y) {
+                    if self.player.is_none() {
+                        press_key(chip_8, &mut self.recorder, key, true);
+                        self.keypad.held = Some(key);
+                        self.redraw = true;
+                    }
+                }
+            }
+            SdlEvent::MouseMotion { x, y, .. } => self.redraw |= self.magnifier.hover(x, y),
+            SdlEvent::MouseWheel { y, .. } if self.paused => {
+                self.disassembly.scroll(chip_8, -y);
+                self.redraw = true;
+            }
+            SdlEvent::DropFile { filename, .. } => match read_program(&filename) {
+                Ok((program, new_symbols)) => {
+                    let message = format!("Loaded {}", filename);
+                    let name = Some(rom_name(&filename));
+                    self.dropped_program = Some((program, new_symbols, message, name));
+                }
+                Err(error) => {
+                    error!("{}: {}", filename, error);
+                    self.osd.show("Couldn't load the dropped file");
+                }
+            },
+            SdlEvent::MouseButtonUp {
+                mouse_btn: MouseButton::Left,
+                ..
+            } => {
+                if let Some(key) = self.keypad.held.take() {
+                    press_key(chip_8, &mut self.recorder, key, false);
+                    self.redraw = true;
+                }
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    fn key_down(
+        &mut self,
+        chip_8: &mut State,
+        keycode: Option<Keycode>,
+        scancode: Option<Scancode>,
+        keymod: Mod,
+        repeat: bool,
+    ) -> Result<(), String> {
+        if self.pause_menu.is_open() {
+            self.redraw = true;
+            if let Some(action) = self.pause_menu.key(keycode) {
+                self.pause_menu_action(chip_8, action)?;
+            }
+            return Ok(());
+        }
+        if self.paused && self.register_panel.key(keycode, chip_8) {
+            self.redraw = true;
+            return Ok(());
+        }
+        if keycode == Some(Keycode::Escape) {
+            self.pause_menu.open();
+            self.redraw = true;
+            return Ok(());
+        }
+        if self.paused {
+            match keycode {
+                Some(Keycode::Up) => self.disassembly.scroll(chip_8, -1),
+                Some(Keycode::Down) => self.disassembly.scroll(chip_8, 1),
+                Some(Keycode::PageUp) => self.disassembly.scroll(chip_8, -8),
+                Some(Keycode::PageDown) => self.disassembly.scroll(chip_8, 8),
+                Some(Keycode::B) => {
+                    let address = self.disassembly.selection(chip_8);
+                    self.osd
+                        .show(toggle_breakpoint(&mut self.debugger, address));
+                }
+                _ => {}
+            }
+            self.redraw = true;
+        }
+        if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) {
+            let message = match keycode {
+                Some(Keycode::G) => {
+                    self.grid = !self.grid;
+                    if self.grid {
+                        "Grid shown"
+                    } else {
+                        "Grid hidden"
+                    }
+                }
+                Some(Keycode::M) => {
+                    self.magnifier.enabled = !self.magnifier.enabled;
+                    if self.magnifier.enabled {
+                        "Magnifier on"
+                    } else {
+                        "Magnifier off"
+                    }
+                }
+                Some(Keycode::D) => {
+                    self.debug_overlay.enabled = !self.debug_overlay.enabled;
+                    if self.debug_overlay.enabled {
+                        "Debug overlay on"
+                    } else {
+                        "Debug overlay off"
+                    }
+                }
+                Some(Keycode::H) => {
+                    self.heatmap.toggle(chip_8);
+                    if self.heatmap.enabled {
+                        "Heatmap on"
+                    } else {
+                        "Heatmap off"
+                    }
+                }
+                _ => "",
+            };
+            if !message.is_empty() {
+                self.redraw = true;
+                self.osd.show(message);
+                return Ok(());
+            }
+        }
+        if keycode == Some(Keycode::F10) {
+            print_stats(
+                chip_8,
+                &self.draw_counts.borrow(),
+                self.frames,
+                self.started.elapsed(),
+            );
+            print_profile(chip_8, 20);
+            self.osd.show("Stats printed");
+        }
+        if keycode == Some(Keycode::F5) {
+            self.paused = !self.paused;
+            self.osd
+                .show(if self.paused { "Paused" } else { "Resumed" });
+            if !self.paused {
+                self.resume_debuggers();
+            }
+        }
+        if keycode == Some(Keycode::Tab) && !repeat {
+            self.turbo = true;
+            self.osd
+                .show(format!("Speed {}x", SPEED_STEPS[self.speed] * TURBO_FACTOR));
+        }
+        if keycode == Some(Keycode::F6) {
+            let index = SLOW_MOTION_STEPS
+                .iter()
+                .position(|step| *step == self.slow_motion)
+                .unwrap_or(0);
+            self.slow_motion = SLOW_MOTION_STEPS[(index + 1) % SLOW_MOTION_STEPS.len()];
+            self.slow_cycles = 0;
+            self.osd.show(match self.slow_motion {
+                1 => "Slow motion off".to_string(),
+                divisor => format!("Slow motion {}x", 1.0 / divisor as f64),
+            });
+        }
+        let step = match keycode {
+            Some(Keycode::Equals | Keycode::Plus | Keycode::KpPlus) => {
+                Some((self.speed + 1).min(SPEED_STEPS.len() - 1))
+            }
+            Some(Keycode::Minus | Keycode::KpMinus) => Some(self.speed.saturating_sub(1)),
+            _ => None,
+        };
+        if let Some(step) = step {
+            self.speed = step;
+            self.osd.show(format!("Speed {}x", SPEED_STEPS[self.speed]));
+        }
+        if keycode == Some(Keycode::F7) {
+            self.next_audio_device()?;
+        }
+        if keycode == Some(Keycode::F8) {
+            self.toggle_keypad()?;
+        }
+        let slot = match keycode {
+            Some(Keycode::F1) => Some(1),
+            Some(Keycode::F2) => Some(2),
+            Some(Keycode::F3) => Some(3),
+            Some(Keycode::F4) => Some(4),
+            _ => None,
+        };
+        if let Some(slot) = slot {
+            if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                self.save_slot(chip_8, slot);
+            } else {
+                self.load_slot(chip_8, slot)?;
+            }
+        }
+        let macro_key = MACRO_KEYS.iter().find(|(key, _)| Some(*key) == keycode);
+        if let (Some((_, hotkey)), false) = (macro_key, repeat) {
+            self.macro_key(hotkey, keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD));
+        }
+        if let (Some(key), false) = (self.keymap.lookup(keycode, scancode), repeat) {
+            if self.player.is_none() {
+                press_key(chip_8, &mut self.recorder, key, true);
+                self.redraw |= self.keypad.visible;
+                record_macro_key(&mut self.macro_recording, self.timing_frame, key, true);
+            }
+        }
+        Ok(())
+    }
+
+    fn pause_menu_action(
+        &mut self,
+        chip_8: &mut State,
+        action: pausemenu::Action,
+    ) -> Result<(), String> {
+        match action {
+            pausemenu::Action::Resume => {}
+            pausemenu::Action::Reset => {
+                chip_8.reset();
+                if let Some(mirror) = &mut self.mirror {
+                    mirror.reset();
+                }
+                if let Some(cheats) = &self.cheats {
+                    cheats.apply_once(chip_8);
+                }
+                if let Some(recorder) = &mut self.recorder {
+                    warn!("The program restarted, restarting the replay recording");
+                    *recorder = replay::Replay::new(&self.rom, chip_8);
+                }
+                self.osd.show("Reset");
+            }
+            pausemenu::Action::Load(path) => match read_program(&path) {
+                Ok((program, new_symbols)) => {
+                    let message = format!("Loaded {}", path);
+                    let name = Some(rom_name(&path));
+                    self.dropped_program = Some((program, new_symbols, message, name));
+                }
+                Err(error) => {
+                    error!("{}: {}", path, error);
+                    self.osd.show("Couldn't load the ROM");
+                }
+            },
+            pausemenu::Action::Adjust(setting, step) => match setting {
+                pausemenu::Setting::Palette => {
+                    let current = theme::THEMES
+                        .iter()
+                        .position(|(_, colors)| *colors == self.palette);
+                    let next = cycle(current, theme::THEMES.len(), step);
+                    self.palette = theme::THEMES[next].1;
+                }
+                pausemenu::Setting::Speed => {
+                    self.speed = cycle(Some(self.speed), SPEED_STEPS.len(), step);
+                }
+                pausemenu::Setting::Sound => self.mute = !self.mute,
+                pausemenu::Setting::Quirks => {
+                    let current = chip8::Quirks::PRESETS
+                        .iter()
+                        .position(|name| chip8::Quirks::preset(name) == Ok(chip_8.quirks()));
+                    let next = cycle(current, chip8::Quirks::PRESETS.len(), step);
+                    chip_8.set_quirks(chip8::Quirks::preset(chip8::Quirks::PRESETS[next])?);
+                }
+                pausemenu::Setting::Keymap => {
+                    let current = keymap::LAYOUTS.iter().position(|name| *name == self.layout);
+                    let next = cycle(current, keymap::LAYOUTS.len(), step);
+                    self.layout = keymap::LAYOUTS[next].to_string();
+                    self.keymap = keymap::Keymap::preset(&self.layout)?;
+                }
+            },
+            pausemenu::Action::SaveForGame => {
+                let settings = sidecar::Settings {
+                    speed: Some(SPEED_STEPS[self.speed]),
+                    quirks: Some(chip_8.quirks()),
+                    palette: Some(self.palette),
+                    ..Default::default()
+                };
+                match settings.save(&self.rom) {
+                    Ok(()) => self.osd.show("Saved settings for this game"),
+                    Err(error) => {
+                        error!("Couldn't save the game's settings: {}", error);
+                        self.osd.show("Couldn't save the settings");
+                    }
+                }
+            }
+            pausemenu::Action::Quit => {
+                self.events.push_event(SdlEvent::Quit { timestamp: 0 })?;
+            }
+        }
+        Ok(())
+    }
+
+    // F7, the buzzer moving to the next audio device
+    fn next_audio_device(&mut self) -> Result<(), String> {
+        let Ok(audio) = &self.audio else {
+            return Ok(());
+        };
+        let current = self.buzzer.as_ref().and_then(audio::Buzzer::device);
+        let next = audio::next_device(audio, current)?;
+        // the old device closes before the next one opens
+        self.buzzer = None;
+        match audio::Buzzer::open(audio, next.as_deref()) {
+            Ok(next) => {
+                let name = next.device().unwrap_or("default").to_string();
+                info!("Sound on {}", name);
+                self.osd.show(format!("Sound on {}", name));
+                self.buzzer = Some(next);
+            }
+            Err(error) => {
+                error!("Couldn't open the audio device: {}", error);
+                self.osd.show("No sound");
+            }
+        }
+        Ok(())
+    }
+
+    // F8, showing or hiding the keypad under the display
+    fn toggle_keypad(&mut self) -> Result<(), String> {
+        self.keypad.visible = !self.keypad.visible;
+        if !self.fullscreen {
+            let height = if self.keypad.visible {
+                self.display_height + ui::Keypad::HEIGHT
+            } else {
+                self.display_height
+            };
+            self.canvas
+                .window_mut()
+                .set_size(self.window_width, height)
+                .map_err(|op| op.to_string())?;
+            render::fit_output(&mut self.canvas)?;
+        }
+        self.osd.show(if self.keypad.visible {
+            "Keypad shown"
+        } else {
+            "Keypad hidden"
+        });
+        Ok(())
+    }
+
+    // Shift+F1-F4
+    fn save_slot(&mut self, chip_8: &State, slot: u8) {
+        match savestate::save_slot(chip_8, &self.rom, slot) {
+            Ok(()) => {
+                info!("State saved to slot {}", slot);
+                self.osd.show(format!("State saved to slot {}", slot));
+            }
+            Err(error) => {
+                error!("Couldn't save slot {}: {}", slot, error);
+                self.osd.show(format!("Couldn't save slot {}", slot));
+            }
+        }
+    }
+
+    // F1-F4
+    fn load_slot(&mut self, chip_8: &mut State, slot: u8) -> Result<(), String> {
+        // replays start from power on, a loaded state couldn't be played back
+        if self.player.is_some() || self.recorder.is_some() {
+            warn!(
+                "Slot {} not loaded, states can't be loaded during a replay",
+                slot
+            );
+            self.osd.show("Can't load states during a replay");
+            return Ok(());
+        }
+        match savestate::load_slot(chip_8, &self.rom, slot) {
+            Ok(timestamp) => {
+                info!(
+                    "State loaded from slot {} ({})",
+                    slot,
+                    savestate::format_timestamp(timestamp)
+                );
+                self.osd.show(format!("State loaded from slot {}", slot));
+                if let Some(mirror) = &mut self.mirror {
+                    mirror.load_state(&chip_8.save_state())?;
+                }
+            }
+            Err(error) => {
+                error!("Couldn't load slot {}: {}", slot, error);
+                self.osd.show(format!("Slot {} is empty", slot));
+            }
+        }
+        Ok(())
+    }
+
+    // F9, F11 or F12 plays a macro, with Shift records one or saves the
+    // one being recorded
+    fn macro_key(&mut self, hotkey: &'static str, shift: bool) {
+        if !shift {
+            if self.config.macro_for(hotkey).is_some() {
+                self.macro_playing = Some((hotkey, self.timing_frame));
+                self.osd.show(format!("Macro {}", hotkey));
+            }
+        } else if let Some((recorded, _, inputs)) = self
+            .macro_recording
+            .take()
+            .filter(|(key, ..)| *key == hotkey)
+        {
+            self.config
+                .set_macro(recorded, inputscript::InputScript::from_inputs(inputs));
+            match self.config.save() {
+                Ok(()) => self.osd.show(format!("Macro {} saved", recorded)),
+                Err(error) => {
+                    error!("Couldn't save the macro: {}", error);
+                    self.osd.show(format!("Macro {} not saved", recorded));
+                }
+            }
+        } else {
+            self.macro_recording = Some((hotkey, self.timing_frame, Vec::new()));
+            self.osd.show(format!(
+                "Recording macro {}, Shift+{} to stop",
+                hotkey, hotkey
+            ));
+        }
+    }
+
+    // Everything kept when the window closes
+    fn quit(&mut self, chip_8: &State) -> Result<(), String> {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.flush()?;
+        }
+        print_stats(
+            chip_8,
+            &self.draw_counts.borrow(),
+            self.frames,
+            self.started.elapsed(),
+        );
+        print_profile(chip_8, 20);
+        if let (Some(path), Some(map)) = (&self.coverage_path, chip_8.coverage()) {
+            coverage::export(path, map)?;
+        }
+        if self.autosave {
+            savestate::write_state(&savestate::autosave_path(&self.rom)?, chip_8)?;
+        }
+        if self.config.save_on_exit {
+            let (first_palette, first_speed, first_mute, first_layout) = &self.starting_settings;
+            if self.palette != *first_palette {
+                self.config.palette = Some(self.palette);
+            }
+            if self.speed != *first_speed {
+                self.config.speed = Some(SPEED_STEPS[self.speed]);
+            }
+            if self.mute != *first_mute {
+                self.config.mute = Some(self.mute);
+            }
+            if self.layout != *first_layout {
+                self.config.layout = Some(self.layout.clone());
+                // a layout picked in the pause menu replaces the overrides
+                self.user_overrides = None;
+            }
+            self.config.keymap = self.user_overrides.clone();
+            if let Err(error) = self.config.save() {
+                error!("Couldn't save the settings: {}", error);
+            }
+        }
+        if let (Some(recorder), Some(path)) = (&mut self.recorder, &self.record_path) {
+            recorder.finish(chip_8.stats().cycles, chip_8);
+            fs::write(path, recorder.to_bytes()).map_err(|op| op.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+impl Frontend for FullWindow {
+    fn draw(&mut self, screen: &[u8]) -> Result<(), String> {
+        let area = self.display_area();
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        self.canvas.clear();
+        render::draw_display(
+            &mut self.canvas,
+            &self.texture_creator,
+            screen,
+            None,
+            &self.palette,
+            self.pixel_outlines,
+            area,
+        )?;
+        self.canvas.present();
+        Ok(())
+    }
+
+    fn poll_events(&mut self) -> Result<Vec<Event>, String> {
+        let mut events = Vec::new();
+        for event in self.event_pump.poll_iter() {
+            match event {
+                SdlEvent::Quit { .. } => events.push(Event::Quit),
+                SdlEvent::KeyDown {
+                    keycode,
+                    scancode,
+                    repeat: false,
+                    ..
+                } => {
+                    if let Some(key) = self.keymap.lookup(keycode, scancode) {
+                        events.push(Event::Key { key, pressed: true });
+                    }
+                }
+                SdlEvent::KeyUp {
+                    keycode, scancode, ..
+                } => {
+                    if let Some(key) = self.keymap.lookup(keycode, scancode) {
+                        events.push(Event::Key {
+                            key,
+                            pressed: false,
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(events)
+    }
+
+    fn set_sound(&mut self, on: bool) {
+        if let Some(buzzer) = &self.buzzer {
+            buzzer.set_on(on && !self.mute);
+        }
+    }
+
+    fn run(
+        &mut self,
+        chip_8: &mut State,
+        timing: TimingModel,
+        timer_hz: Option<u32>,
+    ) -> Result<(), String> {
+        if !self.start(chip_8, timing, timer_hz)? {
+            return Ok(());
+        }
+        while self.frame(chip_8)? {}
+        self.quit(chip_8)
+    }
+}
+
+// A ROM, or Octo source assembled, with any symbols that go with it
+fn read_program(path: &str) -> Result<(Vec<u8>, symbols::Symbols), String> {
+    if is_octo(path) {
+        return assemble_file(path);
+    }
+    let rom = read_rom(path)?;
+    let symbols = symbols::Symbols::find(path).ok().flatten();
+    Ok((rom, symbols.unwrap_or_default()))
+}
+
+// The index step places from current among count, wrapping around. Something
+// not among them goes to the first.
+fn cycle(current: Option<usize>, count: usize, step: i32) -> usize {
+    match current {
+        Some(current) => (current as i32 + step).rem_euclid(count as i32) as usize,
+        None => 0,
+    }
+}
+
+// None if the file can't be read, which counts as a change once it's back
+fn modified_time(path: &str) -> Option<std::time::SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+// The file name without extension, e.g. PONG for roms/pong.ch8
+fn rom_name(path: &str) -> String {
+    std::path::Path::new(path)
+        .file_stem()
+        .map_or_else(
+            || path.to_string(),
+            |stem| stem.to_string_lossy().into_owned(),
+        )
+        .to_uppercase()
+}
+
+// A breakpoint toggled from the disassembly pane, the message to show
+fn toggle_breakpoint(debugger: &mut Option<debugger::Debugger>, address: u16) -> String {
+    let Some(debugger) = debugger else {
+        return "Breakpoints need --debug".to_string();
+    };
+    if debugger.toggle_breakpoint(address) {
+        format!("Breakpoint at {:03X}", address)
+    } else {
+        format!("Breakpoint at {:03X} removed", address)
+    }
+}
+
+// Adds a key to the macro being recorded, taking effect on the next frame
+// like the press itself
+fn record_macro_key(
+    recording: &mut Option<(&str, u64, Vec<inputscript::Input>)>,
+    frame: u64,
+    key: u8,
+    pressed: bool,
+) {
+    if let Some((_, start, inputs)) = recording {
+        inputs.push(inputscript::Input {
+            frame: frame + 1 - *start,
+            key,
+            pressed,
+        });
+    }
+}
+
+// A keypad change made by the user, recorded when a replay is being made.
+// It takes effect from the next cycle.
+fn press_key(chip_8: &mut State, recorder: &mut Option<replay::Replay>, key: u8, pressed: bool) {
+    chip_8.set_key(key as usize, pressed as u8);
+    if let Some(recorder) = recorder {
+        recorder.record(chip_8.stats().cycles, key, pressed);
+    }
+}
+
+// Asks a yes/no question on the terminal, no when there's nobody to ask
+fn confirm(question: &str) -> bool {
+    let stdin = std::io::stdin();
+    if !stdin.is_terminal() {
+        return false;
+    }
+    print!("{} [Y/n] ", question);
+    let _ = std::io::stdout().flush();
+    let mut answer = String::new();
+    if stdin.read_line(&mut answer).is_err() {
+        return false;
+    }
+    !answer.trim().to_ascii_lowercase().starts_with('n')
+}
+
+// Tallied from chip8::Event for print_stats
+#[derive(Debug, Default)]
+struct DrawCounts {
+    sprites: u64,
+    collisions: u64,
+    clears: u64,
+}
+
+fn print_stats(chip_8: &State, draws: &DrawCounts, frames: u64, elapsed: Duration) {
+    let stats = chip_8.stats();
+    let seconds = elapsed.as_secs_f64();
+    println!("Cycles executed: {}", stats.cycles);
+    println!("Frames drawn:    {}", frames);
+    println!(
+        "Sprites drawn:   {} ({} collisions, {} clears)",
+        draws.sprites, draws.collisions, draws.clears
+    );
+    println!("Wall clock:      {:.2}s", seconds);
+    if seconds > 0.0 {
+        println!(
+            "Effective speed: {:.1} instructions/s",
+            stats.cycles as f64 / seconds
+        );
+    }
+    if stats.unknown_opcodes > 0 {
+        println!("Unknown opcodes: {}", stats.unknown_opcodes);
+    }
+    println!("Opcode families:");
+    for (family, count) in stats.opcode_families.iter().enumerate() {
+        if *count > 0 {
+            println!("  {:X}xxx: {}", family, count);
+        }
+    }
+}
+
+fn print_profile(chip_8: &State, count: usize) {
+    if let Some(profile) = chip_8.profile() {
+        let mut hotspots: Vec<(usize, u64)> = profile
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|(_, hits)| *hits > 0)
+            .collect();
+        hotspots.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        println!("Hottest addresses:");
+        for (address, hits) in hotspots.into_iter().take(count) {
+            let address = address as u16;
+            let opcode = ((chip_8.read_memory(address) as u16) << 8)
+                | chip_8.read_memory(address.wrapping_add(1)) as u16;
+            println!(
+                "  {:#05X}: {:>10}  {:04X}  {}",
+                address,
+                hits,
+                opcode,
+                disasm::disassemble(opcode)
+            );
+        }
+    }
+}
@@ -0,0 +1,337 @@
+// Just enough of the ZIP format to pull ROMs out of a ROM pack: the central
+// directory, stored and deflated entries, and CRC checks.
+use crate::hash;
+
+const END_OF_DIRECTORY: u32 = 0x0605_4B50;
+const DIRECTORY_ENTRY: u32 = 0x0201_4B50;
+const LOCAL_HEADER: u32 = 0x0403_4B50;
+// anything bigger can't be a CHIP-8 program, and isn't worth inflating
+const MAX_ENTRY_SIZE: usize = 1 << 20;
+
+// The .ch8 files in an archive as (name, contents), in archive order
+pub fn roms(data: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let end = (0..data.len().saturating_sub(21))
+        .rev()
+        .find(|offset| u32_at(data, *offset) == Some(END_OF_DIRECTORY))
+        .ok_or("Not a ZIP archive")?;
+    let count = u16_at(data, end + 10).ok_or("Truncated ZIP archive")? as usize;
+    let mut offset = u32_at(data, end + 16).ok_or("Truncated ZIP archive")? as usize;
+
+    let mut roms = Vec::new();
+    for _ in 0..count {
+        if u32_at(data, offset) != Some(DIRECTORY_ENTRY) {
+            return Err("Corrupt ZIP directory".to_string());
+        }
+        let field = |at: usize| u16_at(data, offset + at).ok_or("Truncated ZIP directory");
+        let method = field(10)?;
+        let crc = u32_at(data, offset + 16).ok_or("Truncated ZIP directory")?;
+        let compressed = u32_at(data, offset + 20).ok_or("Truncated ZIP directory")? as usize;
+        let size = u32_at(data, offset + 24).ok_or("Truncated ZIP directory")? as usize;
+        let name_length = field(28)? as usize;
+        let extra_length = field(30)? as usize;
+        let comment_length = field(32)? as usize;
+        let local = u32_at(data, offset + 42).ok_or("Truncated ZIP directory")? as usize;
+        let name = data
+            .get(offset + 46..offset + 46 + name_length)
+            .ok_or("Truncated ZIP directory")?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        offset += 46 + name_length + extra_length + comment_length;
+
+        let is_rom = name
+            .rsplit('.')
+            .next()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("ch8"));
+        if !is_rom || name.ends_with('/') {
+            continue;
+        }
+        if size > MAX_ENTRY_SIZE {
+            return Err(format!("{} is too large to be a ROM", name));
+        }
+
+        if u32_at(data, local) != Some(LOCAL_HEADER) {
+            return Err(format!("Corrupt ZIP entry {}", name));
+        }
+        let start = local
+            + 30
+            + u16_at(data, local + 26).ok_or("Truncated ZIP entry")? as usize
+            + u16_at(data, local + 28).ok_or("Truncated ZIP entry")? as usize;
+        let stored = data
+            .get(start..start + compressed)
+            .ok_or_else(|| format!("Truncated ZIP entry {}", name))?;
+        let contents = match method {
+            0 => stored.to_vec(),
+            8 => inflate(stored, size).map_err(|error| format!("{}: {}", name, error))?,
+            _ => return Err(format!("{} uses unsupported compression {}", name, method)),
+        };
+        if contents.len() != size || hash::crc32(&contents) != crc {
+            return Err(format!("{} is corrupt", name));
+        }
+        roms.push((name, contents));
+    }
+    Ok(roms)
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+// DEFLATE (RFC 1951) decoding, stopping with an error past limit bytes
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// the order code length code lengths are sent in
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+struct Bits<'a> {
+    data: &'a [u8],
+    // in bits
+    position: usize,
+}
+
+impl Bits<'_> {
+    fn read(&mut self, count: u8) -> Result<u32, String> {
+        let mut value = 0;
+        for index in 0..count {
+            let byte = self
+                .data
+                .get(self.position / 8)
+                .ok_or("Compressed data ends early")?;
+            value |= (((byte >> (self.position % 8)) & 1) as u32) << index;
+            self.position += 1;
+        }
+        Ok(value)
+    }
+}
+
+// A canonical Huffman code, decoded a bit at a time
+struct Huffman {
+    // how many codes there are of each length
+    counts: [u16; 16],
+    // symbols ordered by code
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0; 16];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut symbols = Vec::with_capacity(lengths.len());
+        for length in 1..16 {
+            for (symbol, symbol_length) in lengths.iter().enumerate() {
+                if *symbol_length == length {
+                    symbols.push(symbol as u16);
+                }
+            }
+        }
+        Self { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Result<u16, String> {
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+        for length in 1..16 {
+            code |= bits.read(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("Invalid Huffman code".to_string())
+    }
+}
+
+fn inflate(data: &[u8], limit: usize) -> Result<Vec<u8>, String> {
+    let mut bits = Bits { data, position: 0 };
+    let mut output = Vec::with_capacity(limit);
+    loop {
+        let last = bits.read(1)? == 1;
+        match bits.read(2)? {
+            0 => {
+                bits.position = bits.position.div_ceil(8) * 8;
+                let length = bits.read(16)? as usize;
+                if bits.read(16)? as usize != !length & 0xFFFF {
+                    return Err("Corrupt stored block".to_string());
+                }
+                let start = bits.position / 8;
+                let block = data
+                    .get(start..start + length)
+                    .ok_or("Compressed data ends early")?;
+                output.extend_from_slice(block);
+                bits.position += length * 8;
+            }
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+                let literals = Huffman::new(&lengths);
+                let distances = Huffman::new(&[5; 30]);
+                inflate_block(&mut bits, &literals, &distances, &mut output, limit)?;
+            }
+            2 => {
+                let (literals, distances) = read_dynamic_codes(&mut bits)?;
+                inflate_block(&mut bits, &literals, &distances, &mut output, limit)?;
+            }
+            _ => return Err("Invalid block type".to_string()),
+        }
+        if output.len() > limit {
+            return Err("Entry is larger than its header says".to_string());
+        }
+        if last {
+            return Ok(output);
+        }
+    }
+}
+
+fn read_dynamic_codes(bits: &mut Bits) -> Result<(Huffman, Huffman), String> {
+    let literal_count = bits.read(5)? as usize + 257;
+    let distance_count = bits.read(5)? as usize + 1;
+    let code_length_count = bits.read(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for index in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+        code_lengths[*index] = bits.read(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths);
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (value, repeat) = match code_lengths.decode(bits)? {
+            length @ 0..=15 => (length as u8, 1),
+            16 => (
+                *lengths.last().ok_or("Repeat with no previous length")?,
+                3 + bits.read(2)?,
+            ),
+            17 => (0, 3 + bits.read(3)?),
+            _ => (0, 11 + bits.read(7)?),
+        };
+        for _ in 0..repeat {
+            lengths.push(value);
+        }
+    }
+    if lengths.len() > literal_count + distance_count {
+        return Err("Code lengths overrun".to_string());
+    }
+    Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ))
+}
+
+fn inflate_block(
+    bits: &mut Bits,
+    literals: &Huffman,
+    distances: &Huffman,
+    output: &mut Vec<u8>,
+    limit: usize,
+) -> Result<(), String> {
+    loop {
+        let symbol = literals.decode(bits)? as usize;
+        match symbol {
+            0..=255 => output.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let index = symbol - 257;
+                if index >= LENGTH_BASE.len() {
+                    return Err("Invalid length code".to_string());
+                }
+                let length = LENGTH_BASE[index] as usize + bits.read(LENGTH_EXTRA[index])? as usize;
+                let index = distances.decode(bits)? as usize;
+                if index >= DISTANCE_BASE.len() {
+                    return Err("Invalid distance code".to_string());
+                }
+                let distance =
+                    DISTANCE_BASE[index] as usize + bits.read(DISTANCE_EXTRA[index])? as usize;
+                if distance > output.len() {
+                    return Err("Distance reaches before the start".to_string());
+                }
+                for _ in 0..length {
+                    output.push(output[output.len() - distance]);
+                }
+            }
+        }
+        if output.len() > limit {
+            return Err("Entry is larger than its header says".to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // pong.ch8 and readme.txt stored, games/tetris.ch8 deflated, from Python's zipfile
+    fn archive() -> Vec<u8> {
+        let hex = concat!(
+            "504b0304140000000000955b4f5d2c622d39020000000200000008000000706f",
+            "6e672e6368381200504b0304140000000000955b4f5dac2a93d8020000000200",
+            "00000a000000726561646d652e7478746869504b0304140000000800955b4f5d",
+            "e2822b522d000000780000001000000067616d65732f7465747269732e636838",
+            "6360646266616563e7e0e4e2e6e1e5e317101412161115139790949296919593",
+            "575054525651555367a0b23a00504b01021403140000000000955b4f5d2c622d",
+            "390200000002000000080000000000000000000000800100000000706f6e672e",
+            "636838504b01021403140000000000955b4f5dac2a93d802000000020000000a",
+            "0000000000000000000000800128000000726561646d652e747874504b010214",
+            "03140000000800955b4f5de2822b522d00000078000000100000000000000000",
+            "00000080015200000067616d65732f7465747269732e636838504b0506000000",
+            "0003000300ac000000ad0000000000",
+        );
+        (0..hex.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(&hex[index..index + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn reads_stored_and_deflated_roms() {
+        let roms = roms(&archive()).unwrap();
+        let tetris: Vec<u8> = (0..3).flat_map(|_| 0..40).collect();
+        assert_eq!(
+            roms,
+            vec![
+                ("pong.ch8".to_string(), vec![0x12, 0x00]),
+                ("games/tetris.ch8".to_string(), tetris),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_corrupt_entries() {
+        let mut data = archive();
+        // the first byte of pong.ch8
+        data[38] ^= 0xFF;
+        assert_eq!(roms(&data), Err("pong.ch8 is corrupt".to_string()));
+        assert!(roms(&data[..100]).is_err());
+        assert!(roms(b"not a zip").is_err());
+    }
+}
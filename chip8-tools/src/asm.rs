@@ -0,0 +1,248 @@
+// Two pass assembler for the mnemonics the disassembler prints.
+// One statement per line, `label:` defines an address, `;` starts a comment,
+// DB/DW emit raw bytes/words. Numbers are decimal, 0x hex or 0b binary.
+use crate::chip8::XO_CHIP_MEMORY_SIZE;
+use crate::symbols::Symbols;
+use std::collections::HashMap;
+
+// programs are loaded here
+pub const ORIGIN: u16 = 0x200;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    V(u16),
+    I,
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    B,
+    Value(String),
+}
+
+struct Statement {
+    line: usize,
+    mnemonic: String,
+    operands: Vec<Operand>,
+}
+
+// The program and its labels
+pub fn assemble(source: &str) -> Result<(Vec<u8>, Symbols), String> {
+    let mut labels = HashMap::new();
+    let mut statements = Vec::new();
+    let mut address = ORIGIN as usize;
+
+    // first pass, find every label's address
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let mut text = line.split(';').next().unwrap_or("").trim();
+        while let Some(colon) = text.find(':') {
+            let label = text[..colon].trim();
+            if label.is_empty() || label.contains(char::is_whitespace) {
+                break;
+            }
+            if address >= XO_CHIP_MEMORY_SIZE {
+                return Err(format!("line {}: program too large", line_number));
+            }
+            if labels.insert(label.to_string(), address as u16).is_some() {
+                return Err(format!("line {}: duplicate label {}", line_number, label));
+            }
+            text = text[colon + 1..].trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+        let statement = parse_statement(line_number, text);
+        address += match statement.mnemonic.as_str() {
+            "DB" => statement.operands.len(),
+            "DW" => statement.operands.len() * 2,
+            _ => 2,
+        };
+        if address > XO_CHIP_MEMORY_SIZE {
+            return Err(format!("line {}: program too large", line_number));
+        }
+        statements.push(statement);
+    }
+
+    // second pass, encode
+    let mut bytes = Vec::with_capacity(address - ORIGIN as usize);
+    for statement in &statements {
+        let error = |message: &str| format!("line {}: {}", statement.line, message);
+        match statement.mnemonic.as_str() {
+            "DB" => {
+                for operand in &statement.operands {
+                    bytes.push(value(operand, &labels, 0xFF).map_err(|op| error(&op))? as u8);
+                }
+            }
+            "DW" => {
+                for operand in &statement.operands {
+                    let word = value(operand, &labels, 0xFFFF).map_err(|op| error(&op))?;
+                    bytes.extend_from_slice(&word.to_be_bytes());
+                }
+            }
+            _ => {
+                let opcode = encode(statement, &labels).map_err(|op| error(&op))?;
+                bytes.extend_from_slice(&opcode.to_be_bytes());
+            }
+        }
+    }
+    Ok((bytes, Symbols::from_labels(&labels)))
+}
+
+fn parse_statement(line: usize, text: &str) -> Statement {
+    let (mnemonic, rest) = match text.find(char::is_whitespace) {
+        Some(split) => (&text[..split], text[split..].trim()),
+        None => (text, ""),
+    };
+    let operands = if rest.is_empty() {
+        Vec::new()
+    } else {
+        rest.split(',').map(|op| parse_operand(op.trim())).collect()
+    };
+    Statement {
+        line,
+        mnemonic: mnemonic.to_ascii_uppercase(),
+        operands,
+    }
+}
+
+fn parse_operand(text: &str) -> Operand {
+    let upper = text.to_ascii_uppercase();
+    match upper.as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::IndirectI,
+        "DT" => Operand::Dt,
+        "ST" => Operand::St,
+        "K" => Operand::K,
+        "F" => Operand::F,
+        "B" => Operand::B,
+        _ => match upper
+            .strip_prefix('V')
+            .filter(|reg| reg.len() == 1)
+            .and_then(|reg| u16::from_str_radix(reg, 16).ok())
+        {
+            Some(register) => Operand::V(register),
+            None => Operand::Value(text.to_string()),
+        },
+    }
+}
+
+fn value(operand: &Operand, labels: &HashMap<String, u16>, max: u32) -> Result<u16, String> {
+    let text = match operand {
+        Operand::Value(text) => text,
+        _ => return Err(format!("expected a value, found {:?}", operand)),
+    };
+    let lower = text.to_ascii_lowercase();
+    let number = if let Some(hex) = lower.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        u32::from_str_radix(binary, 2).ok()
+    } else {
+        lower.parse::<u32>().ok()
+    };
+    let number = match number {
+        Some(number) => number,
+        None => *labels
+            .get(text)
+            .ok_or_else(|| format!("unknown label {}", text))? as u32,
+    };
+    if number > max {
+        return Err(format!("{} is larger than {:#X}", text, max));
+    }
+    Ok(number as u16)
+}
+
+fn encode(statement: &Statement, labels: &HashMap<String, u16>) -> Result<u16, String> {
+    use Operand::*;
+    let address = |operand| value(operand, labels, 0xFFF);
+    let byte = |operand| value(operand, labels, 0xFF);
+    let nibble = |operand| value(operand, labels, 0xF);
+    let xy = |x: u16, y: u16| (x << 8) | (y << 4);
+
+    Ok(
+        match (statement.mnemonic.as_str(), statement.operands.as_slice()) {
+            ("CLS", []) => 0x00E0,
+            ("RET", []) => 0x00EE,
+            ("SYS", [a]) => address(a)?,
+            ("JP", [V(0), a]) => 0xB000 | address(a)?,
+            ("JP", [a]) => 0x1000 | address(a)?,
+            ("CALL", [a]) => 0x2000 | address(a)?,
+            ("SE", [V(x), V(y)]) => 0x5000 | xy(*x, *y),
+            ("SE", [V(x), kk]) => 0x3000 | (x << 8) | byte(kk)?,
+            ("SNE", [V(x), V(y)]) => 0x9000 | xy(*x, *y),
+            ("SNE", [V(x), kk]) => 0x4000 | (x << 8) | byte(kk)?,
+            ("LD", [V(x), V(y)]) => 0x8000 | xy(*x, *y),
+            ("LD", [V(x), Dt]) => 0xF007 | (x << 8),
+            ("LD", [V(x), K]) => 0xF00A | (x << 8),
+            ("LD", [V(x), IndirectI]) => 0xF065 | (x << 8),
+            ("LD", [V(x), kk]) => 0x6000 | (x << 8) | byte(kk)?,
+            ("LD", [I, a]) => 0xA000 | address(a)?,
+            ("LD", [Dt, V(x)]) => 0xF015 | (x << 8),
+            ("LD", [St, V(x)]) => 0xF018 | (x << 8),
+            ("LD", [F, V(x)]) => 0xF029 | (x << 8),
+            ("LD", [B, V(x)]) => 0xF033 | (x << 8),
+            ("LD", [IndirectI, V(x)]) => 0xF055 | (x << 8),
+            ("ADD", [V(x), V(y)]) => 0x8004 | xy(*x, *y),
+            ("ADD", [V(x), kk]) => 0x7000 | (x << 8) | byte(kk)?,
+            ("ADD", [I, V(x)]) => 0xF01E | (x << 8),
+            ("OR", [V(x), V(y)]) => 0x8001 | xy(*x, *y),
+            ("AND", [V(x), V(y)]) => 0x8002 | xy(*x, *y),
+            ("XOR", [V(x), V(y)]) => 0x8003 | xy(*x, *y),
+            ("SUB", [V(x), V(y)]) => 0x8005 | xy(*x, *y),
+            ("SHR", [V(x)]) => 0x8006 | xy(*x, *x),
+            ("SHR", [V(x), V(y)]) => 0x8006 | xy(*x, *y),
+            ("SUBN", [V(x), V(y)]) => 0x8007 | xy(*x, *y),
+            ("SHL", [V(x)]) => 0x800E | xy(*x, *x),
+            ("SHL", [V(x), V(y)]) => 0x800E | xy(*x, *y),
+            ("RND", [V(x), kk]) => 0xC000 | (x << 8) | byte(kk)?,
+            ("DRW", [V(x), V(y), n]) => 0xD000 | xy(*x, *y) | nibble(n)?,
+            ("SKP", [V(x)]) => 0xE09E | (x << 8),
+            ("SKNP", [V(x)]) => 0xE0A1 | (x << 8),
+            _ => return Err(format!("invalid instruction {}", statement.mnemonic)),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn programs_may_go_past_4k() {
+        let source = format!("{}end: DB 1", "DB 0\n".repeat(0x1000));
+        let (bytes, symbols) = assemble(&source).unwrap();
+        assert_eq!(bytes.len(), 0x1001);
+        assert_eq!(symbols.address("end"), Some(0x1200));
+    }
+
+    #[test]
+    fn programs_stop_at_64k() {
+        let source = "DB 0\n".repeat(XO_CHIP_MEMORY_SIZE);
+        assert!(assemble(&source).is_err());
+    }
+
+    #[test]
+    fn round_trips_every_opcode_through_the_disassembler() {
+        for opcode in 0..=0xFFFF {
+            let mnemonic = crate::disasm::disassemble(opcode);
+            let (bytes, _) = assemble(&mnemonic).unwrap();
+            assert_eq!(bytes, opcode.to_be_bytes(), "{}", mnemonic);
+        }
+    }
+
+    #[test]
+    fn labels_can_be_used_before_they_are_defined() {
+        let (bytes, symbols) = assemble("start: JP end\nDB 1, 2\nend: CALL start").unwrap();
+        assert_eq!(bytes, [0x12, 0x04, 1, 2, 0x22, 0x00]);
+        assert_eq!(symbols.address("end"), Some(0x204));
+    }
+
+    #[test]
+    fn reports_the_line_of_an_error() {
+        let error = assemble("CLS\nLD V0, V1, V2\n").unwrap_err();
+        assert!(error.starts_with("line 2"), "{}", error);
+        assert!(assemble("JP nowhere").is_err());
+        assert!(assemble("a: CLS\na: CLS").is_err());
+    }
+}
@@ -0,0 +1,111 @@
+// The asm, disasm and info commands, run by chip8-tools and by chip8 itself
+// with the arguments after the command's name
+use crate::{assemble_file, disasm, hash, quirkdetect, read_rom, symbols};
+use std::fs;
+
+// disasm game.ch8 [--start 0x200] [--symbols game.sym]
+pub fn disasm(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let mut path = None;
+    let mut start = 0x200;
+    let mut symbols_path = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--start" => start = parse_number(&args.next().ok_or("Missing start address")?)?,
+            "--symbols" => symbols_path = Some(args.next().ok_or("Missing symbol file")?),
+            _ => path = Some(arg),
+        }
+    }
+    let path = path.ok_or("No ROM provided")?;
+    let rom = read_rom(&path)?;
+    // a .sym file next to the ROM is used unless another is given
+    let symbols = match symbols_path {
+        Some(symbols_path) => symbols::Symbols::read(std::path::Path::new(&symbols_path))?,
+        None => symbols::Symbols::find(&path)?.unwrap_or_default(),
+    };
+    for line in disasm::listing(&rom, start, &symbols) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+// asm source.s8|source.8o [-o game.ch8], labels are written to game.sym
+pub fn asm(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let mut source = None;
+    let mut output = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = Some(args.next().ok_or("Missing output path")?),
+            _ => source = Some(arg),
+        }
+    }
+    let source = source.ok_or("No source provided")?;
+    let output = output.unwrap_or_else(|| {
+        std::path::Path::new(&source)
+            .with_extension("ch8")
+            .to_string_lossy()
+            .into_owned()
+    });
+    let (program, symbols) = assemble_file(&source)?;
+    fs::write(&output, &program).map_err(|op| op.to_string())?;
+    println!("Wrote {} bytes to {}", program.len(), output);
+    if !symbols.is_empty() {
+        let path = std::path::Path::new(&output).with_extension("sym");
+        fs::write(&path, symbols.to_text()).map_err(|op| op.to_string())?;
+        println!("Wrote labels to {}", path.display());
+    }
+    Ok(())
+}
+
+// info game.ch8
+pub fn info(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let path = args.next().ok_or("No ROM provided")?;
+    let rom = read_rom(&path)?;
+    println!("File:   {}", path);
+    println!("Size:   {} bytes", rom.len());
+    println!("SHA-1:  {}", hash::to_hex(&hash::sha1(&rom)));
+    println!("CRC32:  {:08x}", hash::crc32(&rom));
+    if rom.len() >= 2 {
+        let opcode = ((rom[0] as u16) << 8) | rom[1] as u16;
+        println!("Entry:  {:04X}  {}", opcode, disasm::disassemble(opcode));
+    }
+
+    let mut extensions: Vec<(disasm::Extension, usize, u16)> = Vec::new();
+    let code = disasm::find_code(&rom, 0x200);
+    for offset in (0..rom.len()).filter(|offset| code[*offset]) {
+        let opcode = ((rom[offset] as u16) << 8) | rom[offset + 1] as u16;
+        if let Some(extension) = disasm::extension(opcode) {
+            if !extensions.iter().any(|(seen, _, _)| *seen == extension) {
+                extensions.push((extension, 0x200 + offset, opcode));
+            }
+        }
+    }
+    if extensions.is_empty() {
+        println!("Uses:   CHIP-8 only");
+    }
+    for (extension, address, opcode) in extensions {
+        println!(
+            "Uses:   {} (first {:04X} at {:#05X})",
+            extension, opcode, address
+        );
+    }
+
+    let detection = quirkdetect::detect(&rom, 0x200);
+    match (detection.quirks, detection.preset) {
+        (None, _) => println!("Quirks: nothing depends on them"),
+        (Some(_), Some(preset)) => println!("Quirks: likely {}", preset),
+        (Some(quirks), None) => println!("Quirks: likely {:?}", quirks),
+    }
+    for reason in detection.reasons {
+        println!("        {}", reason);
+    }
+    Ok(())
+}
+
+// accepts decimal or 0x-prefixed hexadecimal
+fn parse_number(text: &str) -> Result<u16, String> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => text.parse(),
+    }
+    .map_err(|_| format!("Invalid number: {}", text))
+}
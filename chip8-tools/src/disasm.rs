@@ -0,0 +1,197 @@
+// Turns opcodes into Cowgod-style mnemonics
+use crate::symbols::Symbols;
+
+pub fn disassemble(opcode: u16) -> String {
+    let x = (opcode & 0x0F00) >> 8;
+    let y = (opcode & 0x00F0) >> 4;
+    let n = opcode & 0x000F;
+    let nn = opcode & 0x00FF;
+    let nnn = opcode & 0x0FFF;
+    match opcode & 0xF000 {
+        0x0000 => match nnn {
+            0x0E0 => "CLS".to_string(),
+            0x0EE => "RET".to_string(),
+            _ => format!("SYS {:#05X}", nnn),
+        },
+        0x1000 => format!("JP {:#05X}", nnn),
+        0x2000 => format!("CALL {:#05X}", nnn),
+        0x3000 => format!("SE V{:X}, {:#04X}", x, nn),
+        0x4000 => format!("SNE V{:X}, {:#04X}", x, nn),
+        0x5000 if n == 0 => format!("SE V{:X}, V{:X}", x, y),
+        0x6000 => format!("LD V{:X}, {:#04X}", x, nn),
+        0x7000 => format!("ADD V{:X}, {:#04X}", x, nn),
+        0x8000 => match n {
+            0x0 => format!("LD V{:X}, V{:X}", x, y),
+            0x1 => format!("OR V{:X}, V{:X}", x, y),
+            0x2 => format!("AND V{:X}, V{:X}", x, y),
+            0x3 => format!("XOR V{:X}, V{:X}", x, y),
+            0x4 => format!("ADD V{:X}, V{:X}", x, y),
+            0x5 => format!("SUB V{:X}, V{:X}", x, y),
+            0x6 => format!("SHR V{:X}, V{:X}", x, y),
+            0x7 => format!("SUBN V{:X}, V{:X}", x, y),
+            0xE => format!("SHL V{:X}, V{:X}", x, y),
+            _ => data(opcode),
+        },
+        0x9000 if n == 0 => format!("SNE V{:X}, V{:X}", x, y),
+        0xA000 => format!("LD I, {:#05X}", nnn),
+        0xB000 => format!("JP V0, {:#05X}", nnn),
+        0xC000 => format!("RND V{:X}, {:#04X}", x, nn),
+        0xD000 => format!("DRW V{:X}, V{:X}, {:#X}", x, y, n),
+        0xE000 => match nn {
+            0x9E => format!("SKP V{:X}", x),
+            0xA1 => format!("SKNP V{:X}", x),
+            _ => data(opcode),
+        },
+        0xF000 => match nn {
+            0x07 => format!("LD V{:X}, DT", x),
+            0x0A => format!("LD V{:X}, K", x),
+            0x15 => format!("LD DT, V{:X}", x),
+            0x18 => format!("LD ST, V{:X}", x),
+            0x1E => format!("ADD I, V{:X}", x),
+            0x29 => format!("LD F, V{:X}", x),
+            0x33 => format!("LD B, V{:X}", x),
+            0x55 => format!("LD [I], V{:X}", x),
+            0x65 => format!("LD V{:X}, [I]", x),
+            _ => data(opcode),
+        },
+        _ => data(opcode),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Extension {
+    SuperChip,
+    XoChip,
+}
+
+impl std::fmt::Display for Extension {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Extension::SuperChip => "SCHIP",
+            Extension::XoChip => "XO-CHIP",
+        })
+    }
+}
+
+// Which extension an opcode outside the base instruction set belongs to
+pub fn extension(opcode: u16) -> Option<Extension> {
+    let n = opcode & 0x000F;
+    match opcode & 0xF000 {
+        0x0000 => match opcode & 0x0FFF {
+            0x0C0..=0x0CF | 0x0FB..=0x0FF => Some(Extension::SuperChip),
+            0x0D0..=0x0DF => Some(Extension::XoChip),
+            _ => None,
+        },
+        0x5000 if n == 2 || n == 3 => Some(Extension::XoChip),
+        0xD000 if n == 0 => Some(Extension::SuperChip),
+        0xF000 => match opcode & 0xFF {
+            0x30 | 0x75 | 0x85 => Some(Extension::SuperChip),
+            0x00 | 0x01 | 0x02 | 0x3A => Some(Extension::XoChip),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// anything that isn't a valid instruction
+fn data(opcode: u16) -> String {
+    format!("DW {:#06X}", opcode)
+}
+
+// Address/opcode/mnemonic listing of a ROM loaded at start. Code is found by
+// following execution from start through jumps, calls and skips, everything
+// that isn't reached is listed as data bytes. Labels from symbols head the
+// lines they name and are noted after instructions that refer to them.
+pub fn listing(rom: &[u8], start: u16, symbols: &Symbols) -> Vec<String> {
+    let code = find_code(rom, start);
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    while offset < rom.len() {
+        let address = start as usize + offset;
+        if let Some(name) = symbols.name(address as u16) {
+            lines.push(format!("{}:", name));
+        }
+        if code[offset] {
+            let opcode = ((rom[offset] as u16) << 8) | rom[offset + 1] as u16;
+            let mut line = format!("{:#05X}  {:04X}  {}", address, opcode, disassemble(opcode));
+            let target = match opcode & 0xF000 {
+                0x1000 | 0x2000 | 0xA000 | 0xB000 => symbols.name(opcode & 0x0FFF),
+                _ => None,
+            };
+            if let Some(name) = target {
+                line += &format!("  ; {}", name);
+            }
+            lines.push(line);
+            offset += 2;
+        } else {
+            lines.push(format!(
+                "{:#05X}  {:02X}    DB {:#04X}",
+                address, rom[offset], rom[offset]
+            ));
+            offset += 1;
+        }
+    }
+    lines
+}
+
+// true for every offset an instruction starts at
+pub fn find_code(rom: &[u8], start: u16) -> Vec<bool> {
+    let mut code = vec![false; rom.len()];
+    let mut pending = vec![start];
+    while let Some(address) = pending.pop() {
+        let offset = match (address as usize).checked_sub(start as usize) {
+            Some(offset) if offset + 1 < rom.len() => offset,
+            _ => continue,
+        };
+        if code[offset] {
+            continue;
+        }
+        code[offset] = true;
+
+        let opcode = ((rom[offset] as u16) << 8) | rom[offset + 1] as u16;
+        let next = address.wrapping_add(2);
+        let nnn = opcode & 0x0FFF;
+        match opcode & 0xF000 {
+            0x0000 if opcode == 0x00EE => {}
+            0x1000 => pending.push(nnn),
+            0x2000 => {
+                pending.push(nnn);
+                pending.push(next);
+            }
+            0x3000 | 0x4000 | 0x5000 | 0x9000 | 0xE000 => {
+                pending.push(next);
+                pending.push(next.wrapping_add(2));
+            }
+            // the target depends on V0 at runtime
+            0xB000 => {}
+            _ if disassemble(opcode).starts_with("DW") && extension(opcode).is_none() => {}
+            _ => pending.push(next),
+        }
+    }
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disassembles_mnemonics() {
+        assert_eq!(disassemble(0x00E0), "CLS");
+        assert_eq!(disassemble(0x1234), "JP 0x234");
+        assert_eq!(disassemble(0x8AB6), "SHR VA, VB");
+        assert_eq!(disassemble(0xD125), "DRW V1, V2, 0x5");
+        assert_eq!(disassemble(0xF355), "LD [I], V3");
+        assert_eq!(disassemble(0x5121), "DW 0x5121");
+    }
+
+    #[test]
+    fn finds_code_by_following_execution() {
+        // call 0x206, jump to itself, a data byte pair, then the subroutine
+        let rom = [0x22, 0x06, 0x12, 0x02, 0xAB, 0xCD, 0x00, 0xEE];
+        let code = find_code(&rom, 0x200);
+        assert_eq!(code, [true, false, true, false, false, false, true, false]);
+        let listing = listing(&rom, 0x200, &Symbols::default());
+        assert_eq!(listing[2], "0x204  AB    DB 0xAB");
+    }
+}
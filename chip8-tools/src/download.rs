@@ -0,0 +1,47 @@
+// ROMs given as http(s) links. The system's curl does the transfer, so TLS
+// and redirects work without building a network stack into the emulator.
+#[cfg(feature = "url")]
+use chip8_core::info;
+#[cfg(feature = "url")]
+use std::process::Command;
+
+// anything bigger can't be a CHIP-8 program or a reasonable ROM pack
+#[cfg(feature = "url")]
+const MAX_SIZE: &str = "1048576";
+
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+#[cfg(feature = "url")]
+pub fn fetch(url: &str) -> Result<Vec<u8>, String> {
+    info!("Downloading {}", url);
+    let output = Command::new("curl")
+        .args([
+            "--fail",
+            "--silent",
+            "--show-error",
+            "--location",
+            "--max-filesize",
+            MAX_SIZE,
+            url,
+        ])
+        .output()
+        .map_err(|op| format!("Couldn't run curl: {}", op))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Couldn't download {}: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(output.stdout)
+}
+
+#[cfg(not(feature = "url"))]
+pub fn fetch(url: &str) -> Result<Vec<u8>, String> {
+    Err(format!(
+        "Can't download {}, this build doesn't have the url feature",
+        url
+    ))
+}
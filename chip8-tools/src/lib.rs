@@ -0,0 +1,46 @@
+// Assembler, disassembler and ROM inspection, used by the chip8-tools
+// command and by the emulator for its debugger and .8o sources
+use chip8_core as chip8;
+use std::fs;
+use std::io::Read;
+
+pub mod asm;
+pub mod commands;
+pub mod disasm;
+pub mod download;
+pub mod hash;
+pub mod octo;
+pub mod quirkdetect;
+pub mod symbols;
+
+// .8o files are Octo, anything else this repo's own assembly
+pub fn assemble_file(path: &str) -> Result<(Vec<u8>, symbols::Symbols), String> {
+    let source = fs::read_to_string(path).map_err(|op| op.to_string())?;
+    if is_octo(path) {
+        octo::assemble(&source)
+    } else {
+        asm::assemble(&source)
+    }
+    .map_err(|op| format!("{}: {}", path, op))
+}
+
+pub fn is_octo(path: &str) -> bool {
+    std::path::Path::new(path)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("8o"))
+}
+
+// A ROM file, - for standard input or, with the url feature, a link to one
+pub fn read_rom(path: &str) -> Result<Vec<u8>, String> {
+    if path == "-" {
+        let mut rom = Vec::new();
+        std::io::stdin()
+            .read_to_end(&mut rom)
+            .map_err(|op| op.to_string())?;
+        Ok(rom)
+    } else if download::is_url(path) {
+        download::fetch(path)
+    } else {
+        fs::read(path).map_err(|op| op.to_string())
+    }
+}
@@ -0,0 +1,13 @@
+// chip8-tools asm|disasm|info, the command line tools that don't need the
+// emulator's window
+use chip8_tools::commands;
+use std::env::args;
+
+pub fn main() -> Result<(), String> {
+    match args().nth(1).as_deref() {
+        Some("disasm") => commands::disasm(args().skip(2)),
+        Some("asm") => commands::asm(args().skip(2)),
+        Some("info") => commands::info(args().skip(2)),
+        _ => Err("Usage: chip8-tools asm|disasm|info ...".to_string()),
+    }
+}
@@ -0,0 +1,519 @@
+// Assembler for the core of Octo's language (.8o source), the de facto
+// standard for modern CHIP-8 development. Supported: `: label`, :const,
+// :alias, :byte, the register, timer and index statements, sprite, bcd,
+// save/load, calls by name, if/then, if/begin/else/end, loop/while/again,
+// raw bytes and # comments. Macros, :calc, :org, :unpack and the SUPER-CHIP
+// and XO-CHIP statements aren't.
+use crate::asm::ORIGIN;
+use crate::chip8::XO_CHIP_MEMORY_SIZE;
+use crate::symbols::Symbols;
+use std::collections::HashMap;
+
+struct Token {
+    text: String,
+    line: usize,
+}
+
+enum Block {
+    // the jump over the body when the condition fails
+    If { jump: usize },
+    // the jump over the else branch
+    Else { jump: usize },
+    Loop { start: u16, exits: Vec<usize> },
+}
+
+struct Compiler {
+    tokens: Vec<Token>,
+    position: usize,
+    output: Vec<u8>,
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, u16>,
+    aliases: HashMap<String, u16>,
+    // address operands to fill in once every label is known
+    fixups: Vec<(usize, String, usize)>,
+    blocks: Vec<Block>,
+}
+
+// The program and its labels
+pub fn assemble(source: &str) -> Result<(Vec<u8>, Symbols), String> {
+    let tokens: Vec<Token> = source
+        .lines()
+        .enumerate()
+        .flat_map(|(index, line)| {
+            line.split('#')
+                .next()
+                .unwrap_or("")
+                .split_whitespace()
+                .map(move |text| Token {
+                    text: text.to_string(),
+                    line: index + 1,
+                })
+        })
+        .collect();
+    let has_main = tokens
+        .windows(2)
+        .any(|pair| pair[0].text == ":" && pair[1].text == "main");
+
+    let mut compiler = Compiler {
+        tokens,
+        position: 0,
+        output: Vec::new(),
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+        aliases: HashMap::new(),
+        fixups: Vec::new(),
+        blocks: Vec::new(),
+    };
+    // like Octo, execution starts at main when there is one
+    if has_main {
+        compiler.jump(0x1000, "main".to_string(), 1);
+    }
+    while compiler.position < compiler.tokens.len() {
+        compiler.statement()?;
+    }
+    if !compiler.blocks.is_empty() {
+        return Err("unterminated begin or loop".to_string());
+    }
+    compiler.resolve()?;
+    if compiler.output.len() > XO_CHIP_MEMORY_SIZE - ORIGIN as usize {
+        return Err("program too large".to_string());
+    }
+    Ok((compiler.output, Symbols::from_labels(&compiler.labels)))
+}
+
+impl Compiler {
+    fn next(&mut self) -> Result<(String, usize), String> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .ok_or("unexpected end of file")?;
+        self.position += 1;
+        Ok((token.text.clone(), token.line))
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        let (text, line) = self.next()?;
+        if text != expected {
+            return Err(format!(
+                "line {}: expected {}, found {}",
+                line, expected, text
+            ));
+        }
+        Ok(())
+    }
+
+    fn here(&self) -> u16 {
+        // a program past the end of memory is an error once it's all compiled
+        ORIGIN.wrapping_add(self.output.len() as u16)
+    }
+
+    fn emit(&mut self, opcode: u16) {
+        self.output.extend_from_slice(&opcode.to_be_bytes());
+    }
+
+    // an instruction with an address to fill in later
+    fn jump(&mut self, opcode: u16, label: String, line: usize) {
+        self.fixups.push((self.output.len(), label, line));
+        self.emit(opcode);
+    }
+
+    fn patch(&mut self, offset: usize, address: u16) {
+        self.output[offset] = (self.output[offset] & 0xF0) | (address >> 8) as u8;
+        self.output[offset + 1] = address as u8;
+    }
+
+    fn resolve(&mut self) -> Result<(), String> {
+        for (offset, label, line) in std::mem::take(&mut self.fixups) {
+            let address = match self.labels.get(&label) {
+                Some(address) => *address,
+                None => number(&label)
+                    .filter(|value| *value <= 0xFFF)
+                    .ok_or_else(|| format!("line {}: undefined label {}", line, label))?,
+            };
+            self.patch(offset, address);
+        }
+        Ok(())
+    }
+
+    fn register(&self, text: &str, line: usize) -> Result<u16, String> {
+        if let Some(register) = self.aliases.get(text) {
+            return Ok(*register);
+        }
+        text.strip_prefix(['v', 'V'])
+            .filter(|digit| digit.len() == 1)
+            .and_then(|digit| u16::from_str_radix(digit, 16).ok())
+            .ok_or_else(|| format!("line {}: expected a register, found {}", line, text))
+    }
+
+    fn is_register(&self, text: &str) -> bool {
+        self.register(text, 0).is_ok()
+    }
+
+    fn value(&self, text: &str, line: usize, max: u16) -> Result<u16, String> {
+        let value = match self.constants.get(text) {
+            Some(value) => *value as i32,
+            None => signed(text).ok_or_else(|| format!("line {}: unknown value {}", line, text))?,
+        };
+        // negative numbers are two's complement bytes, as in Octo
+        let value = if (-128..0).contains(&value) {
+            value + 256
+        } else {
+            value
+        };
+        if value < 0 || value > max as i32 {
+            return Err(format!("line {}: {} is out of range", line, text));
+        }
+        Ok(value as u16)
+    }
+
+    // emits an address operand, a constant or number now, a label later
+    fn address(&mut self, opcode: u16) -> Result<(), String> {
+        let (text, line) = self.next()?;
+        match self.constants.get(&text) {
+            Some(value) => self.emit(opcode | (value & 0xFFF)),
+            None => self.jump(opcode, text, line),
+        }
+        Ok(())
+    }
+
+    // The instructions that skip the next one when the condition is false
+    // and when it's true
+    fn condition(&mut self) -> Result<(u16, u16), String> {
+        let (text, line) = self.next()?;
+        let x = self.register(&text, line)? << 8;
+        let (operator, line) = self.next()?;
+        match operator.as_str() {
+            "key" => return Ok((0xE0A1 | x, 0xE09E | x)),
+            "-key" => return Ok((0xE09E | x, 0xE0A1 | x)),
+            _ => {}
+        }
+        let (operand, operand_line) = self.next()?;
+        let (equal, not_equal) = if self.is_register(&operand) {
+            let y = self.register(&operand, operand_line)? << 4;
+            (0x5000 | x | y, 0x9000 | x | y)
+        } else {
+            let value = self.value(&operand, operand_line, 0xFF)?;
+            (0x3000 | x | value, 0x4000 | x | value)
+        };
+        match operator.as_str() {
+            // skips the next instruction when they differ
+            "==" => Ok((not_equal, equal)),
+            "!=" => Ok((equal, not_equal)),
+            _ => Err(format!(
+                "line {}: unsupported comparison {}",
+                line, operator
+            )),
+        }
+    }
+
+    fn statement(&mut self) -> Result<(), String> {
+        let (text, line) = self.next()?;
+        match text.as_str() {
+            ":" => {
+                let (name, line) = self.next()?;
+                if self.labels.insert(name.clone(), self.here()).is_some() {
+                    return Err(format!("line {}: duplicate label {}", line, name));
+                }
+            }
+            ":const" => {
+                let (name, _) = self.next()?;
+                let (value, line) = self.next()?;
+                let value = match self.labels.get(&value) {
+                    Some(address) => *address,
+                    None => self.value(&value, line, 0xFFF)?,
+                };
+                self.constants.insert(name, value);
+            }
+            ":alias" => {
+                let (name, _) = self.next()?;
+                let (register, line) = self.next()?;
+                let register = self.register(&register, line)?;
+                self.aliases.insert(name, register);
+            }
+            ":byte" => {
+                let (value, line) = self.next()?;
+                let value = self.value(&value, line, 0xFF)?;
+                self.output.push(value as u8);
+            }
+            ":breakpoint" => {
+                self.next()?;
+            }
+            ";" | "return" => self.emit(0x00EE),
+            "clear" => self.emit(0x00E0),
+            "jump" => self.address(0x1000)?,
+            "jump0" => self.address(0xB000)?,
+            "native" => self.address(0x0000)?,
+            "bcd" | "save" | "load" => {
+                let (register, line) = self.next()?;
+                let x = self.register(&register, line)? << 8;
+                self.emit(
+                    match text.as_str() {
+                        "bcd" => 0xF033,
+                        "save" => 0xF055,
+                        _ => 0xF065,
+                    } | x,
+                );
+            }
+            "sprite" => {
+                let (x, x_line) = self.next()?;
+                let (y, y_line) = self.next()?;
+                let (rows, rows_line) = self.next()?;
+                let opcode = 0xD000
+                    | self.register(&x, x_line)? << 8
+                    | self.register(&y, y_line)? << 4
+                    | self.value(&rows, rows_line, 0xF)?;
+                self.emit(opcode);
+            }
+            "i" => {
+                let (operator, line) = self.next()?;
+                match operator.as_str() {
+                    ":=" => {
+                        if self
+                            .tokens
+                            .get(self.position)
+                            .map(|token| token.text.as_str())
+                            == Some("hex")
+                        {
+                            self.next()?;
+                            let (register, line) = self.next()?;
+                            let x = self.register(&register, line)?;
+                            self.emit(0xF029 | x << 8);
+                        } else {
+                            self.address(0xA000)?;
+                        }
+                    }
+                    "+=" => {
+                        let (register, line) = self.next()?;
+                        let x = self.register(&register, line)?;
+                        self.emit(0xF01E | x << 8);
+                    }
+                    _ => {
+                        return Err(format!(
+                            "line {}: unsupported operator i {}",
+                            line, operator
+                        ))
+                    }
+                }
+            }
+            "delay" | "buzzer" => {
+                self.expect(":=")?;
+                let (register, line) = self.next()?;
+                let x = self.register(&register, line)? << 8;
+                self.emit(if text == "delay" { 0xF015 } else { 0xF018 } | x);
+            }
+            "if" => {
+                let (skip_if_false, skip_if_true) = self.condition()?;
+                let (keyword, line) = self.next()?;
+                match keyword.as_str() {
+                    "then" => self.emit(skip_if_false),
+                    "begin" => {
+                        self.emit(skip_if_true);
+                        self.blocks.push(Block::If {
+                            jump: self.output.len(),
+                        });
+                        self.emit(0x1000);
+                    }
+                    _ => return Err(format!("line {}: expected then or begin", line)),
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some(Block::If { jump }) => {
+                    let over = self.output.len();
+                    self.emit(0x1000);
+                    self.patch(jump, self.here());
+                    self.blocks.push(Block::Else { jump: over });
+                }
+                _ => return Err(format!("line {}: else without if ... begin", line)),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If { jump }) | Some(Block::Else { jump }) => {
+                    self.patch(jump, self.here());
+                }
+                _ => return Err(format!("line {}: end without begin", line)),
+            },
+            "loop" => self.blocks.push(Block::Loop {
+                start: self.here(),
+                exits: Vec::new(),
+            }),
+            "while" => {
+                let (_, skip_if_true) = self.condition()?;
+                self.emit(skip_if_true);
+                let exit = self.output.len();
+                self.emit(0x1000);
+                match self.blocks.iter_mut().rev().find_map(|block| match block {
+                    Block::Loop { exits, .. } => Some(exits),
+                    _ => None,
+                }) {
+                    Some(exits) => exits.push(exit),
+                    None => return Err(format!("line {}: while outside a loop", line)),
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop { start, exits }) => {
+                    self.emit(0x1000 | start);
+                    for exit in exits {
+                        self.patch(exit, self.here());
+                    }
+                }
+                _ => return Err(format!("line {}: again without loop", line)),
+            },
+            _ if self.is_register(&text) => self.assignment(&text, line)?,
+            _ if text.starts_with(':') => {
+                return Err(format!("line {}: {} isn't supported", line, text))
+            }
+            // a bare number is a data byte, anything else a call by name
+            _ => match self.value(&text, line, 0xFF) {
+                Ok(value) => self.output.push(value as u8),
+                Err(_) if signed(&text).is_some() => {
+                    return Err(format!("line {}: {} is out of range", line, text))
+                }
+                Err(_) => self.jump(0x2000, text, line),
+            },
+        }
+        Ok(())
+    }
+
+    fn assignment(&mut self, register: &str, line: usize) -> Result<(), String> {
+        let x = self.register(register, line)? << 8;
+        let (operator, line) = self.next()?;
+        let (operand, operand_line) = self.next()?;
+        if self.is_register(&operand) {
+            let y = self.register(&operand, operand_line)? << 4;
+            let operation = match operator.as_str() {
+                ":=" => 0x0,
+                "|=" => 0x1,
+                "&=" => 0x2,
+                "^=" => 0x3,
+                "+=" => 0x4,
+                "-=" => 0x5,
+                ">>=" => 0x6,
+                "=-" => 0x7,
+                "<<=" => 0xE,
+                _ => return Err(format!("line {}: unsupported operator {}", line, operator)),
+            };
+            self.emit(0x8000 | x | y | operation);
+            return Ok(());
+        }
+        match (operator.as_str(), operand.as_str()) {
+            (":=", "random") => {
+                let (mask, line) = self.next()?;
+                let mask = self.value(&mask, line, 0xFF)?;
+                self.emit(0xC000 | x | mask);
+            }
+            (":=", "key") => self.emit(0xF00A | x),
+            (":=", "delay") => self.emit(0xF007 | x),
+            (":=", _) => {
+                let value = self.value(&operand, operand_line, 0xFF)?;
+                self.emit(0x6000 | x | value);
+            }
+            ("+=", _) => {
+                let value = self.value(&operand, operand_line, 0xFF)?;
+                self.emit(0x7000 | x | value);
+            }
+            ("-=", _) => {
+                let value = self.value(&operand, operand_line, 0xFF)?;
+                self.emit(0x7000 | x | (256 - value) & 0xFF);
+            }
+            _ => return Err(format!("line {}: unsupported operator {}", line, operator)),
+        }
+        Ok(())
+    }
+}
+
+fn number(text: &str) -> Option<u16> {
+    signed(text).and_then(|value| u16::try_from(value).ok())
+}
+
+// decimal, 0x hex or 0b binary, optionally negative
+fn signed(text: &str) -> Option<i32> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let lower = digits.to_ascii_lowercase();
+    let value = if let Some(hex) = lower.strip_prefix("0x") {
+        i32::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        i32::from_str_radix(binary, 2).ok()?
+    } else {
+        lower.parse().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::disassemble;
+
+    // the program's mnemonics, two bytes at a time
+    fn mnemonics(source: &str) -> Vec<String> {
+        let (bytes, _) = assemble(source).unwrap();
+        bytes
+            .chunks(2)
+            .map(|pair| disassemble(((pair[0] as u16) << 8) | pair[1] as u16))
+            .collect()
+    }
+
+    #[test]
+    fn compiles_to_the_instructions_the_disassembler_reads_back() {
+        let source = "
+            : main
+              v0 := 5
+              v1 += 2
+              i := glyph
+              sprite v0 v1 2
+              if v0 == 5 then v2 := v3
+              loop
+                v0 -= v1
+                if v0 != 0 then
+              again
+              delay := v0
+              bcd v1
+              save v3
+              subroutine
+            : subroutine
+              return
+            : glyph
+              0xF0 0x90
+        ";
+        let expected = [
+            "JP 0x202",
+            "LD V0, 0x05",
+            "ADD V1, 0x02",
+            "LD I, 0x21E",
+            "DRW V0, V1, 0x2",
+            "SNE V0, 0x05",
+            "LD V2, V3",
+            "SUB V0, V1",
+            "SE V0, 0x00",
+            "JP 0x20E",
+            "LD DT, V0",
+            "LD B, V1",
+            "LD [I], V3",
+            "CALL 0x21C",
+            "RET",
+            "DW 0xF090",
+        ];
+        assert_eq!(mnemonics(source), expected);
+        // and the other assembler agrees
+        let (bytes, symbols) = assemble(source).unwrap();
+        assert_eq!(crate::asm::assemble(&expected.join("\n")).unwrap().0, bytes);
+        assert_eq!(symbols.address("glyph"), Some(0x21E));
+    }
+
+    #[test]
+    fn resolves_constants_and_aliases() {
+        let source = ":const speed 3 :alias x v4 : main x := speed x += speed";
+        assert_eq!(
+            mnemonics(source),
+            ["JP 0x202", "LD V4, 0x03", "ADD V4, 0x03"]
+        );
+    }
+
+    #[test]
+    fn reports_unknown_labels_and_open_blocks() {
+        assert!(assemble(": main jump nowhere").is_err());
+        assert!(assemble(": main loop v0 += 1").is_err());
+    }
+}
@@ -0,0 +1,223 @@
+// Guesses the quirks a ROM was written for from how its instructions are
+// used, for ROMs run without --quirks. Each telling instruction votes for or
+// against a quirk and is kept as a reason that can be logged.
+use crate::chip8::Quirks;
+use crate::disasm;
+
+// how many instructions after FX55/FX65 are looked at for the next use of I
+const FOLLOW_LENGTH: usize = 8;
+// how many instructions before BNNN are looked at for the register it adds
+const LOOKBACK_LENGTH: usize = 4;
+
+pub struct Detection {
+    // None when nothing in the ROM depends on a quirk
+    pub quirks: Option<Quirks>,
+    // the preset quirks matches, if any
+    pub preset: Option<&'static str>,
+    pub reasons: Vec<String>,
+}
+
+// votes for and against one quirk
+#[derive(Default)]
+struct Votes {
+    on: u32,
+    off: u32,
+}
+
+pub fn detect(rom: &[u8], start: u16) -> Detection {
+    let code = disasm::find_code(rom, start);
+    let opcode_at = |offset: usize| -> u16 { ((rom[offset] as u16) << 8) | rom[offset + 1] as u16 };
+    // the offsets of the reachable instructions, in address order
+    let instructions: Vec<usize> = (0..rom.len()).filter(|offset| code[*offset]).collect();
+
+    let mut shift = Votes::default();
+    let mut increment = Votes::default();
+    let mut jump = Votes::default();
+    let mut reasons = Vec::new();
+    for (index, offset) in instructions.iter().enumerate() {
+        let opcode = opcode_at(*offset);
+        let x = (opcode >> 8) & 0xF;
+        let y = (opcode >> 4) & 0xF;
+        let reason = |why: &str| {
+            format!(
+                "{:#05X} {:04X} {}: {}",
+                start as usize + offset,
+                opcode,
+                disasm::disassemble(opcode),
+                why
+            )
+        };
+        match opcode & 0xF00F {
+            // shifting one register into another only means something when
+            // the shift reads VY
+            0x8006 | 0x800E if x != y => {
+                shift.on += 1;
+                reasons.push(reason("shifts VY into VX, shift_vy"));
+            }
+            0xF005 if matches!(opcode & 0xFF, 0x55 | 0x65) => {
+                let following = instructions[index + 1..]
+                    .iter()
+                    .take(FOLLOW_LENGTH)
+                    .map(|offset| opcode_at(*offset));
+                for next in following {
+                    let stop = match next & 0xF0FF {
+                        _ if next & 0xF000 == 0xA000 || next & 0xF0FF == 0xF029 => true,
+                        // advancing I by hand past what was just loaded or stored
+                        0xF01E => {
+                            increment.off += 1;
+                            reasons.push(reason(
+                                "I is advanced by hand afterwards, no load_store_increment",
+                            ));
+                            true
+                        }
+                        0xF055 | 0xF065 if next == opcode => {
+                            increment.on += 1;
+                            reasons.push(reason("repeated without moving I, load_store_increment"));
+                            true
+                        }
+                        // loading, changing and storing back the same registers
+                        0xF055 if opcode & 0xFF == 0x65 && (next >> 8) & 0xF == x => {
+                            increment.off += 1;
+                            reasons.push(reason(
+                                "stored back to the same place, no load_store_increment",
+                            ));
+                            true
+                        }
+                        _ => matches!(next & 0xF000, 0x1000 | 0x2000 | 0xB000) || next == 0x00EE,
+                    };
+                    if stop {
+                        break;
+                    }
+                }
+            }
+            _ if opcode & 0xF000 == 0xB000 && x != 0 => {
+                let preceding = instructions[..index]
+                    .iter()
+                    .rev()
+                    .take(LOOKBACK_LENGTH)
+                    .map(|offset| opcode_at(*offset));
+                for previous in preceding {
+                    if !matches!(previous & 0xF000, 0x6000 | 0x7000 | 0x8000 | 0xC000) {
+                        continue;
+                    }
+                    match (previous >> 8) & 0xF {
+                        0 => {
+                            jump.off += 1;
+                            reasons.push(reason("V0 is set just before, no jump_vx"));
+                        }
+                        register if register == x => {
+                            jump.on += 1;
+                            reasons.push(reason(&format!(
+                                "V{:X} is set just before, jump_vx",
+                                register
+                            )));
+                        }
+                        _ => continue,
+                    }
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // a quirk with no votes either way isn't known
+    let decided = [&shift, &increment, &jump]
+        .map(|votes| (votes.on + votes.off > 0).then_some(votes.on > votes.off));
+    if decided.iter().all(Option::is_none) {
+        return Detection {
+            quirks: None,
+            preset: None,
+            reasons,
+        };
+    }
+    // a preset that agrees with everything decided brings its other quirks
+    // along, default is skipped as it isn't any real interpreter's
+    let preset = Quirks::PRESETS.into_iter().skip(1).find(|name| {
+        let preset = Quirks::preset(name).unwrap();
+        [preset.shift_vy, preset.load_store_increment, preset.jump_vx]
+            .iter()
+            .zip(decided)
+            .all(|(quirk, decided)| decided.is_none_or(|decided| decided == *quirk))
+    });
+    let quirks = match preset {
+        Some(name) => Quirks::preset(name).unwrap(),
+        None => Quirks {
+            shift_vy: decided[0].unwrap_or(false),
+            load_store_increment: decided[1].unwrap_or(false),
+            jump_vx: decided[2].unwrap_or(false),
+            vf_reset: false,
+            clip_sprites: false,
+        },
+    };
+    Detection {
+        quirks: Some(quirks),
+        preset,
+        reasons,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom(opcodes: &[u16]) -> Vec<u8> {
+        opcodes
+            .iter()
+            .flat_map(|opcode| opcode.to_be_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn nothing_telling_decides_nothing() {
+        // LD V0, 5 then loop
+        let detection = detect(&rom(&[0x6005, 0x1202]), 0x200);
+        assert!(detection.quirks.is_none());
+        assert!(detection.preset.is_none());
+        assert!(detection.reasons.is_empty());
+    }
+
+    #[test]
+    fn shifting_vy_matches_chip8() {
+        // SHR V1, V2 then loop
+        let detection = detect(&rom(&[0x8126, 0x1202]), 0x200);
+        assert_eq!(detection.preset, Some("chip8"));
+        assert_eq!(detection.quirks, Some(Quirks::preset("chip8").unwrap()));
+        assert_eq!(detection.reasons.len(), 1);
+        assert!(detection.reasons[0].starts_with("0x200 8126"));
+    }
+
+    #[test]
+    fn jumping_with_vx_matches_schip() {
+        // LD V1, 2 then JP V1, 0x100
+        let detection = detect(&rom(&[0x6102, 0xB100]), 0x200);
+        assert_eq!(detection.preset, Some("schip"));
+        assert!(detection.quirks.unwrap().jump_vx);
+    }
+
+    #[test]
+    fn advancing_i_by_hand_means_no_increment() {
+        // LD V2, [I], ADD I, V2 then loop
+        let detection = detect(&rom(&[0xF265, 0xF21E, 0x1204]), 0x200);
+        assert_eq!(detection.preset, Some("schip"));
+        assert!(!detection.quirks.unwrap().load_store_increment);
+    }
+
+    #[test]
+    fn conflicting_votes_match_no_preset() {
+        // SHR V1, V2, LD V1, 2 then JP V1, 0x100
+        let detection = detect(&rom(&[0x8126, 0x6102, 0xB100]), 0x200);
+        assert_eq!(detection.preset, None);
+        assert_eq!(
+            detection.quirks,
+            Some(Quirks {
+                shift_vy: true,
+                load_store_increment: false,
+                jump_vx: true,
+                vf_reset: false,
+                clip_sprites: false,
+            })
+        );
+        assert_eq!(detection.reasons.len(), 2);
+    }
+}
@@ -0,0 +1,86 @@
+// Label names for addresses, written by the assemblers next to the ROM as a
+// .sym file with one `0x0204 main_loop` pair per line, # starts a comment
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, Default)]
+pub struct Symbols {
+    names: BTreeMap<u16, String>,
+}
+
+impl Symbols {
+    pub fn from_labels<'a>(labels: impl IntoIterator<Item = (&'a String, &'a u16)>) -> Self {
+        Self {
+            names: labels
+                .into_iter()
+                .map(|(name, address)| (*address, name.clone()))
+                .collect(),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut names = BTreeMap::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (address, name) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| format!("line {}: expected an address and a name", index + 1))?;
+            let digits = address.trim_start_matches("0x").trim_start_matches("0X");
+            let address = u16::from_str_radix(digits, 16)
+                .ok()
+                .ok_or_else(|| format!("line {}: invalid address {}", index + 1, address))?;
+            names.insert(address, name.trim().to_string());
+        }
+        Ok(Self { names })
+    }
+
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|op| format!("{}: {}", path.display(), op))?;
+        Self::parse(&text).map_err(|op| format!("{}: {}", path.display(), op))
+    }
+
+    // The .sym file next to a ROM file, if there's one
+    pub fn find(rom_path: &str) -> Result<Option<Self>, String> {
+        let path = Path::new(rom_path).with_extension("sym");
+        if Path::new(rom_path).is_file() && path.is_file() {
+            Self::read(&path).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        self.names
+            .iter()
+            .map(|(address, name)| format!("{:#06x} {}\n", address, name))
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    pub fn name(&self, address: u16) -> Option<&str> {
+        self.names.get(&address).map(String::as_str)
+    }
+
+    pub fn address(&self, name: &str) -> Option<u16> {
+        self.names
+            .iter()
+            .find(|(_, label)| *label == name)
+            .map(|(address, _)| *address)
+    }
+
+    // `0x20A <main_loop+6>` relative to the closest label at or before address
+    pub fn describe(&self, address: u16) -> String {
+        match self.names.range(..=address).next_back() {
+            Some((start, name)) if *start == address => format!("{:#05X} <{}>", address, name),
+            Some((start, name)) => format!("{:#05X} <{}+{}>", address, name, address - start),
+            None => format!("{:#05X}", address),
+        }
+    }
+}